@@ -0,0 +1,267 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::chromosome::{CrossoverOperator, MutationOperator};
+use crate::io::Dataset;
+use crate::population::OffspringOutcome;
+use crate::rng::rng;
+
+/// How the mutation operator of each child is chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperatorSelection {
+    /// Every enabled operator is used in proportion to its configured rate.
+    Fixed,
+    /// Each generation uses the operator with the highest upper confidence bound (UCB1) on its
+    /// recent success rate. Operators that were rarely tried get a bonus that grows with `exploration`.
+    Ucb { exploration: f64 },
+    /// Each operator is used in proportion to its recent success rate, but never less often than
+    /// `min_probability` so that every operator keeps being tried.
+    ProbabilityMatching { min_probability: f64 },
+}
+
+/// How likely each mutation operator is to be applied to a child this generation, and how children are crossed over.
+///
+/// Breeding threads share it by reference.
+#[derive(Debug, Clone)]
+pub struct OperatorMix {
+    /// The probability of each operator, indexed by `MutationOperator::index`.
+    weights: [f64; MutationOperator::ALL.len()],
+    /// The standard deviation of Gaussian constant perturbations this generation.
+    pub sigma: f64,
+    /// How children exchange genes.
+    pub crossover: CrossoverOperator,
+    /// The probe rows, whose first targets semantic backpropagation aims the first output at.
+    pub probe: Option<Arc<Dataset>>,
+    /// The probability of a headless-chicken macro-mutation of each child, on top of the other operators.
+    pub headless_chicken: f64,
+}
+
+impl OperatorMix {
+    /// The standard deviation of Gaussian constant perturbations unless another is set.
+    pub const DEFAULT_SIGMA: f64 = 0.1;
+
+    /// Uses every given operator equally often.
+    ///
+    /// # Arguments
+    ///
+    /// * `operators` - The enabled operators. Must not be empty.
+    pub fn uniform(operators: &[MutationOperator]) -> OperatorMix {
+        return OperatorMix::weighted(&operators.iter().map(|&operator| (operator, 1.0)).collect::<Vec<_>>());
+    }
+
+    /// Uses every given operator in proportion to its rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `rates` - The enabled operators and their rates, which need not sum to 1 but must not all be 0.
+    pub fn weighted(rates: &[(MutationOperator, f64)]) -> OperatorMix {
+        let total: f64 = rates.iter().map(|&(_, rate)| rate).sum();
+        if total.is_nan() || total <= 0.0 {
+            panic!("At least one mutation operator must have a positive rate");
+        }
+        let mut weights = [0.0; MutationOperator::ALL.len()];
+        for &(operator, rate) in rates {
+            weights[operator.index()] += rate / total;
+        }
+        return OperatorMix { weights, sigma: OperatorMix::DEFAULT_SIGMA, crossover: CrossoverOperator::OnePoint, probe: None, headless_chicken: 0.0 };
+    }
+
+    /// Draws an operator according to the weights.
+    pub fn choose(&self) -> MutationOperator {
+        let mut draw = rng().gen::<f64>() * self.weights.iter().sum::<f64>();
+        for operator in MutationOperator::ALL {
+            draw -= self.weights[operator.index()];
+            if draw < 0.0 {
+                return operator;
+            }
+        }
+        // Rounding can leave a tiny remainder, which goes to the last enabled operator
+        return *MutationOperator::ALL.iter().rev().find(|o| self.weights[o.index()] > 0.0).unwrap();
+    }
+
+    /// The probability of applying `operator`.
+    pub fn weight(&self, operator: MutationOperator) -> f64 {
+        return self.weights[operator.index()];
+    }
+}
+
+impl PartialEq for OperatorMix {
+    /// Mixes are equal when they share the same probe rows.
+    fn eq(&self, other: &Self) -> bool {
+        let same_probe = match (&self.probe, &other.probe) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        return self.weights == other.weights && self.sigma == other.sigma && self.crossover == other.crossover
+            && same_probe && self.headless_chicken == other.headless_chicken;
+    }
+}
+
+impl Default for OperatorMix {
+    fn default() -> Self {
+        return OperatorMix::uniform(&[MutationOperator::Point]);
+    }
+}
+
+/// The standard deviation of the Gaussian mutation of constants in a generation, annealed
+/// geometrically from `initial` in the first generation to `last` in the final one.
+///
+/// # Arguments
+///
+/// * `initial` - The standard deviation in the first generation.
+/// * `last` - The standard deviation in the last generation. `None` keeps `initial` throughout.
+/// * `generation` - The current generation, from 0.
+/// * `generations` - How many generations the run has.
+pub fn annealed_sigma(initial: f64, last: Option<f64>, generation: usize, generations: usize) -> f64 {
+    return match last {
+        Some(last) if generations > 1 => initial * (last / initial).powf(generation as f64 / (generations - 1) as f64),
+        _ => initial,
+    };
+}
+
+/// A multi-armed bandit that shares out the mutation operators according to how often the
+/// children they produced were fitter than their parents.
+///
+/// The statistics decay every generation, so the bandit follows the operators that work at the
+/// current stage of the run rather than over the whole run.
+pub struct OperatorBandit {
+    selection: OperatorSelection,
+    /// The enabled operators and their configured rates.
+    rates: Vec<(MutationOperator, f64)>,
+    operators: Vec<MutationOperator>,
+    /// The decayed number of children produced by each operator.
+    uses: [f64; MutationOperator::ALL.len()],
+    /// The decayed number of those children that were fitter than their parents.
+    improvements: [f64; MutationOperator::ALL.len()],
+}
+
+impl OperatorBandit {
+    /// How much of the statistics of an operator is kept from one generation to the next.
+    const DECAY: f64 = 0.8;
+
+    /// Creates a bandit over the given operators with no statistics yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `selection` - How the operators are shared out.
+    /// * `rates` - The enabled operators and their rates. The rates are only used with
+    ///   `OperatorSelection::Fixed`, the other selections start from an equal share.
+    pub fn new(selection: OperatorSelection, rates: Vec<(MutationOperator, f64)>) -> OperatorBandit {
+        let operators: Vec<MutationOperator> = rates.iter().filter(|&&(_, rate)| rate > 0.0).map(|&(operator, _)| operator).collect();
+        if operators.is_empty() {
+            panic!("At least one mutation operator must have a positive rate");
+        }
+        return OperatorBandit {
+            selection,
+            rates,
+            operators,
+            uses: [0.0; MutationOperator::ALL.len()],
+            improvements: [0.0; MutationOperator::ALL.len()],
+        };
+    }
+
+    /// Credits each operator with the children it produced in the last generation.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcomes` - The outcome of every child bred in the last generation. Children that were
+    ///   not mutated are ignored.
+    pub fn update(&mut self, outcomes: &[OffspringOutcome]) {
+        self.uses.iter_mut().chain(&mut self.improvements).for_each(|v| *v *= OperatorBandit::DECAY);
+        for outcome in outcomes {
+            if let Some(operator) = outcome.mutation {
+                self.uses[operator.index()] += 1.0;
+                if outcome.improved {
+                    self.improvements[operator.index()] += 1.0;
+                }
+            }
+        }
+    }
+
+    /// The recent success rate of an operator, 0 when it has not been used.
+    pub fn success_rate(&self, operator: MutationOperator) -> f64 {
+        let uses = self.uses[operator.index()];
+        return if uses > 0.0 { self.improvements[operator.index()] / uses } else { 0.0 };
+    }
+
+    /// The decayed number of children an operator has produced recently.
+    pub fn uses(&self, operator: MutationOperator) -> f64 {
+        return self.uses[operator.index()];
+    }
+
+    /// The operators that are enabled.
+    pub fn operators(&self) -> &[MutationOperator] {
+        return &self.operators;
+    }
+
+    /// How likely each operator should be for the next generation.
+    pub fn mix(&self) -> OperatorMix {
+        let mut weights = [0.0; MutationOperator::ALL.len()];
+        match self.selection {
+            OperatorSelection::Fixed => return OperatorMix::weighted(&self.rates),
+            OperatorSelection::Ucb { exploration } => {
+                // An operator that was never used has an infinite bound, so every operator is tried first
+                let total: f64 = self.operators.iter().map(|&o| self.uses(o)).sum();
+                let bound = |operator: MutationOperator| match self.uses(operator) {
+                    uses if uses > 0.0 => self.success_rate(operator) + exploration * (total.max(1.0).ln() / uses).sqrt(),
+                    _ => f64::INFINITY,
+                };
+                let best = self.operators.iter().copied().max_by(|&a, &b| bound(a).total_cmp(&bound(b))).unwrap();
+                weights[best.index()] = 1.0;
+            }
+            OperatorSelection::ProbabilityMatching { min_probability } => {
+                let total: f64 = self.operators.iter().map(|&o| self.success_rate(o)).sum();
+                if total <= 0.0 {
+                    return OperatorMix::uniform(&self.operators);
+                }
+                let shared = 1.0 - min_probability * self.operators.len() as f64;
+                for &operator in &self.operators {
+                    weights[operator.index()] = min_probability + shared * self.success_rate(operator) / total;
+                }
+            }
+        }
+        return OperatorMix { weights, sigma: OperatorMix::DEFAULT_SIGMA, crossover: CrossoverOperator::OnePoint, probe: None, headless_chicken: 0.0 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcomes(operator: MutationOperator, children: usize, improved: usize) -> Vec<OffspringOutcome> {
+        return (0..children).map(|i| OffspringOutcome { mutation: Some(operator), improved: i < improved }).collect();
+    }
+
+    #[test]
+    /// Ensures that probability matching favours the operator whose children improve more often
+    fn test_probability_matching() {
+        let operators = [MutationOperator::Point, MutationOperator::Active];
+        let mut bandit = OperatorBandit::new(OperatorSelection::ProbabilityMatching { min_probability: 0.1 }, operators.map(|operator| (operator, 1.0)).to_vec());
+        assert_eq!(bandit.mix(), OperatorMix::uniform(&operators));
+        bandit.update(&[outcomes(MutationOperator::Point, 10, 1), outcomes(MutationOperator::Active, 10, 3)].concat());
+        let mix = bandit.mix();
+        assert!((mix.weight(MutationOperator::Point) - (0.1 + 0.8 * 0.25)).abs() < 1e-12);
+        assert!((mix.weight(MutationOperator::Active) - (0.1 + 0.8 * 0.75)).abs() < 1e-12);
+    }
+
+    #[test]
+    /// Ensures that the annealed standard deviation goes from the initial to the last one geometrically
+    fn test_annealed_sigma() {
+        assert_eq!(annealed_sigma(0.5, None, 7, 10), 0.5);
+        assert!((annealed_sigma(1.0, Some(0.01), 0, 3) - 1.0).abs() < 1e-12);
+        assert!((annealed_sigma(1.0, Some(0.01), 1, 3) - 0.1).abs() < 1e-12);
+        assert!((annealed_sigma(1.0, Some(0.01), 2, 3) - 0.01).abs() < 1e-12);
+    }
+
+    #[test]
+    /// Ensures that UCB tries an unused operator before exploiting the successful one
+    fn test_ucb() {
+        let operators = [MutationOperator::Point, MutationOperator::Active];
+        let mut bandit = OperatorBandit::new(OperatorSelection::Ucb { exploration: 0.5 }, operators.map(|operator| (operator, 1.0)).to_vec());
+        bandit.update(&outcomes(MutationOperator::Point, 10, 5));
+        assert_eq!(bandit.mix().choose(), MutationOperator::Active);
+        bandit.update(&outcomes(MutationOperator::Active, 10, 0));
+        assert_eq!(bandit.mix().choose(), MutationOperator::Point);
+    }
+}
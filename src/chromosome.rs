@@ -1,11 +1,23 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Debug, Display, Formatter};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::iter::Peekable;
 use std::mem::swap;
+use std::sync::Arc;
+use std::vec::IntoIter;
 
-use rand::{random, Rng};
+use rand::Rng;
+use rand_distr::StandardNormal;
 use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 
-use crate::chromosome::GeneType::{Binary, Constant, Unary, Variable};
+use crate::chromosome::GeneType::{Binary, Conditional, Constant, Unary, Variable};
+use crate::egraph::{EGraph, Id, Node, SaturationLimits, RULES};
+use crate::fitness::{FitnessMetric, Mse, Objective};
 use crate::functions::*;
+use crate::grammar::Grammar;
+use crate::io::{Dataset, DatasetTraits};
+use crate::rng::{random, rng};
 
 #[derive(Debug)]
 pub enum GeneType {
@@ -13,6 +25,8 @@ pub enum GeneType {
     Variable(usize),
     Unary,
     Binary,
+    /// Chooses between its two branches by comparing its left and right inputs with its operation.
+    Conditional,
 }
 
 impl Display for GeneType {
@@ -22,7 +36,8 @@ impl Display for GeneType {
             Constant(i) => write!(f, "Constant({})", i),
             Variable(i) => write!(f, "Variable({})", i),
             Unary => write!(f, "Unary"),
-            Binary => write!(f, "Binary")
+            Binary => write!(f, "Binary"),
+            Conditional => write!(f, "Conditional"),
         }
     }
 }
@@ -31,10 +46,11 @@ impl Clone for GeneType {
     /// Allows cloning
     fn clone(&self) -> Self {
         return match self {
-            Constant(i) => { Constant(i.clone()) }
-            Variable(i) => { Variable(i.clone()) }
+            Constant(i) => { Constant(*i) }
+            Variable(i) => { Variable(*i) }
             Unary => { Unary }
             Binary => { Binary }
+            Conditional => { Conditional }
         };
     }
 }
@@ -46,6 +62,8 @@ impl Clone for Gene {
             type_of_gene: self.type_of_gene.clone(),
             left_ptr: self.left_ptr,
             right_ptr: self.right_ptr,
+            branch_ptrs: self.branch_ptrs,
+            primitive: self.primitive,
             ops: self.ops,
         };
     }
@@ -58,22 +76,20 @@ impl Display for Gene {
             Constant(i) => write!(f, "Constant({})[{}, {}]", i, self.left_ptr, self.right_ptr),
             Variable(i) => write!(f, "Variable({})[{}, {}]", i, self.left_ptr, self.right_ptr),
             Unary => write!(f, "Unary[{}, {}]", self.left_ptr, self.right_ptr),
-            Binary => write!(f, "Binary[{}, {}]", self.left_ptr, self.right_ptr)
+            Binary => write!(f, "Binary[{}, {}]", self.left_ptr, self.right_ptr),
+            Conditional => write!(f, "Conditional[{}, {}, {}, {}]", self.left_ptr, self.right_ptr, self.branch_ptrs[0], self.branch_ptrs[1]),
         }
     }
 }
 
 impl Debug for Gene {
     /// Allows the to_string() function to work
-
-
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        struct DebugGene {}
-
         f.debug_struct("DebugGene")
             .field("Type", &self.type_of_gene)
             .field("Left", &self.left_ptr)
             .field("Right", &self.right_ptr)
+            .field("Branches", &self.branch_ptrs)
             .field("Ops", &self.get_operator())
             .finish()
         // }
@@ -85,7 +101,12 @@ pub struct Gene {
     pub type_of_gene: GeneType,
     pub left_ptr: usize,
     pub right_ptr: usize,
-    pub ops: fn(f64, f64) -> (f64, String),
+    /// The inputs a conditional gene chooses between; unused by other genes.
+    pub branch_ptrs: [usize; 2],
+    /// The primitive of a function gene, `PrimitiveId::NOTHING` for terminals.
+    pub primitive: PrimitiveId,
+    /// The operation of the primitive, protected as the chromosome requires, see `Gene::protected`.
+    pub ops: Operation,
 }
 
 
@@ -105,7 +126,7 @@ impl Gene {
         return
         if random() || first_or_second_in_chromosome {
             if random() { Gene::new_constant(None) } else { Gene::new_random_variable(num_variables) }
-        } else if random() { Gene::new_binary(curr_loc) } else { Gene::new_unary(curr_loc) };
+        } else if random::<f64>() < CONDITIONAL_PROBABILITY { Gene::new_conditional(curr_loc) } else if random() { Gene::new_binary(curr_loc) } else { Gene::new_unary(curr_loc) };
     }
 
     /// Creates a new Gene with a constant value.
@@ -122,7 +143,9 @@ impl Gene {
             type_of_gene: Constant(constant.unwrap_or(random())),
             left_ptr: 0,
             right_ptr: 0,
-            ops: Gene::nothing,
+            branch_ptrs: [0, 0],
+            primitive: PrimitiveId::NOTHING,
+            ops: &Gene::nothing,
         };
     }
 
@@ -142,19 +165,24 @@ impl Gene {
     /// * `ops` - Represents the operations associated with the gene.
     pub fn new_random_variable(num_variables: usize) -> Gene {
         return Gene {
-            type_of_gene: Variable(rand::thread_rng().gen_range(0..num_variables)),
+            type_of_gene: Variable(rng().gen_range(0..num_variables)),
             left_ptr: 0,
             right_ptr: 0,
-            ops: Gene::nothing,
+            branch_ptrs: [0, 0],
+            primitive: PrimitiveId::NOTHING,
+            ops: &Gene::nothing,
         };
     }
 
+    #[allow(dead_code)]
     pub fn new_variable(variable_number: usize) -> Gene {
         return Gene {
             type_of_gene: Variable(variable_number),
             left_ptr: 0,
             right_ptr: 0,
-            ops: Gene::nothing,
+            branch_ptrs: [0, 0],
+            primitive: PrimitiveId::NOTHING,
+            ops: &Gene::nothing,
         };
     }
 
@@ -168,20 +196,17 @@ impl Gene {
     ///
     /// A `Gene` struct representing the unary gene.
     pub fn new_unary(curr_loc: usize) -> Gene {
-        return Gene {
-            type_of_gene: Unary,
-            left_ptr: rand::thread_rng().gen_range(0..curr_loc),
-            right_ptr: 0,
-            ops: get_unary_function(),
-        };
+        return Gene::new_unary2(rng().gen_range(0..curr_loc), get_unary_function());
     }
 
-    pub fn new_unary2(left: usize, func: fn(f64, f64) -> (f64, String)) -> Gene {
+    pub fn new_unary2(left: usize, primitive: PrimitiveId) -> Gene {
         return Gene {
             type_of_gene: Unary,
             left_ptr: left,
             right_ptr: 0,
-            ops: func,
+            branch_ptrs: [0, 0],
+            primitive,
+            ops: primitive.operation(),
         };
     }
 
@@ -201,27 +226,98 @@ impl Gene {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// use crate::Gene;
     ///
     /// let curr_loc = 10;
     /// let gene = new_binary(curr_loc);
     /// ```
     pub fn new_binary(curr_loc: usize) -> Gene {
-        return Gene {
-            type_of_gene: Binary,
-            left_ptr: rand::thread_rng().gen_range(0..curr_loc),
-            right_ptr: rand::thread_rng().gen_range(0..curr_loc),
-            ops: get_binary_function(),
-        };
+        let mut rng = rng();
+        return Gene::new_binary2(rng.gen_range(0..curr_loc), rng.gen_range(0..curr_loc), get_binary_function());
     }
 
-    pub fn new_binary2(curr_loc: usize, curr_loc2: usize, func: fn(f64, f64) -> (f64, String)) -> Gene {
+    pub fn new_binary2(curr_loc: usize, curr_loc2: usize, primitive: PrimitiveId) -> Gene {
         return Gene {
             type_of_gene: Binary,
             left_ptr: curr_loc,
             right_ptr: curr_loc2,
-            ops: func,
+            branch_ptrs: [0, 0],
+            primitive,
+            ops: primitive.operation(),
+        };
+    }
+
+    /// Constructs a new conditional gene, which outputs its first branch when its left input is less
+    /// than its right input and its second branch otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `curr_loc` - The location (index) of the `Gene` within the `Chromosome`.
+    ///
+    /// # Returns
+    ///
+    /// A `Gene` whose four inputs are drawn at random from the genes before `curr_loc`.
+    pub fn new_conditional(curr_loc: usize) -> Gene {
+        let mut rng = rng();
+        let (left, right) = (rng.gen_range(0..curr_loc), rng.gen_range(0..curr_loc));
+        return Gene::new_conditional2(left, right, [rng.gen_range(0..curr_loc), rng.gen_range(0..curr_loc)], PrimitiveId::IFLT);
+    }
+
+    pub fn new_conditional2(left: usize, right: usize, branches: [usize; 2], primitive: PrimitiveId) -> Gene {
+        return Gene {
+            type_of_gene: Conditional,
+            left_ptr: left,
+            right_ptr: right,
+            branch_ptrs: branches,
+            primitive,
+            ops: primitive.operation(),
+        };
+    }
+
+    /// Returns the gene with the version of its operation that follows `protection`, see `Protection::protect`.
+    pub fn protected(mut self, protection: Protection) -> Gene {
+        if let Unary | Binary = self.type_of_gene {
+            self.ops = protection.protect(self.primitive);
+        }
+        return self;
+    }
+
+    /// The type of the value the gene outputs, see `ValueType`. Terminals and conditionals are real.
+    pub fn output_type(&self) -> ValueType {
+        return match self.type_of_gene {
+            Unary | Binary => FunctionRegistry::global().signature(self.primitive).1,
+            Constant(_) | Variable(_) | Conditional => ValueType::Real,
+        };
+    }
+
+    /// The types of the values the gene takes as inputs, in the order of `inputs`.
+    pub fn input_types(&self) -> Vec<ValueType> {
+        let input = match self.type_of_gene {
+            Unary | Binary => FunctionRegistry::global().signature(self.primitive).0,
+            Constant(_) | Variable(_) | Conditional => ValueType::Real,
+        };
+        return vec![input; self.inputs().len()];
+    }
+
+    /// Returns the positions of the genes this gene takes as inputs, in order.
+    pub fn inputs(&self) -> Vec<usize> {
+        return match self.type_of_gene {
+            Constant(_) | Variable(_) => vec![],
+            Unary => vec![self.left_ptr],
+            Binary => vec![self.left_ptr, self.right_ptr],
+            Conditional => vec![self.left_ptr, self.right_ptr, self.branch_ptrs[0], self.branch_ptrs[1]],
+        };
+    }
+
+    /// Returns the pointers of a function gene that have to point before it. This is every input,
+    /// plus the unused right input of a unary gene, which is kept valid so that it can become binary.
+    fn pointers_mut(&mut self) -> Vec<&mut usize> {
+        let [first, second] = &mut self.branch_ptrs;
+        return match self.type_of_gene {
+            Constant(_) | Variable(_) => vec![],
+            Unary | Binary => vec![&mut self.left_ptr, &mut self.right_ptr],
+            Conditional => vec![&mut self.left_ptr, &mut self.right_ptr, first, second],
         };
     }
 
@@ -238,7 +334,7 @@ impl Gene {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// let result = nothing(5.0, 10.0);
     /// assert_eq!(result, 0.0);
     /// ```
@@ -259,14 +355,405 @@ impl Gene {
             Constant(x) => x,
             Unary => (self.ops)(chromosome.genes[self.left_ptr].operation(chromosome, vec), -1.0).0,
             Binary => (self.ops)(chromosome.genes[self.left_ptr].operation(chromosome, vec), chromosome.genes[self.right_ptr].operation(chromosome, vec)).0,
+            Conditional => {
+                let branch = self.branch(chromosome.genes[self.left_ptr].operation(chromosome, vec), chromosome.genes[self.right_ptr].operation(chromosome, vec));
+                chromosome.genes[branch].operation(chromosome, vec)
+            }
+            Variable(x) => vec[x],
+        };
+    }
+
+    /// Computes the output of the gene from the outputs of the genes before it, without recursion.
+    ///
+    /// # Arguments
+    ///
+    /// * `outputs` - The outputs of the earlier genes of the chromosome on `vec`.
+    /// * `vec`: One row of the dataset
+    pub fn apply(&self, outputs: &[f64], vec: &[f64]) -> f64 {
+        return match self.type_of_gene {
+            Constant(x) => x,
+            Unary => (self.ops)(outputs[self.left_ptr], -1.0).0,
+            Binary => (self.ops)(outputs[self.left_ptr], outputs[self.right_ptr]).0,
+            Conditional => outputs[self.branch(outputs[self.left_ptr], outputs[self.right_ptr])],
             Variable(x) => vec[x],
         };
     }
 
+    /// Returns the branch a conditional gene takes when its left and right inputs are `left` and `right`.
+    fn branch(&self, left: f64, right: f64) -> usize {
+        return if (self.ops)(left, right).0 != 0.0 { self.branch_ptrs[0] } else { self.branch_ptrs[1] };
+    }
+
     /// Returns the type of the function.
     pub fn get_operator(&self) -> String {
         return (self.ops)(0.0, 0.0).1;
     }
+
+    /// Whether two genes compute the same thing from the same inputs. Pointers that the gene does not use are ignored.
+    pub fn same_as(&self, other: &Gene) -> bool {
+        return match (&self.type_of_gene, &other.type_of_gene) {
+            (Constant(a), Constant(b)) => a == b,
+            (Variable(a), Variable(b)) => a == b,
+            (Unary, Unary) => self.left_ptr == other.left_ptr && self.primitive == other.primitive,
+            (Binary, Binary) | (Conditional, Conditional) => self.inputs() == other.inputs() && self.primitive == other.primitive,
+            _ => false,
+        };
+    }
+}
+
+/// How the distance between two chromosomes is measured. Both distances are between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distance {
+    /// The fraction of gene positions that differ, see `Chromosome::genotypic_distance`.
+    Genotypic,
+    /// How differently the chromosomes behave on a probe set of rows, see `Chromosome::behavioral_distance`.
+    Behavioral,
+}
+
+/// Upper bounds on the complexity of the expression a chromosome encodes. `None` leaves a measure unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ComplexityLimits {
+    /// The most genes that may contribute to the output, see `Chromosome::active_genes`.
+    pub max_active_genes: Option<usize>,
+    /// The most genes a chromosome may have, active or not, which bounds the growth of variable-length chromosomes.
+    pub max_genes: Option<usize>,
+    /// The deepest the expression may nest, see `Chromosome::depth`.
+    pub max_depth: Option<usize>,
+}
+
+/// How large the expression of a chromosome is, to monitor bloat, see `Chromosome::complexity`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Complexity {
+    /// The number of genes that contribute to the output, see `Chromosome::active_genes`.
+    pub active_genes: usize,
+    /// How deeply the expression nests, see `Chromosome::depth`.
+    pub depth: usize,
+    /// How many active genes use each function, by name as printed in expressions.
+    pub operators: BTreeMap<String, usize>,
+}
+
+impl Display for Complexity {
+    /// E.g. `7 active genes, depth 4: add 2, sin 1`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} active genes, depth {}", self.active_genes, self.depth)?;
+        let operators: Vec<String> = self.operators.iter().map(|(operator, count)| format!("{} {}", operator, count)).collect();
+        if !operators.is_empty() {
+            write!(f, ": {}", operators.join(", "))?;
+        }
+        return Ok(());
+    }
+}
+
+/// How an expression is written, see `Chromosome::expression`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Notation {
+    /// Arithmetic written with operators and as few parentheses as their precedence allows, e.g.
+    /// `x0 + 2.5*sin(x1)`, see `Chromosome::to_infix`.
+    #[default]
+    Infix,
+    /// Every function applied to its inputs, e.g. `add(x0, mul(2.5, sin(x1)))`, see `Chromosome::function_string_with_names`.
+    Prefix,
+    /// Lisp S-expressions, e.g. `(add x0 (mul 2.5 (sin x1)))`, see `Chromosome::to_sexpr`.
+    SExpression,
+    /// LaTeX math, e.g. `x_{0} + 2.5 \cdot \sin\left(x_{1}\right)`, see `Chromosome::to_latex`.
+    Latex,
+}
+
+/// The precedence of the infix operators, from the loosest to the tightest binding, see `Chromosome::to_infix`.
+/// LaTeX cases bind the loosest of all, so they are always parenthesized, and LaTeX functions such as
+/// `\log_2\left(x\right)` bind looser than atoms, since an exponent after them would read as a power of the function.
+const CASES: u8 = 0;
+const SUM: u8 = 1;
+const PRODUCT: u8 = 2;
+const NEGATION: u8 = 3;
+const POWER: u8 = 4;
+const FUNCTION: u8 = 5;
+const ATOM: u8 = 6;
+
+/// Operator rates that a chromosome carries and passes on to its children, so that good rates
+/// evolve along with the genes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperatorRates {
+    /// The probability of mutating a child.
+    pub mutation: f64,
+    /// The probability of crossing a child over with its sibling.
+    pub crossover: f64,
+}
+
+impl OperatorRates {
+    /// The learning rate of the log-normal perturbation.
+    const TAU: f64 = 0.2;
+
+    /// Multiplies each rate by `exp(TAU * N(0, 1))`, keeping it between 0.001 and 1.
+    ///
+    /// The perturbation is log-normal so that rates are as likely to halve as to double, whatever their size.
+    pub fn perturb(&mut self) {
+        let mut rng = rng();
+        for rate in [&mut self.mutation, &mut self.crossover] {
+            let step: f64 = rng.sample(StandardNormal);
+            *rate = (*rate * (OperatorRates::TAU * step).exp()).clamp(0.001, 1.0);
+        }
+    }
+}
+
+/// How genes are arranged, as in Cartesian GP: the genes fill a grid column by column, `rows`
+/// genes to a column, and a gene may only use genes from the `levels_back` columns before its own.
+///
+/// The default single row with no levels-back limit lets a gene use any earlier gene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    /// How many genes each column has.
+    pub rows: usize,
+    /// How many columns back the inputs of a gene may reach. `None` lets a gene use every earlier column.
+    pub levels_back: Option<usize>,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        return Grid { rows: 1, levels_back: None };
+    }
+}
+
+impl Grid {
+    /// The positions the gene at `pos` may use as inputs, which is empty for the genes of the first column.
+    pub fn inputs(&self, pos: usize) -> std::ops::Range<usize> {
+        let column = pos / self.rows;
+        let first = self.levels_back.map_or(0, |levels_back| column.saturating_sub(levels_back));
+        return first * self.rows..column * self.rows;
+    }
+}
+
+/// Where the values of new random constants are drawn from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstantDistribution {
+    /// Uniformly between `low` (inclusive) and `high` (exclusive).
+    Uniform { low: f64, high: f64 },
+    /// From a normal distribution.
+    Normal { mean: f64, std: f64 },
+    /// Uniformly on a log scale between the positive `low` and `high`, so that every order of
+    /// magnitude is as likely.
+    LogUniform { low: f64, high: f64 },
+}
+
+impl Default for ConstantDistribution {
+    fn default() -> Self {
+        return ConstantDistribution::Uniform { low: 0.0, high: 1.0 };
+    }
+}
+
+impl ConstantDistribution {
+    /// Draws a constant.
+    pub fn sample(&self) -> f64 {
+        let mut rng = rng();
+        return match *self {
+            ConstantDistribution::Uniform { low, high } => rng.gen_range(low..high),
+            ConstantDistribution::Normal { mean, std } => mean + std * rng.sample::<f64, _>(StandardNormal),
+            ConstantDistribution::LogUniform { low, high } => rng.gen_range(low.ln()..high.ln()).exp(),
+        };
+    }
+}
+
+/// Which values constants may take.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstantDomain {
+    /// Any number.
+    Real,
+    /// Only whole numbers.
+    Integer,
+    /// Only fractions whose denominator is at most `max_denominator`.
+    Rational { max_denominator: u32 },
+}
+
+impl ConstantDomain {
+    /// The value in the domain closest to `value`.
+    pub fn round(&self, value: f64) -> f64 {
+        return match *self {
+            ConstantDomain::Real => value,
+            ConstantDomain::Integer => value.round(),
+            ConstantDomain::Rational { max_denominator } => (1..=max_denominator.max(1))
+                .map(|denominator| (value * denominator as f64).round() / denominator as f64)
+                .min_by(|a, b| (a - value).abs().total_cmp(&(b - value).abs()))
+                .unwrap(),
+        };
+    }
+
+    /// `value` moved by `step` and rounded into the domain. A step too small to reach another value
+    /// of the domain moves to the neighbouring value in its direction instead, so that small
+    /// perturbations still change the constant.
+    pub fn nudge(&self, value: f64, step: f64) -> f64 {
+        let moved = self.round(value + step);
+        let unit = match *self {
+            ConstantDomain::Real => return moved,
+            ConstantDomain::Integer => 1.0,
+            ConstantDomain::Rational { max_denominator } => 1.0 / max_denominator.max(1) as f64,
+        };
+        return if moved == value && step != 0.0 { self.round(value + unit.copysign(step)) } else { moved };
+    }
+}
+
+/// What the error of a chromosome is when its output is NaN or infinite on some rows.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InvalidRows {
+    /// The chromosome is invalid, with the worst possible fitness.
+    #[default]
+    Worst,
+    /// The invalid rows are left out of the error, which is multiplied by one plus this much times
+    /// the (weighted) fraction of rows that are invalid. A chromosome without a valid row is still invalid.
+    Penalize(f64),
+}
+
+impl InvalidRows {
+    /// The weighted mean of the errors of the rows under this policy, `f64::MAX` when the chromosome
+    /// is invalid. The result is never NaN.
+    ///
+    /// # Arguments
+    ///
+    /// * `errors` - The error on each row, with the weight of the row, see `DatasetTraits::weight`.
+    ///   Rows with a weight of 0 are ignored.
+    pub fn weighted_mean(&self, errors: impl IntoIterator<Item=(f64, f64)>) -> f64 {
+        let (mut total, mut valid, mut invalid) = (0.0, 0.0, 0.0);
+        for (error, weight) in errors.into_iter().filter(|&(_, weight)| weight > 0.0) {
+            match error.is_finite() {
+                true => (total, valid) = (total + weight * error, valid + weight),
+                false => invalid += weight,
+            }
+        }
+        return self.apply(total / valid, valid, invalid);
+    }
+
+    /// The error under this policy, given the error on the valid rows and the total weights of the
+    /// valid and invalid rows. `f64::MAX` when the chromosome is invalid, and never NaN.
+    pub fn apply(&self, error: f64, valid: f64, invalid: f64) -> f64 {
+        let error = match *self {
+            _ if valid == 0.0 => f64::MAX,
+            InvalidRows::Worst if invalid > 0.0 => f64::MAX,
+            InvalidRows::Worst => error,
+            InvalidRows::Penalize(penalty) => error * (1.0 + penalty * invalid / (valid + invalid)),
+        };
+        return if error.is_finite() { error } else { f64::MAX };
+    }
+}
+
+/// The shape of new chromosomes and the configuration of the run they evolve in: how their genes
+/// are arranged, what they are made of, and how their fitness is measured. A population shares a
+/// single layout with every chromosome in it, see `Chromosome::layout`.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    /// Which earlier genes each gene may use as inputs.
+    pub grid: Grid,
+    /// Where the values of new random constants are drawn from, by mutation as well as creation.
+    pub constants: ConstantDistribution,
+    /// Which values constants may take. Every operator that sets a constant rounds it into the domain.
+    pub constant_domain: ConstantDomain,
+    /// What the protected functions of new function genes return where their plain versions are undefined.
+    pub protection: Protection,
+    /// The primitives that the functions of new and mutated function genes are drawn from.
+    pub function_set: FunctionSet,
+    /// Whether genes only take inputs of the types their functions expect, see `ValueType`. Genes
+    /// are created and mutated that way, and inputs that crossover or other changes leave with the
+    /// wrong type are rewired, see `Chromosome::repair_constraints`.
+    pub typed: bool,
+    /// The rules the expressions must follow. New and mutated function genes are drawn until the
+    /// grammar allows them, and genes that other changes make break a rule become constants.
+    pub grammar: Grammar,
+    /// What the error is when the output is NaN or infinite on some rows, see `Chromosome::mse`.
+    pub invalid_rows: InvalidRows,
+    /// How the error is measured, the mean squared error by default. Weights of multi-gene
+    /// chromosomes are still fitted by least squares.
+    pub metric: Arc<dyn FitnessMetric>,
+    /// A custom fitness that replaces the error as the fitness, see `Chromosome::fitness`. The
+    /// local optimizers that fit by least squares still minimize the squared error.
+    pub objective: Option<Objective>,
+    /// How many output genes chromosomes have.
+    pub outputs: usize,
+    /// Whether the outputs are combined into a single prediction by least-squares weights (multi-gene
+    /// GP) rather than each predicting a target, see `Chromosome::weights`.
+    pub multigene: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        return Layout {
+            grid: Grid::default(),
+            constants: ConstantDistribution::default(),
+            constant_domain: ConstantDomain::Real,
+            protection: Protection::default(),
+            function_set: FunctionSet::default(),
+            typed: false,
+            grammar: Grammar::default(),
+            invalid_rows: InvalidRows::Worst,
+            metric: Arc::new(Mse),
+            objective: None,
+            outputs: 1,
+            multigene: false,
+        };
+    }
+}
+
+/// The ways a child can be mutated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutationOperator {
+    /// Replaces a random gene, see `Chromosome::mutate`.
+    Point,
+    /// Replaces random genes until an active one changes, see `Chromosome::mutate_active`.
+    Active,
+    /// Rewires an input of a function gene, see `Chromosome::mutate_pointer`.
+    Pointer,
+    /// Swaps the function of a gene, keeping its inputs, see `Chromosome::mutate_function`.
+    Function,
+    /// Replaces the value of a constant gene, see `Chromosome::mutate_constant`.
+    Constant,
+    /// Moves an output to another gene, see `Chromosome::mutate_output`.
+    Output,
+    /// Nudges a constant the output uses by a small random amount, see `Chromosome::perturb_constant`.
+    Gaussian,
+    /// Replaces an active gene with the earlier gene or constant closest to what it should output
+    /// for the whole expression to hit the targets, see `Chromosome::backpropagate`.
+    Backpropagation,
+    /// Copies a block of genes over another part of the chromosome, see `Chromosome::duplicate_block`.
+    Duplication,
+    /// Moves a block of genes to another part of the chromosome, see `Chromosome::transpose_block`.
+    Transposition,
+    /// Inserts a random gene, lengthening the chromosome, see `Chromosome::insert_gene`.
+    Insertion,
+    /// Deletes a gene, shortening the chromosome, see `Chromosome::delete_gene`.
+    Deletion,
+}
+
+impl MutationOperator {
+    /// Every operator, in the order of their indices.
+    pub const ALL: [MutationOperator; 12] = [MutationOperator::Point, MutationOperator::Active, MutationOperator::Pointer,
+        MutationOperator::Function, MutationOperator::Constant, MutationOperator::Output, MutationOperator::Gaussian,
+        MutationOperator::Backpropagation, MutationOperator::Duplication, MutationOperator::Transposition, MutationOperator::Insertion,
+        MutationOperator::Deletion];
+
+    /// The position of the operator in `ALL`.
+    pub fn index(self) -> usize {
+        return self as usize;
+    }
+}
+
+/// The ways two children can exchange genes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossoverOperator {
+    /// Swaps every gene from a random position on, see `Chromosome::cross_with`.
+    OnePoint,
+    /// Swaps every gene between two random positions, see `Chromosome::cross_two_point`.
+    TwoPoint,
+    /// Swaps each gene with even odds, see `Chromosome::cross_uniform`.
+    Uniform,
+    /// Transplants the genes feeding a random active gene, see `Chromosome::cross_subgraph`.
+    Subgraph,
+    /// Transplants the genes feeding the gene where the parents compute the most (or least, but
+    /// not exactly the same) different outputs, see `Chromosome::cross_semantic`.
+    Semantic { most_different: bool },
+}
+
+impl CrossoverOperator {
+    /// Whether the operator needs the outputs of every gene, see `Chromosome::record_semantics`.
+    pub fn is_semantic(&self) -> bool {
+        return matches!(self, CrossoverOperator::Semantic { .. });
+    }
 }
 
 /// Represents a chromosome with genes and fitness value.
@@ -274,16 +761,57 @@ impl Gene {
 pub struct Chromosome {
     pub genes: Vec<Gene>,
     pub fitness_value: f64,
+    /// The loss on each row from the last call to `evaluate_fitness_cases`, used by lexicase selection.
+    pub case_errors: Vec<f64>,
+    /// How crowded this chromosome's niche is when fitness sharing is used, at least 1. Parent
+    /// selection uses the fitness multiplied by this, see `shared_fitness`.
+    pub niche_count: f64,
+    /// When novelty search is used, a blend of how fit and how novel this chromosome is compared
+    /// to the population, from 0 (best) to 1. Parent selection uses it instead of the fitness, see `shared_fitness`.
+    pub novelty_score: Option<f64>,
+    /// How many generations the oldest genetic material in this chromosome has been evolving, used by ALPS.
+    pub age: usize,
+    /// The outputs on the probe set from the last call to `record_behavior`, used for behavioral distances.
+    pub behavior: Vec<f64>,
+    /// The outputs of every gene on the probe set from the last call to `record_semantics`, gene
+    /// after gene, used by semantic crossover. Empty unless semantic crossover is used.
+    pub semantics: Vec<f64>,
+    /// The mutation and crossover rates of this chromosome when rates are self-adaptive. `None`
+    /// uses the rates of the run.
+    pub rates: Option<OperatorRates>,
+    /// The configuration of the run, shared by every chromosome of the population: the grid, the
+    /// constants and functions of new genes, the grammar and how the fitness is measured, see `Layout`.
+    pub layout: Arc<Layout>,
+    /// The positions of the genes whose values are the outputs of the chromosome, one per target.
+    /// Empty uses the last gene as the only output, see `Chromosome::output_genes`.
+    pub outputs: Vec<usize>,
+    /// With multi-gene GP, the bias followed by a weight for each output gene, which combine the
+    /// outputs into a single prediction. Linear scaling is the case of a single output gene. They
+    /// are fitted by least squares whenever the fitness is evaluated, see `Chromosome::fit_weights`.
+    /// `None` uses the outputs as they are.
+    pub weights: Option<Vec<f64>>,
+    /// The fitness of the fitter parent of a new child, until its success has been counted. Used to
+    /// adapt the operator rates, see `Chromosome::offspring`.
+    pub parent_fitness: Option<f64>,
+    /// The operator that mutated a new child, if any, until its success has been counted. Used to
+    /// choose between the operators, see `OperatorBandit`.
+    pub mutation_operator: Option<MutationOperator>,
     pub accessed: bool,
 }
 
+impl Default for Chromosome {
+    fn default() -> Self {
+        return Chromosome::new();
+    }
+}
+
 // TODO: add combine method for combining islands
 impl Chromosome {
     /// Creates a new `Chromosome` instance.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// let chromosome = Chromosome::new();
     /// ```
     ///
@@ -294,6 +822,18 @@ impl Chromosome {
         Chromosome {
             genes: Vec::new(),
             fitness_value: f64::MAX,
+            case_errors: Vec::new(),
+            niche_count: 1.0,
+            novelty_score: None,
+            age: 0,
+            behavior: Vec::new(),
+            semantics: Vec::new(),
+            rates: None,
+            layout: Arc::new(Layout::default()),
+            outputs: Vec::new(),
+            weights: None,
+            parent_fitness: None,
+            mutation_operator: None,
             accessed: false,
         }
     }
@@ -307,10 +847,23 @@ impl Chromosome {
     /// # Returns
     ///
     /// A new `Chromosome` instance with the given genes and the maximum fitness value.
+    #[allow(dead_code)]
     pub fn new_from_genes_array(genes_array: Vec<Gene>) -> Chromosome {
         Chromosome {
             genes: genes_array,
             fitness_value: f64::MAX,
+            case_errors: Vec::new(),
+            niche_count: 1.0,
+            novelty_score: None,
+            age: 0,
+            behavior: Vec::new(),
+            semantics: Vec::new(),
+            rates: None,
+            layout: Arc::new(Layout::default()),
+            outputs: Vec::new(),
+            weights: None,
+            parent_fitness: None,
+            mutation_operator: None,
             accessed: false, // Thread testing
         }
     }
@@ -328,17 +881,53 @@ impl Chromosome {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// let c = Chromosome::new_x(5, 5)
     /// ```
     pub fn new_x(num_genes: usize, num_variables: usize) -> Chromosome {
         return Chromosome {
-            genes: (0..num_genes).into_iter().map(|_| Gene::new_random_gene(0, num_variables, true)).collect(),
+            genes: (0..num_genes).map(|_| Gene::new_random_gene(0, num_variables, true)).collect(),
             fitness_value: f64::MAX,
+            case_errors: Vec::new(),
+            niche_count: 1.0,
+            novelty_score: None,
+            age: 0,
+            behavior: Vec::new(),
+            semantics: Vec::new(),
+            rates: None,
+            layout: Arc::new(Layout::default()),
+            outputs: Vec::new(),
+            weights: None,
+            parent_fitness: None,
+            mutation_operator: None,
             accessed: false,
         };
     }
 
+    /// Generates a random chromosome like `new_x`, shaped by `layout`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_genes`: How many genes to generate
+    /// * `num_variables`: How many variables there are in the dataset
+    /// * `layout`: The grid and outputs of the chromosome
+    pub fn new_random(num_genes: usize, num_variables: usize, layout: &Arc<Layout>) -> Chromosome {
+        let mut chromosome = Chromosome::new_x(num_genes, num_variables);
+        chromosome.layout = Arc::clone(layout);
+        for pos in 0..chromosome.len() {
+            if let Constant(_) = chromosome.genes[pos].type_of_gene {
+                chromosome.genes[pos].type_of_gene = Constant(chromosome.random_constant());
+            }
+        }
+        chromosome.set_outputs(layout.outputs);
+        if layout.multigene {
+            // Set even for a single output, since the weights are matched with the outputs
+            chromosome.outputs = chromosome.output_genes();
+            chromosome.weights = Some(vec![0.0; layout.outputs + 1]);
+        }
+        return chromosome;
+    }
+
     /// Evaluates the fitness of an individual based on a given vector of values.
     ///
     /// # Arguments
@@ -349,10 +938,131 @@ impl Chromosome {
     ///
     /// * The fitness value as a `f64` number.
     fn evaluate_fitness(&self, vec: &Vec<f64>) -> f64 {
-        return self.genes[self.genes.len() - 1].operation(self, vec);
+        return match &self.weights {
+            Some(weights) => weights[0] + self.outputs.iter().zip(&weights[1..]).map(|(&pos, weight)| weight * self.genes[pos].operation(self, vec)).sum::<f64>(),
+            None => self.genes[self.output()].operation(self, vec),
+        };
+    }
+
+    /// Fits the weights of a multi-gene chromosome to `dataset` by least squares, see `weights`.
+    /// Each row counts as much as its sample weight.
+    ///
+    /// Does nothing unless the chromosome is multi-gene.
+    pub fn fit_weights(&mut self, dataset: &Dataset) {
+        if self.weights.is_none() {
+            return;
+        }
+        // Weighted least squares is least squares on rows scaled by the root of their weight
+        let features: Vec<Vec<f64>> = dataset.rows.iter()
+            .map(|row| {
+                let scale = dataset.weight(row).sqrt();
+                std::iter::once(scale).chain(self.outputs.iter().map(|&pos| scale * self.genes[pos].operation(self, row))).collect()
+            })
+            .collect();
+        let targets: Vec<f64> = dataset.rows.iter().map(|row| dataset.weight(row).sqrt() * row[row.len() - 1]).collect();
+        self.weights = Some(least_squares(&features, &targets));
+    }
+
+    /// The values of the outputs on one row: the prediction, or one value per output when several
+    /// outputs regress toward several targets or score several classes.
+    pub fn output_values(&self, row: &Vec<f64>) -> Vec<f64> {
+        if self.outputs.len() <= 1 || self.weights.is_some() {
+            return vec![self.evaluate_fitness(row)];
+        }
+        return self.outputs.iter().map(|&pos| self.genes[pos].operation(self, row)).collect();
     }
 
-    /// Calculates the mean squared error (MSE) fitness of the given dataset for a `Chromosome`.
+    /// The loss of the outputs on one row under `metric`, see `FitnessMetric::row_loss`.
+    fn row_loss(&self, row: &Vec<f64>, metric: &dyn FitnessMetric) -> f64 {
+        if self.outputs.len() <= 1 || self.weights.is_some() {
+            return metric.loss(self.evaluate_fitness(row), row[row.len() - 1]);
+        }
+        return metric.row_loss(&self.output_values(row), row);
+    }
+
+    /// The errors of the outputs on every row, scaled so that the mean of their squares over the rows
+    /// is the (weighted) MSE. There is one error per row, or one per target and row when several
+    /// outputs regress toward several targets.
+    pub fn residuals(&self, dataset: &Dataset) -> Vec<f64> {
+        let mean_weight = dataset.rows.iter().map(|row| dataset.weight(row)).sum::<f64>() / dataset.len() as f64;
+        let row_scale = |row: &Vec<f64>| (dataset.weight(row) / mean_weight).sqrt();
+        if self.outputs.len() <= 1 || self.weights.is_some() {
+            return dataset.rows.iter().map(|row| row_scale(row) * (self.evaluate_fitness(row) - row[row.len() - 1])).collect();
+        }
+        let scale = (self.outputs.len() as f64).sqrt();
+        return dataset.rows.iter()
+            .flat_map(|row| {
+                let targets = &row[row.len() - self.outputs.len()..];
+                let row_scale = row_scale(row);
+                self.outputs.iter().zip(targets).map(move |(&pos, target)| row_scale * (self.genes[pos].operation(self, row) - target) / scale)
+            })
+            .collect();
+    }
+
+    /// Calculates the mean squared error (MSE) of the predictions in the original units of the target.
+    ///
+    /// When the target was normalized, the predictions and expected values are passed through the
+    /// inverse of the target scaler first.
+    pub fn original_scale_mse(&self, dataset: &Dataset) -> f64 {
+        // Only a single target can be normalized
+        let Some(scaler) = dataset.target_scaler else {
+            return self.layout.invalid_rows.weighted_mean(dataset.rows.iter().map(|row| (self.row_loss(row, &Mse), dataset.weight(row))));
+        };
+        return self.layout.invalid_rows.weighted_mean(dataset.rows.iter()
+            .map(|row| ((scaler.inverse(self.evaluate_fitness(row)) - scaler.inverse(row[row.len() - 1])).powi(2), dataset.weight(row))));
+    }
+
+    /// Calculates the error of the `Chromosome` on a dataset with its metric, without changing its fitness value.
+    ///
+    /// Used to score a chromosome on data it is not trained on, such as a validation set. With
+    /// several outputs, the losses are averaged over the targets as well, and each row counts as much
+    /// as its sample weight, see `DatasetTraits::weight`. Rows where the
+    /// output is NaN or infinite are handled by `Layout::invalid_rows`, so the result is
+    /// `f64::MAX` rather than NaN for an invalid chromosome.
+    pub fn error(&self, dataset: &Dataset) -> f64 {
+        if self.layout.metric.ranks() {
+            return self.ranking_error(dataset);
+        }
+        let mean_loss = self.layout.invalid_rows.weighted_mean(dataset.rows.iter().map(|row| (self.row_loss(row, self.layout.metric.as_ref()), dataset.weight(row))));
+        return self.finish_error(mean_loss, dataset);
+    }
+
+    /// The fitness of the `Chromosome` on a dataset, without changing its fitness value: the custom
+    /// objective when there is one, and its error otherwise.
+    pub fn fitness(&self, dataset: &Dataset) -> f64 {
+        return match &self.layout.objective {
+            Some(objective) => objective.fitness(self, dataset),
+            None => self.error(dataset),
+        };
+    }
+
+    /// The error under a metric that ranks the rows by the prediction, see `FitnessMetric::ranks`.
+    /// Rows where the prediction is NaN or infinite cannot be ranked, and are handled by `Layout::invalid_rows`.
+    fn ranking_error(&self, dataset: &Dataset) -> f64 {
+        let (mut scored, mut invalid) = (Vec::with_capacity(dataset.len()), 0.0);
+        for row in dataset.rows.iter().filter(|row| dataset.weight(row) > 0.0) {
+            let score = self.evaluate_fitness(row);
+            match score.is_finite() {
+                true => scored.push((score, row[row.len() - 1], dataset.weight(row))),
+                false => invalid += dataset.weight(row),
+            }
+        }
+        scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let valid = scored.iter().map(|&(_, _, weight)| weight).sum();
+        return self.layout.invalid_rows.apply(self.layout.metric.rank_error(&scored), valid, invalid);
+    }
+
+    /// The error of the chromosome from its mean loss, see `FitnessMetric::finish`. Invalid chromosomes stay invalid.
+    fn finish_error(&self, mean_loss: f64, dataset: &Dataset) -> f64 {
+        if mean_loss == f64::MAX {
+            return f64::MAX;
+        }
+        let error = self.layout.metric.finish(mean_loss, dataset);
+        return if error.is_finite() { error } else { f64::MAX };
+    }
+
+    /// Calculates the fitness of the given dataset for a `Chromosome`: its error under its metric,
+    /// the mean squared error (MSE) by default, unless there is a custom objective, see `Chromosome::fitness`.
     ///
     /// The MSE fitness is a measure of how well the genetic algorithm's prediction matches the expected output.
     /// It is calculated by summing the squared differences between the predicted and expected values for each row in the dataset,
@@ -360,192 +1070,1886 @@ impl Chromosome {
     ///
     /// # Arguments
     ///
-    /// * `vec` - A reference to a vector of vectors representing the dataset. Each sub-vector represents a row in the dataset,
-    ///           with the last element in the row being the expected output.
+    /// * `dataset` - The dataset to evaluate against. Each row holds the variables with the last element in the
+    ///   row being the expected output.
     ///
     /// # Returns
     ///
-    /// Returns the calculated mean squared error as a `f64` value. If the value calculated is infinity, it will return `f64::MAX`.
+    /// Returns the calculated error as a `f64` value, or `f64::MAX` when the chromosome
+    /// is invalid, see `Chromosome::error`.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// use crate::GeneticAlgorithm;
     ///
     /// let c = Chromosome::new_x(5); // Create chromosome with 5 genes
-    /// let dataset = vec![
-    ///     vec![1.0, 2.0, 3.0],
-    ///     vec![4.0, 5.0, 6.0],
-    ///     vec![7.0, 8.0, 9.0]
-    /// ];
-    /// let mse = c.evaluate_fitness_mse(&dataset);
+    /// let dataset = read_csv(open_dataset("data.csv"), &DatasetOptions::default());
+    /// let error = c.evaluate_fitness_error(&dataset);
     /// ```
-    pub fn evaluate_fitness_mse(&mut self, vec: &Vec<Vec<f64>>) -> f64 {
-        let mut total: f64 = 0.0;
-        for row in vec {
-            let expected = row[row.len() - 1];
-            let predicted = self.evaluate_fitness(row);
-            total += (predicted - expected).powi(2);
-        }
-        total /= vec.len() as f64;
-        match total.is_infinite() {
-            true => {
-                self.accessed = true; // Thread testing
-                self.fitness_value = f64::MAX;
-            }
-            false => {
-                self.accessed = true; // Thread testing
-                self.fitness_value = total;
-            }
-        };
-
+    pub fn evaluate_fitness_error(&mut self, dataset: &Dataset) -> f64 {
+        self.fit_weights(dataset);
+        self.accessed = true; // Thread testing
+        self.fitness_value = self.fitness(dataset);
         return self.fitness_value;
     }
 
-    fn iter(&self) -> impl Iterator<Item=&Gene> {
-        self.genes.iter()
+    /// The fitness used to select parents: the fitness scaled up by the niche count, so that
+    /// chromosomes in crowded regions are less likely to be chosen, or the novelty score when
+    /// novelty search is used. Invalid fitness values stay as they are.
+    pub fn shared_fitness(&self) -> f64 {
+        return match (self.fitness_value < f64::MAX, self.novelty_score) {
+            (true, Some(score)) => score,
+            (true, None) => self.fitness_value * self.niche_count,
+            (false, _) => self.fitness_value,
+        };
     }
 
-    /// Returns the length of the genes array (`Chromosome`) in the provided instance.
+    /// Calculates the fitness like `evaluate_fitness_error`, and also keeps the loss on every row in `case_errors`.
     ///
-    /// # Example
-    /// ```
-    /// let instance = Instance { genes: vec![1, 2, 3] };
-    /// assert_eq!(instance.len(), 3);
-    /// ```
+    /// # Arguments
+    ///
+    /// * `dataset` - The dataset to evaluate against.
     ///
     /// # Returns
     ///
-    /// - `usize`: The length of the genes array (`Chromosome`) in the provided instance.
-    fn len(&self) -> usize {
-        self.genes.len()
+    /// The error, or `f64::MAX` when the chromosome is invalid.
+    pub fn evaluate_fitness_cases(&mut self, dataset: &Dataset) -> f64 {
+        self.fit_weights(dataset);
+        let mut errors = std::mem::take(&mut self.case_errors);
+        errors.clear();
+        errors.extend(dataset.rows.iter().map(|row| self.row_loss(row, self.layout.metric.as_ref())));
+        let mean_loss = self.layout.invalid_rows.weighted_mean(errors.iter().zip(&dataset.rows).map(|(&error, row)| (error, dataset.weight(row))));
+        self.fitness_value = match &self.layout.objective {
+            Some(objective) => objective.fitness(self, dataset),
+            None if self.layout.metric.ranks() => self.ranking_error(dataset),
+            None => self.finish_error(mean_loss, dataset),
+        };
+        self.case_errors = errors;
+        self.accessed = true; // Thread testing
+        return self.fitness_value;
     }
 
-    /// Converts a GeneticExpression into a string representation of the function.
+    /// Returns the positions of the genes that contribute to the output, in ascending order.
     ///
-    /// # Arguments
-    /// * `position` - An optional position parameter. If `Some`, the function will start the conversion from this position in the gene list. If `None`, it will start from the last gene in the list.
-    pub fn make_function_string(&self, position: Option<usize>, mut builder: String) -> String {
-        let pos = position.unwrap_or(self.genes.len() - 1);
-        match &self.genes[pos].type_of_gene {
-            Constant(i) => {
-                builder.push_str(&format!("{}", i));
-            }
-            Variable(i) => {
-                builder.push_str(&format!("v{}", i));
-            }
-            Unary => {
-                builder.push_str(&format!("{}({})", &self.genes[pos].get_operator(), &self.make_function_string(Some(self.genes[pos].left_ptr), builder.clone())))
-            }
-            Binary => {
-                builder.push_str(&format!("{}({}, {})", &self.genes[pos].get_operator(), &self.make_function_string(Some(self.genes[pos].left_ptr), builder.clone()), &self.make_function_string(Some(self.genes[pos].right_ptr), builder.clone())))
-            }
-        }
-        return builder.to_string();
+    /// A gene is active when it is an output gene or is pointed to by an active unary or binary
+    /// gene. Inactive genes have no effect on the function the `Chromosome` encodes.
+    pub fn active_genes(&self) -> Vec<usize> {
+        return self.subgraph_of(&self.output_genes());
     }
 
-    pub fn function_string(&self) -> String {
-        self.make_function_string(None, String::new())
+    /// The positions of the active constant genes, in order, which are what constant optimization tunes.
+    pub fn active_constants(&self) -> Vec<usize> {
+        return self.active_genes().into_iter().filter(|&pos| matches!(self.genes[pos].type_of_gene, Constant(_))).collect();
     }
 
-    /// Shuffles the genes within the struct.
-    ///
-    /// This function shuffles the genes within the struct using the Fisher-Yates algorithm.
-    /// It modifies the genes in-place.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use rand::seq::SliceRandom;
-    ///
-    /// // Create a new instance of the struct
-    /// let mut c = Chromosome::new();
+    /// The values of the given constant genes.
     ///
-    /// // Shuffle the genes within the struct
-    /// c.shuffle();
+    /// # Arguments
     ///
-    /// // Print the shuffled genes
-    /// println!("{:?}", my_struct.genes);
-    /// ```
-    pub fn shuffle(&mut self) {
-        self.genes.shuffle(&mut rand::thread_rng());
+    /// * `positions` - The positions of constant genes, e.g. from `active_constants`.
+    pub fn constants(&self, positions: &[usize]) -> Vec<f64> {
+        return positions.iter().map(|&pos| match self.genes[pos].type_of_gene {
+            Constant(value) => value,
+            _ => panic!("Gene {} is not a constant", pos),
+        }).collect();
     }
-}
 
-impl Chromosome {
-    /// Crosses the current chromosome with another chromosome.
+    /// Writes new values into the given constant genes, rounded into the constant domain.
     ///
     /// # Arguments
     ///
-    /// * `parent_2` - A mutable reference to the second parent chromosome.
-    /// * `crossover_loc` - Optional. The index at which the crossover operation will start.
-    ///                    If not provided, a random index between 0 and the length of the current chromosome is chosen.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// let mut chromosome_1 = Chromosome::new();
-    /// let mut chromosome_2 = Chromosome::new();
-    ///
-    /// chromosome_1.cross_with(&mut chromosome_2, None);
-    /// ```
-    pub fn cross_with(&mut self, parent_2: &mut Chromosome, crossover_loc: Option<usize>) {
-        let cross_loc = crossover_loc.unwrap_or(rand::thread_rng().gen_range(0..self.len()));
-        for i in cross_loc..self.len() {
-            swap(&mut self.genes[i], &mut parent_2.genes[i])
+    /// * `positions` - The positions of constant genes, e.g. from `active_constants`.
+    /// * `values` - The value of each of those genes.
+    pub fn set_constants(&mut self, positions: &[usize], values: &[f64]) {
+        for (&pos, &value) in positions.iter().zip(values) {
+            match &mut self.genes[pos].type_of_gene {
+                Constant(constant) => *constant = self.layout.constant_domain.round(value),
+                _ => panic!("Gene {} is not a constant", pos),
+            }
         }
     }
 
-    /// Mutates a gene by randomly selecting a location within the gene and replacing it with a new random gene.
-    ///
-    /// # Arguments
-    ///
-    /// * `num_variables` - The number of variables in the GP dataset.
+    /// The position of the first output gene.
+    pub fn output(&self) -> usize {
+        return self.outputs.first().copied().unwrap_or(self.genes.len().saturating_sub(1));
+    }
+
+    /// The positions of the output genes: those set in `outputs`, or else the last gene. Empty for a chromosome without genes.
+    pub fn output_genes(&self) -> Vec<usize> {
+        return match (self.outputs.is_empty(), self.genes.len()) {
+            (_, 0) => Vec::new(),
+            (true, length) => vec![length - 1],
+            (false, _) => self.outputs.clone(),
+        };
+    }
+
+    /// Makes the last `count` genes the outputs, one for each of `count` targets. A single output
+    /// leaves `outputs` empty, so that it is the last gene whatever the length of the chromosome.
+    pub fn set_outputs(&mut self, count: usize) {
+        if count > self.len() {
+            panic!("A chromosome with {} genes cannot have {} outputs", self.len(), count);
+        }
+        self.outputs = match count {
+            0 | 1 => Vec::new(),
+            _ => (self.len() - count..self.len()).collect(),
+        };
+    }
+
+    /// Returns the positions of the genes that feed the gene at `root`, including `root`, in increasing order.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The position of the gene whose inputs are followed.
+    pub fn subgraph(&self, root: usize) -> Vec<usize> {
+        return self.subgraph_of(&[root]);
+    }
+
+    /// Returns the positions of the genes that feed any of the genes at `roots`, including the roots, in increasing order.
+    fn subgraph_of(&self, roots: &[usize]) -> Vec<usize> {
+        let end = roots.iter().max().map_or(0, |&root| root + 1);
+        let mut active = vec![false; end];
+        roots.iter().for_each(|&root| active[root] = true);
+        for pos in (0..end).rev() {
+            if !active[pos] {
+                continue;
+            }
+            self.genes[pos].inputs().into_iter().for_each(|input| active[input] = true);
+        }
+        return (0..end).filter(|&pos| active[pos]).collect();
+    }
+
+    /// Replaces every active function gene whose inputs are all constants by a constant gene of its
+    /// value, from the first active gene to the outputs, so whole subgraphs of constants collapse
+    /// into single constants. The chromosome computes the same values with fewer genes to
+    /// evaluate, and keeps its length and layout. Values that are not finite are not folded.
+    ///
+    /// # Returns
+    ///
+    /// How many genes were folded.
+    pub fn fold_constants(&mut self) -> usize {
+        let mut folded = 0;
+        for pos in self.active_genes() {
+            if let Some(value) = self.folded(pos) {
+                self.genes[pos] = Gene::new_constant(Some(value));
+                folded += 1;
+            }
+        }
+        return folded;
+    }
+
+    /// The value of the function gene at `pos` when all of its inputs are constants and the value is finite.
+    fn folded(&self, pos: usize) -> Option<f64> {
+        let gene = &self.genes[pos];
+        if matches!(gene.type_of_gene, Constant(_) | Variable(_)) || !gene.inputs().into_iter().all(|input| matches!(self.genes[input].type_of_gene, Constant(_))) {
+            return None;
+        }
+        // Functions of constants only need the constants, so they are evaluated on an empty row
+        let value = gene.operation(self, &Vec::new());
+        return value.is_finite().then_some(value);
+    }
+
+    /// Simplifies the expression in place, from the first active gene to the outputs: functions of
+    /// constants become constants as in `fold_constants`, and algebraic rules such as x + 0 = x, x * 1 = x, x * 0 = 0,
+    /// x - x = 0, neg(neg(x)) = x and log(exp(x)) = x replace a gene by a constant or by a copy of
+    /// the earlier gene it equals. The graph keeps its length and layout, so the chromosome can
+    /// evolve on as before. The rules hold for finite values, so the fitness of a chromosome whose
+    /// genes overflow may change, and is not updated.
+    ///
+    /// # Returns
+    ///
+    /// How many genes were rewritten.
+    pub fn simplify(&mut self) -> usize {
+        let mut rewritten = 0;
+        for pos in self.active_genes() {
+            let mut gene = match self.simplified(pos) {
+                Some(Simplified::Constant(value)) => Gene::new_constant(Some(value)),
+                Some(Simplified::Copy(input)) => self.genes[input].clone(),
+                None => continue,
+            };
+            // A copied function gene may reach further back than the grid allows at its new position
+            if gene.pointers_mut().into_iter().all(|ptr| self.layout.grid.inputs(pos).contains(ptr)) {
+                self.genes[pos] = gene;
+                rewritten += 1;
+            }
+        }
+        return rewritten;
+    }
+
+    /// What the gene at `pos` simplifies to, see `simplify`, or `None` when no rule applies.
+    fn simplified(&self, pos: usize) -> Option<Simplified> {
+        let gene = &self.genes[pos];
+        let constant = |input: usize| match self.genes[input].type_of_gene {
+            Constant(value) => Some(value),
+            _ => None,
+        };
+        let function = |input: usize| match self.genes[input].type_of_gene {
+            Unary | Binary => Some(self.genes[input].primitive),
+            _ => None,
+        };
+        let (left, right) = (gene.left_ptr, gene.right_ptr);
+        let is = |input: usize, value: f64| constant(input) == Some(value);
+        if let Some(value) = self.folded(pos) {
+            return Some(Simplified::Constant(value));
+        }
+        return match gene.type_of_gene {
+            Constant(_) | Variable(_) => None,
+            Conditional => match (constant(left), constant(right)) {
+                (Some(x), Some(y)) => Some(Simplified::Copy(gene.branch(x, y))),
+                _ if self.equivalent(gene.branch_ptrs[0], gene.branch_ptrs[1]) => Some(Simplified::Copy(gene.branch_ptrs[0])),
+                _ => None,
+            },
+            Unary => match (gene.primitive, function(left)) {
+                (PrimitiveId::NEG, Some(PrimitiveId::NEG)) | (PrimitiveId::LOG, Some(PrimitiveId::EXP)) => Some(Simplified::Copy(self.genes[left].left_ptr)),
+                (PrimitiveId::ABS, Some(PrimitiveId::ABS)) | (PrimitiveId::FLOOR, Some(PrimitiveId::FLOOR)) => Some(Simplified::Copy(left)),
+                _ => None,
+            },
+            Binary => match gene.primitive {
+                PrimitiveId::ADD if is(right, 0.0) => Some(Simplified::Copy(left)),
+                PrimitiveId::ADD if is(left, 0.0) => Some(Simplified::Copy(right)),
+                PrimitiveId::SUB if is(right, 0.0) => Some(Simplified::Copy(left)),
+                PrimitiveId::SUB if self.equivalent(left, right) => Some(Simplified::Constant(0.0)),
+                PrimitiveId::MUL if is(right, 1.0) => Some(Simplified::Copy(left)),
+                PrimitiveId::MUL if is(left, 1.0) => Some(Simplified::Copy(right)),
+                PrimitiveId::MUL if is(left, 0.0) || is(right, 0.0) => Some(Simplified::Constant(0.0)),
+                PrimitiveId::DIV if is(right, 1.0) => Some(Simplified::Copy(left)),
+                PrimitiveId::MAX | PrimitiveId::MIN if self.equivalent(left, right) => Some(Simplified::Copy(left)),
+                _ => None,
+            },
+        };
+    }
+
+    /// Canonicalizes and minimizes the expression by equality saturation, see `egraph::EGraph`: the
+    /// active genes are added to an e-graph, saturated with `egraph::RULES`, and the smallest
+    /// expression equal to each output is extracted into the genes of a new chromosome. Expressions
+    /// that the rules prove equal come out the same, e.g. `add(v1, v0)` and `sub(add(v0, v1), 0)`,
+    /// which makes it the form to print and publish the champion in. As with `simplify`, the rules
+    /// hold for finite values.
+    ///
+    /// The new chromosome has as many genes as its expression has distinct subexpressions, so it is
+    /// meant to be printed or saved rather than evolved further.
+    pub fn canonical(&self) -> Chromosome {
+        let mut egraph = EGraph::new(self.layout.protection);
+        let mut classes: Vec<Id> = Vec::with_capacity(self.genes.len());
+        for gene in &self.genes {
+            let node = match gene.type_of_gene {
+                Constant(value) => Node::constant(value),
+                Variable(variable) => Node::Variable(variable),
+                Unary | Binary | Conditional => Node::Apply(gene.primitive, gene.inputs().iter().map(|&input| classes[input]).collect()),
+            };
+            classes.push(egraph.add(node));
+        }
+        let roots: Vec<Id> = self.output_genes().iter().map(|&pos| classes[pos]).collect();
+        egraph.saturate(&RULES, SaturationLimits::default());
+        let (nodes, outputs) = egraph.extract(&roots);
+        let mut chromosome = self.clone();
+        chromosome.genes = nodes.into_iter().map(|node| match node {
+            Node::Constant(bits) => Gene::new_constant(Some(f64::from_bits(bits))),
+            Node::Variable(variable) => Gene::new_variable(variable),
+            Node::Apply(primitive, inputs) => match *inputs.as_slice() {
+                [left] => Gene::new_unary2(left, primitive).protected(self.layout.protection),
+                [left, right] => Gene::new_binary2(left, right, primitive).protected(self.layout.protection),
+                [left, right, first, second] => Gene::new_conditional2(left, right, [first, second], primitive),
+                _ => unreachable!("functions take one, two or four inputs"),
+            },
+        }).collect();
+        // A single output is the last gene, as extraction writes each expression after its inputs
+        chromosome.outputs = if self.outputs.is_empty() { Vec::new() } else { outputs };
+        chromosome.behavior.clear();
+        chromosome.semantics.clear();
+        return chromosome;
+    }
+
+    /// Whether the genes at `a` and `b` compute the same expression, whether or not they are the same gene.
+    fn equivalent(&self, a: usize, b: usize) -> bool {
+        return self.equivalent_within(a, b, &mut HashMap::new());
+    }
+
+    /// `equivalent`, remembering in `known` the pairs of genes already compared, so that shared
+    /// subexpressions are compared once rather than once per path to them.
+    fn equivalent_within(&self, a: usize, b: usize, known: &mut HashMap<(usize, usize), bool>) -> bool {
+        if a == b {
+            return true;
+        }
+        if let Some(&equivalent) = known.get(&(a, b)) {
+            return equivalent;
+        }
+        let (x, y) = (&self.genes[a], &self.genes[b]);
+        let equivalent = match (&x.type_of_gene, &y.type_of_gene) {
+            (Constant(p), Constant(q)) => p == q,
+            (Variable(p), Variable(q)) => p == q,
+            (Unary, Unary) | (Binary, Binary) | (Conditional, Conditional) =>
+                x.primitive == y.primitive && x.inputs().into_iter().zip(y.inputs()).all(|(p, q)| self.equivalent_within(p, q, known)),
+            _ => false,
+        };
+        known.insert((a, b), equivalent);
+        return equivalent;
+    }
+
+    /// Returns a hash of the expression the chromosome encodes.
+    ///
+    /// Only the active genes are hashed, by what they compute rather than where they are, so two
+    /// chromosomes that print as the same expression have the same hash even when their genes are
+    /// laid out differently.
+    pub fn structure_hash(&self) -> u64 {
+        let mut hashes: Vec<u64> = Vec::with_capacity(self.genes.len());
+        for gene in &self.genes {
+            let mut hasher = DefaultHasher::new();
+            match gene.type_of_gene {
+                Constant(x) => ("constant", x.to_bits()).hash(&mut hasher),
+                Variable(x) => ("variable", x).hash(&mut hasher),
+                Unary | Binary | Conditional => (gene.primitive, gene.inputs().iter().map(|&input| hashes[input]).collect::<Vec<u64>>()).hash(&mut hasher),
+            }
+            hashes.push(hasher.finish());
+        }
+        let outputs: Vec<u64> = self.output_genes().iter().map(|&pos| hashes[pos]).collect();
+        return match outputs.as_slice() {
+            [] => 0,
+            [hash] => *hash,
+            _ => {
+                let mut hasher = DefaultHasher::new();
+                outputs.hash(&mut hasher);
+                hasher.finish()
+            }
+        };
+    }
+
+    /// Returns the distinct variables the expression uses, in ascending order.
+    pub fn variables_used(&self) -> Vec<usize> {
+        let mut variables: Vec<usize> = self.active_genes().iter()
+            .filter_map(|&pos| match self.genes[pos].type_of_gene {
+                Variable(x) => Some(x),
+                _ => None,
+            })
+            .collect();
+        variables.sort();
+        variables.dedup();
+        return variables;
+    }
+
+    /// Returns the depth of the expression: 1 for a constant or a variable, and one more than the
+    /// deepest input for a function.
+    ///
+    /// Genes can be shared by several functions, so the depth can be much smaller than the size of
+    /// the printed expression, but evaluating the expression still recurses this deep.
+    pub fn depth(&self) -> usize {
+        let mut depth: Vec<usize> = Vec::with_capacity(self.genes.len());
+        for gene in &self.genes {
+            depth.push(match gene.type_of_gene {
+                Constant(_) | Variable(_) => 1,
+                Unary | Binary | Conditional => 1 + gene.inputs().iter().map(|&input| depth[input]).max().unwrap_or(0),
+            });
+        }
+        return self.output_genes().iter().map(|&pos| depth[pos]).max().unwrap_or(0);
+    }
+
+    /// Returns the size, depth and functions of the expression.
+    pub fn complexity(&self) -> Complexity {
+        let active = self.active_genes();
+        let mut operators = BTreeMap::new();
+        for gene in active.iter().map(|&pos| &self.genes[pos]) {
+            let operator = match gene.type_of_gene {
+                Constant(_) | Variable(_) => continue,
+                Conditional => format!("if{}", gene.get_operator()),
+                Unary | Binary => gene.get_operator(),
+            };
+            *operators.entry(operator).or_insert(0) += 1;
+        }
+        return Complexity { active_genes: active.len(), depth: self.depth(), operators };
+    }
+
+    /// Whether the expression stays within `limits`.
+    pub fn within(&self, limits: &ComplexityLimits) -> bool {
+        return limits.max_genes.is_none_or(|max| self.len() <= max)
+            && limits.max_active_genes.is_none_or(|max| self.active_genes().len() <= max)
+            && limits.max_depth.is_none_or(|max| self.depth() <= max);
+    }
+
+    fn iter(&self) -> impl Iterator<Item=&Gene> {
+        self.genes.iter()
+    }
+
+    /// Returns the length of the genes array (`Chromosome`) in the provided instance.
     ///
     /// # Example
+    /// ```ignore
+    /// let instance = Instance { genes: vec![1, 2, 3] };
+    /// assert_eq!(instance.len(), 3);
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// - `usize`: The length of the genes array (`Chromosome`) in the provided instance.
+    fn len(&self) -> usize {
+        self.genes.len()
+    }
+
+    /// Converts a GeneticExpression into a string representation of the function.
+    ///
+    /// # Arguments
+    /// * `position` - An optional position parameter. If `Some`, the function will start the conversion from this position in the gene list. If `None`, it will start from the first output gene.
+    /// * `variable_names` - The names used for the variables. Variables without a name are written as `v0`, `v1`, ...
+    pub fn make_function_string(&self, position: Option<usize>, mut builder: String, variable_names: &[String]) -> String {
+        let pos = position.unwrap_or(self.output());
+        match &self.genes[pos].type_of_gene {
+            Constant(i) => {
+                builder.push_str(&format!("{}", i));
+            }
+            Variable(i) => match variable_names.get(*i) {
+                Some(name) => builder.push_str(name),
+                None => builder.push_str(&format!("v{}", i)),
+            },
+            Unary | Binary => {
+                let inputs: Vec<String> = self.genes[pos].inputs().iter().map(|&input| self.make_function_string(Some(input), builder.clone(), variable_names)).collect();
+                builder.push_str(&FunctionRegistry::global().print(self.genes[pos].primitive, &inputs))
+            }
+            Conditional => {
+                let inputs: Vec<String> = self.genes[pos].inputs().iter().map(|&input| self.make_function_string(Some(input), builder.clone(), variable_names)).collect();
+                builder.push_str(&format!("if{}({})", &self.genes[pos].get_operator(), inputs.join(", ")))
+            }
+        }
+        return builder.to_string();
+    }
+
+    pub fn function_string(&self) -> String {
+        self.function_string_with_names(&[])
+    }
+
+    /// Converts the `Chromosome` into a string representation of the function using the column names of the dataset.
+    ///
+    /// The functions of several outputs are separated by `; `, in the order of the targets. A
+    /// multi-gene chromosome is written as the weighted sum of its outputs.
+    pub fn function_string_with_names(&self, variable_names: &[String]) -> String {
+        if let Some(weights) = &self.weights {
+            return self.outputs.iter().zip(&weights[1..]).fold(format!("{}", weights[0]), |sum, (&pos, weight)| {
+                format!("add({}, mul({}, {}))", sum, weight, self.make_function_string(Some(pos), String::new(), variable_names))
+            });
+        }
+        self.output_genes().iter()
+            .map(|&pos| self.make_function_string(Some(pos), String::new(), variable_names))
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+
+    /// Writes the expression in `notation`.
+    pub fn expression(&self, notation: Notation, variable_names: &[String]) -> String {
+        return match notation {
+            Notation::Infix => self.to_infix(variable_names),
+            Notation::Prefix => self.function_string_with_names(variable_names),
+            Notation::SExpression => self.to_sexpr(variable_names),
+            Notation::Latex => self.to_latex(variable_names),
+        };
+    }
+
+    /// Writes the expression in infix notation, e.g. `x0*x1 + 3` rather than `add(mul(x0, x1), 3)`.
+    /// Addition, subtraction, multiplication, division, negation, squares and cubes are written
+    /// with operators, registered functions with a printer as its output in parentheses, and every
+    /// other function as a call. Parentheses are otherwise only added where the
+    /// precedence of the operators needs them, reading operators of the same precedence from left
+    /// to right, so that `new_from_string` parses the expression back into the same genes.
+    /// Several outputs are separated by `; ` as in `function_string_with_names`.
+    pub fn to_infix(&self, variable_names: &[String]) -> String {
+        if let Some(weights) = &self.weights {
+            return self.outputs.iter().zip(&weights[1..]).fold(format!("{}", weights[0]), |sum, (&pos, weight)| {
+                format!("{} + {}*{}", sum, weight, parenthesize(self.make_infix(pos, variable_names), PRODUCT + 1))
+            });
+        }
+        return self.output_genes().iter().map(|&pos| self.make_infix(pos, variable_names).0).collect::<Vec<String>>().join("; ");
+    }
+
+    /// Writes the expression of the gene at `pos` in infix notation, see `to_infix`.
+    ///
+    /// # Returns
+    ///
+    /// The expression and the precedence of its outermost operator.
+    fn make_infix(&self, pos: usize, variable_names: &[String]) -> (String, u8) {
+        let gene = &self.genes[pos];
+        let operator = gene.get_operator();
+        let mut inputs: Vec<(String, u8)> = gene.inputs().iter().map(|&input| self.make_infix(input, variable_names)).collect();
+        return match gene.type_of_gene {
+            Constant(value) => (format!("{}", value), if value.is_sign_negative() { NEGATION } else { ATOM }),
+            Variable(i) => (variable_names.get(i).cloned().unwrap_or_else(|| format!("v{}", i)), ATOM),
+            Conditional => {
+                let inputs: Vec<String> = inputs.into_iter().map(|(input, _)| input).collect();
+                (format!("if{}({})", operator, inputs.join(", ")), ATOM)
+            }
+            Unary | Binary => match gene.primitive {
+                PrimitiveId::ADD | PrimitiveId::SUB | PrimitiveId::MUL | PrimitiveId::DIV => {
+                    let (symbol, precedence) = match gene.primitive {
+                        PrimitiveId::ADD => (" + ", SUM),
+                        PrimitiveId::SUB => (" - ", SUM),
+                        PrimitiveId::MUL => ("*", PRODUCT),
+                        _ => ("/", PRODUCT),
+                    };
+                    let right = parenthesize(inputs.pop().unwrap(), precedence + 1);
+                    let left = parenthesize(inputs.pop().unwrap(), precedence);
+                    (format!("{}{}{}", left, symbol, right), precedence)
+                }
+                PrimitiveId::NEG => (format!("-{}", parenthesize(inputs.remove(0), NEGATION + 1)), NEGATION),
+                PrimitiveId::SQUARE => (format!("{}^2", parenthesize(inputs.remove(0), ATOM)), POWER),
+                PrimitiveId::CUBE => (format!("{}^3", parenthesize(inputs.remove(0), ATOM)), POWER),
+                _ => {
+                    let inputs: Vec<String> = inputs.into_iter().map(|(input, _)| input).collect();
+                    let registry = FunctionRegistry::global();
+                    match registry.primitive(gene.primitive).printer {
+                        // Whatever a printer writes, the parentheses keep it together next to any operator
+                        Some(_) => (format!("({})", registry.print(gene.primitive, &inputs)), ATOM),
+                        None => (format!("{}({})", operator, inputs.join(", ")), ATOM),
+                    }
+                }
+            },
+        };
+    }
+
+    /// Writes the expression as LaTeX math to paste into a paper, e.g. `\frac{x_{0}}{2} + \log_2\left(x_{1}\right)^{2}`.
+    /// Division is written as a fraction, powers and `exp` as exponents, and the other functions
+    /// by their usual symbols, with parentheses where the precedence of the operators needs them.
+    /// Functions are written as their mathematical counterparts, without the protection that keeps
+    /// them defined everywhere. Several outputs are separated by `, \quad `.
+    pub fn to_latex(&self, variable_names: &[String]) -> String {
+        if let Some(weights) = &self.weights {
+            return self.outputs.iter().zip(&weights[1..]).fold(latex_constant(weights[0]).0, |sum, (&pos, &weight)| {
+                format!("{} + {} \\cdot {}", sum, parenthesize_latex(latex_constant(weight), PRODUCT), parenthesize_latex(self.make_latex(pos, variable_names), PRODUCT + 1))
+            });
+        }
+        return self.output_genes().iter().map(|&pos| self.make_latex(pos, variable_names).0).collect::<Vec<String>>().join(", \\quad ");
+    }
+
+    /// Writes the expression of the gene at `pos` as LaTeX, see `to_latex`.
+    ///
+    /// # Returns
     ///
+    /// The expression and the precedence of its outermost operator.
+    fn make_latex(&self, pos: usize, variable_names: &[String]) -> (String, u8) {
+        let gene = &self.genes[pos];
+        let operator = gene.get_operator();
+        let inputs: Vec<(String, u8)> = gene.inputs().iter().map(|&input| self.make_latex(input, variable_names)).collect();
+        let argument = |input: &(String, u8)| format!("\\left({}\\right)", input.0);
+        return match gene.type_of_gene {
+            Constant(value) => latex_constant(value),
+            Variable(i) => (latex_variable(&variable_names.get(i).cloned().unwrap_or_else(|| format!("v{}", i))), ATOM),
+            Conditional => {
+                let comparison = match gene.primitive {
+                    PrimitiveId::IFLT => format!("{} < {}", inputs[0].0, inputs[1].0),
+                    _ => format!("\\operatorname{{{}}}\\left({}, {}\\right)", latex_escape(&operator), inputs[0].0, inputs[1].0),
+                };
+                (format!("\\begin{{cases}} {} & \\text{{if }} {} \\\\ {} & \\text{{otherwise}} \\end{{cases}}", inputs[2].0, comparison, inputs[3].0), CASES)
+            }
+            Unary | Binary => match (gene.primitive, inputs.as_slice()) {
+                // A negative right operand is parenthesized rather than written after another sign
+                (PrimitiveId::ADD, [left, right]) => (format!("{} + {}", parenthesize_latex(left.clone(), SUM), parenthesize_latex(right.clone(), if right.1 == NEGATION { ATOM } else { SUM + 1 })), SUM),
+                (PrimitiveId::SUB, [left, right]) => (format!("{} - {}", parenthesize_latex(left.clone(), SUM), parenthesize_latex(right.clone(), if right.1 == NEGATION { ATOM } else { SUM + 1 })), SUM),
+                (PrimitiveId::MUL, [left, right]) => (format!("{} \\cdot {}", parenthesize_latex(left.clone(), PRODUCT), parenthesize_latex(right.clone(), if right.1 == NEGATION { ATOM } else { PRODUCT + 1 })), PRODUCT),
+                // A fraction delimits its inputs, but reads as a power would next to a sign or an exponent
+                (PrimitiveId::DIV, [left, right]) => (format!("\\frac{{{}}}{{{}}}", left.0, right.0), POWER),
+                (PrimitiveId::INV, [input]) => (format!("\\frac{{1}}{{{}}}", input.0), POWER),
+                (PrimitiveId::NEG, [input]) => (format!("-{}", parenthesize_latex(input.clone(), NEGATION + 1)), NEGATION),
+                (PrimitiveId::SQUARE, [input]) => (format!("{}^{{2}}", parenthesize_latex(input.clone(), ATOM)), POWER),
+                (PrimitiveId::CUBE, [input]) => (format!("{}^{{3}}", parenthesize_latex(input.clone(), ATOM)), POWER),
+                (PrimitiveId::EXP, [input]) => (format!("e^{{{}}}", input.0), POWER),
+                (PrimitiveId::SQRT, [input]) => (format!("\\sqrt{{{}}}", input.0), ATOM),
+                (PrimitiveId::ABS, [input]) => (format!("\\left|{}\\right|", input.0), ATOM),
+                (PrimitiveId::FLOOR, [input]) => (format!("\\left\\lfloor {} \\right\\rfloor", input.0), ATOM),
+                (PrimitiveId::LOG2, [input]) => (format!("\\log_2{}", argument(input)), FUNCTION),
+                (PrimitiveId::LOG, [input]) => (format!("\\ln{}", argument(input)), FUNCTION),
+                (PrimitiveId::SIN | PrimitiveId::COS | PrimitiveId::TAN | PrimitiveId::TANH, [input]) => (format!("\\{}{}", operator, argument(input)), FUNCTION),
+                (PrimitiveId::MAX | PrimitiveId::MIN, [left, right]) => (format!("\\{}\\left({}, {}\\right)", operator, left.0, right.0), FUNCTION),
+                // Comparisons are 1 when they hold, as an Iverson bracket
+                (PrimitiveId::LT, [left, right]) => (format!("\\left[{} < {}\\right]", left.0, right.0), ATOM),
+                (PrimitiveId::GT, [left, right]) => (format!("\\left[{} > {}\\right]", left.0, right.0), ATOM),
+                _ => {
+                    let inputs: Vec<String> = inputs.into_iter().map(|(input, _)| input).collect();
+                    (format!("\\operatorname{{{}}}\\left({}\\right)", latex_escape(&operator), inputs.join(", ")), FUNCTION)
+                }
+            },
+        };
+    }
+
+    /// Writes the expression as an S-expression, e.g. `(add x0 (mul 2.5 (sin x1)))`, the notation
+    /// of Lisp-based GP tools, with one S-expression per output separated by spaces. Functions are
+    /// written by the name they print with, and conditionals as `iflt` and the like with their four inputs.
+    pub fn to_sexpr(&self, variable_names: &[String]) -> String {
+        if let Some(weights) = &self.weights {
+            return self.outputs.iter().zip(&weights[1..]).fold(format!("{}", weights[0]), |sum, (&pos, weight)| {
+                format!("(add {} (mul {} {}))", sum, weight, self.make_sexpr(pos, variable_names))
+            });
+        }
+        return self.output_genes().iter().map(|&pos| self.make_sexpr(pos, variable_names)).collect::<Vec<String>>().join(" ");
+    }
+
+    /// Writes the expression of the gene at `pos` as an S-expression, see `to_sexpr`.
+    fn make_sexpr(&self, pos: usize, variable_names: &[String]) -> String {
+        let gene = &self.genes[pos];
+        return match gene.type_of_gene {
+            Constant(value) => format!("{}", value),
+            Variable(i) => variable_names.get(i).cloned().unwrap_or_else(|| format!("v{}", i)),
+            Unary | Binary | Conditional => {
+                let operator = match gene.type_of_gene {
+                    Conditional => format!("if{}", gene.get_operator()),
+                    _ => gene.get_operator(),
+                };
+                let inputs: Vec<String> = gene.inputs().iter().map(|&input| self.make_sexpr(input, variable_names)).collect();
+                format!("({} {})", operator, inputs.join(" "))
+            }
+        };
+    }
+
+    /// Builds a chromosome from S-expressions as written by `to_sexpr`, one per output.
+    ///
+    /// # Arguments
+    ///
+    /// * `expression` - The S-expressions to parse.
+    /// * `variable_names` - The names of the variables, by index. `v0`, `v1`, ... also name the variables.
+    ///
+    /// # Panics
+    ///
+    /// When the expression is malformed, or uses an unknown function or variable.
+    pub fn from_sexpr(expression: &str, variable_names: &[String]) -> Chromosome {
+        let mut tokens = sexpr_tokens(expression).into_iter().peekable();
+        let mut genes = Vec::new();
+        let mut outputs = Vec::new();
+        while tokens.peek().is_some() {
+            outputs.push(parse_sexpr(&mut tokens, &mut genes, variable_names));
+        }
+        if outputs.is_empty() {
+            panic!("The expression is empty");
+        }
+        let mut chromosome = Chromosome::new_from_genes_array(genes);
+        if outputs.len() > 1 {
+            chromosome.outputs = outputs;
+        }
+        return chromosome;
+    }
+
+    /// Builds a chromosome from an expression, with one gene per function, variable and constant.
+    /// The expression can be written in the prefix notation of `function_string_with_names`, e.g.
+    /// `add(x0, mul(2.5, sin(x1)))`, in infix notation with `+`, `-`, `*`, `/` and the powers `^2`
+    /// and `^3`, e.g. `x0 + 2.5 * sin(x1)`, or in a mix of both. Functions are written by the name
+    /// they print with or the name `--functions` knows them by. The expressions of several outputs
+    /// are separated by `;`.
+    ///
+    /// # Arguments
+    ///
+    /// * `expression` - The expression to parse.
+    /// * `variable_names` - The names of the variables, by index. `v0`, `v1`, ... also name the variables.
+    ///
+    /// # Panics
+    ///
+    /// When the expression is malformed, or uses an unknown function or variable.
+    pub fn new_from_string(expression: &str, variable_names: &[String]) -> Chromosome {
+        let mut genes = Vec::new();
+        let mut outputs = Vec::new();
+        for part in expression.split(';') {
+            let mut tokens = tokenize(part).into_iter().peekable();
+            outputs.push(parse_sum(&mut tokens, &mut genes, variable_names));
+            if let Some(token) = tokens.next() {
+                panic!("Unexpected {} after the end of the expression {}", token, part.trim());
+            }
+        }
+        let mut chromosome = Chromosome::new_from_genes_array(genes);
+        if outputs.len() > 1 {
+            chromosome.outputs = outputs;
+        }
+        return chromosome;
+    }
+
+    /// Builds a chromosome from an expression as given on the command line: S-expressions as
+    /// written by `to_sexpr`, see `is_sexpr`, or else any notation `new_from_string` reads.
+    ///
+    /// # Arguments
+    ///
+    /// * `expression` - The expression to parse.
+    /// * `variable_names` - The names of the variables, by index. `v0`, `v1`, ... also name the variables.
+    ///
+    /// # Panics
+    ///
+    /// When the expression is malformed, or uses an unknown function or variable.
+    pub fn parse(expression: &str, variable_names: &[String]) -> Chromosome {
+        if is_sexpr(expression) {
+            return Chromosome::from_sexpr(expression, variable_names);
+        }
+        return Chromosome::new_from_string(expression, variable_names);
+    }
+
+    /// Shuffles the genes within the struct.
+    ///
+    /// This function shuffles the genes within the struct using the Fisher-Yates algorithm.
+    /// It modifies the genes in-place.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use rand::seq::SliceRandom;
+    ///
+    /// // Create a new instance of the struct
+    /// let mut c = Chromosome::new();
+    ///
+    /// // Shuffle the genes within the struct
+    /// c.shuffle();
+    ///
+    /// // Print the shuffled genes
+    /// println!("{:?}", my_struct.genes);
     /// ```
+    #[allow(dead_code)]
+    pub fn shuffle(&mut self) {
+        self.genes.shuffle(&mut rng());
+    }
+}
+
+impl Chromosome {
+    /// Crosses the current chromosome with another chromosome.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_2` - A mutable reference to the second parent chromosome.
+    /// * `crossover_loc` - Optional. The index at which the crossover operation will start.
+    ///   If not provided, a random index before the end of the shorter chromosome is chosen.
+    ///
+    /// The tails are swapped from the same index, so chromosomes of different lengths exchange
+    /// lengths too, and every gene keeps its position.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let mut chromosome_1 = Chromosome::new();
+    /// let mut chromosome_2 = Chromosome::new();
+    ///
+    /// chromosome_1.cross_with(&mut chromosome_2, None);
+    /// ```
+    pub fn cross_with(&mut self, parent_2: &mut Chromosome, crossover_loc: Option<usize>) {
+        let cross_loc = crossover_loc.unwrap_or(rng().gen_range(0..self.len().min(parent_2.len())));
+        let tail_one = self.genes.split_off(cross_loc);
+        let tail_two = parent_2.genes.split_off(cross_loc);
+        self.genes.extend(tail_two);
+        parent_2.genes.extend(tail_one);
+        self.fit_output();
+        parent_2.fit_output();
+        self.share_parent_fitness(parent_2);
+    }
+
+    /// Moves the outputs that are beyond the end of a shortened chromosome to its last gene.
+    fn fit_output(&mut self) {
+        let last = self.len().saturating_sub(1);
+        self.outputs.iter_mut().for_each(|pos| *pos = (*pos).min(last));
+    }
+
+    /// Both children of a crossover descend from both parents, so they are compared with the fitter one.
+    fn share_parent_fitness(&mut self, parent_2: &mut Chromosome) {
+        if let (Some(a), Some(b)) = (self.parent_fitness, parent_2.parent_fitness) {
+            self.parent_fitness = Some(a.min(b));
+            parent_2.parent_fitness = Some(a.min(b));
+        }
+    }
+
+    /// Crosses the chromosome over with another using the given operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_2` - The other chromosome, which becomes the second child.
+    /// * `operator` - How the genes are exchanged.
+    pub fn crossover(&mut self, parent_2: &mut Chromosome, operator: CrossoverOperator) {
+        match operator {
+            CrossoverOperator::OnePoint => self.cross_with(parent_2, None),
+            CrossoverOperator::TwoPoint => self.cross_two_point(parent_2),
+            CrossoverOperator::Uniform => self.cross_uniform(parent_2),
+            CrossoverOperator::Subgraph => self.cross_subgraph(parent_2),
+            CrossoverOperator::Semantic { most_different } => self.cross_semantic(parent_2, most_different),
+        }
+        self.repair_constraints();
+        parent_2.repair_constraints();
+    }
+
+    /// Swaps the genes between two random positions, within the shorter chromosome, with the other chromosome.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_2` - The other chromosome, which becomes the second child.
+    pub fn cross_two_point(&mut self, parent_2: &mut Chromosome) {
+        let mut rng = rng();
+        let length = self.len().min(parent_2.len());
+        let (a, b) = (rng.gen_range(0..=length), rng.gen_range(0..=length));
+        self.swap_genes(parent_2, a.min(b)..a.max(b));
+    }
+
+    /// Swaps every gene with the gene at the same position in the other chromosome with probability
+    /// one half. The genes beyond the end of the shorter chromosome stay where they are.
+    ///
+    /// Genes only point at earlier positions, so any mix of positions is a valid chromosome.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_2` - The other chromosome, which becomes the second child.
+    pub fn cross_uniform(&mut self, parent_2: &mut Chromosome) {
+        let positions: Vec<usize> = (0..self.len().min(parent_2.len())).filter(|_| random()).collect();
+        self.swap_genes(parent_2, positions);
+    }
+
+    /// Swaps the genes at `positions`, which both chromosomes have, with the other chromosome.
+    fn swap_genes(&mut self, parent_2: &mut Chromosome, positions: impl IntoIterator<Item = usize>) {
+        for i in positions {
+            swap(&mut self.genes[i], &mut parent_2.genes[i])
+        }
+        self.share_parent_fitness(parent_2);
+    }
+
+    /// Each child receives from the other parent the subgraph that feeds one of its own active genes.
+    ///
+    /// The genes of the subgraph are copied to the same positions, so they stay connected, and the
+    /// subexpression computed at the chosen gene is replaced with the one the other parent computes
+    /// there. Only genes at positions the other parent also has can be chosen, and parents without
+    /// such a gene get a one-point crossover instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_2` - The other chromosome, which becomes the second child.
+    pub fn cross_subgraph(&mut self, parent_2: &mut Chromosome) {
+        if self.genes.is_empty() || parent_2.genes.is_empty() {
+            return;
+        }
+        let mut rng = rng();
+        let roots_one: Vec<usize> = self.active_genes().into_iter().filter(|&pos| pos < parent_2.len()).collect();
+        let roots_two: Vec<usize> = parent_2.active_genes().into_iter().filter(|&pos| pos < self.len()).collect();
+        match (roots_one.choose(&mut rng), roots_two.choose(&mut rng)) {
+            (Some(&root_one), Some(&root_two)) => self.exchange_subgraphs(parent_2, root_one, root_two),
+            _ => self.cross_with(parent_2, None),
+        }
+    }
+
+    /// Gives this chromosome the subgraph of `parent_2` feeding `root_one`, and `parent_2` the
+    /// subgraph of this chromosome feeding `root_two`, both taken from before the exchange.
+    fn exchange_subgraphs(&mut self, parent_2: &mut Chromosome, root_one: usize, root_two: usize) {
+        let from_two: Vec<(usize, Gene)> = parent_2.subgraph(root_one).into_iter().map(|pos| (pos, parent_2.genes[pos].clone())).collect();
+        for pos in self.subgraph(root_two) {
+            parent_2.genes[pos] = self.genes[pos].clone();
+        }
+        for (pos, gene) in from_two {
+            self.genes[pos] = gene;
+        }
+        self.share_parent_fitness(parent_2);
+    }
+
+    /// Exchanges the subgraphs feeding the gene where the parents behave the most, or the least,
+    /// differently on the probe set.
+    ///
+    /// Only genes that are active in both parents are considered, and the output genes only when
+    /// there is no other, since exchanging it swaps the whole expressions. Genes where both parents
+    /// compute exactly the same outputs are skipped, since exchanging them changes nothing. Parents
+    /// without recorded semantics, or without a gene to exchange, get a one-point crossover instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_2` - The other chromosome, which becomes the second child.
+    /// * `most_different` - Whether the most different gene is chosen, to explore, rather than the
+    ///   least different, to make small semantic steps.
+    pub fn cross_semantic(&mut self, parent_2: &mut Chromosome, most_different: bool) {
+        let rows = self.behavior.len();
+        if rows == 0 || self.semantics.len() != self.len() * rows || parent_2.semantics.len() != parent_2.len() * rows {
+            return self.cross_with(parent_2, None);
+        }
+        let active_two = parent_2.active_genes();
+        let mut shared: Vec<usize> = self.active_genes().into_iter().filter(|pos| active_two.contains(pos)).collect();
+        if shared.len() > 1 {
+            let outputs = [self.output_genes(), parent_2.output_genes()].concat();
+            shared.retain(|pos| !outputs.contains(pos));
+        }
+        let candidates = shared.into_iter()
+            .map(|pos| (pos, behavior_distance(&self.semantics[pos * rows..(pos + 1) * rows], &parent_2.semantics[pos * rows..(pos + 1) * rows])))
+            .filter(|&(_, distance)| distance > 0.0);
+        let chosen = match most_different {
+            true => candidates.max_by(|a, b| a.1.total_cmp(&b.1)),
+            false => candidates.min_by(|a, b| a.1.total_cmp(&b.1)),
+        };
+        match chosen {
+            Some((root, _)) => self.exchange_subgraphs(parent_2, root, root),
+            None => self.cross_with(parent_2, None),
+        }
+    }
+
+    /// Copies the chromosome as a new child, which remembers the fitness of its parent.
+    pub fn offspring(&self) -> Chromosome {
+        let mut child = self.clone();
+        child.parent_fitness = Some(self.fitness_value);
+        child.mutation_operator = None;
+        return child;
+    }
+
+    /// Mutates the chromosome with the given operator and remembers which operator was used.
+    ///
+    /// # Arguments
+    ///
+    /// * `operator` - The mutation to apply.
+    /// * `sigma` - The standard deviation of Gaussian constant perturbations.
+    /// * `probe` - The probe rows, for semantic backpropagation. Without them it becomes a point mutation.
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn mutate_with(&mut self, operator: MutationOperator, sigma: f64, probe: Option<&Dataset>, num_variables: usize) {
+        match operator {
+            MutationOperator::Point => self.mutate(num_variables),
+            MutationOperator::Active => self.mutate_active(num_variables),
+            MutationOperator::Pointer => self.mutate_pointer(num_variables),
+            MutationOperator::Function => self.mutate_function(num_variables),
+            MutationOperator::Constant => self.mutate_constant(num_variables),
+            MutationOperator::Output => self.mutate_output(num_variables),
+            MutationOperator::Gaussian => self.perturb_constant(sigma, num_variables),
+            MutationOperator::Backpropagation => match probe {
+                Some(probe) => self.backpropagate(probe, num_variables),
+                None => self.mutate(num_variables),
+            },
+            MutationOperator::Duplication => self.duplicate_block(num_variables),
+            MutationOperator::Transposition => self.transpose_block(num_variables),
+            MutationOperator::Insertion => self.insert_gene(num_variables),
+            MutationOperator::Deletion => self.delete_gene(num_variables),
+        }
+        self.repair_constraints();
+        self.mutation_operator = Some(operator);
+    }
+
+    /// Mutates a gene by randomly selecting a location within the gene and replacing it with a new random gene.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
     /// let c = Chromosome::New()
     /// c.mutate(5)
     /// ```
     pub fn mutate(&mut self, num_variables: usize) {
-        let mut_loc = rand::thread_rng().gen_range(0..self.len());
-        self.genes[mut_loc] = Gene::new_random_gene(mut_loc, num_variables, (mut_loc == 0) || (mut_loc == 1))
+        let mut_loc = rng().gen_range(0..self.len());
+        self.genes[mut_loc] = self.random_gene(mut_loc, num_variables)
+    }
+
+    /// Whether the gene at `pos` must be a constant or a variable: the first two genes always are,
+    /// and so are the genes that have no inputs to use in the grid.
+    fn terminal_only(&self, pos: usize) -> bool {
+        return pos < 2 || self.layout.grid.inputs(pos).is_empty();
+    }
+
+    /// A random value for a constant gene, drawn from the constant distribution and rounded into the domain.
+    fn random_constant(&self) -> f64 {
+        return self.layout.constant_domain.round(self.layout.constants.sample());
+    }
+
+    /// A random input for the gene at `pos` allowed by the grid.
+    fn random_input(&self, pos: usize) -> usize {
+        return rng().gen_range(self.layout.grid.inputs(pos));
+    }
+
+    /// A random gene for position `pos`, whose inputs are allowed by the grid.
+    /// Terminals and functions are equally likely, and functions are drawn from the function set.
+    ///
+    /// With strong typing, the inputs also have the types the function takes, and the gene must be
+    /// allowed by the grammar. Functions that cannot satisfy these are drawn again, a few times
+    /// before giving up for a constant.
+    fn random_gene(&self, pos: usize, num_variables: usize) -> Gene {
+        if random() || self.terminal_only(pos) {
+            return if random() { Gene::new_constant(Some(self.random_constant())) } else { Gene::new_random_variable(num_variables) };
+        }
+        for _ in 0..10 {
+            let (primitive, arity) = self.layout.function_set.random();
+            let mut gene = match arity {
+                1 => Gene::new_unary2(0, primitive).protected(self.layout.protection),
+                2 => Gene::new_binary2(0, 0, primitive).protected(self.layout.protection),
+                _ => Gene::new_conditional2(0, 0, [0, 0], primitive),
+            };
+            gene.pointers_mut().into_iter().for_each(|ptr| *ptr = self.random_input(pos));
+            if self.layout.typed {
+                let inputs: Option<Vec<usize>> = gene.input_types().into_iter().map(|input_type| self.random_input_of_type(pos, input_type)).collect();
+                let Some(inputs) = inputs else { continue };
+                gene.pointers_mut().into_iter().zip(inputs).for_each(|(ptr, input)| *ptr = input);
+            }
+            if self.layout.grammar.allows(&gene, &self.genes[..pos]) {
+                return gene;
+            }
+        }
+        return Gene::new_constant(Some(self.random_constant()));
+    }
+
+    /// A random input for the gene at `pos` allowed by the grid whose gene outputs `value_type`,
+    /// `None` when there is none.
+    fn random_input_of_type(&self, pos: usize, value_type: ValueType) -> Option<usize> {
+        let inputs: Vec<usize> = self.layout.grid.inputs(pos).filter(|&input| self.genes[input].output_type() == value_type).collect();
+        return inputs.choose(&mut rng()).copied();
+    }
+
+    /// Makes the genes respect the types and the grammar again after a change elsewhere in the chromosome.
+    ///
+    /// With strong typing, every input whose gene outputs another type than the function takes is
+    /// rewired to a random input of the right type. A gene that has no input of the right type, or
+    /// that the grammar does not allow, becomes a random constant, and the genes after it are checked
+    /// against the new gene.
+    fn repair_constraints(&mut self) {
+        if !self.layout.typed && self.layout.grammar == Grammar::default() {
+            return;
+        }
+        for pos in 0..self.len() {
+            let input_types = if self.layout.typed { self.genes[pos].input_types() } else { Vec::new() };
+            for (i, input_type) in input_types.into_iter().enumerate() {
+                if self.genes[self.genes[pos].inputs()[i]].output_type() == input_type {
+                    continue;
+                }
+                match self.random_input_of_type(pos, input_type) {
+                    Some(input) => *self.genes[pos].pointers_mut()[i] = input,
+                    None => {
+                        self.genes[pos] = Gene::new_constant(Some(self.random_constant()));
+                        break;
+                    }
+                }
+            }
+            if !self.layout.grammar.allows(&self.genes[pos], &self.genes[..pos]) {
+                self.genes[pos] = Gene::new_constant(Some(self.random_constant()));
+            }
+        }
+    }
+
+    /// Whether every gene respects the grammar, see `Grammar::allows`.
+    pub fn obeys_grammar(&self) -> bool {
+        return (0..self.len()).all(|pos| self.layout.grammar.allows(&self.genes[pos], &self.genes[..pos]));
+    }
+
+    /// The distance between two chromosomes, from 0 (identical) to 1.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The chromosome to compare with.
+    /// * `metric` - Whether genes or behavior are compared. Behavioral distances need both
+    ///   chromosomes to have recorded their behavior on the same probe set.
+    pub fn distance(&self, other: &Chromosome, metric: Distance) -> f64 {
+        return match metric {
+            Distance::Genotypic => self.genotypic_distance(other),
+            Distance::Behavioral => self.behavioral_distance(other),
+        };
+    }
+
+    /// Records the outputs of the chromosome on every row of `probe` in `behavior`.
+    pub fn record_behavior(&mut self, probe: &Dataset) {
+        let mut behavior = std::mem::take(&mut self.behavior);
+        behavior.clear();
+        behavior.extend(probe.rows.iter().map(|row| self.evaluate_fitness(row)));
+        self.behavior = behavior;
+    }
+
+    /// Records the outputs of every gene on every row of `probe` in `semantics`, and of the first output gene (or the prediction of a multi-gene chromosome) in `behavior`.
+    pub fn record_semantics(&mut self, probe: &Dataset) {
+        let genes = self.len();
+        let mut semantics = vec![0.0; genes * probe.rows.len()];
+        let mut outputs = vec![0.0; genes];
+        for (row_index, row) in probe.rows.iter().enumerate() {
+            for pos in 0..genes {
+                outputs[pos] = self.genes[pos].apply(&outputs[..pos], row);
+                semantics[pos * probe.rows.len() + row_index] = outputs[pos];
+            }
+        }
+        let output = self.output();
+        self.behavior = match genes {
+            0 => Vec::new(),
+            _ => semantics[output * probe.rows.len()..(output + 1) * probe.rows.len()].to_vec(),
+        };
+        self.semantics = semantics;
+        if self.weights.is_some() {
+            self.record_behavior(probe);
+        }
+    }
+
+    /// How differently two chromosomes behave on the probe set, from 0 (the same outputs) to 1.
+    ///
+    /// Each row contributes `|a - b| / (1 + |a - b|)` for outputs `a` and `b`, and the contributions are averaged.
+    /// Two NaN outputs are the same, and a NaN output differs completely from any other output.
+    pub fn behavioral_distance(&self, other: &Chromosome) -> f64 {
+        return behavior_distance(&self.behavior, &other.behavior);
+    }
+
+    /// The fraction of positions whose genes differ between two chromosomes, from 0 (identical) to 1.
+    ///
+    /// Genes are the same when they have the same type and operator, the same constant or variable,
+    /// and the same pointers for the inputs they use. Positions that only one of the chromosomes has count as different.
+    pub fn genotypic_distance(&self, other: &Chromosome) -> f64 {
+        let length = self.len().max(other.len());
+        if length == 0 {
+            return 0.0;
+        }
+        let same = self.genes.iter().zip(&other.genes).filter(|(a, b)| a.same_as(b)).count();
+        return (length - same) as f64 / length as f64;
+    }
+
+    /// Mutates random genes until one of the genes that was active has changed.
+    ///
+    /// This is the "single active mutation" of Cartesian GP: mutations of inactive genes are kept
+    /// (they drift neutrally), but the result always differs from the original in an active gene.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn mutate_active(&mut self, num_variables: usize) {
+        let active = self.active_genes();
+        loop {
+            let mut_loc = rng().gen_range(0..self.len());
+            self.genes[mut_loc] = self.random_gene(mut_loc, num_variables);
+            if active.contains(&mut_loc) {
+                break;
+            }
+        }
+        self.repair_constraints();
+    }
+
+    /// A random position whose gene satisfies `predicate`, `None` when there is none.
+    fn random_position(&self, predicate: impl Fn(&Gene) -> bool) -> Option<usize> {
+        let positions: Vec<usize> = (0..self.len()).filter(|&pos| predicate(&self.genes[pos])).collect();
+        return positions.choose(&mut rng()).copied();
+    }
+
+    /// Points one input of a random function gene at another earlier gene, keeping its function.
+    ///
+    /// A chromosome without function genes gets a point mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn mutate_pointer(&mut self, num_variables: usize) {
+        let Some(pos) = self.random_position(|gene| matches!(gene.type_of_gene, Unary | Binary | Conditional)) else {
+            return self.mutate(num_variables);
+        };
+        let i = rng().gen_range(0..self.genes[pos].inputs().len());
+        let input = match self.layout.typed {
+            true => match self.random_input_of_type(pos, self.genes[pos].input_types()[i]) {
+                Some(input) => input,
+                None => return,
+            },
+            false => self.random_input(pos),
+        };
+        *self.genes[pos].pointers_mut()[i] = input;
+    }
+
+    /// Replaces the function of a random function gene with another of the same arity, keeping its inputs.
+    ///
+    /// A chromosome without function genes gets a point mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn mutate_function(&mut self, num_variables: usize) {
+        let Some(pos) = self.random_position(|gene| matches!(gene.type_of_gene, Unary | Binary) && !self.layout.function_set.functions(gene.inputs().len()).is_empty()) else {
+            return self.mutate(num_variables);
+        };
+        let arity = self.genes[pos].inputs().len();
+        let primitive = match self.layout.typed {
+            true => {
+                let (input, output) = FunctionRegistry::global().signature(self.genes[pos].primitive);
+                let Some(primitive) = self.layout.function_set.random_typed(arity, input, output) else { return };
+                primitive
+            }
+            false => self.layout.function_set.random_of_arity(arity).unwrap(),
+        };
+        self.genes[pos].primitive = primitive;
+        self.genes[pos].ops = self.layout.protection.protect(primitive);
+    }
+
+    /// Gives a random constant gene a new random value.
+    ///
+    /// A chromosome without constant genes gets a point mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn mutate_constant(&mut self, num_variables: usize) {
+        let Some(pos) = self.random_position(|gene| matches!(gene.type_of_gene, Constant(_))) else {
+            return self.mutate(num_variables);
+        };
+        self.genes[pos] = Gene::new_constant(Some(self.random_constant()));
+    }
+
+    /// Adds a draw from a normal distribution with mean 0 and standard deviation `sigma` to a random
+    /// active constant gene, so that constants can be tuned finely instead of only replaced.
+    ///
+    /// A chromosome whose output uses no constant gets a point mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `sigma` - The standard deviation of the perturbation.
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn perturb_constant(&mut self, sigma: f64, num_variables: usize) {
+        let constants = self.active_constants();
+        let Some(&pos) = constants.choose(&mut rng()) else {
+            return self.mutate(num_variables);
+        };
+        let domain = self.layout.constant_domain;
+        if let Constant(value) = &mut self.genes[pos].type_of_gene {
+            let step: f64 = rng().sample(StandardNormal);
+            *value = domain.nudge(*value, sigma * step);
+        }
+    }
+
+    /// Semantic backpropagation: replaces a random gene feeding the first output with whatever comes
+    /// closest to the outputs it should have for the first output to hit the first target of the
+    /// probe rows.
+    ///
+    /// The outputs of the genes are recorded on the probe rows first, since crossover or another
+    /// change may have left the recorded semantics behind the genes.
+    ///
+    /// The desired outputs are found by inverting the genes on a path from the output down to the
+    /// chosen gene, see `invert`. Rows where a gene cannot be inverted are ignored. The candidates
+    /// are a copy of every earlier gene, whose subexpression is already in the chromosome, and the
+    /// constant closest to the desired outputs. A chromosome whose desired outputs are all unknown
+    /// gets a point mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `probe` - The rows to aim the output at.
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn backpropagate(&mut self, probe: &Dataset, num_variables: usize) {
+        let rows = probe.rows.len();
+        if rows == 0 || self.genes.is_empty() {
+            return self.mutate(num_variables);
+        }
+        self.record_semantics(probe);
+        let targets: Vec<f64> = probe.rows.iter().map(|row| row[row.len() - probe.target_names.len()]).collect();
+        let output = |pos: usize| &self.semantics[pos * rows..(pos + 1) * rows];
+        let root = *self.subgraph(self.output()).choose(&mut rng()).unwrap();
+
+        let mut desired = targets;
+        let mut current = self.output();
+        while current != root {
+            let gene = &self.genes[current];
+            let child = *gene.inputs().iter().find(|&&input| input == root || self.subgraph(input).contains(&root)).unwrap();
+            if matches!(gene.type_of_gene, Conditional) {
+                // A branch passes the desired output through on the rows that take it, and nothing is known about the comparison
+                for (row, desired) in desired.iter_mut().enumerate() {
+                    if gene.branch(output(gene.left_ptr)[row], output(gene.right_ptr)[row]) != child {
+                        *desired = f64::NAN;
+                    }
+                }
+            } else {
+                let sibling = if child == gene.left_ptr { gene.right_ptr } else { gene.left_ptr };
+                for (row, desired) in desired.iter_mut().enumerate() {
+                    *desired = invert(gene.primitive, *desired, output(sibling)[row], child == gene.left_ptr, output(child)[row]);
+                }
+            }
+            current = child;
+        }
+
+        let known: Vec<usize> = (0..rows).filter(|&row| desired[row].is_finite()).collect();
+        if known.is_empty() {
+            return self.mutate(num_variables);
+        }
+        let error = |values: &[f64]| known.iter().map(|&row| (values[row] - desired[row]).powi(2)).sum::<f64>();
+        let constant = known.iter().map(|&row| desired[row]).sum::<f64>() / known.len() as f64;
+        let closest = (0..root).min_by(|&a, &b| error(output(a)).total_cmp(&error(output(b))));
+        self.genes[root] = match closest {
+            Some(pos) if error(output(pos)) < error(&vec![constant; rows]) => self.genes[pos].clone(),
+            _ => Gene::new_constant(Some(self.layout.constant_domain.round(constant))),
+        };
+        self.repair(num_variables);
+    }
+
+    /// Headless-chicken macro-mutation: replaces a large part of the chromosome with random genes,
+    /// as if crossing it over with a random chromosome.
+    ///
+    /// Either every gene from a random position on, or every gene feeding a random active gene, is
+    /// regenerated, with even odds.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn headless_chicken(&mut self, num_variables: usize) {
+        if self.genes.is_empty() {
+            return;
+        }
+        let mut rng = rng();
+        let positions: Vec<usize> = match rng.gen() {
+            true => (rng.gen_range(0..self.len())..self.len()).collect(),
+            false => self.subgraph(*self.active_genes().choose(&mut rng).unwrap()),
+        };
+        for pos in positions {
+            self.genes[pos] = self.random_gene(pos, num_variables);
+        }
+        self.repair_constraints();
+    }
+
+    /// Picks a random block of genes for duplication or transposition and where it goes, as
+    /// `(source, length, destination)`. Blocks are at most a quarter of the chromosome long, and
+    /// neither the block nor its destination includes the first two genes or the last gene.
+    /// `None` when the chromosome is too short.
+    fn random_block(&self) -> Option<(usize, usize, usize)> {
+        let body = self.len().checked_sub(1)?;
+        if body < 3 {
+            return None;
+        }
+        let mut rng = rng();
+        let length = rng.gen_range(1..=(body / 4).max(1));
+        return Some((rng.gen_range(0..=body - length), length, rng.gen_range(2..=body - length)));
+    }
+
+    /// GEP-style duplication: copies a random block of genes over another part of the chromosome,
+    /// so a building block can be used twice. See `Chromosome::duplicate_segment`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn duplicate_block(&mut self, num_variables: usize) {
+        match self.random_block() {
+            Some((source, length, destination)) => self.duplicate_segment(source, length, destination, num_variables),
+            None => self.mutate(num_variables),
+        }
+    }
+
+    /// Copies the `length` genes from `source` to `destination`, overwriting the genes there.
+    ///
+    /// Inputs within the block move with it, so the copy computes the same subexpressions, while
+    /// inputs before the block are kept. Inputs that would no longer be before their gene are
+    /// rewired, see `Chromosome::repair`.
+    fn duplicate_segment(&mut self, source: usize, length: usize, destination: usize, num_variables: usize) {
+        let block: Vec<Gene> = self.genes[source..source + length].to_vec();
+        for (offset, mut gene) in block.into_iter().enumerate() {
+            for ptr in gene.pointers_mut() {
+                if (source..source + length).contains(ptr) {
+                    *ptr = *ptr - source + destination;
+                }
+            }
+            self.genes[destination + offset] = gene;
+        }
+        self.repair(num_variables);
+    }
+
+    /// GEP-style transposition: moves a random block of genes to another part of the chromosome,
+    /// shifting the genes in between. See `Chromosome::transpose_segment`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn transpose_block(&mut self, num_variables: usize) {
+        match self.random_block() {
+            Some((source, length, destination)) => self.transpose_segment(source, length, destination, num_variables),
+            None => self.mutate(num_variables),
+        }
+    }
+
+    /// Moves the `length` genes from `source` so that they start at `destination` once moved,
+    /// keeping the last gene last.
+    ///
+    /// Every input follows the gene it points to, so the expression is unchanged unless a gene ends
+    /// up before one of its inputs, in which case that input is rewired, see `Chromosome::repair`.
+    fn transpose_segment(&mut self, source: usize, length: usize, destination: usize, num_variables: usize) {
+        let last = self.len() - 1;
+        let mut order: Vec<usize> = (0..last).filter(|pos| !(source..source + length).contains(pos)).collect();
+        order.splice(destination..destination, source..source + length);
+        order.push(last);
+        let mut new_position = vec![0; self.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_position[old] = new;
+        }
+        self.genes = order.iter()
+            .map(|&old| {
+                let mut gene = self.genes[old].clone();
+                gene.pointers_mut().into_iter().for_each(|ptr| *ptr = new_position[*ptr]);
+                return gene;
+            })
+            .collect();
+        self.outputs.iter_mut().for_each(|pos| *pos = new_position[*pos]);
+        self.repair(num_variables);
+    }
+
+    /// Inserts a random gene before a random gene of the body (not one of the first two genes),
+    /// lengthening the chromosome by one.
+    ///
+    /// Inputs that pointed at or after the insertion point follow their genes, so the expression
+    /// is unchanged until something points at the new gene. A chromosome too short to have a body
+    /// gets a point mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn insert_gene(&mut self, num_variables: usize) {
+        if self.len() < 3 {
+            return self.mutate(num_variables);
+        }
+        let pos = rng().gen_range(2..self.len());
+        for gene in &mut self.genes[pos..] {
+            for ptr in gene.pointers_mut() {
+                if *ptr >= pos {
+                    *ptr += 1;
+                }
+            }
+        }
+        let gene = self.random_gene(pos, num_variables);
+        self.genes.insert(pos, gene);
+        self.outputs.iter_mut().filter(|output| **output >= pos).for_each(|output| *output += 1);
+        // Inputs that were just at the limit of the grid may have moved beyond it
+        self.repair(num_variables);
+    }
+
+    /// Deletes a random gene of the body (neither one of the first two genes, the last gene nor the
+    /// output gene), shortening the chromosome by one.
+    ///
+    /// Inputs after the deleted gene follow their genes, and inputs that pointed at the deleted gene
+    /// are rewired to a random earlier gene. A chromosome too short to have a body gets a point
+    /// mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn delete_gene(&mut self, num_variables: usize) {
+        if self.len() < 4 {
+            return self.mutate(num_variables);
+        }
+        let outputs = self.output_genes();
+        let body: Vec<usize> = (2..self.len() - 1).filter(|pos| !outputs.contains(pos)).collect();
+        let Some(&pos) = body.choose(&mut rng()) else {
+            return self.mutate(num_variables);
+        };
+        self.genes.remove(pos);
+        self.outputs.iter_mut().filter(|output| **output > pos).for_each(|output| *output -= 1);
+        for gene in &mut self.genes[pos..] {
+            for ptr in gene.pointers_mut() {
+                match (*ptr).cmp(&pos) {
+                    std::cmp::Ordering::Greater => *ptr -= 1,
+                    // Left for `repair` to rewire
+                    std::cmp::Ordering::Equal => *ptr = usize::MAX,
+                    std::cmp::Ordering::Less => {}
+                }
+            }
+        }
+        self.repair(num_variables);
+    }
+
+    /// Makes the chromosome valid again after genes were moved: function genes in positions that
+    /// must hold terminals become random constants or variables, and inputs the grid does not allow
+    /// are rewired to a random input it allows.
+    fn repair(&mut self, num_variables: usize) {
+        for pos in 0..self.len() {
+            let inputs = self.layout.grid.inputs(pos);
+            match self.genes[pos].type_of_gene {
+                Constant(_) | Variable(_) => {}
+                _ if self.terminal_only(pos) => self.genes[pos] = self.random_gene(pos, num_variables),
+                // The unused right input of unary genes is kept before the gene as well, see `Gene::pointers_mut`
+                _ => self.genes[pos].pointers_mut().into_iter()
+                    .filter(|ptr| !inputs.contains(ptr))
+                    .for_each(|ptr| *ptr = rng().gen_range(inputs.clone())),
+            }
+        }
+    }
+
+    /// Moves a random output to a random other gene, so that the subexpression computed there
+    /// becomes the expression of that output. A chromosome with a single gene gets a point mutation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the GP dataset.
+    pub fn mutate_output(&mut self, num_variables: usize) {
+        if self.len() < 2 {
+            return self.mutate(num_variables);
+        }
+        let mut rng = rng();
+        let mut outputs = self.output_genes();
+        let moved = rng.gen_range(0..outputs.len());
+        let pos = rng.gen_range(0..self.len() - 1);
+        outputs[moved] = if pos >= outputs[moved] { pos + 1 } else { pos };
+        self.outputs = outputs;
+    }
+}
+
+/// The coefficients that minimize the squared error of `features · coefficients` against `targets`,
+/// found by solving the normal equations. Coefficients of features that are constant or duplicate
+/// earlier features are 0.
+///
+/// # Arguments
+///
+/// * `features` - The features of every row, all of the same length.
+/// * `targets` - The target of every row.
+pub fn least_squares(features: &[Vec<f64>], targets: &[f64]) -> Vec<f64> {
+    let n = features.first().map_or(0, |row| row.len());
+    // The augmented normal equations [XᵀX | Xᵀy]
+    let mut system = vec![vec![0.0; n + 1]; n];
+    for (row, &target) in features.iter().zip(targets) {
+        for i in 0..n {
+            for j in 0..n {
+                system[i][j] += row[i] * row[j];
+            }
+            system[i][n] += row[i] * target;
+        }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, skipping columns without a usable pivot
+    let mut pivots: Vec<Option<usize>> = vec![None; n];
+    let mut next_row = 0;
+    for column in 0..n {
+        let scale = system.iter().map(|r| r[column].abs()).fold(0.0, f64::max).max(1.0);
+        let Some(pivot) = (next_row..n).max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs())) else {
+            break;
+        };
+        if system[pivot][column].is_nan() || system[pivot][column].abs() <= 1e-10 * scale {
+            continue;
+        }
+        system.swap(next_row, pivot);
+        let divisor = system[next_row][column];
+        system[next_row].iter_mut().for_each(|x| *x /= divisor);
+        let pivot_row = system[next_row].clone();
+        for (other, row) in system.iter_mut().enumerate() {
+            let factor = row[column];
+            if other != next_row && factor != 0.0 {
+                row.iter_mut().zip(&pivot_row).for_each(|(x, p)| *x -= factor * p);
+            }
+        }
+        pivots[column] = Some(next_row);
+        next_row += 1;
+    }
+    return pivots.iter().map(|pivot| pivot.map_or(0.0, |row| system[row][n])).collect();
+}
+
+/// The value one input of a gene must take for the gene to output `desired`, given the value of its
+/// other input. NaN when no value works or the operator cannot be inverted.
+///
+/// # Arguments
+///
+/// * `primitive` - The function of the gene.
+/// * `desired` - The output the gene should have.
+/// * `sibling` - The value of the other input of a binary gene, ignored by unary genes.
+/// * `on_left` - Whether the input being inverted is the left one.
+/// * `current` - The value the input has now, which picks the sign when two values work.
+fn invert(primitive: PrimitiveId, desired: f64, sibling: f64, on_left: bool, current: f64) -> f64 {
+    return match (primitive, on_left) {
+        (PrimitiveId::ADD, _) => desired - sibling,
+        (PrimitiveId::SUB, true) => desired + sibling,
+        (PrimitiveId::SUB, false) => sibling - desired,
+        (PrimitiveId::MUL, _) if sibling != 0.0 => desired / sibling,
+        (PrimitiveId::DIV, true) if sibling != 0.0 => desired * sibling,
+        (PrimitiveId::DIV, false) if desired != 0.0 => sibling / desired,
+        (PrimitiveId::MAX, _) if desired > sibling => desired,
+        (PrimitiveId::MIN, _) if desired < sibling => desired,
+        (PrimitiveId::SQUARE, _) if desired >= 0.0 => desired.sqrt().copysign(current),
+        (PrimitiveId::LOG2, _) => desired.exp2(),
+        (PrimitiveId::SIN, _) if desired.abs() <= 1.0 => desired.asin(),
+        (PrimitiveId::COS, _) if desired.abs() <= 1.0 => desired.acos(),
+        (PrimitiveId::TAN, _) => desired.atan(),
+        (PrimitiveId::EXP, _) if desired > 0.0 => desired.ln(),
+        (PrimitiveId::LOG, _) => desired.exp().copysign(current),
+        (PrimitiveId::SQRT, _) if desired >= 0.0 => (desired * desired).copysign(current),
+        (PrimitiveId::ABS, _) if desired >= 0.0 => desired.copysign(current),
+        (PrimitiveId::NEG, _) => -desired,
+        (PrimitiveId::INV, _) if desired != 0.0 => 1.0 / desired,
+        (PrimitiveId::CUBE, _) => desired.cbrt(),
+        (PrimitiveId::TANH, _) if desired.abs() < 1.0 => desired.atanh(),
+        _ => f64::NAN,
+    };
+}
+
+/// The distance between two recorded behaviors, see `Chromosome::behavioral_distance`.
+pub fn behavior_distance(a: &[f64], b: &[f64]) -> f64 {
+    let rows = a.len().min(b.len());
+    if rows == 0 {
+        return 0.0;
+    }
+    let total: f64 = a.iter().zip(b)
+        .map(|(a, b)| match (a.is_nan(), b.is_nan()) {
+            (true, true) => 0.0,
+            (false, false) if a == b => 0.0,
+            (false, false) => {
+                let difference = (a - b).abs();
+                if difference.is_finite() { difference / (1.0 + difference) } else { 1.0 }
+            }
+            _ => 1.0,
+        })
+        .sum();
+    return total / rows as f64;
+}
+
+impl Display for Chromosome {
+    ///
+    /// Formats the genes in a string and writes them to the given formatter.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - A mutable reference to a `std::fmt::Formatter` object.
+    ///
+    /// # Errors
+    ///
+    /// This function returns a `std::fmt::Result` object. It will return an
+    /// `Err` value if writing the formatted string to the formatter fails.
+    ///
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut string_builder = "".to_string();
+        for gene in self.iter() {
+            string_builder.push_str(&gene.to_string());
+            string_builder.push(' ');
+        }
+        write!(f, "{}", string_builder)
+    }
+}
+
+/// What a gene simplifies to, see `Chromosome::simplify`.
+enum Simplified {
+    Constant(f64),
+    /// A copy of the earlier gene at this position.
+    Copy(usize),
+}
+
+/// Whether an expression is written as S-expressions, that is, it starts with `(` followed by the
+/// name of a function and a space, as in `(add x0 1)`. An infix expression such as `(x0 + 1)` or
+/// `(sin(x0))` starts with a variable, or with a function that is followed by its `(`.
+pub fn is_sexpr(expression: &str) -> bool {
+    let Some(rest) = expression.trim_start().strip_prefix('(') else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    let head = &rest[..rest.find(|c: char| c.is_whitespace() || c == '(' || c == ')').unwrap_or(rest.len())];
+    let followed_by_space = rest[head.len()..].starts_with(char::is_whitespace);
+    let registry = FunctionRegistry::global();
+    return followed_by_space && registry.primitives().iter().any(|primitive| {
+        return head == primitive.name || head == primitive.operator || head == format!("if{}", primitive.operator);
+    });
+}
+
+/// Splits S-expressions into parentheses, names and numbers.
+fn sexpr_tokens(expression: &str) -> Vec<String> {
+    return expression.replace('(', " ( ").replace(')', " ) ").split_whitespace().map(String::from).collect();
+}
+
+/// The names of the variables an expression uses, in the order they first appear, see `Chromosome::parse`.
+pub fn expression_variables(expression: &str) -> Vec<String> {
+    let sexpr = is_sexpr(expression);
+    let tokens = if sexpr { sexpr_tokens(expression) } else { tokenize(expression) };
+    let mut names: Vec<String> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        // A function is followed by its inputs in prefix notation, and starts a list in an S-expression
+        let function = if sexpr { i > 0 && tokens[i - 1] == "(" } else { tokens.get(i + 1).is_some_and(|next| next == "(") };
+        if !function && !is_symbol(token) && token.parse::<f64>().is_err() && !names.contains(token) {
+            names.push(token.clone());
+        }
+    }
+    return names;
+}
+
+/// Whether a token is punctuation or an infix operator rather than a name or number.
+fn is_symbol(token: &str) -> bool {
+    return ["(", ")", ",", "+", "-", "*", "/", "^"].contains(&token);
+}
+
+/// Splits an expression into names, numbers, parentheses, commas and infix operators. The sign of
+/// the exponent of a number such as `1e-7` stays part of the number.
+fn tokenize(expression: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in expression.chars() {
+        let exponent_sign = (c == '+' || c == '-') && current.ends_with(['e', 'E'])
+            && current[..current.len() - 1].parse::<f64>().is_ok();
+        if !exponent_sign && (is_symbol(&c.to_string()) || c.is_whitespace()) {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    return tokens;
+}
+
+/// Writes an infix expression of precedence `precedence` where an operand of at least `min` is needed, in parentheses when it binds looser.
+fn parenthesize((expression, precedence): (String, u8), min: u8) -> String {
+    return if precedence < min { format!("({})", expression) } else { expression };
+}
+
+/// Writes a LaTeX expression of precedence `precedence` where an operand of at least `min` is needed, in parentheses when it binds looser.
+fn parenthesize_latex((expression, precedence): (String, u8), min: u8) -> String {
+    return if precedence < min { format!("\\left({}\\right)", expression) } else { expression };
+}
+
+/// Writes a constant as LaTeX with the precedence of a negation when it is negative.
+fn latex_constant(value: f64) -> (String, u8) {
+    let latex = match value {
+        f64::INFINITY => "\\infty".to_string(),
+        f64::NEG_INFINITY => "-\\infty".to_string(),
+        _ if value.is_nan() => "\\mathrm{NaN}".to_string(),
+        _ => format!("{}", value),
+    };
+    return (latex, if value.is_sign_negative() { NEGATION } else { ATOM });
+}
+
+/// Writes a variable name as LaTeX: a letter followed by digits, such as `x12`, with the digits
+/// as a subscript, a single character as itself, and other names in italics.
+fn latex_variable(name: &str) -> String {
+    let mut chars = name.chars();
+    let letter = chars.next().filter(|c| c.is_ascii_alphabetic());
+    let digits = chars.as_str();
+    return match letter {
+        Some(letter) if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) => format!("{}_{{{}}}", letter, digits),
+        _ if name.chars().count() == 1 => latex_escape(name),
+        _ => format!("\\mathit{{{}}}", latex_escape(name)),
+    };
+}
+
+/// Escapes the characters LaTeX gives a meaning to, so that `text` is written as it is in math mode.
+fn latex_escape(text: &str) -> String {
+    return text.chars().map(|c| match c {
+        '_' | '%' | '&' | '#' | '$' | '{' | '}' => format!("\\{}", c),
+        '^' => "\\hat{}".to_string(),
+        '\\' => "\\backslash ".to_string(),
+        _ => c.to_string(),
+    }).collect();
+}
+
+/// Parses a sum or difference of terms from `tokens`, appending the genes of the expression to
+/// `genes` with the inputs of every function before it. Infix operators bind as usual: `^` before
+/// `*` and `/`, before `+` and `-`.
+///
+/// # Returns
+///
+/// The position of the gene of the whole expression.
+fn parse_sum(tokens: &mut Peekable<IntoIter<String>>, genes: &mut Vec<Gene>, variable_names: &[String]) -> usize {
+    let mut left = parse_product(tokens, genes, variable_names);
+    while let Some(operator) = tokens.next_if(|token| token == "+" || token == "-") {
+        let right = parse_product(tokens, genes, variable_names);
+        genes.push(function_gene(if operator == "+" { "add" } else { "sub" }, &[left, right]));
+        left = genes.len() - 1;
     }
+    return left;
 }
 
-impl Display for Chromosome {
-    ///
-    /// Formats the genes in a string and writes them to the given formatter.
-    ///
-    /// # Arguments
-    ///
-    /// * `f` - A mutable reference to a `std::fmt::Formatter` object.
-    ///
-    /// # Errors
-    ///
-    /// This function returns a `std::fmt::Result` object. It will return an
-    /// `Err` value if writing the formatted string to the formatter fails.
-    ///
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut string_builder = "".to_string();
-        for gene in &self.genes {
-            string_builder.push_str(&gene.to_string());
-            string_builder.push(' ');
+/// Parses a product or quotient of factors, see `parse_sum`.
+fn parse_product(tokens: &mut Peekable<IntoIter<String>>, genes: &mut Vec<Gene>, variable_names: &[String]) -> usize {
+    let mut left = parse_factor(tokens, genes, variable_names);
+    while let Some(operator) = tokens.next_if(|token| token == "*" || token == "/") {
+        let right = parse_factor(tokens, genes, variable_names);
+        genes.push(function_gene(if operator == "*" { "mul" } else { "truediv" }, &[left, right]));
+        left = genes.len() - 1;
+    }
+    return left;
+}
+
+/// Parses a negation, or a power of an operand, see `parse_sum`. Negated constants are negative
+/// constants, and only the powers 2 and 3 can be written with `^`, as `square` and `cube`.
+fn parse_factor(tokens: &mut Peekable<IntoIter<String>>, genes: &mut Vec<Gene>, variable_names: &[String]) -> usize {
+    if tokens.next_if(|token| token == "-").is_some() {
+        let operand = parse_factor(tokens, genes, variable_names);
+        match genes[operand].type_of_gene {
+            Constant(value) if operand == genes.len() - 1 => genes[operand] = Gene::new_constant(Some(-value)),
+            _ => genes.push(function_gene("neg", &[operand])),
         }
-        write!(f, "{}", string_builder)
+        return genes.len() - 1;
+    }
+    let operand = parse_operand(tokens, genes, variable_names);
+    if tokens.next_if(|token| token == "^").is_none() {
+        return operand;
+    }
+    let power = tokens.next().expect("The expression ended after ^");
+    let function = match power.as_str() {
+        "2" => "square",
+        "3" => "cube",
+        _ => panic!("Only the powers 2 and 3 can be written with ^, not {}", power),
+    };
+    genes.push(function_gene(function, &[operand]));
+    return genes.len() - 1;
+}
+
+/// Parses a constant, a variable, a function applied to its inputs or an expression in parentheses, see `parse_sum`.
+fn parse_operand(tokens: &mut Peekable<IntoIter<String>>, genes: &mut Vec<Gene>, variable_names: &[String]) -> usize {
+    let token = tokens.next().expect("The expression ended early");
+    if token == "(" {
+        let inner = parse_sum(tokens, genes, variable_names);
+        if tokens.next().as_deref() != Some(")") {
+            panic!("A parenthesis is not closed");
+        }
+        return inner;
     }
+    if is_symbol(&token) {
+        panic!("Expected a function, variable or constant but found {}", token);
+    }
+    let gene = if tokens.next_if(|next| next == "(").is_some() {
+        let mut inputs = Vec::new();
+        loop {
+            inputs.push(parse_sum(tokens, genes, variable_names));
+            match tokens.next().as_deref() {
+                Some(",") => continue,
+                Some(")") => break,
+                Some(other) => panic!("Expected , or ) after an input of {} but found {}", token, other),
+                None => panic!("The inputs of {} are not closed", token),
+            }
+        }
+        function_gene(&token, &inputs)
+    } else {
+        match token.parse::<f64>() {
+            Ok(value) => Gene::new_constant(Some(value)),
+            Err(_) => Gene::new_variable(variable_index(&token, variable_names)),
+        }
+    };
+    genes.push(gene);
+    return genes.len() - 1;
+}
+
+/// Parses one S-expression from `tokens`, appending its genes to `genes` with the inputs of every
+/// function before it, see `Chromosome::from_sexpr`.
+///
+/// # Returns
+///
+/// The position of the gene of the whole expression.
+fn parse_sexpr(tokens: &mut Peekable<IntoIter<String>>, genes: &mut Vec<Gene>, variable_names: &[String]) -> usize {
+    let token = tokens.next().expect("The expression ended early");
+    let gene = match token.as_str() {
+        "(" => {
+            let operator = tokens.next_if(|next| next != "(" && next != ")").expect("A list must start with a function");
+            let mut inputs = Vec::new();
+            while tokens.next_if(|next| next == ")").is_none() {
+                if tokens.peek().is_none() {
+                    panic!("The inputs of {} are not closed", operator);
+                }
+                inputs.push(parse_sexpr(tokens, genes, variable_names));
+            }
+            function_gene(&operator, &inputs)
+        }
+        ")" => panic!("Unexpected )"),
+        _ => match token.parse::<f64>() {
+            Ok(value) => Gene::new_constant(Some(value)),
+            Err(_) => Gene::new_variable(variable_index(&token, variable_names)),
+        },
+    };
+    genes.push(gene);
+    return genes.len() - 1;
+}
+
+/// The gene of the function printed or named as `operator`, applied to the genes at `inputs`.
+/// Conditionals are printed as `if` followed by their comparison.
+///
+/// # Panics
+///
+/// When no primitive of the `FunctionRegistry` is printed or named as `operator` and takes that many inputs.
+fn function_gene(operator: &str, inputs: &[usize]) -> Gene {
+    let primitive = FunctionRegistry::global().find(operator, inputs.len())
+        .unwrap_or_else(|| panic!("Unknown function {} of {} inputs", operator, inputs.len()));
+    return match *inputs {
+        [left, right, yes, no] => Gene::new_conditional2(left, right, [yes, no], primitive),
+        [left] => Gene::new_unary2(left, primitive),
+        [left, right] => Gene::new_binary2(left, right, primitive),
+        _ => unreachable!("Primitives take one, two or four inputs"),
+    };
+}
+
+/// The index of the variable called `name`, or numbered by it as in `v3`.
+///
+/// # Panics
+///
+/// When there is no such variable.
+fn variable_index(name: &str, variable_names: &[String]) -> usize {
+    return variable_names.iter().position(|variable| variable == name)
+        .or_else(|| name.strip_prefix('v').and_then(|index| index.parse().ok()))
+        .unwrap_or_else(|| panic!("Unknown variable {}", name));
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use lazy_static::lazy_static;
-    use crate::functions::{add, log2, square};
-    use crate::io::read_csv;
+    use crate::functions::{and, greater_than, less_than, log, not, or, reciprocal, sqrt, xor};
+    use crate::io::{DatasetOptions, open_dataset, read_csv};
     use super::*;
 
     lazy_static! {
-        static ref ROOT: Vec<Vec<f64>> = read_csv("test.csv");
+        static ref ROOT: Vec<Vec<f64>> = read_csv(open_dataset("test.csv"), &DatasetOptions::default()).rows;
     }
 
 
@@ -572,19 +2976,886 @@ mod tests {
 
     #[test]
     fn test_single_unary_function() {
-        for func in vec![square, log2] {
-            let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(1), Gene::new_unary2(0, func)]).evaluate_fitness(&ROOT[0]);
-            assert_eq!(result, func(ROOT[0][1], -1.0).0);
+        for primitive in [PrimitiveId::SQUARE, PrimitiveId::LOG2] {
+            let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(1), Gene::new_unary2(0, primitive)]).evaluate_fitness(&ROOT[0]);
+            assert_eq!(result, primitive.operation()(ROOT[0][1], -1.0).0);
+        }
+    }
+
+    #[test]
+    fn test_named_variables() {
+        let names = vec!["heart_rate".to_string(), "age".to_string()];
+        let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Option::from(3.2)), Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::LOG2)]);
+        assert_eq!(result.function_string_with_names(&names), "log2(add(heart_rate, 3.2))");
+        assert_eq!(result.function_string(), "log2(add(v0, 3.2))");
+    }
+
+    #[test]
+    fn test_active_genes() {
+        // Gene 1 is not used by the output gene
+        let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Option::from(2.0)), Gene::new_binary2(0, 2, PrimitiveId::ADD)]);
+        assert_eq!(result.active_genes(), vec![0, 2, 3]);
+    }
+
+    #[test]
+    /// Ensures that the hash depends on the expression and not on the layout of the genes
+    fn test_structure_hash() {
+        let a = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let b = Chromosome::new_from_genes_array(vec![Gene::new_variable(1), Gene::new_constant(Option::from(2.0)), Gene::new_variable(0), Gene::new_binary2(2, 0, PrimitiveId::ADD)]);
+        let swapped = Chromosome::new_from_genes_array(vec![Gene::new_variable(1), Gene::new_variable(0), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let squared = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::SQUARE)]);
+        assert_eq!(a.structure_hash(), b.structure_hash());
+        assert_ne!(a.structure_hash(), swapped.structure_hash());
+        assert_ne!(a.structure_hash(), squared.structure_hash());
+    }
+
+    #[test]
+    /// Ensures that perturbed rates stay valid probabilities
+    fn test_perturb_rates() {
+        let mut rates = OperatorRates { mutation: 1.0, crossover: 0.001 };
+        for _ in 0..100 {
+            rates.perturb();
+            assert!((0.001..=1.0).contains(&rates.mutation));
+            assert!((0.001..=1.0).contains(&rates.crossover));
+        }
+    }
+
+    #[test]
+    fn test_variables_used() {
+        // Variable 1 is inactive and variable 0 is used twice
+        let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_variable(2), Gene::new_binary2(0, 2, PrimitiveId::ADD), Gene::new_binary2(3, 0, PrimitiveId::ADD)]);
+        assert_eq!(result.variables_used(), vec![0, 2]);
+    }
+
+    #[test]
+    /// Ensures that the targeted mutations only change the part of the genes they target
+    fn test_targeted_mutations() {
+        let original = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Option::from(2.0)), Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::SQUARE)]);
+        for _ in 0..20 {
+            let mut result = original.clone();
+            result.mutate_with(MutationOperator::Function, 0.1, None, 1);
+            assert!(result.genes.iter().zip(&original.genes).all(|(a, b)| a.left_ptr == b.left_ptr && a.right_ptr == b.right_ptr));
+
+            let mut result = original.clone();
+            result.mutate_with(MutationOperator::Pointer, 0.1, None, 1);
+            assert!(result.genes.iter().zip(&original.genes).all(|(a, b)| a.get_operator() == b.get_operator()));
+            assert!(result.genes.iter().enumerate().skip(2).all(|(pos, gene)| gene.left_ptr < pos && gene.right_ptr < pos));
+
+            let mut result = original.clone();
+            result.mutate_with(MutationOperator::Constant, 0.1, None, 1);
+            assert!(result.genes.iter().zip(&original.genes).enumerate().all(|(pos, (a, b))| pos == 1 || a.same_as(b)));
+            assert!(matches!(result.genes[1].type_of_gene, Constant(_)));
+            assert_eq!(result.mutation_operator, Some(MutationOperator::Constant));
+
+            let mut result = original.clone();
+            result.mutate_with(MutationOperator::Output, 0.1, None, 1);
+            assert!(result.output() < 3);
+            assert!(result.genes.iter().zip(&original.genes).all(|(a, b)| a.same_as(b)));
+        }
+    }
+
+    #[test]
+    /// Ensures that the crossovers only exchange genes between the same positions, and that a
+    /// subgraph transplant brings the whole subgraph along
+    fn test_crossovers() {
+        let one = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::SQUARE)]);
+        let two = Chromosome::new_from_genes_array(vec![Gene::new_constant(Option::from(1.0)), Gene::new_constant(Option::from(2.0)), Gene::new_binary2(1, 0, PrimitiveId::MUL), Gene::new_binary2(2, 2, PrimitiveId::SUB)]);
+        let cross = |crossover: &dyn Fn(&mut Chromosome, &mut Chromosome)| {
+            let (mut child_one, mut child_two) = (one.clone(), two.clone());
+            crossover(&mut child_one, &mut child_two);
+            return (child_one.function_string(), child_two.function_string());
+        };
+        // One-point swaps the tails from position 3
+        assert_eq!(cross(&|a, b| a.cross_with(b, Some(3))), ("sub(add(v0, v1), add(v0, v1))".to_string(), "square(mul(2, 1))".to_string()));
+        // Two-point swaps the genes between two positions, here 1 and 2
+        assert_eq!(cross(&|a, b| a.swap_genes(b, 1..3)), ("square(mul(2, v0))".to_string(), "sub(add(1, v1), add(1, v1))".to_string()));
+        // Uniform swaps any positions, here only the first
+        assert_eq!(cross(&|a, b| a.swap_genes(b, [0])), ("square(add(1, v1))".to_string(), "sub(mul(2, v0), mul(2, v0))".to_string()));
+        // The first child gets the subgraph of the second at gene 2, and the second child the whole first parent
+        assert_eq!(cross(&|a, b| a.exchange_subgraphs(b, 2, 3)), ("square(mul(2, 1))".to_string(), "square(add(v0, v1))".to_string()));
+
+        let (mut child_one, mut child_two) = (one.clone(), two.clone());
+        child_one.cross_subgraph(&mut child_two);
+        // Whatever the roots, each child's subgraph at its root is intact, so the output is a valid expression
+        assert!(child_one.active_genes().iter().all(|&pos| child_one.genes[pos].left_ptr <= pos));
+        assert_eq!(one.subgraph(2), vec![0, 1, 2]);
+        assert_eq!(two.subgraph(3), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    /// Ensures that the recorded semantics end with the behavior, and that semantic crossover
+    /// exchanges the subgraph at the gene where the parents differ the most or the least
+    fn test_semantic_crossover() {
+        let probe = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        // The parents agree on gene 2 (x0 + x1 against x1 + x0), differ less on gene 3 (x0 + x0
+        // against max(x0, x0)) than on gene 4 (x1 - x0 against x1 * x1)
+        let one = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD),
+                                                        Gene::new_binary2(0, 0, PrimitiveId::ADD), Gene::new_binary2(1, 0, PrimitiveId::SUB), Gene::new_binary2(3, 4, PrimitiveId::ADD)]);
+        let two = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(1, 0, PrimitiveId::ADD),
+                                                        Gene::new_binary2(0, 0, PrimitiveId::MAX), Gene::new_binary2(1, 1, PrimitiveId::MUL), Gene::new_binary2(3, 4, PrimitiveId::ADD)]);
+        let (mut one, mut two) = (one, two);
+        for chromosome in [&mut one, &mut two] {
+            chromosome.record_semantics(&probe);
+        }
+        let mut behavior = one.clone();
+        behavior.record_behavior(&probe);
+        assert_eq!(one.behavior, behavior.behavior);
+        assert_eq!(one.semantics.len(), one.len() * probe.rows.len());
+
+        let (mut child_one, mut child_two) = (one.clone(), two.clone());
+        child_one.cross_semantic(&mut child_two, true);
+        assert!(child_one.genes[4].same_as(&two.genes[4]));
+        assert!(child_one.genes[3].same_as(&one.genes[3]));
+        let (mut child_one, mut child_two) = (one.clone(), two.clone());
+        child_one.cross_semantic(&mut child_two, false);
+        assert!(child_one.genes[3].same_as(&two.genes[3]));
+        assert!(child_one.genes[4].same_as(&one.genes[4]));
+    }
+
+    #[test]
+    /// Ensures that inverting a gene gives the input that produces the desired output
+    fn test_invert() {
+        assert_eq!(invert(PrimitiveId::ADD, 5.0, 2.0, true, 0.0), 3.0);
+        assert_eq!(invert(PrimitiveId::SUB, 5.0, 2.0, true, 0.0), 7.0);
+        assert_eq!(invert(PrimitiveId::SUB, 5.0, 2.0, false, 0.0), -3.0);
+        assert_eq!(invert(PrimitiveId::DIV, 5.0, 2.0, false, 0.0), 0.4);
+        assert_eq!(invert(PrimitiveId::SQUARE, 9.0, 0.0, true, -1.0), -3.0);
+        assert_eq!(invert(PrimitiveId::SQRT, 3.0, 0.0, true, -1.0), -9.0);
+        assert_eq!(invert(PrimitiveId::CUBE, -8.0, 0.0, true, 0.0), -2.0);
+        assert!(invert(PrimitiveId::SIN, 2.0, 0.0, true, 0.0).is_nan());
+        assert!(invert(PrimitiveId::MUL, 5.0, 0.0, true, 0.0).is_nan());
+        assert!(invert(PrimitiveId::MAX, 1.0, 2.0, true, 0.0).is_nan());
+    }
+
+    #[test]
+    /// Ensures that semantic backpropagation can repair an expression by copying the earlier gene it needs
+    fn test_backpropagate() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let probe = dataset.with_rows(dataset.rows.iter().map(|row| [&row[..3], &[row[0] + row[1]]].concat()).collect());
+        let targets: Vec<f64> = probe.rows.iter().map(|row| row[3]).collect();
+        // x0 + 5, which becomes x0 + x1 when the constant is replaced with a copy of x1
+        let mut original = Chromosome::new_from_genes_array(vec![Gene::new_variable(2), Gene::new_variable(1), Gene::new_constant(Option::from(5.0)), Gene::new_binary2(0, 2, PrimitiveId::ADD)]);
+        // The semantics are recorded for x2 + 5, then the first gene changes as crossover would change it
+        original.record_semantics(&probe);
+        original.genes[0] = Gene::new_variable(0);
+        let repaired = (0..100).any(|_| {
+            let mut result = original.clone();
+            result.mutate_with(MutationOperator::Backpropagation, 0.1, Some(&probe), 3);
+            result.record_behavior(&probe);
+            return result.behavior == targets;
+        });
+        assert!(repaired);
+    }
+
+    #[test]
+    /// Ensures that headless-chicken macro-mutation keeps the chromosome valid
+    fn test_headless_chicken() {
+        for _ in 0..50 {
+            let mut result = Chromosome::new_x(10, 3);
+            result.headless_chicken(3);
+            assert_eq!(result.len(), 10);
+            assert!(matches!(result.genes[0].type_of_gene, Constant(_) | Variable(_)));
+            assert!(result.genes.iter().enumerate().all(|(pos, gene)| matches!(gene.type_of_gene, Constant(_) | Variable(_)) || (gene.left_ptr < pos && gene.right_ptr < pos)));
+        }
+    }
+
+    #[test]
+    /// Ensures that moving a block keeps the expression when no input has to be rewired, and that
+    /// duplicated blocks compute the same subexpressions as the original
+    fn test_duplication_and_transposition() {
+        let probe = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut original = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Option::from(2.0)),
+                                                                 Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(3, PrimitiveId::SQUARE), Gene::new_binary2(4, 2, PrimitiveId::MUL),
+                                                                 Gene::new_constant(Option::from(3.0)), Gene::new_constant(Option::from(4.0)), Gene::new_binary2(5, 2, PrimitiveId::ADD)]);
+        original.record_semantics(&probe);
+
+        // The constant 2 moves after the square, and the genes that use it follow it
+        let mut result = original.clone();
+        result.transpose_segment(2, 1, 4, 3);
+        assert!(result.genes[4].same_as(&original.genes[2]));
+        result.record_semantics(&probe);
+        assert_eq!(result.behavior, original.behavior);
+
+        // x0 + x1 and its square are copied over the constants 3 and 4
+        let mut result = original.clone();
+        result.duplicate_segment(3, 2, 6, 3);
+        assert!(result.genes[6].same_as(&original.genes[3]));
+        assert_eq!(result.genes[7].left_ptr, 6);
+        result.record_semantics(&probe);
+        assert_eq!(result.semantics[7 * probe.rows.len()..8 * probe.rows.len()], original.semantics[4 * probe.rows.len()..5 * probe.rows.len()]);
+
+        for _ in 0..50 {
+            let mut result = original.clone();
+            result.duplicate_block(3);
+            result.transpose_block(3);
+            assert!(result.genes.iter().enumerate().all(|(pos, gene)| matches!(gene.type_of_gene, Constant(_) | Variable(_)) || (gene.left_ptr < pos && gene.right_ptr < pos)));
+        }
+    }
+
+    #[test]
+    /// Ensures that inserting a gene keeps the expression, and that deleting genes keeps the chromosome valid
+    fn test_insertion_and_deletion() {
+        let probe = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut original = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Option::from(2.0)),
+                                                                 Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(3, PrimitiveId::SQUARE), Gene::new_binary2(4, 2, PrimitiveId::MUL)]);
+        original.record_behavior(&probe);
+        let mut result = original.clone();
+        for _ in 0..10 {
+            result.mutate_with(MutationOperator::Insertion, 0.1, None, 3);
+        }
+        assert_eq!(result.len(), original.len() + 10);
+        result.record_behavior(&probe);
+        assert_eq!(result.behavior, original.behavior);
+
+        for _ in 0..13 {
+            result.mutate_with(MutationOperator::Deletion, 0.1, None, 3);
+            assert!(result.genes.iter().enumerate().all(|(pos, gene)| matches!(gene.type_of_gene, Constant(_) | Variable(_)) || (gene.left_ptr < pos && gene.right_ptr < pos)));
+        }
+        assert_eq!(result.len(), 3);
+
+        // Chromosomes of different lengths exchange their tails
+        let (mut child_one, mut child_two) = (original.clone(), Chromosome::new_x(10, 3));
+        child_one.cross_with(&mut child_two, Some(4));
+        assert_eq!((child_one.len(), child_two.len()), (10, 6));
+    }
+
+    #[test]
+    /// Ensures that the output gene decides the expression, and that it follows its gene when genes are inserted and deleted
+    fn test_output_gene() {
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Option::from(2.0)),
+                                                                   Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(3, PrimitiveId::SQUARE), Gene::new_binary2(4, 2, PrimitiveId::MUL)]);
+        assert_eq!(chromosome.active_genes(), vec![0, 1, 2, 3, 4, 5]);
+        chromosome.outputs = vec![3];
+        assert_eq!(chromosome.active_genes(), vec![0, 1, 3]);
+        assert_eq!(chromosome.depth(), 2);
+        assert_eq!(chromosome.evaluate_fitness(&vec![2.0, 3.0]), 5.0);
+        let expression = chromosome.function_string_with_names(&[]);
+        for _ in 0..10 {
+            chromosome.insert_gene(2);
+            chromosome.delete_gene(2);
+            assert_eq!(chromosome.function_string_with_names(&[]), expression);
+        }
+    }
+
+    #[test]
+    /// Ensures that the error of several outputs is averaged over the targets, and that the outputs follow their genes
+    fn test_multiple_outputs() {
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Option::from(2.0)),
+                                                                   Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(3, PrimitiveId::SQUARE), Gene::new_binary2(4, 2, PrimitiveId::MUL)]);
+        chromosome.set_outputs(2);
+        assert_eq!(chromosome.outputs, vec![4, 5]);
+        chromosome.outputs = vec![3, 2];
+        assert_eq!(chromosome.active_genes(), vec![0, 1, 2, 3]);
+        assert_eq!(chromosome.function_string().matches("; ").count(), 1);
+        // The outputs are 5 and 2, so the squared errors are 1 and 4
+        assert_eq!(chromosome.row_loss(&vec![2.0, 3.0, 4.0, 4.0], &Mse), 2.5);
+
+        let hash = chromosome.structure_hash();
+        for _ in 0..10 {
+            chromosome.insert_gene(2);
+            chromosome.delete_gene(2);
+        }
+        assert_eq!(chromosome.outputs.len(), 2);
+        assert_eq!(chromosome.row_loss(&vec![2.0, 3.0, 4.0, 4.0], &Mse), 2.5);
+        assert_eq!(chromosome.structure_hash(), hash);
+    }
+
+    #[test]
+    /// Ensures that least squares recovers exact coefficients and ignores a duplicated feature
+    fn test_least_squares() {
+        let features: Vec<Vec<f64>> = (0..6).map(|i| vec![1.0, i as f64, (i * i) as f64, i as f64]).collect();
+        let targets: Vec<f64> = (0..6).map(|i| 3.0 - 2.0 * i as f64 + 0.5 * (i * i) as f64).collect();
+        let coefficients = least_squares(&features, &targets);
+        assert_eq!(coefficients[3], 0.0);
+        for (coefficient, expected) in coefficients.iter().zip([3.0, -2.0, 0.5]) {
+            assert!((coefficient - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    /// Ensures that least squares recovers the bias and weights that combine the output genes
+    /// exactly, and that rows count as much as their sample weight
+    fn test_fit_weights() {
+        let dataset = read_csv("x0,x1,y\n0,0,1\n1,0,3\n0,1,-2\n2,1,2\n3,2,1\n".as_bytes(), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1)]);
+        chromosome.outputs = vec![0, 1];
+        chromosome.weights = Some(vec![0.0; 3]);
+        chromosome.fit_weights(&dataset);
+        let weights = chromosome.weights.clone().unwrap();
+        assert!(weights.iter().zip([1.0, 2.0, -3.0]).all(|(weight, expected)| (weight - expected).abs() < 1e-9), "{:?}", weights);
+        assert!(chromosome.evaluate_fitness_error(&dataset) < 1e-12);
+
+        // The last row is off the line y = 1 + 2 * x0, but its weight of 0 leaves it out of the fit
+        let options = DatasetOptions { weight_column: Some("w".to_string()), ..DatasetOptions::default() };
+        let dataset = read_csv("x0,w,y\n0,1,1\n1,2,3\n2,1,5\n3,0,100\n".as_bytes(), &options);
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0)]);
+        chromosome.outputs = vec![0];
+        chromosome.weights = Some(vec![0.0; 2]);
+        chromosome.fit_weights(&dataset);
+        let weights = chromosome.weights.unwrap();
+        assert!((weights[0] - 1.0).abs() < 1e-9 && (weights[1] - 2.0).abs() < 1e-9, "{:?}", weights);
+    }
+
+    #[test]
+    /// Ensures that a multi-gene chromosome fits the weights of its roots when it is evaluated
+    fn test_multigene() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let layout = Arc::new(Layout { multigene: true, outputs: 2, ..Layout::default() });
+        let mut chromosome = Chromosome::new_random(6, 3, &layout);
+        assert_eq!(chromosome.weights, Some(vec![0.0; 3]));
+        chromosome.genes = vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_variable(2),
+                                Gene::new_binary2(0, 1, PrimitiveId::MUL), Gene::new_unary2(2, PrimitiveId::SQUARE), Gene::new_binary2(0, 2, PrimitiveId::ADD)];
+        chromosome.outputs = vec![3, 4];
+        chromosome.evaluate_fitness_error(&dataset);
+        let mut linear = chromosome.clone();
+        linear.outputs = vec![0, 1, 2];
+        linear.weights = Some(Vec::new());
+        linear.evaluate_fitness_error(&dataset);
+        // Adding roots can only lower the training error
+        let mut wider = chromosome.clone();
+        wider.outputs = vec![0, 1, 2, 3, 4];
+        wider.evaluate_fitness_error(&dataset);
+        assert!(wider.fitness_value <= chromosome.fitness_value + 1e-9 && wider.fitness_value <= linear.fitness_value + 1e-9);
+        assert_eq!(wider.weights.as_ref().unwrap().len(), 6);
+        assert!(wider.function_string().starts_with("add(add("));
+
+        // Linear scaling never does worse than the unscaled output
+        let mut scaled = Chromosome::new_random(6, 3, &Arc::new(Layout { multigene: true, ..Layout::default() }));
+        scaled.genes = chromosome.genes.clone();
+        let mut unscaled = scaled.clone();
+        unscaled.outputs = Vec::new();
+        unscaled.weights = None;
+        assert_eq!(scaled.outputs, vec![5]);
+        assert!(scaled.evaluate_fitness_error(&dataset) <= unscaled.evaluate_fitness_error(&dataset));
+    }
+
+    #[test]
+    /// Ensures that every mutation keeps the inputs of the genes within the grid
+    fn test_grid() {
+        let grid = Grid { rows: 3, levels_back: Some(2) };
+        assert_eq!(grid.inputs(2), 0..0);
+        assert_eq!(grid.inputs(5), 0..3);
+        assert_eq!(grid.inputs(11), 3..9);
+        assert_eq!(Grid::default().inputs(7), 0..7);
+
+        let probe = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        for grid in [Grid { rows: 1, levels_back: Some(3) }, grid] {
+            let mut chromosome = Chromosome::new_x(30, 3);
+            Arc::make_mut(&mut chromosome.layout).grid = grid;
+            for i in 0..500 {
+                let operator = MutationOperator::ALL[i % MutationOperator::ALL.len()];
+                chromosome.record_semantics(&probe);
+                chromosome.mutate_with(operator, 0.1, Some(&probe), 3);
+                chromosome.mutate(3);
+                assert!(chromosome.genes.iter().enumerate().all(|(pos, gene)| gene.inputs().iter().all(|input| grid.inputs(pos).contains(input))), "{:?} broke the grid", operator);
+            }
+        }
+    }
+
+    #[test]
+    /// Ensures that new and mutated constants are drawn from the configured distribution
+    fn test_constant_distribution() {
+        for constants in [ConstantDistribution::Uniform { low: -200.0, high: -100.0 }, ConstantDistribution::LogUniform { low: 100.0, high: 1000.0 }] {
+            let range = match constants {
+                ConstantDistribution::Uniform { low, high } | ConstantDistribution::LogUniform { low, high } => low..high,
+                ConstantDistribution::Normal { .. } => unreachable!(),
+            };
+            let layout = Arc::new(Layout { constants, ..Layout::default() });
+            let mut chromosome = Chromosome::new_random(30, 3, &layout);
+            for _ in 0..200 {
+                chromosome.mutate_with(MutationOperator::Constant, 0.1, None, 3);
+                chromosome.mutate(3);
+                assert!(chromosome.genes.iter().all(|gene| match gene.type_of_gene {
+                    Constant(value) => range.contains(&value),
+                    _ => true,
+                }), "{:?} drew a constant outside of it", constants);
+            }
+        }
+    }
+
+    #[test]
+    /// Ensures that integer and rational constants stay in their domain however they are changed
+    fn test_constant_domain() {
+        let rational = ConstantDomain::Rational { max_denominator: 4 };
+        assert_eq!(ConstantDomain::Integer.round(2.6), 3.0);
+        assert_eq!(rational.round(0.27), 0.25);
+        assert_eq!(rational.round(0.32), 1.0 / 3.0);
+        assert_eq!(ConstantDomain::Integer.nudge(3.0, 0.01), 4.0);
+        assert_eq!(ConstantDomain::Integer.nudge(3.0, -2.2), 1.0);
+        assert_eq!(rational.nudge(0.5, -0.001), 0.25);
+
+        let layout = Arc::new(Layout { constants: ConstantDistribution::Uniform { low: -10.0, high: 10.0 }, constant_domain: ConstantDomain::Integer, ..Layout::default() });
+        let mut chromosome = Chromosome::new_random(20, 3, &layout);
+        for i in 0..300 {
+            let operator = [MutationOperator::Constant, MutationOperator::Gaussian, MutationOperator::Point][i % 3];
+            chromosome.mutate_with(operator, 0.1, None, 3);
+            assert!(chromosome.genes.iter().all(|gene| match gene.type_of_gene {
+                Constant(value) => value == value.round(),
+                _ => true,
+            }), "{:?} made a constant fractional", operator);
+        }
+    }
+
+    #[test]
+    /// Ensures that a Gaussian perturbation only nudges an active constant
+    fn test_perturb_constant() {
+        // The constant 3 is inactive
+        let original = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Option::from(2.0)), Gene::new_constant(Option::from(3.0)), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let mut result = original.clone();
+        result.perturb_constant(0.01, 1);
+        match result.genes[1].type_of_gene {
+            Constant(value) => assert!(value != 2.0 && (value - 2.0).abs() < 0.1),
+            _ => panic!("The constant gene changed type"),
+        }
+        assert!(result.genes[2].same_as(&original.genes[2]));
+    }
+
+    #[test]
+    /// Ensures that the depth follows the longest chain of inputs and that limits are checked against it
+    fn test_depth() {
+        let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_unary2(0, PrimitiveId::SQUARE), Gene::new_constant(Option::from(2.0)), Gene::new_binary2(1, 2, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::LOG2)]);
+        assert_eq!(result.depth(), 2);
+        let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_unary2(0, PrimitiveId::SQUARE), Gene::new_constant(Option::from(2.0)), Gene::new_binary2(1, 2, PrimitiveId::ADD)]);
+        assert_eq!(result.depth(), 3);
+        assert!(result.within(&ComplexityLimits { max_active_genes: Some(4), max_depth: Some(3), max_genes: None }));
+        assert!(!result.within(&ComplexityLimits { max_active_genes: Some(3), max_depth: None, max_genes: None }));
+        assert!(!result.within(&ComplexityLimits { max_active_genes: None, max_depth: Some(2), max_genes: None }));
+        assert!(!result.within(&ComplexityLimits { max_active_genes: None, max_depth: None, max_genes: Some(3) }));
+
+        let complexity = result.complexity();
+        assert_eq!((complexity.active_genes, complexity.depth), (4, 3));
+        assert_eq!(complexity.to_string(), "4 active genes, depth 3: add 1, square 1");
+    }
+
+    #[test]
+    /// Ensures that a conditional gene takes the branch its comparison selects, and that rewiring
+    /// and moving genes keep all four of its inputs before it
+    fn test_conditional() {
+        let probe = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut original = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Option::from(2.0)),
+                                                                 Gene::new_conditional2(0, 1, [0, 2], PrimitiveId::IFLT), Gene::new_unary2(3, PrimitiveId::SQUARE)]);
+        assert_eq!(original.function_string(), "square(iflt(v0, v1, v0, 2))");
+        assert_eq!(original.active_genes(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(original.depth(), 3);
+        original.record_semantics(&probe);
+        for (row, values) in probe.rows.iter().enumerate() {
+            let expected = if values[0] < values[1] { values[0] } else { 2.0 };
+            assert_eq!(original.evaluate_fitness(values), expected * expected);
+            assert_eq!(original.semantics[4 * probe.rows.len() + row], expected * expected);
+        }
+
+        for i in 0..300 {
+            let mut result = original.clone();
+            let operator = [MutationOperator::Pointer, MutationOperator::Transposition, MutationOperator::Duplication, MutationOperator::Insertion, MutationOperator::Deletion][i % 5];
+            result.mutate_with(operator, 0.1, None, 3);
+            assert!(result.genes.iter().enumerate().all(|(pos, gene)| gene.inputs().iter().all(|&input| input < pos)), "{:?} left an input after its gene", operator);
+        }
+    }
+
+    #[test]
+    /// Ensures that the genotypic distance counts the differing positions
+    fn test_genotypic_distance() {
+        let a = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let b = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(2), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let c = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1)]);
+        assert_eq!(a.genotypic_distance(&a), 0.0);
+        assert_eq!(a.genotypic_distance(&b), 1.0 / 3.0);
+        assert_eq!(a.genotypic_distance(&c), 1.0 / 3.0);
+    }
+
+    #[test]
+    /// Ensures that the behavioral distance compares the outputs on the probe rows
+    fn test_behavioral_distance() {
+        let probe = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut a = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let mut b = Chromosome::new_from_genes_array(vec![Gene::new_variable(1), Gene::new_variable(0), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let mut c = Chromosome::new_from_genes_array(vec![Gene::new_constant(Option::from(1.0)), Gene::new_variable(0), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        for chromosome in [&mut a, &mut b, &mut c] {
+            chromosome.record_behavior(&probe);
+        }
+        // x0 + x1 and x1 + x0 behave the same although their genes differ
+        assert_eq!(a.distance(&b, Distance::Behavioral), 0.0);
+        assert!(a.distance(&b, Distance::Genotypic) > 0.0);
+        assert!(a.distance(&c, Distance::Behavioral) > 0.0);
+        assert!(a.distance(&c, Distance::Behavioral) < 1.0);
+    }
+
+    #[test]
+    /// Ensures that the per-row errors add up to the MSE
+    fn test_case_errors() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut result = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let fitness = result.evaluate_fitness_cases(&dataset);
+        assert_eq!(result.case_errors.len(), dataset.rows.len());
+        assert_eq!(fitness, result.error(&dataset));
+        assert_eq!(result.case_errors[0], (ROOT[0][0] + ROOT[0][1] - ROOT[0][3]).powi(2));
+    }
+
+    #[test]
+    /// Ensures that the protected unary functions stay finite where their unprotected forms do not
+    fn test_protected_unary_functions() {
+        assert_eq!(log(0.0, 0.0).0, 0.0);
+        assert_eq!(log(-std::f64::consts::E, 0.0).0, 1.0);
+        assert_eq!(sqrt(-4.0, 0.0).0, 2.0);
+        assert_eq!(reciprocal(0.0, 0.0).0, f64::MAX);
+        assert_eq!(reciprocal(-4.0, 0.0).0, -0.25);
+        for primitive in [PrimitiveId::SIN, PrimitiveId::COS, PrimitiveId::TAN, PrimitiveId::EXP, PrimitiveId::LOG, PrimitiveId::SQRT, PrimitiveId::ABS,
+                          PrimitiveId::NEG, PrimitiveId::INV, PrimitiveId::CUBE, PrimitiveId::TANH, PrimitiveId::FLOOR] {
+            let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_unary2(0, primitive)]);
+            assert_eq!(result.evaluate_fitness(&ROOT[0]), primitive.operation()(ROOT[0][0], 0.0).0);
+        }
+    }
+
+    #[test]
+    /// Ensures that every protection policy is followed where division, the logarithms and sqrt are
+    /// undefined, and that NaN outputs make the fitness invalid
+    fn test_protection() {
+        let cases = [(Protection::One, [1.0, 1.0, 1.0, 1.0, 1.0]), (Protection::Numerator, [-2.0, 1.0, -2.0, -2.0, -2.0]),
+                     (Protection::Standard, [-f64::MAX, f64::MAX, 2f64.ln(), 2f64.sqrt(), 1.0])];
+        for (protection, expected) in cases {
+            assert_eq!(protection.protect(PrimitiveId::DIV)(-2.0, 0.0).0, expected[0], "{:?}", protection);
+            assert_eq!(protection.protect(PrimitiveId::INV)(0.0, 0.0).0, expected[1], "{:?}", protection);
+            assert_eq!(protection.protect(PrimitiveId::LOG)(-2.0, 0.0).0, expected[2], "{:?}", protection);
+            assert_eq!(protection.protect(PrimitiveId::SQRT)(-2.0, 0.0).0, expected[3], "{:?}", protection);
+            assert_eq!(protection.protect(PrimitiveId::LOG2)(-2.0, 0.0).0, expected[4], "{:?}", protection);
+            assert_eq!(protection.protect(PrimitiveId::LOG2)(8.0, 0.0), (3.0, "log2".to_string()));
+            assert_eq!(protection.protect(PrimitiveId::DIV)(3.0, 2.0), (1.5, "truediv".to_string()));
+        }
+        assert!(Protection::Nan.protect(PrimitiveId::SQRT)(-2.0, 0.0).0.is_nan());
+        assert!(Protection::Nan.protect(PrimitiveId::LOG2)(0.0, 0.0).0.is_nan());
+        assert_eq!(Protection::Standard.protect(PrimitiveId::LOG2)(0.0, 0.0).0, 0.0);
+
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_constant(Some(-1.0)), Gene::new_variable(0),
+                                                                   Gene::new_binary2(1, 0, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::LOG).protected(Protection::Nan)]);
+        assert_eq!(chromosome.evaluate_fitness_error(&dataset), f64::MAX);
+    }
+
+    #[test]
+    /// Ensures that rows with NaN or infinite outputs either invalidate the chromosome or are left out
+    /// and penalized, and that the error is never NaN
+    fn test_invalid_rows() {
+        let unweighted = |errors: &[f64]| errors.iter().map(|&error| (error, 1.0)).collect::<Vec<_>>();
+        let errors = unweighted(&[1.0, f64::NAN, 3.0, f64::INFINITY]);
+        assert_eq!(InvalidRows::Worst.weighted_mean(errors.clone()), f64::MAX);
+        assert_eq!(InvalidRows::Penalize(2.0).weighted_mean(errors), 4.0);
+        assert_eq!(InvalidRows::Penalize(2.0).weighted_mean(unweighted(&[f64::NAN, f64::NAN])), f64::MAX);
+        assert_eq!(InvalidRows::Worst.weighted_mean(unweighted(&[1.0, 3.0])), 2.0);
+        // Weighted, and an invalid row of weight 0 does not count
+        assert_eq!(InvalidRows::Worst.weighted_mean([(1.0, 3.0), (5.0, 1.0), (f64::NAN, 0.0)]), 2.0);
+
+        // log(x0 - 1) is NaN where x0 is 0.5 or 1
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_constant(Some(-1.0)), Gene::new_variable(0),
+                                                                   Gene::new_binary2(1, 0, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::LOG).protected(Protection::Nan)]);
+        Arc::make_mut(&mut chromosome.layout).invalid_rows = InvalidRows::Penalize(1.0);
+        let valid: Vec<f64> = dataset.rows.iter().filter(|row| row[0] > 1.0).map(|row| ((row[0] - 1.0).ln() - row[3]).powi(2)).collect();
+        let expected = valid.iter().sum::<f64>() / 6.0 * 1.25;
+        assert!((chromosome.evaluate_fitness_error(&dataset) - expected).abs() < 1e-9);
+        assert!((chromosome.evaluate_fitness_cases(&dataset) - expected).abs() < 1e-9);
+        assert!((chromosome.error(&dataset) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    /// Ensures that a chosen function set is the only source of functions, whatever their arities,
+    /// and that its functions are drawn in proportion to their weights
+    fn test_function_selection() {
+        let functions = FunctionSet::from_names(&["add", "sin", "iflt"]);
+        assert_eq!(functions.functions(2).iter().map(|function| function(1.0, 2.0)).collect::<Vec<_>>(), vec![add(1.0, 2.0)]);
+        assert_eq!(functions.functions(1).len(), 1);
+        assert_eq!(FunctionSet::from_names(&["sub"]).functions(1).len(), 0);
+
+        let layout = Arc::new(Layout { function_set: functions, ..Layout::default() });
+        let mut chromosome = Chromosome::new_random(30, 3, &layout);
+        for i in 0..300 {
+            let operator = [MutationOperator::Point, MutationOperator::Function, MutationOperator::Insertion][i % 3];
+            chromosome.mutate_with(operator, 0.1, None, 3);
+            assert!(chromosome.genes.iter().all(|gene| matches!(gene.type_of_gene, Constant(_) | Variable(_)) || ["add", "sin", "lt"].contains(&gene.get_operator().as_str())),
+                    "{:?} used a function outside the set", operator);
+        }
+        let weighted = FunctionSet::from_weights(&[("add", 3.0), ("mul", 3.0), ("min", 1.0), ("max", 1.0)]);
+        let draws: Vec<PrimitiveId> = (0..4000).map(|_| weighted.random_of_arity(2).unwrap()).collect();
+        let count = |primitive: PrimitiveId| draws.iter().filter(|&&draw| draw == primitive).count();
+        assert!(count(PrimitiveId::ADD) > 2 * count(PrimitiveId::MIN) && count(PrimitiveId::MUL) > 2 * count(PrimitiveId::MAX), "add and mul are not drawn three times as often");
+        let mut binary_only = Chromosome::new_random(30, 3, &Arc::new(Layout { function_set: FunctionSet::from_names(&["mul"]), ..Layout::default() }));
+        (0..100).for_each(|_| binary_only.mutate(3));
+        assert!(binary_only.genes.iter().all(|gene| !matches!(gene.type_of_gene, Unary | Conditional)));
+    }
+
+    #[test]
+    /// Ensures that with strong typing every input of every gene has the type its function takes,
+    /// through mutation and crossover
+    fn test_typed() {
+        let layout = Arc::new(Layout { function_set: FunctionSet::from_names(&["add", "mul", "and", "or", "not", "lt"]), typed: true, ..Layout::default() });
+        let well_typed = |chromosome: &Chromosome| chromosome.genes.iter()
+            .all(|gene| gene.inputs().into_iter().zip(gene.input_types()).all(|(input, input_type)| chromosome.genes[input].output_type() == input_type));
+        let (mut a, mut b) = (Chromosome::new_random(30, 3, &layout), Chromosome::new_random(30, 3, &layout));
+        for i in 0..300 {
+            let operator = [MutationOperator::Point, MutationOperator::Pointer, MutationOperator::Function, MutationOperator::Insertion][i % 4];
+            a.mutate_with(operator, 0.1, None, 3);
+            b.mutate_with(MutationOperator::Active, 0.1, None, 3);
+            assert!(well_typed(&a), "{:?} wired an input of the wrong type", operator);
+            a.crossover(&mut b, [CrossoverOperator::OnePoint, CrossoverOperator::Uniform][i % 2]);
+            assert!(well_typed(&a) && well_typed(&b), "crossover left an input of the wrong type");
+        }
+        assert!(a.genes.iter().chain(&b.genes).any(|gene| gene.output_type() == ValueType::Bool));
+        assert_eq!(Gene::new_binary2(0, 1, PrimitiveId::LT).input_types(), vec![ValueType::Real; 2]);
+        assert_eq!(Gene::new_unary2(0, PrimitiveId::NOT).output_type(), ValueType::Bool);
+    }
+
+    #[test]
+    /// Ensures that a grammar is parsed into rules that mutation and crossover respect
+    fn test_grammar() {
+        let grammar = Grammar::parse("# Domain knowledge\nforbid sin cos under sin cos\nnest 2 exp log  # at most exp(log(x))\nno-self-products\n");
+        let genes = vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_unary2(0, PrimitiveId::SIN), Gene::new_unary2(2, PrimitiveId::EXP), Gene::new_unary2(3, PrimitiveId::LOG)];
+        assert!(grammar.allows(&genes[3], &genes[..3]) && grammar.allows(&genes[4], &genes[..4]));
+        assert!(!grammar.allows(&Gene::new_unary2(3, PrimitiveId::COS), &genes), "cos takes an input with sin inside");
+        assert!(!grammar.allows(&Gene::new_unary2(4, PrimitiveId::EXP), &genes), "exp and log nest three deep");
+        assert!(!grammar.allows(&Gene::new_binary2(0, 0, PrimitiveId::MUL), &genes) && !grammar.allows(&Gene::new_unary2(1, PrimitiveId::SQUARE), &genes));
+        assert!(grammar.allows(&Gene::new_binary2(0, 1, PrimitiveId::MUL), &genes) && Grammar::default().allows(&Gene::new_unary2(1, PrimitiveId::SQUARE), &genes));
+
+        let layout = Arc::new(Layout { function_set: FunctionSet::from_names(&["mul", "square", "sin", "cos", "exp", "log"]), grammar, ..Layout::default() });
+        let (mut a, mut b) = (Chromosome::new_random(30, 2, &layout), Chromosome::new_random(30, 2, &layout));
+        for i in 0..300 {
+            let operator = [MutationOperator::Point, MutationOperator::Pointer, MutationOperator::Function, MutationOperator::Transposition][i % 4];
+            a.mutate_with(operator, 0.1, None, 2);
+            b.mutate_with(MutationOperator::Active, 0.1, None, 2);
+            assert!(a.obeys_grammar(), "{:?} broke the grammar", operator);
+            a.crossover(&mut b, [CrossoverOperator::OnePoint, CrossoverOperator::Subgraph][i % 2]);
+            assert!(a.obeys_grammar() && b.obeys_grammar(), "crossover broke the grammar");
+        }
+    }
+
+    #[test]
+    /// Ensures that primitives registered with closures are evaluated, printed and drawn like the built-in ones
+    fn test_function_registry() {
+        let weight = 0.25;
+        let lerp = FunctionRegistry::register("lerp", 2, move |x, y| x + weight * (y - x), Some(Arc::new(|inputs: &[String]| format!("({} ~ {})", inputs[0], inputs[1]))));
+        let halve = FunctionRegistry::register("halve", 1, |x, _| x / 2.0, None);
+        let set = FunctionSet::from_names(&["lerp", "halve"]);
+        assert_eq!((set.random_of_arity(2), set.random_of_arity(1)), (Some(lerp), Some(halve)));
+
+        let chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Some(4.0)), Gene::new_binary2(0, 1, lerp), Gene::new_unary2(2, halve)]);
+        assert_eq!(chromosome.function_string(), "halve((v0 ~ 4))");
+        assert_eq!(chromosome.evaluate_fitness(&vec![0.0]), 0.5);
+        // Registered primitives only join the presets when they are asked for
+        assert!(!FunctionSet::arithmetic().functions(2).iter().any(|function| function(0.0, 0.0).1 == "lerp"));
+        assert_eq!(FunctionSet::arithmetic().including(&["lerp", "add"]).functions(2).len(), FunctionSet::arithmetic().functions(2).len() + 1);
+    }
+
+    #[test]
+    /// Ensures that the logic functions threshold their inputs, and that a boolean run only uses the boolean functions
+    fn test_boolean_function_set() {
+        assert_eq!([and(0.7, 1.0).0, and(0.7, 0.2).0, or(0.0, 0.6).0, or(0.5, -3.0).0], [1.0, 0.0, 1.0, 0.0]);
+        assert_eq!([xor(1.0, 0.0).0, xor(2.0, 0.9).0, not(0.5, 0.0).0, not(0.51, 0.0).0], [1.0, 0.0, 1.0, 0.0]);
+        assert_eq!([less_than(-1.0, 2.0).0, greater_than(-1.0, 2.0).0], [1.0, 0.0]);
+
+        let names: Vec<String> = [FunctionSet::boolean().functions(1), FunctionSet::boolean().functions(2)].concat()
+            .iter().map(|function| function(0.0, 0.0).1).collect();
+        let layout = Arc::new(Layout { function_set: FunctionSet::boolean(), ..Layout::default() });
+        let mut chromosome = Chromosome::new_random(30, 3, &layout);
+        for i in 0..200 {
+            let operator = [MutationOperator::Point, MutationOperator::Function, MutationOperator::Insertion][i % 3];
+            chromosome.mutate_with(operator, 0.1, None, 3);
+            assert!(chromosome.genes.iter().all(|gene| matches!(gene.type_of_gene, Constant(_) | Variable(_)) || names.contains(&gene.get_operator())),
+                    "{:?} used a function outside the boolean set", operator);
         }
     }
 
     #[test]
     /// Ensures that the fitness value of a binary function is calculated correctly
     fn test_single_binary_function() {
-        for func in vec![add, subtract, divide, multiply, max, min] {
-            let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(1), Gene::new_variable(2), Gene::new_binary2(0, 1, func)]); //.evaluate_fitness(&ROOT[0]);
-            println!("{:?}", result.genes);
-            assert_eq!(result.evaluate_fitness(&ROOT[0]), func(ROOT[0][1], ROOT[0][2]).0);
+        for primitive in [PrimitiveId::ADD, PrimitiveId::SUB, PrimitiveId::DIV, PrimitiveId::MUL, PrimitiveId::MAX, PrimitiveId::MIN] {
+            let result = Chromosome::new_from_genes_array(vec![Gene::new_variable(1), Gene::new_variable(2), Gene::new_binary2(0, 1, primitive)]); //.evaluate_fitness(&ROOT[0]);
+            assert_eq!(result.evaluate_fitness(&ROOT[0]), primitive.operation()(ROOT[0][1], ROOT[0][2]).0);
+        }
+    }
+
+    #[test]
+    /// Ensures that a printed expression is parsed back into a chromosome that prints and evaluates the same
+    fn test_new_from_string() {
+        let names: Vec<String> = ["x0", "x1", "x2"].map(String::from).to_vec();
+        let chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Some(-2.5)), Gene::new_variable(2),
+                                                               Gene::new_binary2(0, 1, PrimitiveId::MUL), Gene::new_unary2(2, PrimitiveId::SIN),
+                                                               Gene::new_conditional2(0, 2, [3, 4], PrimitiveId::IFLT)]);
+        let expression = chromosome.function_string_with_names(&names);
+        assert_eq!(expression, "iflt(x0, x2, mul(x0, -2.5), sin(x2))");
+        let parsed = Chromosome::new_from_string(&expression, &names);
+        assert_eq!(parsed.function_string_with_names(&names), expression);
+        for row in ROOT.iter() {
+            assert_eq!(parsed.evaluate_fitness(row), chromosome.evaluate_fitness(row));
+        }
+
+        // Several outputs, with variables named by their index
+        let parsed = Chromosome::new_from_string("add(v0, 1); truediv(v1, v2)", &[]);
+        assert_eq!(parsed.output_genes().len(), 2);
+        assert_eq!(parsed.function_string(), "add(v0, 1); truediv(v1, v2)");
+
+        // Infix notation, mixed with functions, with the usual precedence
+        for (infix, prefix) in [("x0 + 2.5 * sin(x1)", "add(x0, mul(2.5, sin(x1)))"), ("-x0^2 - -3e-2 / (x1 - x2)", "sub(neg(square(x0)), truediv(-0.03, sub(x1, x2)))"),
+                                ("div(x0, x1) * x2^3", "mul(truediv(x0, x1), cube(x2))")] {
+            assert_eq!(Chromosome::new_from_string(infix, &names).function_string_with_names(&names), prefix, "{}", infix);
+        }
+
+        // Random expressions print back the same and compute the same values
+        for _ in 0..100 {
+            let chromosome = Chromosome::new_random(30, 3, &Arc::new(Layout::default()));
+            let expression = chromosome.function_string();
+            let parsed = Chromosome::new_from_string(&expression, &[]);
+            assert_eq!(parsed.function_string(), expression);
+            for row in ROOT.iter() {
+                let (expected, actual) = (chromosome.evaluate_fitness(row), parsed.evaluate_fitness(row));
+                assert!(expected == actual || (expected.is_nan() && actual.is_nan()), "{}", expression);
+            }
+        }
+    }
+
+    #[test]
+    /// Ensures that simplification folds constants and applies the algebraic rules in place
+    fn test_simplify() {
+        let names = expression_variables("sub(neg(neg(log(exp(a)))), add(mul(b, 1), sub(2, 2)))");
+        assert_eq!(names, vec!["a", "b"]);
+        let mut chromosome = Chromosome::new_from_string("sub(neg(neg(log(exp(a)))), add(mul(b, 1), sub(2, 2)))", &names);
+        let length = chromosome.genes.len();
+        assert!(chromosome.simplify() > 0);
+        assert_eq!(chromosome.function_string_with_names(&names), "sub(a, b)");
+        assert_eq!(chromosome.genes.len(), length);
+
+        for (expression, simplified) in [("mul(sub(v0, v0), v1)", "0"), ("iflt(1, 2, v0, v1)", "v0"), ("max(sin(v0), sin(v0))", "sin(v0)"),
+                                         ("truediv(v0, 0)", "truediv(v0, 0)")] {
+            let mut chromosome = Chromosome::new_from_string(expression, &[]);
+            chromosome.simplify();
+            assert_eq!(chromosome.function_string(), simplified, "{}", expression);
+        }
+
+        // Two chains of 64 doublings, whose expressions have 2^64 paths each but 65 distinct subexpressions
+        let mut genes = vec![Gene::new_variable(0), Gene::new_variable(0)];
+        for pos in 2..130 {
+            genes.push(Gene::new_binary2(pos - 2, pos - 2, PrimitiveId::ADD));
+        }
+        genes.push(Gene::new_binary2(128, 129, PrimitiveId::SUB));
+        let mut chromosome = Chromosome::new_from_genes_array(genes);
+        chromosome.simplify();
+        assert_eq!(chromosome.function_string(), "0");
+    }
+
+    #[test]
+    /// Ensures that expressions the rewrite rules prove equal canonicalize to the same smallest expression
+    fn test_canonical() {
+        let canonical = Chromosome::new_from_string("add(v1, v0)", &[]).canonical().function_string();
+        for expression in ["add(v0, v1)", "sub(add(v0, v1), 0)", "add(mul(v1, 1), sub(v0, sub(2, 2)))", "sub(add(add(v0, v1), v2), v2)"] {
+            assert_eq!(Chromosome::new_from_string(expression, &[]).canonical().function_string(), canonical, "{}", expression);
+        }
+        for (expression, canonical) in [("add(mul(v0, v1), mul(v0, 2))", "mul(v0, add(v1, 2))"), ("sqrt(square(neg(v0)))", "abs(v0)"),
+                                         ("sub(v0, neg(v1))", "add(v0, v1)"), ("mul(v0, mul(v0, v0))", "cube(v0)")] {
+            let size = |chromosome: &Chromosome| chromosome.function_string().matches(['(', ',']).count();
+            assert_eq!(size(&Chromosome::new_from_string(expression, &[]).canonical()), size(&Chromosome::new_from_string(canonical, &[])), "{}", expression);
+        }
+
+        // Several outputs keep their order, and the canonical chromosome computes what the original does
+        let chromosome = Chromosome::new_from_string("add(0, mul(v0, 1)); sub(v1, v1); add(v0, v1)", &[]);
+        let canonical = chromosome.canonical();
+        assert_eq!(canonical.function_string(), format!("v0; 0; {}", Chromosome::new_from_string("add(v1, v0)", &[]).canonical().function_string()));
+        for row in [vec![1.0, 2.0], vec![-3.5, 0.25]] {
+            assert_eq!(canonical.output_values(&row), chromosome.output_values(&row));
+        }
+    }
+
+    #[test]
+    /// Ensures that subgraphs of constants collapse into single constants without changing the values the chromosome computes
+    fn test_fold_constants() {
+        let mut chromosome = Chromosome::new_from_string("add(v0, mul(2, add(sin(0), 1.5))); iflt(1, 2, 3, v1); add(v0, 0)", &[]);
+        let unfolded = chromosome.clone();
+        // The conditional compares constants but may choose a variable, so it is not a subgraph of constants
+        assert_eq!(chromosome.fold_constants(), 3);
+        assert_eq!(chromosome.function_string(), "add(v0, 3); iflt(1, 2, 3, v1); add(v0, 0)");
+        for row in ROOT.iter() {
+            assert_eq!(chromosome.output_values(row), unfolded.output_values(row));
+        }
+        // Infinite values are left as they are
+        let mut chromosome = Chromosome::new_from_string("exp(1000)", &[]);
+        assert_eq!(chromosome.fold_constants(), 0);
+    }
+
+    #[test]
+    /// Ensures that chromosomes are written as S-expressions and read back from them
+    fn test_sexpr() {
+        let names: Vec<String> = ["x0", "x1", "x2"].map(String::from).to_vec();
+        let chromosome = Chromosome::new_from_string("iflt(x0, -1.5, x1 * x2, sin(x0)); x2 + 1", &names);
+        let sexpr = chromosome.to_sexpr(&names);
+        assert_eq!(sexpr, "(iflt x0 -1.5 (mul x1 x2) (sin x0)) (add x2 1)");
+        assert_eq!(Chromosome::from_sexpr(&sexpr, &names).to_sexpr(&names), sexpr);
+
+        // Expressions on the command line are read as S-expressions when they start like one
+        assert!(is_sexpr(&sexpr) && is_sexpr(" ( add x2 1)"));
+        assert!(!is_sexpr("(x0 + 1) * 2") && !is_sexpr("(sin(x0))") && !is_sexpr("add(x0, 1)"));
+        assert_eq!(Chromosome::parse(&sexpr, &names).function_string(), chromosome.function_string());
+        assert_eq!(Chromosome::parse("(x2 + 1)", &names).function_string(), "add(v2, 1)");
+        assert_eq!(expression_variables("(add b (mul 2 (sin a)))"), vec!["b", "a"]);
+
+        for _ in 0..100 {
+            let chromosome = Chromosome::new_random(30, 3, &Arc::new(Layout::default()));
+            let parsed = Chromosome::from_sexpr(&chromosome.to_sexpr(&[]), &[]);
+            assert_eq!(parsed.function_string(), chromosome.function_string());
+        }
+    }
+
+    #[test]
+    /// Ensures that infix expressions only have the parentheses that precedence needs, and parse
+    /// back into expressions that print the same and compute the same values
+    fn test_infix() {
+        let names: Vec<String> = ["x0", "x1", "x2"].map(String::from).to_vec();
+        for (prefix, infix) in [("add(mul(x0, x1), 3)", "x0*x1 + 3"), ("mul(add(x0, x1), sub(x2, neg(x0)))", "(x0 + x1)*(x2 - -x0)"),
+                                ("sub(x0, sub(x1, x2))", "x0 - (x1 - x2)"), ("truediv(truediv(x0, x1), mul(x2, x0))", "x0/x1/(x2*x0)"),
+                                ("neg(square(add(x0, -1.5)))", "-(x0 + -1.5)^2"), ("cube(sin(x0)); iflt(x0, x1, x2, 2)", "sin(x0)^3; iflt(x0, x1, x2, 2)")] {
+            let chromosome = Chromosome::new_from_string(prefix, &names);
+            assert_eq!(chromosome.to_infix(&names), infix, "{}", prefix);
+            assert_eq!(Chromosome::new_from_string(infix, &names).function_string_with_names(&names), prefix, "{}", infix);
+        }
+
+        for _ in 0..100 {
+            let chromosome = Chromosome::new_random(30, 3, &Arc::new(Layout::default()));
+            let infix = chromosome.to_infix(&[]);
+            let parsed = Chromosome::new_from_string(&infix, &[]);
+            assert_eq!(parsed.to_infix(&[]), infix);
+            for row in ROOT.iter() {
+                let (expected, actual) = (chromosome.evaluate_fitness(row), parsed.evaluate_fitness(row));
+                assert!(expected == actual || (expected.is_nan() && actual.is_nan()), "{}", infix);
+            }
+        }
+    }
+
+    #[test]
+    /// Ensures that the output of a registered printer is parenthesized in infix notation, and parses back into an expression with the same values
+    fn test_infix_printer() {
+        let names: Vec<String> = ["x0", "x1", "x2"].map(String::from).to_vec();
+        let average = FunctionRegistry::register("average", 2, |x, y| (x + y) / 2.0, Some(Arc::new(|inputs: &[String]| format!("{} + {} / 2 - {} / 2", inputs[0], inputs[1], inputs[0]))));
+        let chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, average),
+                                                               Gene::new_variable(2), Gene::new_binary2(3, 2, PrimitiveId::MUL),
+                                                               Gene::new_unary2(2, PrimitiveId::NEG), Gene::new_binary2(4, 5, PrimitiveId::SUB)]);
+        let infix = chromosome.to_infix(&names);
+        assert_eq!(infix, "x2*(x0 + x1 / 2 - x0 / 2) - -(x0 + x1 / 2 - x0 / 2)");
+        assert_eq!(Chromosome::new_from_genes_array(chromosome.genes[..3].to_vec()).to_infix(&names), "(x0 + x1 / 2 - x0 / 2)");
+        let parsed = Chromosome::new_from_string(&infix, &names);
+        for row in ROOT.iter() {
+            assert!((parsed.evaluate_fitness(row) - chromosome.evaluate_fitness(row)).abs() < 1e-9, "{}", infix);
+        }
+    }
+
+    #[test]
+    /// Ensures that LaTeX writes division as fractions, logarithms and powers with their symbols,
+    /// and only parenthesizes where precedence needs it
+    fn test_latex() {
+        let names: Vec<String> = ["x0", "x1", "rate_2"].map(String::from).to_vec();
+        for (expression, latex) in [("x0 / 2 + log2(x1)^2", r"\frac{x_{0}}{2} + \left(\log_2\left(x_{1}\right)\right)^{2}"),
+                                    ("(x0 + rate_2) * exp(-x1) - -x0", r"\left(x_{0} + \mathit{rate\_2}\right) \cdot e^{-x_{1}} - \left(-x_{0}\right)"),
+                                    ("sqrt(abs(x0 / x1)^3); iflt(x0, 1, x1, 0)", r"\sqrt{\left|\frac{x_{0}}{x_{1}}\right|^{3}}, \quad \begin{cases} x_{1} & \text{if } x_{0} < 1 \\ 0 & \text{otherwise} \end{cases}")] {
+            assert_eq!(Chromosome::new_from_string(expression, &names).to_latex(&names), latex, "{}", expression);
+        }
+        assert_eq!(Chromosome::new_from_string("sin(x0)^3 - -max(x0, x1)", &names).to_latex(&names),
+                   r"\left(\sin\left(x_{0}\right)\right)^{3} - \left(-\max\left(x_{0}, x_{1}\right)\right)");
+        for (name, latex) in [("a%b&c", r"\mathit{a\%b\&c}"), ("#", r"\#"), ("{$x^2}", r"\mathit{\{\$x\hat{}2\}}"), (r"a\b", r"\mathit{a\backslash b}")] {
+            assert_eq!(latex_variable(name), latex);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,545 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::iter::Peekable;
+use std::sync::LazyLock;
+use std::vec::IntoIter;
+
+use crate::functions::{FunctionRegistry, PrimitiveId, Protection};
+
+/// Identifies an e-class: a set of expressions known to be equal.
+pub type Id = usize;
+
+/// An expression whose inputs are e-classes rather than expressions. Constants are held by their
+/// bits so that nodes can be hashed; conditionals are the functions with four inputs.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Node {
+    Constant(u64),
+    Variable(usize),
+    Apply(PrimitiveId, Vec<Id>),
+}
+
+impl Node {
+    /// A constant node of `value`, with -0 taken as 0 so that the two are the same node.
+    pub fn constant(value: f64) -> Node {
+        return Node::Constant(if value == 0.0 { 0.0f64.to_bits() } else { value.to_bits() });
+    }
+
+    /// The e-classes the node takes as inputs, in order.
+    pub fn inputs(&self) -> &[Id] {
+        return match self {
+            Node::Apply(_, inputs) => inputs,
+            Node::Constant(_) | Node::Variable(_) => &[],
+        };
+    }
+}
+
+/// A side of a rewrite rule: an expression with holes, e.g. `(add ?a 0)`.
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    Hole(String),
+    Constant(f64),
+    Apply(PrimitiveId, Vec<Pattern>),
+}
+
+impl Pattern {
+    /// Reads a pattern written as an S-expression, with holes starting with `?`.
+    ///
+    /// # Panics
+    ///
+    /// When the pattern is malformed or uses an unknown function.
+    fn parse(pattern: &str) -> Pattern {
+        let tokens: Vec<String> = pattern.replace('(', " ( ").replace(')', " ) ").split_whitespace().map(String::from).collect();
+        let mut tokens = tokens.into_iter().peekable();
+        let parsed = Pattern::parse_tokens(&mut tokens, pattern);
+        if tokens.next().is_some() {
+            panic!("Unexpected input after the end of the pattern {}", pattern);
+        }
+        return parsed;
+    }
+
+    fn parse_tokens(tokens: &mut Peekable<IntoIter<String>>, pattern: &str) -> Pattern {
+        let token = tokens.next().unwrap_or_else(|| panic!("The pattern {} ends early", pattern));
+        if token != "(" {
+            if let Some(name) = token.strip_prefix('?') {
+                return Pattern::Hole(name.to_string());
+            }
+            return Pattern::Constant(token.parse().unwrap_or_else(|_| panic!("{} is neither a hole nor a number in {}", token, pattern)));
+        }
+        let name = tokens.next().unwrap_or_else(|| panic!("The pattern {} ends early", pattern));
+        let mut inputs = Vec::new();
+        while tokens.peek().is_some_and(|token| token != ")") {
+            inputs.push(Pattern::parse_tokens(tokens, pattern));
+        }
+        if tokens.next().is_none() {
+            panic!("The pattern {} ends early", pattern);
+        }
+        let primitive = FunctionRegistry::global().find(&name, inputs.len())
+            .unwrap_or_else(|| panic!("Unknown function {} with {} inputs in {}", name, inputs.len(), pattern));
+        return Pattern::Apply(primitive, inputs);
+    }
+}
+
+/// A rule that an expression matching `from` equals `to`, with the holes of `to` standing for what
+/// the holes of `from` matched.
+#[derive(Debug, Clone)]
+pub struct Rewrite {
+    pub name: &'static str,
+    from: Pattern,
+    to: Pattern,
+}
+
+impl Rewrite {
+    /// # Arguments
+    ///
+    /// * `name` - The name of the rule.
+    /// * `from` - The expression the rule matches, as an S-expression with holes such as `(add ?a 0)`.
+    /// * `to` - The expression it equals, using the holes of `from`.
+    ///
+    /// # Panics
+    ///
+    /// When a pattern is malformed, or `to` uses a hole that `from` does not.
+    pub fn new(name: &'static str, from: &str, to: &str) -> Rewrite {
+        let (from, to) = (Pattern::parse(from), Pattern::parse(to));
+        let mut holes = Vec::new();
+        collect_holes(&from, &mut holes);
+        let mut used = Vec::new();
+        collect_holes(&to, &mut used);
+        if let Some(hole) = used.iter().find(|hole| !holes.contains(hole)) {
+            panic!("The rule {} rewrites to the hole ?{} that it does not match", name, hole);
+        }
+        return Rewrite { name, from, to };
+    }
+}
+
+fn collect_holes(pattern: &Pattern, holes: &mut Vec<String>) {
+    match pattern {
+        Pattern::Hole(name) => holes.push(name.clone()),
+        Pattern::Constant(_) => {}
+        Pattern::Apply(_, inputs) => inputs.iter().for_each(|input| collect_holes(input, holes)),
+    }
+}
+
+/// The rules the expressions of chromosomes are saturated with. Each holds for finite values of its
+/// holes and for every protection, so that the expression extracted from the e-graph computes what
+/// the chromosome computes wherever its genes stay finite, as `Chromosome::simplify` assumes.
+pub static RULES: LazyLock<Vec<Rewrite>> = LazyLock::new(|| {
+    let mut rules = Vec::new();
+    for function in ["add", "mul", "max", "min", "and", "or", "xor"] {
+        rules.push(Rewrite::new("commute", &format!("({} ?a ?b)", function), &format!("({} ?b ?a)", function)));
+    }
+    for function in ["add", "mul"] {
+        rules.push(Rewrite::new("associate", &format!("({0} ?a ({0} ?b ?c))", function), &format!("({0} ({0} ?a ?b) ?c)", function)));
+    }
+    for (name, from, to) in [
+        ("add-zero", "(add ?a 0)", "?a"),
+        ("sub-zero", "(sub ?a 0)", "?a"),
+        ("sub-self", "(sub ?a ?a)", "0"),
+        ("sub-to-add", "(sub ?a ?b)", "(add ?a (neg ?b))"),
+        ("add-to-sub", "(add ?a (neg ?b))", "(sub ?a ?b)"),
+        ("sub-add", "(sub (add ?a ?b) ?b)", "?a"),
+        ("neg-sub", "(neg (sub ?a ?b))", "(sub ?b ?a)"),
+        ("neg-neg", "(neg (neg ?a))", "?a"),
+        ("mul-one", "(mul ?a 1)", "?a"),
+        ("mul-zero", "(mul ?a 0)", "0"),
+        ("mul-minus-one", "(mul ?a -1)", "(neg ?a)"),
+        ("neg-mul", "(mul (neg ?a) ?b)", "(neg (mul ?a ?b))"),
+        ("div-one", "(div ?a 1)", "?a"),
+        ("add-self", "(add ?a ?a)", "(mul ?a 2)"),
+        ("factor", "(add (mul ?a ?b) (mul ?a ?c))", "(mul ?a (add ?b ?c))"),
+        ("square", "(mul ?a ?a)", "(square ?a)"),
+        ("cube", "(mul ?a (square ?a))", "(cube ?a)"),
+        ("square-neg", "(square (neg ?a))", "(square ?a)"),
+        ("sqrt-square", "(sqrt (square ?a))", "(abs ?a)"),
+        ("abs-neg", "(abs (neg ?a))", "(abs ?a)"),
+        ("abs-abs", "(abs (abs ?a))", "(abs ?a)"),
+        ("abs-square", "(abs (square ?a))", "(square ?a)"),
+        ("floor-floor", "(floor (floor ?a))", "(floor ?a)"),
+        ("log-exp", "(log (exp ?a))", "?a"),
+        ("max-self", "(max ?a ?a)", "?a"),
+        ("min-self", "(min ?a ?a)", "?a"),
+        ("iflt-same", "(iflt ?a ?b ?c ?c)", "?c"),
+    ] {
+        rules.push(Rewrite::new(name, from, to));
+    }
+    return rules;
+});
+
+/// How far equality saturation goes before it stops with what it has proven.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaturationLimits {
+    /// The most times every rule is matched against the whole e-graph.
+    pub iterations: usize,
+    /// The most nodes the e-graph may hold; rewriting stops once it holds more.
+    pub nodes: usize,
+}
+
+impl Default for SaturationLimits {
+    fn default() -> Self {
+        return SaturationLimits { iterations: 16, nodes: 10_000 };
+    }
+}
+
+/// An e-graph: expressions grouped into e-classes of expressions known to be equal, sharing their
+/// subexpressions, see `saturate` and `extract`. Functions of constants are folded into constants
+/// as they are added, with the operations of `protection`.
+pub struct EGraph {
+    /// The union-find over e-classes: the e-class each e-class was merged into, itself for the roots.
+    parents: Vec<Id>,
+    /// The nodes of each root e-class. Merged e-classes are empty.
+    classes: Vec<Vec<Node>>,
+    /// The constant each e-class equals, when it is known.
+    values: Vec<Option<f64>>,
+    /// The e-class of every node, with its inputs as roots as of the last `rebuild`.
+    memo: HashMap<Node, Id>,
+    protection: Protection,
+}
+
+impl EGraph {
+    pub fn new(protection: Protection) -> EGraph {
+        return EGraph { parents: Vec::new(), classes: Vec::new(), values: Vec::new(), memo: HashMap::new(), protection };
+    }
+
+    /// How many nodes the e-graph holds.
+    pub fn len(&self) -> usize {
+        return self.classes.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.classes.is_empty();
+    }
+
+    /// The root of the e-class `id` was merged into.
+    pub fn find(&self, mut id: Id) -> Id {
+        while self.parents[id] != id {
+            id = self.parents[id];
+        }
+        return id;
+    }
+
+    /// The constant the e-class `id` equals, when it is known.
+    pub fn value(&self, id: Id) -> Option<f64> {
+        return self.values[self.find(id)];
+    }
+
+    fn canonicalize(&self, node: Node) -> Node {
+        return match node {
+            Node::Apply(primitive, inputs) => Node::Apply(primitive, inputs.into_iter().map(|input| self.find(input)).collect()),
+            node => node,
+        };
+    }
+
+    /// Adds a node, or finds the e-class that already holds it.
+    ///
+    /// # Returns
+    ///
+    /// The e-class of the node.
+    pub fn add(&mut self, node: Node) -> Id {
+        let node = self.canonicalize(node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id);
+        }
+        let id = self.classes.len();
+        self.parents.push(id);
+        self.values.push(match node {
+            Node::Constant(bits) => Some(f64::from_bits(bits)),
+            _ => None,
+        });
+        self.classes.push(vec![node.clone()]);
+        self.memo.insert(node.clone(), id);
+        self.fold(id, &node);
+        return self.find(id);
+    }
+
+    /// Merges the e-class of a function of constants with the constant it computes, and the
+    /// e-class of a conditional whose comparison is constant with the branch it takes. Values that
+    /// are not finite are not folded.
+    fn fold(&mut self, id: Id, node: &Node) {
+        let Node::Apply(primitive, inputs) = node else {
+            return;
+        };
+        let values: Vec<Option<f64>> = inputs.iter().map(|&input| self.value(input)).collect();
+        match (inputs.len(), values.as_slice()) {
+            (4, [Some(left), Some(right), ..]) => {
+                let branch = if (primitive.operation())(*left, *right).0 != 0.0 { inputs[2] } else { inputs[3] };
+                self.union(id, branch);
+            }
+            (1 | 2, _) if values.iter().all(Option::is_some) => {
+                let value = (self.protection.protect(*primitive))(values[0].unwrap(), values.get(1).copied().flatten().unwrap_or(-1.0)).0;
+                if value.is_finite() {
+                    let constant = self.add(Node::constant(value));
+                    self.union(id, constant);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Records that the e-classes `a` and `b` are equal. The e-graph has to be rebuilt before it is searched again.
+    ///
+    /// # Returns
+    ///
+    /// Whether they were not known to be equal yet.
+    pub fn union(&mut self, a: Id, b: Id) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        // The older e-class stays the root, so that the e-graph grows the same way on every run
+        let (root, merged) = if a < b { (a, b) } else { (b, a) };
+        self.parents[merged] = root;
+        let nodes = std::mem::take(&mut self.classes[merged]);
+        self.classes[root].extend(nodes);
+        self.values[root] = self.values[root].or(self.values[merged]);
+        return true;
+    }
+
+    /// Restores the invariants that unions break: every node is held once, by the root of its
+    /// e-class, and nodes that became the same after their inputs were merged are merged too, as
+    /// are functions whose inputs became constants and the constants they fold to.
+    pub fn rebuild(&mut self) {
+        loop {
+            self.memo.clear();
+            let mut merges = Vec::new();
+            let mut folds = Vec::new();
+            for id in 0..self.classes.len() {
+                if self.parents[id] != id {
+                    continue;
+                }
+                let nodes = std::mem::take(&mut self.classes[id]);
+                let mut nodes: Vec<Node> = nodes.into_iter().map(|node| self.canonicalize(node)).collect();
+                nodes.sort();
+                nodes.dedup();
+                for node in &nodes {
+                    match self.memo.get(node) {
+                        Some(&other) => merges.push((other, id)),
+                        None => {
+                            self.memo.insert(node.clone(), id);
+                        }
+                    }
+                    if self.values[id].is_none() && !node.inputs().is_empty() && node.inputs().iter().all(|&input| self.values[self.find(input)].is_some()) {
+                        folds.push((id, node.clone()));
+                    }
+                }
+                self.classes[id] = nodes;
+            }
+            let mut changed = false;
+            for (a, b) in merges {
+                changed |= self.union(a, b);
+            }
+            for (id, node) in folds {
+                let before = self.len();
+                let (root, node) = (self.find(id), self.canonicalize(node));
+                self.fold(root, &node);
+                changed |= self.len() != before || self.find(id) != root || self.values[self.find(id)].is_some();
+            }
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    /// Matches `pattern` against the e-class `id`.
+    ///
+    /// # Returns
+    ///
+    /// Every way the holes can be filled, extending `filled`.
+    fn search(&self, pattern: &Pattern, id: Id, filled: &[(String, Id)]) -> Vec<Vec<(String, Id)>> {
+        let id = self.find(id);
+        return match pattern {
+            Pattern::Hole(name) => match filled.iter().find(|(hole, _)| hole == name) {
+                Some(&(_, other)) if self.find(other) != id => Vec::new(),
+                Some(_) => vec![filled.to_vec()],
+                None => {
+                    let mut filled = filled.to_vec();
+                    filled.push((name.clone(), id));
+                    vec![filled]
+                }
+            },
+            Pattern::Constant(value) => match self.values[id] {
+                Some(constant) if constant == *value => vec![filled.to_vec()],
+                _ => Vec::new(),
+            },
+            Pattern::Apply(primitive, patterns) => self.classes[id].iter()
+                .filter_map(|node| match node {
+                    Node::Apply(other, inputs) if other == primitive && inputs.len() == patterns.len() => Some(inputs),
+                    _ => None,
+                })
+                .flat_map(|inputs| patterns.iter().zip(inputs).fold(vec![filled.to_vec()], |partial, (pattern, &input)| {
+                    partial.iter().flat_map(|filled| self.search(pattern, input, filled)).collect()
+                }))
+                .collect(),
+        };
+    }
+
+    /// Adds the expression `pattern` stands for with its holes filled.
+    fn instantiate(&mut self, pattern: &Pattern, filled: &[(String, Id)]) -> Id {
+        return match pattern {
+            Pattern::Hole(name) => filled.iter().find(|(hole, _)| hole == name).map(|&(_, id)| id).unwrap(),
+            Pattern::Constant(value) => self.add(Node::constant(*value)),
+            Pattern::Apply(primitive, patterns) => {
+                let inputs = patterns.iter().map(|pattern| self.instantiate(pattern, filled)).collect();
+                self.add(Node::Apply(*primitive, inputs))
+            }
+        };
+    }
+
+    /// Equality saturation: matches every rule against every e-class and merges each match with
+    /// what the rule rewrites it to, until no rule adds anything new or `limits` are reached.
+    ///
+    /// # Returns
+    ///
+    /// Whether the e-graph saturated, so that it holds every expression the rules prove equal.
+    pub fn saturate(&mut self, rules: &[Rewrite], limits: SaturationLimits) -> bool {
+        self.rebuild();
+        for _ in 0..limits.iterations {
+            let mut matches = Vec::new();
+            for rule in rules {
+                for id in 0..self.classes.len() {
+                    // A constant is already as small as an expression gets, and rewriting it only adds more constants
+                    if self.parents[id] == id && self.values[id].is_none() {
+                        matches.extend(self.search(&rule.from, id, &[]).into_iter().map(|filled| (rule, id, filled)));
+                    }
+                }
+            }
+            let before = self.len();
+            let mut changed = false;
+            for (rule, id, filled) in matches {
+                if self.len() > limits.nodes {
+                    break;
+                }
+                let rewritten = self.instantiate(&rule.to, &filled);
+                changed |= self.union(id, rewritten);
+            }
+            self.rebuild();
+            if !changed && self.len() == before {
+                return true;
+            }
+            if self.len() > limits.nodes {
+                return false;
+            }
+        }
+        return false;
+    }
+
+    /// Extracts the smallest expression of each of `roots`, counting every function, constant and
+    /// variable of the printed expression. Expressions of the same size are told apart by their
+    /// structure, so that equal expressions are extracted the same way whichever form they were added in.
+    ///
+    /// # Returns
+    ///
+    /// The nodes of the expressions, each with its inputs as positions of earlier nodes, and the
+    /// position of each root.
+    pub fn extract(&self, roots: &[Id]) -> (Vec<Node>, Vec<usize>) {
+        let mut best: Vec<Option<(usize, u64, Node)>> = vec![None; self.classes.len()];
+        loop {
+            let mut changed = false;
+            for id in (0..self.classes.len()).filter(|&id| self.parents[id] == id) {
+                for node in &self.classes[id] {
+                    let Some(candidate) = self.candidate(node, &best) else {
+                        continue;
+                    };
+                    if best[id].as_ref().is_none_or(|(size, key, _)| (candidate.0, candidate.1) < (*size, *key)) {
+                        best[id] = Some(candidate);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        let mut nodes = Vec::new();
+        let mut positions = HashMap::new();
+        let outputs = roots.iter().map(|&root| self.emit(self.find(root), &best, &mut nodes, &mut positions)).collect();
+        return (nodes, outputs);
+    }
+
+    /// The size, structural key and node of the smallest expression with `node` at its top, given
+    /// the best expressions of its inputs so far, or `None` when an input has none yet.
+    fn candidate(&self, node: &Node, best: &[Option<(usize, u64, Node)>]) -> Option<(usize, u64, Node)> {
+        let mut hasher = DefaultHasher::new();
+        let mut size = 1usize;
+        match node {
+            Node::Constant(bits) => ("constant", bits).hash(&mut hasher),
+            Node::Variable(variable) => ("variable", variable).hash(&mut hasher),
+            Node::Apply(primitive, inputs) => {
+                primitive.hash(&mut hasher);
+                for &input in inputs {
+                    let (input_size, key, _) = best[self.find(input)].as_ref()?;
+                    size = size.saturating_add(*input_size);
+                    key.hash(&mut hasher);
+                }
+            }
+        }
+        return Some((size, hasher.finish(), node.clone()));
+    }
+
+    fn emit(&self, id: Id, best: &[Option<(usize, u64, Node)>], nodes: &mut Vec<Node>, positions: &mut HashMap<Id, usize>) -> usize {
+        if let Some(&position) = positions.get(&id) {
+            return position;
+        }
+        let node = match best[id].as_ref().map(|(_, _, node)| node.clone()) {
+            Some(Node::Apply(primitive, inputs)) => {
+                Node::Apply(primitive, inputs.into_iter().map(|input| self.emit(self.find(input), best, nodes, positions)).collect())
+            }
+            Some(node) => node,
+            None => unreachable!("every e-class holds an expression without cycles"),
+        };
+        nodes.push(node);
+        positions.insert(id, nodes.len() - 1);
+        return nodes.len() - 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Ensures that saturation proves rewritten expressions equal and extracts the smallest of them
+    fn test_saturate() {
+        let mut egraph = EGraph::new(Protection::default());
+        let (x, y) = (egraph.add(Node::Variable(0)), egraph.add(Node::Variable(1)));
+        let xy = egraph.add(Node::Apply(PrimitiveId::ADD, vec![x, y]));
+        let yx = egraph.add(Node::Apply(PrimitiveId::ADD, vec![y, x]));
+        let zero = egraph.add(Node::constant(0.0));
+        let padded = egraph.add(Node::Apply(PrimitiveId::ADD, vec![yx, zero]));
+        let neg = egraph.add(Node::Apply(PrimitiveId::NEG, vec![y]));
+        let difference = egraph.add(Node::Apply(PrimitiveId::ADD, vec![padded, neg]));
+        assert_ne!(egraph.find(xy), egraph.find(padded));
+        let rules = [Rewrite::new("commute", "(add ?a ?b)", "(add ?b ?a)"), Rewrite::new("add-zero", "(add ?a 0)", "?a")];
+        assert!(egraph.saturate(&rules, SaturationLimits::default()));
+        assert_eq!(egraph.find(xy), egraph.find(padded));
+        assert_ne!(egraph.find(difference), egraph.find(x));
+        // The full rule set grows without end through associativity, so it stops at the limits
+        assert!(!egraph.saturate(&RULES, SaturationLimits::default()));
+        assert_eq!(egraph.find(difference), egraph.find(x));
+
+        let (nodes, outputs) = egraph.extract(&[padded, difference]);
+        assert_eq!(nodes[outputs[1]], Node::Variable(0));
+        assert!(matches!(&nodes[outputs[0]], Node::Apply(PrimitiveId::ADD, inputs) if inputs.len() == 2));
+        assert_eq!(nodes.len(), 3);
+    }
+
+    #[test]
+    /// Ensures that functions of constants fold as they are added and once their inputs are proven constant
+    fn test_fold() {
+        let mut egraph = EGraph::new(Protection::default());
+        let (two, three) = (egraph.add(Node::constant(2.0)), egraph.add(Node::constant(3.0)));
+        let product = egraph.add(Node::Apply(PrimitiveId::MUL, vec![two, three]));
+        assert_eq!(egraph.value(product), Some(6.0));
+
+        let x = egraph.add(Node::Variable(0));
+        let zero = egraph.add(Node::Apply(PrimitiveId::SUB, vec![x, x]));
+        let exp = egraph.add(Node::Apply(PrimitiveId::EXP, vec![zero]));
+        assert_eq!(egraph.value(exp), None);
+        egraph.saturate(&RULES, SaturationLimits::default());
+        assert_eq!(egraph.value(exp), Some(1.0));
+
+        // Division by 0 folds to what the protection makes it
+        let mut egraph = EGraph::new(Protection::One);
+        let (one, zero) = (egraph.add(Node::constant(1.0)), egraph.add(Node::constant(0.0)));
+        let quotient = egraph.add(Node::Apply(PrimitiveId::DIV, vec![one, zero]));
+        assert_eq!(egraph.value(quotient), Some(1.0));
+    }
+}
@@ -0,0 +1,641 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chromosome::Chromosome;
+use crate::io::{Dataset, DatasetTraits};
+
+/// How the error of a chromosome is measured. The error is the mean of a loss over the rows (and
+/// targets), weighted by the sample weights of the rows, which `finish` can turn into the final value, e.g. its square root for the RMSE.
+/// Lower is better.
+pub trait FitnessMetric: Debug + Send + Sync {
+    /// The name `--metric` knows the metric by.
+    fn name(&self) -> &'static str;
+
+    /// The loss of one prediction of `target`.
+    fn loss(&self, prediction: f64, target: f64) -> f64;
+
+    /// The loss of the predictions of several outputs on `row`. By default, each output predicts
+    /// its own target, in order at the end of the row, and their losses are averaged.
+    fn row_loss(&self, predictions: &[f64], row: &[f64]) -> f64 {
+        let targets = &row[row.len() - predictions.len()..];
+        return predictions.iter().zip(targets).map(|(&prediction, &target)| self.loss(prediction, target)).sum::<f64>() / predictions.len() as f64;
+    }
+
+    /// The error of a chromosome whose mean loss over the rows of `dataset` is `mean_loss`.
+    fn finish(&self, mean_loss: f64, _dataset: &Dataset) -> f64 {
+        return mean_loss;
+    }
+
+    /// Whether the error depends on how the rows rank by the prediction, like the ROC-AUC, rather
+    /// than on a mean loss. The error is then `rank_error`, and the loss is only used per row, e.g.
+    /// by lexicase selection.
+    fn ranks(&self) -> bool {
+        return false;
+    }
+
+    /// The error from the `(score, label, weight)` of every row, sorted by ascending score, for metrics that rank.
+    fn rank_error(&self, _scored: &[(f64, f64, f64)]) -> f64 {
+        panic!("The {} metric does not rank the rows", self.name());
+    }
+
+    /// Whether the error is 1 minus a score that is usually reported instead, like R² or the accuracy.
+    fn is_complement(&self) -> bool {
+        return false;
+    }
+
+    /// The parameters of the metric, such as the delta of the Huber loss, which tell apart metrics of the same name.
+    fn parameters(&self) -> Vec<f64> {
+        return Vec::new();
+    }
+}
+
+impl PartialEq for dyn FitnessMetric {
+    /// The same metric with the same parameters.
+    fn eq(&self, other: &Self) -> bool {
+        return self.name() == other.name() && self.parameters() == other.parameters();
+    }
+}
+
+/// The mean squared error.
+#[derive(Debug)]
+pub struct Mse;
+
+impl FitnessMetric for Mse {
+    fn name(&self) -> &'static str {
+        return "mse";
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        return (prediction - target).powi(2);
+    }
+}
+
+/// The mean absolute error, which weighs outliers less than the MSE.
+#[derive(Debug)]
+pub struct Mae;
+
+impl FitnessMetric for Mae {
+    fn name(&self) -> &'static str {
+        return "mae";
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        return (prediction - target).abs();
+    }
+}
+
+/// The root of the mean squared error, in the units of the target.
+#[derive(Debug)]
+pub struct Rmse;
+
+impl FitnessMetric for Rmse {
+    fn name(&self) -> &'static str {
+        return "rmse";
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        return (prediction - target).powi(2);
+    }
+
+    fn finish(&self, mean_loss: f64, _dataset: &Dataset) -> f64 {
+        return mean_loss.sqrt();
+    }
+}
+
+/// The coefficient of determination, minimized as 1 - R², the MSE relative to the variance of the targets.
+#[derive(Debug)]
+pub struct RSquared;
+
+impl FitnessMetric for RSquared {
+    fn name(&self) -> &'static str {
+        return "r2";
+    }
+
+    fn is_complement(&self) -> bool {
+        return true;
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        return (prediction - target).powi(2);
+    }
+
+    fn finish(&self, mean_loss: f64, dataset: &Dataset) -> f64 {
+        let targets: Vec<(f64, f64)> = dataset.rows.iter()
+            .flat_map(|row| row[row.len() - dataset.target_names.len()..].iter().map(|&target| (target, dataset.weight(row))))
+            .collect();
+        let total_weight = targets.iter().map(|(_, weight)| weight).sum::<f64>();
+        let mean = targets.iter().map(|(target, weight)| weight * target).sum::<f64>() / total_weight;
+        let variance = targets.iter().map(|(target, weight)| weight * (target - mean).powi(2)).sum::<f64>() / total_weight;
+        return mean_loss / variance;
+    }
+}
+
+/// The mean absolute percentage error, relative to the magnitude of the target. Rows whose target
+/// is 0 are invalid, see `InvalidRows`.
+#[derive(Debug)]
+pub struct Mape;
+
+impl FitnessMetric for Mape {
+    fn name(&self) -> &'static str {
+        return "mape";
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        return 100.0 * ((prediction - target) / target).abs();
+    }
+}
+
+/// The Huber loss, squared for residuals up to `delta` and linear beyond, which keeps the
+/// sensitivity of the MSE to small errors while weighing outliers like the MAE.
+#[derive(Debug)]
+pub struct Huber {
+    /// The size of a residual where the loss turns from quadratic to linear.
+    pub delta: f64,
+}
+
+impl Huber {
+    /// # Panics
+    ///
+    /// When `delta` is not positive.
+    pub fn new(delta: f64) -> Huber {
+        if delta.is_nan() || delta <= 0.0 {
+            panic!("The Huber delta must be positive, not {}", delta);
+        }
+        return Huber { delta };
+    }
+}
+
+impl FitnessMetric for Huber {
+    fn name(&self) -> &'static str {
+        return "huber";
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        let residual = (prediction - target).abs();
+        return if residual <= self.delta { 0.5 * residual * residual } else { self.delta * (residual - 0.5 * self.delta) };
+    }
+
+    fn parameters(&self) -> Vec<f64> {
+        return vec![self.delta];
+    }
+}
+
+/// The quantile (pinball) loss, which is minimized by the `tau` quantile of the target given the
+/// variables rather than its mean, e.g. the median at 0.5 or an upper bound at 0.95.
+#[derive(Debug)]
+pub struct Quantile {
+    /// The quantile to predict, between 0 and 1.
+    pub tau: f64,
+}
+
+impl Quantile {
+    /// # Panics
+    ///
+    /// When `tau` is not strictly between 0 and 1.
+    pub fn new(tau: f64) -> Quantile {
+        if !(0.0 < tau && tau < 1.0) {
+            panic!("The quantile must be between 0 and 1, not {}", tau);
+        }
+        return Quantile { tau };
+    }
+}
+
+impl FitnessMetric for Quantile {
+    fn name(&self) -> &'static str {
+        return "quantile";
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        let residual = target - prediction;
+        return if residual >= 0.0 { self.tau * residual } else { (self.tau - 1.0) * residual };
+    }
+
+    fn parameters(&self) -> Vec<f64> {
+        return vec![self.tau];
+    }
+}
+
+/// The log-loss (binary cross-entropy) of a 0/1 label, with the sigmoid of the prediction as the
+/// probability of the positive class. With several outputs, one score per class, the cross-entropy
+/// of the class label with the softmax of the scores as the probabilities of the classes.
+#[derive(Debug)]
+pub struct LogLoss;
+
+impl FitnessMetric for LogLoss {
+    fn name(&self) -> &'static str {
+        return "log-loss";
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        // -ln(sigmoid(s)) for a positive label and -ln(1 - sigmoid(s)) for a negative one, without overflowing
+        return prediction.max(0.0) - prediction * target + (-prediction.abs()).exp().ln_1p();
+    }
+
+    fn row_loss(&self, predictions: &[f64], row: &[f64]) -> f64 {
+        let label = row[row.len() - 1];
+        let [score] = predictions else {
+            // -ln(softmax(scores)[label]), shifted by the largest score so that exp cannot overflow
+            let max = predictions.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let log_sum = max + predictions.iter().map(|score| (score - max).exp()).sum::<f64>().ln();
+            return log_sum - predictions[label as usize];
+        };
+        return self.loss(*score, label);
+    }
+}
+
+/// The fraction of misclassified labels, so 1 - accuracy, with the classes predicted by `predicted_class`.
+#[derive(Debug)]
+pub struct Accuracy;
+
+impl FitnessMetric for Accuracy {
+    fn name(&self) -> &'static str {
+        return "accuracy";
+    }
+
+    fn is_complement(&self) -> bool {
+        return true;
+    }
+
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        if prediction.is_nan() {
+            return f64::NAN;
+        }
+        return ((prediction > 0.0) != (target > 0.5)) as usize as f64;
+    }
+
+    fn row_loss(&self, predictions: &[f64], row: &[f64]) -> f64 {
+        if predictions.iter().any(|score| score.is_nan()) {
+            return f64::NAN;
+        }
+        return (predicted_class(predictions) as f64 != row[row.len() - 1]) as usize as f64;
+    }
+}
+
+/// The class a classifier predicts from its scores: with a single score, the positive class 1 where
+/// it is positive, and otherwise the class with the highest score.
+pub fn predicted_class(scores: &[f64]) -> usize {
+    if let [score] = scores {
+        return (*score > 0.0) as usize;
+    }
+    return (0..scores.len()).rev().max_by(|&a, &b| scores[a].total_cmp(&scores[b])).unwrap_or(0);
+}
+
+/// The area under the ROC curve of a binary classifier, minimized as 1 - AUC: the chance that a
+/// random negative row scores at least as high as a random positive one, ties counting half. Only
+/// the order of the scores matters, which suits imbalanced classes. It is 0.5 when a class is missing.
+#[derive(Debug)]
+pub struct RocAuc;
+
+impl FitnessMetric for RocAuc {
+    fn name(&self) -> &'static str {
+        return "roc-auc";
+    }
+
+    fn is_complement(&self) -> bool {
+        return true;
+    }
+
+    /// The log-loss, as a stand-in for the rank of a single row.
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        return LogLoss.loss(prediction, target);
+    }
+
+    fn ranks(&self) -> bool {
+        return true;
+    }
+
+    fn rank_error(&self, scored: &[(f64, f64, f64)]) -> f64 {
+        // Each positive row beats the weight of the negative rows below it, and ties with those of the same score
+        let (mut negatives_below, mut wins, mut positives) = (0.0, 0.0, 0.0);
+        for tied in scored.chunk_by(|a, b| a.0 == b.0) {
+            let weight_of = |label: bool| tied.iter().filter(|&&(_, target, _)| (target > 0.5) == label).map(|&(_, _, weight)| weight).sum::<f64>();
+            let (tied_positives, tied_negatives) = (weight_of(true), weight_of(false));
+            wins += tied_positives * (negatives_below + 0.5 * tied_negatives);
+            negatives_below += tied_negatives;
+            positives += tied_positives;
+        }
+        if positives == 0.0 || negatives_below == 0.0 {
+            return 0.5;
+        }
+        return 1.0 - wins / (positives * negatives_below);
+    }
+}
+
+/// The average precision of a binary classifier, the area under its precision-recall curve,
+/// minimized as 1 - AP. Unlike the ROC-AUC, it focuses on how well the rare positive rows are
+/// ranked at the top. It is 0 when there is no positive row.
+#[derive(Debug)]
+pub struct AveragePrecision;
+
+impl FitnessMetric for AveragePrecision {
+    fn name(&self) -> &'static str {
+        return "average-precision";
+    }
+
+    fn is_complement(&self) -> bool {
+        return true;
+    }
+
+    /// The log-loss, as a stand-in for the rank of a single row.
+    fn loss(&self, prediction: f64, target: f64) -> f64 {
+        return LogLoss.loss(prediction, target);
+    }
+
+    fn ranks(&self) -> bool {
+        return true;
+    }
+
+    fn rank_error(&self, scored: &[(f64, f64, f64)]) -> f64 {
+        // From the highest threshold down, each group of tied scores adds its recall times the precision above it
+        let (mut true_positives, mut predicted, mut area) = (0.0, 0.0, 0.0);
+        for tied in scored.chunk_by(|a, b| a.0 == b.0).rev() {
+            let positives = tied.iter().filter(|&&(_, target, _)| target > 0.5).map(|&(_, _, weight)| weight).sum::<f64>();
+            true_positives += positives;
+            predicted += tied.iter().map(|&(_, _, weight)| weight).sum::<f64>();
+            area += positives * true_positives / predicted;
+        }
+        if true_positives == 0.0 {
+            return 1.0;
+        }
+        return 1.0 - area / true_positives;
+    }
+}
+
+/// What the target of a run is.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Task {
+    /// Predict a real value.
+    #[default]
+    Regression,
+    /// Predict a class label. With two classes, 0 and 1, a single expression predicts the positive
+    /// class where it is positive, i.e. where its sigmoid is above one half. With k > 2 classes,
+    /// labelled 0 to k - 1, each of k outputs scores a class and the highest score wins.
+    Classification,
+}
+
+/// How many rows of each actual class were predicted as each class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfusionMatrix {
+    /// `counts[actual][predicted]`.
+    pub counts: Vec<Vec<usize>>,
+}
+
+/// Checks that the labels of `dataset`, its last column, are classes numbered from 0 to `classes` - 1.
+///
+/// # Panics
+///
+/// When a label is not one of the classes, with the row it is on.
+pub fn check_labels(dataset: &Dataset, classes: usize) {
+    let label = |row: &Vec<f64>| row[row.len() - 1];
+    if let Some(row) = dataset.rows.iter().position(|row| !(label(row) >= 0.0 && label(row).fract() == 0.0 && label(row) < classes as f64)) {
+        panic!("Row {} has the label {}, but the classifier only knows the classes 0 to {}", row + 1, label(&dataset.rows[row]), classes - 1);
+    }
+}
+
+impl ConfusionMatrix {
+    /// The confusion matrix of a classifier on `dataset`, whose labels, from 0 to `classes` - 1, are the last column.
+    pub fn new(chromosome: &Chromosome, dataset: &Dataset, classes: usize) -> ConfusionMatrix {
+        let mut counts = vec![vec![0; classes]; classes];
+        for row in &dataset.rows {
+            counts[row[row.len() - 1] as usize][predicted_class(&chromosome.output_values(row))] += 1;
+        }
+        return ConfusionMatrix { counts };
+    }
+}
+
+impl Display for ConfusionMatrix {
+    /// One line per actual class, with the counts of each predicted class.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self.counts.iter().flatten().max().map_or(1, |count| count.to_string().len()).max(self.counts.len().to_string().len());
+        write!(f, "actual \\ predicted")?;
+        for class in 0..self.counts.len() {
+            write!(f, " {:>width$}", class)?;
+        }
+        for (class, row) in self.counts.iter().enumerate() {
+            write!(f, "\n{:>18}", class)?;
+            for count in row {
+                write!(f, " {:>width$}", count)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// A custom fitness, which replaces the error of chromosomes under their metric for objectives a
+/// per-row loss cannot express, e.g. penalizing expressions that use some variable. Lower is
+/// better, and NaN makes the chromosome invalid.
+///
+/// Set it in `PopulationParameters::objective` when driving `gp` as a library:
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use rust_gp::chromosome::Chromosome;
+/// use rust_gp::io::{DatasetOptions, open_dataset, read_csv};
+/// use rust_gp::Objective;
+///
+/// let objective = Objective::new(|chromosome, dataset| {
+///     let uses_x2 = chromosome.function_string_with_names(&dataset.variable_names).contains("x2");
+///     chromosome.error(dataset) * if uses_x2 { 2.0 } else { 1.0 }
+/// });
+///
+/// let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+/// let mut chromosome = Chromosome::new_from_string("x0 + x2", &dataset.variable_names);
+/// let error = chromosome.error(&dataset);
+/// Arc::make_mut(&mut chromosome.layout).objective = Some(objective);
+/// assert_eq!(chromosome.fitness(&dataset), 2.0 * error);
+/// ```
+#[derive(Clone)]
+pub struct Objective(Arc<ObjectiveFn>);
+
+/// The function behind an `Objective`.
+type ObjectiveFn = dyn Fn(&Chromosome, &Dataset) -> f64 + Send + Sync;
+
+impl Objective {
+    /// Wraps `fitness`, which is shared by every chromosome the objective is given to.
+    pub fn new(fitness: impl Fn(&Chromosome, &Dataset) -> f64 + Send + Sync + 'static) -> Objective {
+        return Objective(Arc::new(fitness));
+    }
+
+    /// The fitness of `chromosome` on `dataset`, `f64::MAX` when it is NaN.
+    pub fn fitness(&self, chromosome: &Chromosome, dataset: &Dataset) -> f64 {
+        let fitness = (self.0)(chromosome, dataset);
+        return if fitness.is_nan() { f64::MAX } else { fitness.min(f64::MAX) };
+    }
+}
+
+impl Debug for Objective {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Objective")
+    }
+}
+
+impl PartialEq for Objective {
+    /// The same closure.
+    fn eq(&self, other: &Self) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+/// The metrics without parameters `--metric` can choose from.
+pub fn metrics() -> [Arc<dyn FitnessMetric>; 9] {
+    return [Arc::new(Mse), Arc::new(Mae), Arc::new(Rmse), Arc::new(RSquared), Arc::new(Mape), Arc::new(LogLoss), Arc::new(Accuracy),
+        Arc::new(RocAuc), Arc::new(AveragePrecision)];
+}
+
+/// The metric called `name`.
+///
+/// # Panics
+///
+/// When no metric has that name.
+pub fn metric_named(name: &str) -> Arc<dyn FitnessMetric> {
+    return metrics().into_iter().find(|metric| metric.name() == name).unwrap_or_else(|| panic!("Unknown metric {}", name));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chromosome::{Chromosome, Gene};
+    use crate::functions::{PrimitiveId, Protection};
+    use crate::io::{DatasetOptions, open_dataset, read_csv};
+
+    use super::*;
+
+    #[test]
+    /// Ensures that each metric scores a chromosome from the residuals of its predictions, and that
+    /// the metric is looked up by name
+    fn test_metrics() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let residuals: Vec<f64> = dataset.rows.iter().map(|row| row[0] + row[1] - row[3]).collect();
+        let targets: Vec<f64> = dataset.rows.iter().map(|row| row[3]).collect();
+        let n = residuals.len() as f64;
+        let mse = residuals.iter().map(|r| r * r).sum::<f64>() / n;
+        let mean = targets.iter().sum::<f64>() / n;
+        let variance = targets.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / n;
+        let expected = [
+            ("mse", mse),
+            ("mae", residuals.iter().map(|r| r.abs()).sum::<f64>() / n),
+            ("rmse", mse.sqrt()),
+            ("r2", mse / variance),
+            ("mape", 100.0 * residuals.iter().zip(&targets).map(|(r, t)| (r / t).abs()).sum::<f64>() / n),
+        ];
+        for (name, error) in expected {
+            Arc::make_mut(&mut chromosome.layout).metric = metric_named(name);
+            assert!((chromosome.evaluate_fitness_error(&dataset) - error).abs() < 1e-9, "{}", name);
+            assert!((chromosome.evaluate_fitness_cases(&dataset) - error).abs() < 1e-9, "{}", name);
+        }
+
+        // A target of 0 makes the percentage error of its row invalid
+        assert!(!Mape.loss(3.0, 0.0).is_finite());
+
+        let huber = Huber::new(1.0);
+        assert_eq!((huber.loss(2.5, 2.0), huber.loss(-1.0, 2.0)), (0.125, 2.5));
+        // Underpredictions cost three times as much as overpredictions at the 0.75 quantile
+        let quantile = Quantile::new(0.75);
+        assert_eq!((quantile.loss(1.0, 3.0), quantile.loss(5.0, 3.0)), (1.5, 0.5));
+
+        // Metrics of the same name differ by their parameters
+        let (a, b): (&dyn FitnessMetric, &dyn FitnessMetric) = (&Huber::new(1.0), &Huber::new(5.0));
+        assert!(a != b && a == &Huber::new(1.0) as &dyn FitnessMetric);
+    }
+
+    #[test]
+    /// Ensures that classifiers are scored by the sigmoid of their output, and that the confusion
+    /// matrix counts the predictions of each class
+    fn test_classification() {
+        // -ln(sigmoid(2)) and -ln(1 - sigmoid(2)), also for scores where the sigmoid saturates
+        assert!((LogLoss.loss(2.0, 1.0) - (1.0 + (-2.0f64).exp()).ln()).abs() < 1e-12);
+        assert!((LogLoss.loss(2.0, 0.0) - (1.0 + 2.0f64.exp()).ln()).abs() < 1e-12);
+        assert_eq!(LogLoss.loss(1000.0, 0.0), 1000.0);
+        assert_eq!((Accuracy.loss(0.5, 1.0), Accuracy.loss(-0.5, 1.0), Accuracy.loss(-0.5, 0.0)), (0.0, 1.0, 0.0));
+
+        // Predicts x0 > 1
+        let dataset = read_csv("x0,y\n0,0\n2,1\n3,0\n0.5,1\n4,1".as_bytes(), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Some(-1.0)), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        assert_eq!(ConfusionMatrix::new(&chromosome, &dataset, 2).counts, vec![vec![1, 1], vec![1, 2]]);
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::new(Accuracy);
+        assert!((chromosome.evaluate_fitness_error(&dataset) - 0.4).abs() < 1e-12);
+
+        // Three classes scored by -x0, x0 - 1.5 and 0, so class 2 wins between 0 and 1.5
+        let dataset = read_csv("x0,y\n-1,0\n0.5,2\n1,1\n2,1\n3,0".as_bytes(), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Some(-1.5)), Gene::new_constant(Some(0.0)),
+                                                                   Gene::new_unary2(0, PrimitiveId::NEG), Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_binary2(2, 2, PrimitiveId::ADD)]);
+        chromosome.outputs = vec![3, 4, 5];
+        assert_eq!(ConfusionMatrix::new(&chromosome, &dataset, 3).counts, vec![vec![1, 1, 0], vec![0, 1, 1], vec![0, 0, 1]]);
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::new(Accuracy);
+        assert!((chromosome.evaluate_fitness_error(&dataset) - 0.4).abs() < 1e-12);
+        // The cross-entropy of the first row, whose scores are 1, -2.5 and 0
+        let expected = (1.0f64.exp() + (-2.5f64).exp() + 1.0).ln() - 1.0;
+        assert!((LogLoss.row_loss(&[1.0, -2.5, 0.0], &dataset.rows[0]) - expected).abs() < 1e-12);
+        assert_eq!(predicted_class(&[1.0, 3.0, 3.0]), 1, "ties go to the first class");
+    }
+
+    #[test]
+    /// Ensures that the multiclass log-loss is the cross-entropy of the softmax of the scores, and
+    /// that the predicted class is the one with the highest score
+    fn test_multiclass_log_loss() {
+        let row = |label: f64| vec![0.0, label];
+        // Equal scores give each of three classes a probability of 1/3
+        assert!((LogLoss.row_loss(&[0.0, 0.0, 0.0], &row(1.0)) - 3.0f64.ln()).abs() < 1e-12);
+        // Scores of ln 2, 0 and 0 give probabilities of 1/2, 1/4 and 1/4
+        assert!((LogLoss.row_loss(&[2.0f64.ln(), 0.0, 0.0], &row(0.0)) - 2.0f64.ln()).abs() < 1e-12);
+        assert!((LogLoss.row_loss(&[2.0f64.ln(), 0.0, 0.0], &row(2.0)) - 4.0f64.ln()).abs() < 1e-12);
+        // Large scores do not overflow
+        assert!(LogLoss.row_loss(&[1000.0, 0.0, 0.0, 0.0], &row(0.0)).abs() < 1e-12);
+        assert!((LogLoss.row_loss(&[1000.0, 0.0, 0.0, 0.0], &row(3.0)) - 1000.0).abs() < 1e-9);
+
+        assert_eq!([predicted_class(&[0.2, -1.0, 0.7]), predicted_class(&[3.0, -1.0, 0.7, 2.9])], [2, 0]);
+        assert_eq!([predicted_class(&[-0.1]), predicted_class(&[0.1])], [0, 1]);
+
+        // The three-class chromosome of test_classification, scoring -x0, x0 - 1.5 and 0. The
+        // cross-entropies of its rows are 0.3351, 0.6803, 1.1803, 0.5239 and 4.7105
+        let dataset = read_csv("x0,y\n-1,0\n0.5,2\n1,1\n2,1\n3,0".as_bytes(), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Some(-1.5)), Gene::new_constant(Some(0.0)),
+                                                                   Gene::new_unary2(0, PrimitiveId::NEG), Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_binary2(2, 2, PrimitiveId::ADD)]);
+        chromosome.outputs = vec![3, 4, 5];
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::new(LogLoss);
+        assert!((chromosome.evaluate_fitness_error(&dataset) - 1.4860001411503532).abs() < 1e-12);
+        let classes: Vec<usize> = dataset.rows.iter().map(|row| predicted_class(&chromosome.output_values(row))).collect();
+        assert_eq!(classes, vec![0, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    /// Ensures that the ranking metrics only depend on the order of the scores, and weigh and tie rows correctly
+    fn test_ranking_metrics() {
+        let scored = [(0.1, 0.0, 1.0), (0.35, 1.0, 1.0), (0.4, 0.0, 1.0), (0.8, 1.0, 1.0)];
+        assert!((RocAuc.rank_error(&scored) - 0.25).abs() < 1e-12);
+        assert!((AveragePrecision.rank_error(&scored) - (1.0 - 5.0 / 6.0)).abs() < 1e-12);
+        // A positive row tied with a negative one wins half the time, and a weight of 2 counts twice
+        assert!((RocAuc.rank_error(&[(0.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 1.0, 2.0)]) - 0.25).abs() < 1e-12);
+        assert_eq!(RocAuc.rank_error(&[(0.0, 1.0, 1.0), (1.0, 1.0, 1.0)]), 0.5);
+
+        // x0 - 1 ranks the rows of the classification example above, where a NaN score is invalid
+        let dataset = read_csv("x0,y\n0,0\n2,1\n3,0\n0.5,1\n4,1".as_bytes(), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Some(-1.0)), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::new(RocAuc);
+        assert!((chromosome.evaluate_fitness_error(&dataset) - (1.0 - 4.0 / 6.0)).abs() < 1e-12);
+        assert_eq!(chromosome.evaluate_fitness_cases(&dataset), chromosome.fitness_value);
+        chromosome.genes[2] = Gene::new_unary2(0, PrimitiveId::LOG).protected(Protection::Nan);
+        assert_eq!(chromosome.evaluate_fitness_error(&dataset), f64::MAX, "the log of 0 is not finite");
+    }
+
+    #[test]
+    /// Ensures that a custom objective replaces the error as the fitness, and can build on it
+    fn test_objective() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let error = chromosome.evaluate_fitness_error(&dataset);
+        // Penalize using x1
+        Arc::make_mut(&mut chromosome.layout).objective = Some(Objective::new(|chromosome, dataset| {
+            chromosome.error(dataset) + if chromosome.function_string_with_names(&dataset.variable_names).contains("x1") { 10.0 } else { 0.0 }
+        }));
+        assert_eq!(chromosome.evaluate_fitness_error(&dataset), error + 10.0);
+        assert_eq!(chromosome.evaluate_fitness_cases(&dataset), error + 10.0);
+        assert_eq!(chromosome.fitness(&dataset), error + 10.0);
+
+        Arc::make_mut(&mut chromosome.layout).objective = Some(Objective::new(|_, _| f64::NAN));
+        assert_eq!(chromosome.evaluate_fitness_error(&dataset), f64::MAX);
+    }
+}
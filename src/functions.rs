@@ -1,15 +1,401 @@
-use rand::Rng;
+use std::sync::{Arc, LazyLock, RwLock, RwLockReadGuard};
 
-pub fn get_unary_function() -> fn(f64, f64) -> (f64, String) {
-    let unary_functions: Vec<fn(f64, f64) -> (f64, String)> = vec![square, log2];
-    let random_string_index: usize = rand::thread_rng().gen_range(0..unary_functions.len());
-    unary_functions[random_string_index]
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::rng::{random, rng};
+
+/// A primitive operation. Returns the result together with the name of the operation.
+pub type Operation = &'static (dyn Fn(f64, f64) -> (f64, String) + Send + Sync);
+
+/// Identifies a primitive by its position in the `FunctionRegistry`: the built-in primitives have
+/// their positions in `PRIMITIVES`, and the registered ones follow in the order they were registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PrimitiveId(pub usize);
+
+impl PrimitiveId {
+    pub const ADD: PrimitiveId = PrimitiveId(0);
+    pub const SUB: PrimitiveId = PrimitiveId(1);
+    pub const MUL: PrimitiveId = PrimitiveId(2);
+    pub const DIV: PrimitiveId = PrimitiveId(3);
+    pub const MAX: PrimitiveId = PrimitiveId(4);
+    pub const MIN: PrimitiveId = PrimitiveId(5);
+    pub const SQUARE: PrimitiveId = PrimitiveId(6);
+    pub const LOG2: PrimitiveId = PrimitiveId(7);
+    pub const SIN: PrimitiveId = PrimitiveId(8);
+    pub const COS: PrimitiveId = PrimitiveId(9);
+    pub const TAN: PrimitiveId = PrimitiveId(10);
+    pub const EXP: PrimitiveId = PrimitiveId(11);
+    pub const LOG: PrimitiveId = PrimitiveId(12);
+    pub const SQRT: PrimitiveId = PrimitiveId(13);
+    pub const ABS: PrimitiveId = PrimitiveId(14);
+    pub const NEG: PrimitiveId = PrimitiveId(15);
+    pub const INV: PrimitiveId = PrimitiveId(16);
+    pub const CUBE: PrimitiveId = PrimitiveId(17);
+    pub const TANH: PrimitiveId = PrimitiveId(18);
+    pub const FLOOR: PrimitiveId = PrimitiveId(19);
+    pub const AND: PrimitiveId = PrimitiveId(20);
+    pub const OR: PrimitiveId = PrimitiveId(21);
+    pub const XOR: PrimitiveId = PrimitiveId(22);
+    pub const NOT: PrimitiveId = PrimitiveId(23);
+    pub const LT: PrimitiveId = PrimitiveId(24);
+    pub const GT: PrimitiveId = PrimitiveId(25);
+    pub const IFLT: PrimitiveId = PrimitiveId(26);
+    /// The function of constant and variable genes, which is not in the registry.
+    pub const NOTHING: PrimitiveId = PrimitiveId(usize::MAX);
+
+    /// The operation of the primitive, unprotected. Built-in primitives are looked up without
+    /// locking the registry.
+    ///
+    /// # Panics
+    ///
+    /// When the primitive is not in the registry.
+    pub fn operation(self) -> Operation {
+        return match PRIMITIVES.get(self.0) {
+            Some(&(_, _, operation)) => operation,
+            None => FunctionRegistry::global().primitive(self).operation,
+        };
+    }
+}
+
+/// What the protected functions return where their plain versions are undefined: division by 0, and
+/// the logarithms or square root of a negative number (or the logarithms of 0).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Protection {
+    /// Division by 0 gives ±f64::MAX, and log, log2 and sqrt use the magnitude of their input, with log(0) = log2(0) = 0.
+    Standard,
+    /// 1.
+    One,
+    /// The numerator of a division, or the input of log, log2 and sqrt.
+    Numerator,
+    /// NaN, which makes the fitness of the whole chromosome invalid.
+    Nan,
+}
+
+impl Default for Protection {
+    fn default() -> Self {
+        return Protection::Standard;
+    }
+}
+
+impl Protection {
+    /// The version of the operation of `primitive` that follows this policy. Primitives that need no
+    /// protection, and every primitive under `Standard`, keep their operation.
+    pub fn protect(&self, primitive: PrimitiveId) -> Operation {
+        return match (self, primitive) {
+            (Protection::One, PrimitiveId::DIV) => &|x, y| (if y == 0.0 { 1.0 } else { x / y }, "truediv".to_string()),
+            (Protection::Numerator, PrimitiveId::DIV) => &|x, y| (if y == 0.0 { x } else { x / y }, "truediv".to_string()),
+            (Protection::Nan, PrimitiveId::DIV) => &|x, y| (if y == 0.0 { f64::NAN } else { x / y }, "truediv".to_string()),
+            (Protection::One | Protection::Numerator, PrimitiveId::INV) => &|x, _y| (if x == 0.0 { 1.0 } else { 1.0 / x }, "inv".to_string()),
+            (Protection::Nan, PrimitiveId::INV) => &|x, _y| (if x == 0.0 { f64::NAN } else { 1.0 / x }, "inv".to_string()),
+            (Protection::One, PrimitiveId::LOG) => &|x, _y| (if x > 0.0 { x.ln() } else { 1.0 }, "log".to_string()),
+            (Protection::Numerator, PrimitiveId::LOG) => &|x, _y| (if x > 0.0 { x.ln() } else { x }, "log".to_string()),
+            (Protection::Nan, PrimitiveId::LOG) => &|x, _y| (if x > 0.0 { x.ln() } else { f64::NAN }, "log".to_string()),
+            (Protection::One, PrimitiveId::LOG2) => &|x, _y| (if x > 0.0 { x.log2() } else { 1.0 }, "log2".to_string()),
+            (Protection::Numerator, PrimitiveId::LOG2) => &|x, _y| (if x > 0.0 { x.log2() } else { x }, "log2".to_string()),
+            (Protection::Nan, PrimitiveId::LOG2) => &|x, _y| (if x > 0.0 { x.log2() } else { f64::NAN }, "log2".to_string()),
+            (Protection::One, PrimitiveId::SQRT) => &|x, _y| (if x >= 0.0 { x.sqrt() } else { 1.0 }, "sqrt".to_string()),
+            (Protection::Numerator, PrimitiveId::SQRT) => &|x, _y| (if x >= 0.0 { x.sqrt() } else { x }, "sqrt".to_string()),
+            (Protection::Nan, PrimitiveId::SQRT) => &|x, _y| (if x >= 0.0 { x.sqrt() } else { f64::NAN }, "sqrt".to_string()),
+            _ => primitive.operation(),
+        };
+    }
+}
+
+/// The built-in primitives, with the name `--functions` knows each by and its arity. Division
+/// prints as `truediv`, and the conditional gene compares with `less_than`. Each is at the position
+/// of its `PrimitiveId`.
+pub const PRIMITIVES: [(&str, usize, Operation); 27] = [
+    ("add", 2, &add), ("sub", 2, &subtract), ("mul", 2, &multiply), ("div", 2, &divide), ("max", 2, &max), ("min", 2, &min),
+    ("square", 1, &square), ("log2", 1, &log2), ("sin", 1, &sin), ("cos", 1, &cos), ("tan", 1, &tan), ("exp", 1, &exp),
+    ("log", 1, &log), ("sqrt", 1, &sqrt), ("abs", 1, &abs), ("neg", 1, &negate), ("inv", 1, &reciprocal), ("cube", 1, &cube),
+    ("tanh", 1, &tanh), ("floor", 1, &floor),
+    ("and", 2, &and), ("or", 2, &or), ("xor", 2, &xor), ("not", 1, &not), ("lt", 2, &less_than), ("gt", 2, &greater_than),
+    ("iflt", 4, &less_than),
+];
+
+/// Writes a primitive applied to its printed inputs, e.g. `(a + b) / 2`.
+pub type Printer = Arc<dyn Fn(&[String]) -> String + Send + Sync>;
+
+/// The types of values genes compute, used by strongly typed GP to wire genes only to inputs of the
+/// type they take. Booleans are 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    Real,
+    Bool,
+}
+
+/// A primitive that function sets can select.
+#[derive(Clone)]
+pub struct Primitive {
+    /// The name `--functions` knows the primitive by.
+    pub name: String,
+    pub arity: usize,
+    /// What genes with this primitive call.
+    pub operation: Operation,
+    /// The name the operation returns, which genes are printed and recorded with.
+    pub operator: String,
+    /// The type of every input of the primitive. The conditional takes real inputs.
+    pub input: ValueType,
+    /// The type of the output of the primitive.
+    pub output: ValueType,
+    /// How the primitive is printed. `None` prints the name of the operation followed by the inputs
+    /// in parentheses.
+    pub printer: Option<Printer>,
+}
+
+/// The primitives function genes can use: the built-in `PRIMITIVES`, followed by the ones registered
+/// at run time, in the order they were registered. There is one registry for the whole run, see
+/// `FunctionRegistry::global`.
+pub struct FunctionRegistry {
+    primitives: Vec<Primitive>,
+}
+
+static REGISTRY: LazyLock<RwLock<FunctionRegistry>> = LazyLock::new(|| RwLock::new(FunctionRegistry {
+    primitives: PRIMITIVES.iter()
+        .map(|&(name, arity, operation)| {
+            let (input, output) = match name {
+                "and" | "or" | "xor" | "not" => (ValueType::Bool, ValueType::Bool),
+                "lt" | "gt" => (ValueType::Real, ValueType::Bool),
+                _ => (ValueType::Real, ValueType::Real),
+            };
+            Primitive { name: name.to_string(), arity, operation, operator: operation(0.0, 0.0).1, input, output, printer: None }
+        })
+        .collect(),
+}));
+
+impl FunctionRegistry {
+    /// The registry of the run.
+    pub fn global() -> RwLockReadGuard<'static, FunctionRegistry> {
+        return REGISTRY.read().unwrap();
+    }
+
+    /// Adds a primitive to the registry of the run. Function sets created afterwards can select it
+    /// by name, see `FunctionSet::from_names` and `FunctionSet::including`. The presets never include
+    /// it. Registered primitives take and return real values.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the primitive is selected and, without a printer, printed by.
+    /// * `arity` - How many inputs the primitive takes, 1 or 2.
+    /// * `implementation` - Computes the output from the inputs. Unary primitives get 0 as their second input.
+    /// * `printer` - Writes the primitive applied to its printed inputs. `None` writes `name(inputs)`.
+    ///
+    /// # Returns
+    ///
+    /// The id of the new primitive.
+    ///
+    /// # Panics
+    ///
+    /// When the name is taken, or the arity is not 1 or 2.
+    pub fn register(name: &str, arity: usize, implementation: impl Fn(f64, f64) -> f64 + Send + Sync + 'static, printer: Option<Printer>) -> PrimitiveId {
+        let mut registry = REGISTRY.write().unwrap();
+        if registry.primitives.iter().any(|primitive| primitive.name == name) {
+            panic!("There is already a primitive called {}", name);
+        }
+        if !(1..=2).contains(&arity) {
+            panic!("The primitive {} must take one or two inputs, not {}", name, arity);
+        }
+        // Genes share operations by reference, and a name is registered at most once, so each
+        // registered operation is leaked once and lives for the rest of the run
+        let printed = name.to_string();
+        let operation: Operation = Box::leak(Box::new(move |x, y| (implementation(x, if arity == 1 { 0.0 } else { y }), printed.clone())));
+        registry.primitives.push(Primitive {
+            name: name.to_string(),
+            arity,
+            operation,
+            operator: name.to_string(),
+            input: ValueType::Real,
+            output: ValueType::Real,
+            printer,
+        });
+        return PrimitiveId(registry.primitives.len() - 1);
+    }
+
+    /// Every primitive of the registry, at the positions of their `PrimitiveId`.
+    pub fn primitives(&self) -> &[Primitive] {
+        return &self.primitives;
+    }
+
+    /// The primitive identified by `id`.
+    ///
+    /// # Panics
+    ///
+    /// When the primitive is not in the registry.
+    pub fn primitive(&self, id: PrimitiveId) -> &Primitive {
+        return self.primitives.get(id.0).unwrap_or_else(|| panic!("There is no primitive {:?}", id));
+    }
+
+    /// The primitive that `--functions` or a saved model knows by `name`, either the name it is
+    /// selected by or the name its operation prints, that takes `arity` inputs.
+    pub fn find(&self, name: &str, arity: usize) -> Option<PrimitiveId> {
+        return self.primitives.iter()
+            .position(|primitive| (primitive.name == name || primitive.operator == name) && primitive.arity == arity)
+            .map(PrimitiveId);
+    }
+
+    /// The input and output types of `primitive`. Primitives that are not in the registry take and
+    /// return real values.
+    pub fn signature(&self, primitive: PrimitiveId) -> (ValueType, ValueType) {
+        return self.primitives.get(primitive.0).map_or((ValueType::Real, ValueType::Real), |primitive| (primitive.input, primitive.output));
+    }
+
+    /// Writes `primitive` applied to the printed `inputs`, with its printer if it has one.
+    pub fn print(&self, primitive: PrimitiveId, inputs: &[String]) -> String {
+        let primitive = self.primitive(primitive);
+        return match &primitive.printer {
+            Some(printer) => printer(inputs),
+            None => format!("{}({})", primitive.operator, inputs.join(", ")),
+        };
+    }
+}
+
+/// The probability that a new function gene is a conditional rather than a unary or binary gene,
+/// when the function set has the conditional.
+pub const CONDITIONAL_PROBABILITY: f64 = 0.1;
+
+/// The primitives that the functions of new function genes are drawn from, a selection of
+/// the primitives of the `FunctionRegistry` with a sampling weight for each.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSet {
+    /// The weight of each primitive by `PrimitiveId`, 0 for the primitives that are not in the set
+    /// and for the ones registered after the set was created. Shared by the chromosomes that copy the set.
+    weights: Arc<[f64]>,
+}
+
+impl Default for FunctionSet {
+    fn default() -> Self {
+        return FunctionSet::arithmetic();
+    }
+}
+
+impl FunctionSet {
+    /// Arithmetic, trigonometric and other real functions and the conditional, for symbolic regression.
+    /// Like the other presets, it only holds built-in primitives, see `FunctionSet::including`.
+    pub fn arithmetic() -> FunctionSet {
+        let names = ["add", "sub", "mul", "div", "max", "min", "square", "log2", "sin", "cos", "tan", "exp", "log", "sqrt", "abs",
+                     "neg", "inv", "cube", "tanh", "floor", "iflt"];
+        return FunctionSet::from_names(&names);
+    }
+
+    /// Logic on inputs thresholded at `TRUTH_THRESHOLD`, comparisons and the conditional, all of
+    /// which output 0 or 1 on 0/1 inputs, for rule induction on binary features.
+    pub fn boolean() -> FunctionSet {
+        let names = ["and", "or", "xor", "not", "lt", "gt", "iflt"];
+        return FunctionSet::from_names(&names);
+    }
+
+    /// The set of the primitives named in `names`, all equally likely, see `FunctionRegistry`.
+    ///
+    /// # Panics
+    ///
+    /// When a name is not a primitive, or no name is given.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> FunctionSet {
+        return FunctionSet::from_weights(&names.iter().map(|name| (name.as_ref(), 1.0)).collect::<Vec<(&str, f64)>>());
+    }
+
+    /// The set of the primitives named in `weights`, each drawn in proportion to its weight among
+    /// the functions of the set with the same arity.
+    ///
+    /// # Panics
+    ///
+    /// When a name is not a primitive, a weight is not positive, or no primitive is given.
+    pub fn from_weights(weights: &[(&str, f64)]) -> FunctionSet {
+        if weights.is_empty() {
+            panic!("The function set needs at least one function");
+        }
+        let registry = FunctionRegistry::global();
+        let mut set = vec![0.0; registry.primitives().len()];
+        for &(name, weight) in weights {
+            let Some(index) = registry.primitives().iter().position(|primitive| primitive.name == name) else {
+                let known: Vec<&str> = registry.primitives().iter().map(|primitive| primitive.name.as_str()).collect();
+                panic!("Unknown function {}, the functions are {}", name, known.join(", "));
+            };
+            if !(weight > 0.0 && weight.is_finite()) {
+                panic!("The weight of {} must be positive", name);
+            }
+            set[index] = weight;
+        }
+        return FunctionSet { weights: set.into() };
+    }
+
+    /// The set with the primitives named in `names` added with a weight of 1, e.g. to add registered
+    /// primitives to a preset. Primitives already in the set keep their weight.
+    ///
+    /// # Panics
+    ///
+    /// When a name is not a primitive.
+    pub fn including<S: AsRef<str>>(&self, names: &[S]) -> FunctionSet {
+        let mut weights: Vec<(String, f64)> = FunctionRegistry::global().primitives().iter().zip(self.weights.iter().copied())
+            .filter(|(_, weight)| *weight > 0.0)
+            .map(|(primitive, weight)| (primitive.name.clone(), weight))
+            .collect();
+        for name in names {
+            if !weights.iter().any(|(included, _)| included == name.as_ref()) {
+                weights.push((name.as_ref().to_string(), 1.0));
+            }
+        }
+        return FunctionSet::from_weights(&weights.iter().map(|(name, weight)| (name.as_str(), *weight)).collect::<Vec<(&str, f64)>>());
+    }
+
+    /// The functions of the set that take `arity` inputs, with their weights.
+    pub fn weighted_functions(&self, arity: usize) -> Vec<(Operation, f64)> {
+        return FunctionRegistry::global().primitives().iter().zip(self.weights.iter().copied())
+            .filter(|(primitive, weight)| *weight > 0.0 && primitive.arity == arity)
+            .map(|(primitive, weight)| (primitive.operation, weight))
+            .collect();
+    }
+
+    /// The functions of the set that take `arity` inputs.
+    pub fn functions(&self, arity: usize) -> Vec<Operation> {
+        return self.weighted_functions(arity).into_iter().map(|(operation, _)| operation).collect();
+    }
+
+    /// A random primitive of the set that takes `arity` inputs, drawn in proportion to the weights,
+    /// `None` when the set has none.
+    pub fn random_of_arity(&self, arity: usize) -> Option<PrimitiveId> {
+        return self.random_where(|primitive| primitive.arity == arity);
+    }
+
+    /// A random primitive of the set that takes `arity` inputs of type `input` and outputs a value of
+    /// type `output`, drawn in proportion to the weights, `None` when the set has none.
+    pub fn random_typed(&self, arity: usize, input: ValueType, output: ValueType) -> Option<PrimitiveId> {
+        return self.random_where(|primitive| primitive.arity == arity && primitive.input == input && primitive.output == output);
+    }
+
+    /// A random primitive of the set that satisfies `predicate`, drawn in proportion to the weights.
+    fn random_where(&self, predicate: impl Fn(&Primitive) -> bool) -> Option<PrimitiveId> {
+        let primitives: Vec<(PrimitiveId, f64)> = FunctionRegistry::global().primitives().iter().zip(self.weights.iter().copied()).enumerate()
+            .filter(|(_, (primitive, weight))| *weight > 0.0 && predicate(primitive))
+            .map(|(index, (_, weight))| (PrimitiveId(index), weight))
+            .collect();
+        return primitives.choose_weighted(&mut rng(), |(_, weight)| *weight).ok().map(|(primitive, _)| *primitive);
+    }
+
+    /// A random primitive of the set and its arity. The conditional, if selected, is drawn with
+    /// `CONDITIONAL_PROBABILITY`, and otherwise unary and binary functions are equally likely, so
+    /// the weights only compare functions of the same arity.
+    pub fn random(&self) -> (PrimitiveId, usize) {
+        let conditional = self.random_of_arity(4).filter(|_| random::<f64>() < CONDITIONAL_PROBABILITY);
+        let unary = self.random_of_arity(1);
+        let binary = self.random_of_arity(2);
+        return match (conditional, unary, binary) {
+            (Some(primitive), _, _) => (primitive, 4),
+            (None, Some(primitive), None) => (primitive, 1),
+            (None, Some(primitive), Some(_)) if random() => (primitive, 1),
+            (None, _, Some(primitive)) => (primitive, 2),
+            // Only the conditional is selected
+            (None, None, None) => (self.random_of_arity(4).unwrap(), 4),
+        };
+    }
+}
+
+pub fn get_unary_function() -> PrimitiveId {
+    FunctionSet::arithmetic().random_of_arity(1).unwrap()
 }
 
-pub fn get_binary_function() -> fn(f64, f64) -> (f64, String) {
-    let binary_functions: Vec<fn(f64, f64) -> (f64, String)> = vec![add, subtract, divide, multiply, max, min];
-    let random_string_index: usize = rand::thread_rng().gen_range(0..binary_functions.len());
-    binary_functions[random_string_index]
+pub fn get_binary_function() -> PrimitiveId {
+    FunctionSet::arithmetic().random_of_arity(2).unwrap()
 }
 
 // Binary Functions
@@ -22,7 +408,7 @@ pub fn subtract(x: f64, y: f64) -> (f64, String) {
 }
 
 pub fn divide(x: f64, y: f64) -> (f64, String) {
-    (if y == 0.0 { if x >= 0.0 { f64::MAX } else { -1.0 * f64::MAX } } else { x / y }, "truediv".to_string())
+    (if y == 0.0 { if x >= 0.0 { f64::MAX } else { -f64::MAX } } else { x / y }, "truediv".to_string())
 }
 
 pub fn multiply(x: f64, y: f64) -> (f64, String) {
@@ -37,12 +423,95 @@ pub fn min(x: f64, y: f64) -> (f64, String) {
     (x.min(y), "min".to_string())
 }
 
+// Comparisons, also used by conditional genes. They return 1 when they hold and 0 otherwise.
+pub fn less_than(x: f64, y: f64) -> (f64, String) {
+    (if x < y { 1.0 } else { 0.0 }, "lt".to_string())
+}
+
+pub fn greater_than(x: f64, y: f64) -> (f64, String) {
+    (if x > y { 1.0 } else { 0.0 }, "gt".to_string())
+}
+
+// Logic functions. Their inputs are true above `TRUTH_THRESHOLD`, and they return 1 for true and 0 for false.
+pub const TRUTH_THRESHOLD: f64 = 0.5;
+
+fn truth(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+pub fn and(x: f64, y: f64) -> (f64, String) {
+    (truth(x > TRUTH_THRESHOLD && y > TRUTH_THRESHOLD), "and".to_string())
+}
+
+pub fn or(x: f64, y: f64) -> (f64, String) {
+    (truth(x > TRUTH_THRESHOLD || y > TRUTH_THRESHOLD), "or".to_string())
+}
+
+pub fn xor(x: f64, y: f64) -> (f64, String) {
+    (truth((x > TRUTH_THRESHOLD) != (y > TRUTH_THRESHOLD)), "xor".to_string())
+}
+
+pub fn not(x: f64, _y: f64) -> (f64, String) {
+    (truth(x <= TRUTH_THRESHOLD), "not".to_string())
+}
 
 // Unary Functions
 pub fn square(x: f64, _y: f64) -> (f64, String) {
     (x * x, "square".to_string())
 }
 
+/// Protected base-2 logarithm: the logarithm of the magnitude, and 0 for 0.
 pub fn log2(x: f64, _y: f64) -> (f64, String) {
-    (x.log2(), "log2".to_string())
-}
\ No newline at end of file
+    (if x == 0.0 { 0.0 } else { x.abs().log2() }, "log2".to_string())
+}
+
+pub fn sin(x: f64, _y: f64) -> (f64, String) {
+    (x.sin(), "sin".to_string())
+}
+
+pub fn cos(x: f64, _y: f64) -> (f64, String) {
+    (x.cos(), "cos".to_string())
+}
+
+pub fn tan(x: f64, _y: f64) -> (f64, String) {
+    (x.tan(), "tan".to_string())
+}
+
+pub fn exp(x: f64, _y: f64) -> (f64, String) {
+    (x.exp(), "exp".to_string())
+}
+
+/// Protected natural logarithm: the logarithm of the magnitude, and 0 for 0.
+pub fn log(x: f64, _y: f64) -> (f64, String) {
+    (if x == 0.0 { 0.0 } else { x.abs().ln() }, "log".to_string())
+}
+
+/// Protected square root: the square root of the magnitude.
+pub fn sqrt(x: f64, _y: f64) -> (f64, String) {
+    (x.abs().sqrt(), "sqrt".to_string())
+}
+
+pub fn abs(x: f64, _y: f64) -> (f64, String) {
+    (x.abs(), "abs".to_string())
+}
+
+pub fn negate(x: f64, _y: f64) -> (f64, String) {
+    (-x, "neg".to_string())
+}
+
+/// Protected reciprocal, which handles 0 like `divide`.
+pub fn reciprocal(x: f64, _y: f64) -> (f64, String) {
+    (divide(1.0, x).0, "inv".to_string())
+}
+
+pub fn cube(x: f64, _y: f64) -> (f64, String) {
+    (x * x * x, "cube".to_string())
+}
+
+pub fn tanh(x: f64, _y: f64) -> (f64, String) {
+    (x.tanh(), "tanh".to_string())
+}
+
+pub fn floor(x: f64, _y: f64) -> (f64, String) {
+    (x.floor(), "floor".to_string())
+}
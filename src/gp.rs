@@ -1,25 +1,356 @@
+use std::sync::Arc;
 use std::time::Instant;
 
-use crate::io::{Dataset, DataToWrite};
-use crate::population::{Population, PopulationTraits};
+use crate::bandit::{annealed_sigma, OperatorBandit};
+use crate::chromosome::{Chromosome, Distance, Layout, MutationOperator, OperatorRates};
+use crate::fitness::Task;
+use crate::hall_of_fame::{HallOfFame, ParetoArchive};
+use crate::interval::variable_bounds;
+use crate::io::{Dataset, DatasetTraits, DataToWrite};
+use crate::model::Model;
+use crate::optimize::{hill_climb, optimize_constants};
+use crate::report::Report;
+use crate::rng;
+use crate::population::{NoveltySearch, Population, RateAdaptation, success_rate, PopulationParameters, PopulationTraits, Selection, Strategy, SubsamplePolicy};
+use tracing::{debug, info, info_span, trace};
 
-pub fn gp(gen: usize, pop_size: usize, num_genes: usize, mut_chance: f64, crossover_chance: f64, dataset: Dataset) {
+pub fn gp(params: PopulationParameters, dataset: Dataset) {
     let now = Instant::now();
-    let mut population = Population::initialize(pop_size, num_genes, &dataset);
+    rng::seed(params.seed);
+    #[cfg(feature = "metrics")]
+    let mut metrics = params.metrics_address.as_deref().map(crate::metrics::Metrics::serve);
+    #[cfg(not(feature = "metrics"))]
+    if params.metrics_address.is_some() {
+        panic!("Serving metrics requires building with the metrics feature");
+    }
+    let weighted = params.multigene.is_some() || params.linear_scaling;
+    if weighted && dataset.target_names.len() > 1 {
+        panic!("Multi-gene GP and linear scaling fit a single prediction, so they need a single target column");
+    }
+    // Text labels are ordinal encoded, so they are already numbered from 0. The classes are
+    // counted over the validation rows as well, so that held-out rows cannot have a class the run does not know
+    let classes = match params.task {
+        Task::Regression => 0,
+        Task::Classification => {
+            if dataset.target_names.len() > 1 {
+                panic!("Classification needs a single target column of class labels");
+            }
+            let label = |row: &Vec<f64>| row[row.len() - 1];
+            let rows = || dataset.rows.iter().chain(&dataset.validation_rows);
+            if let Some(row) = rows().find(|row| label(row) < 0.0 || label(row).fract() != 0.0) {
+                panic!("Classification needs class labels numbered from 0, but a row has the label {}", label(row));
+            }
+            let classes = rows().map(|row| label(row) as usize + 1).max().unwrap().max(2);
+            if classes > 2 {
+                if weighted || params.local_learning.is_some() {
+                    panic!("Multi-class classification cannot be used with multi-gene GP, linear scaling or local learning");
+                }
+                if params.objective.is_none() && !matches!(params.metric.name(), "log-loss" | "accuracy") {
+                    panic!("Multi-class classification needs the log-loss or accuracy metric, not {}", params.metric.name());
+                }
+            }
+            classes
+        }
+    };
+    // The validation rows were held out when the dataset was loaded, before imputation and scaling were fitted
+    let validation = (!dataset.validation_rows.is_empty()).then(|| dataset.with_rows(dataset.validation_rows.clone()));
+    // Linear scaling is multi-gene GP with a single root, whose weights are the slope and intercept
+    let layout = Layout {
+        grid: params.grid,
+        constants: params.constants,
+        constant_domain: params.constant_domain,
+        protection: params.protection,
+        function_set: params.function_set.clone(),
+        typed: params.typed,
+        grammar: params.grammar.clone(),
+        invalid_rows: params.invalid_rows,
+        metric: Arc::clone(&params.metric),
+        objective: params.objective.clone(),
+        outputs: params.multigene.unwrap_or(match classes {
+            // One score per class
+            3.. => classes,
+            _ if params.linear_scaling => 1,
+            _ => dataset.target_names.len(),
+        }),
+        multigene: weighted,
+    };
+    let mut population = Population::initialize(params.population_size, params.num_genes, &dataset, layout);
+    population.keep_case_errors = params.selection.uses_case_errors();
+    population.sharing_radius = params.sharing_radius;
+    population.limits = params.limits;
+    population.units = params.units.clone();
+    // An infinite score is a confident class rather than an invalid one, so classifiers are not prescreened
+    population.bounds = (params.prescreen && params.task == Task::Regression).then(|| variable_bounds(&dataset));
+    population.local_learning = params.local_learning;
+    population.memetic = params.memetic;
+    population.duplicates = params.duplicates;
+    if params.self_adaptive {
+        // Random chromosomes created later on (e.g. by restarts) use the rates of the run until they breed
+        let rates = OperatorRates { mutation: params.mut_chance, crossover: params.crossover_chance };
+        population.population.iter_mut().for_each(|c| c.rates = Some(rates));
+    }
+    population.distance = params.distance;
+    population.semantics = params.crossover.is_semantic()
+        || params.mutation_operators.iter().any(|&(operator, rate)| operator == MutationOperator::Backpropagation && rate > 0.0);
+    // Recording behaviors costs an evaluation on the probe rows, so it is only done when something
+    // compares them. The phenotypic diversity is not reported otherwise
+    if params.distance == Distance::Behavioral || params.novelty.is_some() || population.semantics {
+        population.probe = Some(Arc::new(dataset.sample_rows(params.probe_size)));
+    }
+    population.novelty = params.novelty.map(|weight| NoveltySearch::new(weight, params.novelty_neighbours));
+    if params.selection == Selection::EpsilonLexicase {
+        population.case_epsilons = Some(Vec::new());
+    }
     let mut fitness_graph: Vec<DataToWrite> = vec![];
+    let mut hall_of_fame = HallOfFame::new(params.hall_of_fame);
+    let mut pareto_archive = params.pareto_front_path.as_ref().map(|_| ParetoArchive::new());
+    // The training best with the lowest validation error seen so far, and that error
+    let mut champion: Option<(Chromosome, f64)> = None;
+    // Early stopping state: the best score so far and how many generations it has not improved
+    let mut best_score = f64::INFINITY;
+    let mut stagnant_generations = 0;
+    // Restart state: the best training fitness since the last restart and when it last improved
+    let mut restart_fitness = f64::INFINITY;
+    let mut generations_since_restart_improvement = 0;
+    // The mutation and crossover chances, which change over the run when they are adapted
+    let mut chances = (params.mut_chance, params.crossover_chance);
+    // Shares out the mutation operators by their recent success
+    let mut bandit = OperatorBandit::new(params.operator_selection, params.mutation_operators.clone());
+    population.operators = bandit.mix();
+    // How many more generations use the hypermutation chance
+    let mut hypermutation_left = 0;
+    // How many generations improved the best chromosome through breeding and through hill climbing
+    let (mut evolution_wins, mut local_search_wins) = (0, 0);
+    let mut previous_best = f64::INFINITY;
 
 
-    for g in 0..gen {
-        population.evaluate(&dataset);
+    for g in 0..params.generations {
+        let _generation = info_span!("generation", g).entered();
+        // Every `full_evaluation_interval` generations the whole dataset is used so that the
+        // elite is not selected purely on the luck of a single batch
+        let batch = match g % params.full_evaluation_interval {
+            0 => None,
+            _ => params.subsample.sample(&dataset),
+        };
+        let batch = batch.as_ref().unwrap_or(&dataset);
 
-        let best = population.mate(dataset[0].len() - 2, crossover_chance, mut_chance, &dataset);
-        fitness_graph.push(DataToWrite { generation: g, fitness: best });
+        // Set before evaluating, since the semantic operators need the outputs of every gene recorded
+        population.operators.sigma = annealed_sigma(params.constant_sigma, params.final_constant_sigma, g, params.generations);
+        population.operators.crossover = params.crossover;
+        population.operators.probe = population.probe.clone();
+        population.operators.headless_chicken = params.headless_chicken;
+        // The temperature cools on the same geometric schedule as the constant perturbations
+        population.temperature = params.temperature.map(|t| annealed_sigma(t, Some(params.final_temperature), g, params.generations));
+        if params.simplify_interval.is_some_and(|interval| g > 0 && g % interval == 0) {
+            let rewritten: usize = population.population.iter_mut().map(|chromosome| chromosome.simplify()).sum();
+            debug!(rewritten, "simplified the population");
+        }
+        if params.fold_constants {
+            let folded: usize = population.population.iter_mut().map(|chromosome| chromosome.fold_constants()).sum();
+            trace!(folded, "folded the constants of the population");
+        }
+        population.evaluate(batch);
+
+        // Hypermutation raises the mutation chance while the population recovers its diversity
+        let (mut_chance, crossover_chance) = chances;
+        let mut_chance = match hypermutation_left {
+            0 => mut_chance,
+            _ => {
+                hypermutation_left -= 1;
+                params.hypermutation_chance
+            }
+        };
+        let best = match params.strategy {
+            Strategy::Generational => population.mate(dataset.get_num_variables(), crossover_chance, mut_chance, params.selection, batch),
+            Strategy::SteadyState { offspring } =>
+                population.steady_state(dataset.get_num_variables(), crossover_chance, mut_chance, params.selection, offspring, batch),
+            Strategy::MuPlusLambda { lambda } =>
+                population.mu_plus_lambda(dataset.get_num_variables(), crossover_chance, mut_chance, params.selection, lambda, batch),
+            Strategy::Alps { layers, age_gap } => population.alps(dataset.get_num_variables(), params.num_genes, crossover_chance,
+                                                                  mut_chance, params.selection, layers, age_gap, g, batch),
+            Strategy::Speciation { threshold, protection } => population.speciation(dataset.get_num_variables(), crossover_chance,
+                                                                                    mut_chance, params.selection, threshold, protection, batch),
+            Strategy::Nsga2 => population.nsga2(dataset.get_num_variables(), crossover_chance, mut_chance, batch),
+            Strategy::Spea2 { archive_size } =>
+                population.spea2(dataset.get_num_variables(), crossover_chance, mut_chance, archive_size, batch),
+            Strategy::MapElites => population.map_elites(dataset.get_num_variables(), crossover_chance, mut_chance, batch),
+            Strategy::Crowding => population.crowding(dataset.get_num_variables(), crossover_chance, mut_chance, batch),
+            Strategy::OnePlusLambda { lambda } => population.one_plus_lambda(dataset.get_num_variables(), lambda, batch),
+        };
+        if best < previous_best && g > 0 {
+            evolution_wins += 1;
+        }
+        let best = match params.hill_climb {
+            Some(budget) => {
+                let (evaluations, improvements) = hill_climb(&mut population.best, &dataset, dataset.get_num_variables(), &params.limits, budget);
+                population.evaluations += evaluations;
+                if improvements > 0 {
+                    local_search_wins += 1;
+                }
+                debug!(improvements, fitness = population.best.fitness_value, "hill climbed the best chromosome");
+                population.best.fitness_value
+            }
+            None => best,
+        };
+        // Tuning on the whole training set rather than the batch, so the constants do not chase the sample
+        let best = match (params.optimize_constants, params.optimize_interval) {
+            (Some(budget), Some(interval)) if (g + 1) % interval == 0 => {
+                population.evaluations += optimize_constants(&mut population.best, &dataset, budget);
+                debug!(fitness = population.best.fitness_value, "tuned the constants of the best chromosome");
+                population.best.fitness_value
+            }
+            _ => best,
+        };
+        previous_best = best;
+        let outcomes = population.offspring_outcomes();
+        bandit.update(&outcomes);
+        population.operators = bandit.mix();
+        for &operator in bandit.operators() {
+            debug!(?operator, uses = bandit.uses(operator), success_rate = bandit.success_rate(operator),
+                   next_weight = population.operators.weight(operator), "mutation operator");
+        }
+        let success_rate = success_rate(&outcomes);
+        chances = params.rate_adaptation.adapt(chances, success_rate);
+        if params.rate_adaptation != RateAdaptation::Fixed {
+            debug!(success_rate, mutation = chances.0, crossover = chances.1, "adapted operator chances");
+        }
+        if params.self_adaptive {
+            let rates: Vec<OperatorRates> = population.population.iter().filter_map(|c| c.rates).collect();
+            if !rates.is_empty() {
+                debug!(mutation = rates.iter().map(|r| r.mutation).sum::<f64>() / rates.len() as f64,
+                       crossover = rates.iter().map(|r| r.crossover).sum::<f64>() / rates.len() as f64, "mean self-adaptive rates");
+            }
+        }
+        hall_of_fame.update(population.population.iter().chain([&population.best]));
+        if let Some(pareto_archive) = &mut pareto_archive {
+            pareto_archive.update(population.population.iter().chain([&population.best]));
+        }
+        let statistics = population.statistics();
+        let complexity = population.best.complexity();
+        info!(best, size = complexity.active_genes, depth = complexity.depth, "generation finished");
+        debug!(min = statistics.min, mean = statistics.mean, median = statistics.median, std = statistics.std,
+               mean_size = statistics.mean_size, mean_depth = statistics.mean_depth, invalid = statistics.invalid, "population statistics");
+        trace!(operators = ?complexity.operators, "functions of the best chromosome");
+        debug!(unique = statistics.unique, phenotypic = statistics.phenotypic_diversity, "population diversity");
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &mut metrics {
+            metrics.record(g, best, &statistics, population.evaluations);
+        }
+        if let Some(threshold) = params.diversity_threshold {
+            let unique_fraction = statistics.unique as f64 / population.len() as f64;
+            if hypermutation_left == 0 && unique_fraction < threshold {
+                debug!(unique_fraction, "diversity is low, hypermutating for {} generations", params.hypermutation_generations);
+                hypermutation_left = params.hypermutation_generations;
+                let immigrants = (params.immigrants * population.len() as f64).round() as usize;
+                if immigrants > 0 {
+                    population.restart(population.len() - immigrants.min(population.len()), params.num_genes, dataset.get_num_variables());
+                }
+            }
+        }
+        fitness_graph.push(DataToWrite { generation: g, fitness: best, statistics, complexity });
+
+        let score = match &validation {
+            Some(validation) => {
+                let validation_error = population.best.fitness(validation);
+                if champion.as_ref().is_none_or(|(_, best_error)| validation_error < *best_error) {
+                    champion = Some((population.best.clone(), validation_error));
+                }
+                validation_error
+            }
+            None => best,
+        };
+        if validation.is_some() {
+            trace!(validation = score, "validation error");
+        }
+        trace!(elapsed = ?now.elapsed());
+
+        if let Some(patience) = params.patience {
+            if score < best_score - params.min_delta {
+                best_score = score;
+                stagnant_generations = 0;
+            } else {
+                stagnant_generations += 1;
+            }
+            if stagnant_generations >= patience {
+                info!(patience, "stopping early: no improvement for {} generations", patience);
+                break;
+            }
+        }
+
+        if let Some(restart_after) = params.restart_after {
+            if best < restart_fitness {
+                restart_fitness = best;
+                generations_since_restart_improvement = 0;
+            } else {
+                generations_since_restart_improvement += 1;
+            }
+            if generations_since_restart_improvement >= restart_after {
+                debug!(restart_after, "restarting the population after {} generations without improvement", restart_after);
+                population.restart(params.restart_keep, params.num_genes, dataset.get_num_variables());
+                generations_since_restart_improvement = 0;
+            }
+        }
+
+        if params.stop_at_fitness.is_some_and(|target| best < target) {
+            info!(best, "stopping early: the fitness is below the target");
+            break;
+        }
     }
 
+    // The final report always uses the full dataset
+    if params.subsample != SubsamplePolicy::Full {
+        population.evaluate(&dataset);
+    }
+    let mut best = match champion {
+        Some((chromosome, _)) => chromosome,
+        None => population.best.clone(),
+    };
+    if let Some(budget) = params.optimize_constants {
+        population.evaluations += optimize_constants(&mut best, &dataset, budget);
+    }
 
-    println!("{}", population.best.evaluate_fitness_mse(&dataset));
-    println!("{}", population.best.make_function_string(None, "".parse().unwrap()));
-    let elapsed = now.elapsed();
-    println!("Elapsed: {:.2?}", elapsed);
+    if dataset.missing.dropped_rows > 0 || !dataset.missing.imputed.is_empty() {
+        info!(dropped_rows = dataset.missing.dropped_rows, imputed_cells = ?dataset.missing.imputed, "missing values");
+    }
+    info!(outputs = ?best.output_genes(), genes = best.genes.len(), "output genes");
+    // Refits the weights of multi-gene GP on the full dataset before scoring
+    best.evaluate_fitness_error(&dataset);
+    if params.canonical {
+        best = best.canonical();
+    }
+    let report = Report::new(&best, &dataset, validation.as_ref(), &params, classes, fitness_graph.len(), population.evaluations, now.elapsed());
+    info!("report\n{}", report);
+    // The expression is the result of the run, so it goes to stdout whatever the verbosity
+    if params.strategy.is_multi_objective() {
+        // Multi-objective runs produce a trade-off between error and size rather than a single champion
+        for chromosome in population.pareto_front() {
+            println!("{}\t{}\t{}", chromosome.active_genes().len(), chromosome.fitness_value,
+                     chromosome.expression(params.notation, &dataset.variable_names));
+        }
+    } else {
+        println!("{}", best.expression(params.notation, &dataset.variable_names));
+    }
+    if params.hill_climb.is_some() {
+        info!(evolution = evolution_wins, local_search = local_search_wins, "generations that improved the best chromosome");
+    }
+    info!(elapsed = ?now.elapsed(), evaluations = population.evaluations, "finished");
+    if let Some(path) = &params.model_path {
+        Model::new(&best, &dataset, params.task).save(path);
+    }
+    if let Some(path) = &params.report_path {
+        report.save(path);
+    }
+    if params.hall_of_fame > 0 {
+        hall_of_fame.rescore(&dataset);
+        hall_of_fame.write(&params.hall_of_fame_path, &dataset.variable_names);
+    }
+    if params.strategy == Strategy::MapElites {
+        population.elites.rescore(&dataset);
+        population.elites.write(&params.elites_path, &dataset.variable_names);
+    }
+    if let (Some(pareto_archive), Some(path)) = (&mut pareto_archive, &params.pareto_front_path) {
+        pareto_archive.rescore(&dataset);
+        pareto_archive.write(path, &dataset.variable_names);
+    }
     crate::io::write_graph_data(fitness_graph, "gp_out.txt")
-}
\ No newline at end of file
+}
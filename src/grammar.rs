@@ -0,0 +1,180 @@
+use std::fs;
+
+use crate::chromosome::Gene;
+use crate::chromosome::GeneType::{Constant, Variable};
+use crate::functions::{FunctionRegistry, PrimitiveId};
+
+/// The most `nest` rules a grammar can have.
+pub const MAX_NESTING_RULES: usize = 4;
+
+/// The most distinct primitives the rules of a grammar can name. Sets of primitives are bit masks
+/// over the primitives the rules name.
+pub const MAX_GRAMMAR_PRIMITIVES: usize = u64::BITS as usize;
+
+/// Rules about which expressions chromosomes may encode, which bake domain knowledge into the
+/// search: new and mutated genes must respect them, see `Layout::grammar`.
+///
+/// A grammar is written one rule per line, with `#` starting a comment:
+///
+/// ```text
+/// forbid sin cos tan under sin cos tan   # no trigonometric function inside another
+/// nest 2 exp log                         # at most two exp or log on any path of the expression
+/// no-self-products                       # no mul(x, x), square(x) or cube(x) of a variable x
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grammar {
+    /// The primitives the rules name, in the order of the bits of the sets.
+    named: Vec<PrimitiveId>,
+    /// For each named primitive, the primitives that may not appear anywhere in its inputs.
+    forbidden: Vec<u64>,
+    /// Groups of primitives, and how many of them may be nested in one another at most.
+    nesting: [(u64, usize); MAX_NESTING_RULES],
+    /// Whether a variable may be multiplied by itself.
+    self_products: bool,
+}
+
+impl Default for Grammar {
+    /// The grammar without rules, which allows every expression.
+    fn default() -> Self {
+        return Grammar { named: vec![], forbidden: vec![], nesting: [(0, 0); MAX_NESTING_RULES], self_products: true };
+    }
+}
+
+impl Grammar {
+    /// Reads a grammar from the file at `path`, see `Grammar::parse`.
+    ///
+    /// # Panics
+    ///
+    /// When the file cannot be read or is not a grammar.
+    pub fn load(path: &str) -> Grammar {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("Could not read the grammar {}: {}", path, e));
+        return Grammar::parse(&text);
+    }
+
+    /// Parses the rules of a grammar, one per line:
+    ///
+    /// * `forbid <primitives> under <primitives>` - None of the first primitives may appear anywhere
+    ///   in the inputs of the second ones.
+    /// * `nest <depth> <primitives>` - At most `depth` of the primitives may be nested in one another.
+    /// * `no-self-products` - No variable is multiplied by itself, with mul, square or cube.
+    ///
+    /// # Panics
+    ///
+    /// When a line is not a rule, names an unknown primitive, or there are more than
+    /// `MAX_NESTING_RULES` nest rules or `MAX_GRAMMAR_PRIMITIVES` named primitives.
+    pub fn parse(text: &str) -> Grammar {
+        let mut grammar = Grammar::default();
+        let mut nesting_rules = 0;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                ["forbid", rule @ ..] => {
+                    let Some(under) = rule.iter().position(|&word| word == "under").filter(|&under| under > 0 && under + 1 < rule.len()) else {
+                        panic!("Line {} of the grammar must be forbid <primitives> under <primitives>: {}", number + 1, line);
+                    };
+                    let children = grammar.primitives(&rule[..under]);
+                    for &name in &rule[under + 1..] {
+                        let parent = grammar.primitives(&[name]);
+                        grammar.forbidden[parent.trailing_zeros() as usize] |= children;
+                    }
+                }
+                ["nest", depth, names @ ..] if !names.is_empty() => {
+                    let depth = depth.parse().unwrap_or_else(|_| panic!("Line {} of the grammar must nest a number of primitives: {}", number + 1, line));
+                    if nesting_rules == MAX_NESTING_RULES {
+                        panic!("A grammar can have at most {} nest rules", MAX_NESTING_RULES);
+                    }
+                    grammar.nesting[nesting_rules] = (grammar.primitives(names), depth);
+                    nesting_rules += 1;
+                }
+                ["no-self-products"] => grammar.self_products = false,
+                _ => panic!("Line {} of the grammar is not a rule: {}", number + 1, line),
+            }
+        }
+        return grammar;
+    }
+
+    /// The set of the primitives called `names`, which the grammar names from now on.
+    ///
+    /// # Panics
+    ///
+    /// When a name is not a primitive, or the grammar would name more than `MAX_GRAMMAR_PRIMITIVES` primitives.
+    fn primitives(&mut self, names: &[&str]) -> u64 {
+        return names.iter().fold(0, |set, &name| {
+            let Some(index) = FunctionRegistry::global().primitives().iter().position(|primitive| primitive.name == name) else {
+                panic!("Unknown function {} in the grammar", name);
+            };
+            let bit = self.named.iter().position(|&primitive| primitive == PrimitiveId(index)).unwrap_or_else(|| {
+                if self.named.len() == MAX_GRAMMAR_PRIMITIVES {
+                    panic!("A grammar can name at most {} functions", MAX_GRAMMAR_PRIMITIVES);
+                }
+                self.named.push(PrimitiveId(index));
+                self.forbidden.push(0);
+                self.named.len() - 1
+            });
+            set | 1 << bit
+        });
+    }
+
+    /// The set holding the primitive of `gene`, empty for terminals and the primitives no rule names.
+    fn primitive_of(&self, gene: &Gene) -> u64 {
+        if let Constant(_) | Variable(_) = gene.type_of_gene {
+            return 0;
+        }
+        return self.named.iter().position(|&primitive| primitive == gene.primitive).map_or(0, |bit| 1 << bit);
+    }
+
+    /// Whether `gene` may take its inputs from `genes`, the genes before it in its chromosome.
+    ///
+    /// # Arguments
+    ///
+    /// * `gene` - The gene to check.
+    /// * `genes` - The genes the inputs of `gene` point at.
+    pub fn allows(&self, gene: &Gene, genes: &[Gene]) -> bool {
+        if matches!(gene.type_of_gene, Constant(_) | Variable(_)) || *self == Grammar::default() {
+            return true;
+        }
+        let primitive = self.primitive_of(gene);
+        let inputs = gene.inputs();
+        let variable = |pos: usize| match genes[pos].type_of_gene {
+            Variable(variable) => Some(variable),
+            _ => None,
+        };
+        let self_product = match (gene.primitive, inputs.as_slice()) {
+            (PrimitiveId::MUL, &[left, right]) => variable(left).is_some() && variable(left) == variable(right),
+            (PrimitiveId::SQUARE | PrimitiveId::CUBE, &[input]) => variable(input).is_some(),
+            _ => false,
+        };
+        if self_product && !self.self_products {
+            return false;
+        }
+
+        // The primitives in the expression of each gene that feeds `gene`, and how deeply each nest rule's primitives nest in it
+        let end = inputs.iter().max().map_or(0, |&input| input + 1);
+        let mut feeds = vec![false; end];
+        inputs.iter().for_each(|&input| feeds[input] = true);
+        for pos in (0..end).rev() {
+            if feeds[pos] {
+                genes[pos].inputs().into_iter().for_each(|input| feeds[input] = true);
+            }
+        }
+        let mut below = vec![0u64; end];
+        let mut depths = vec![[0usize; MAX_NESTING_RULES]; end];
+        for pos in (0..end).filter(|&pos| feeds[pos]) {
+            let (own, inputs) = (self.primitive_of(&genes[pos]), genes[pos].inputs());
+            below[pos] = inputs.iter().fold(own, |set, &input| set | below[input]);
+            for (rule, (group, _)) in self.nesting.iter().enumerate() {
+                depths[pos][rule] = (own & group != 0) as usize + inputs.iter().map(|&input| depths[input][rule]).max().unwrap_or(0);
+            }
+        }
+
+        let forbidden = if primitive == 0 { 0 } else { self.forbidden[primitive.trailing_zeros() as usize] };
+        if inputs.iter().any(|&input| below[input] & forbidden != 0) {
+            return false;
+        }
+        return self.nesting.iter().enumerate().all(|(rule, &(group, depth))| {
+            (primitive & group != 0) as usize + inputs.iter().map(|&input| depths[input][rule]).max().unwrap_or(0) <= depth
+        });
+    }
+}
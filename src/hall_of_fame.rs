@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+
+use crate::chromosome::Chromosome;
+use crate::io::Dataset;
+
+/// An archive of the best distinct chromosomes seen during a run.
+///
+/// Chromosomes are distinct when they print as different expressions, so two genomes that only
+/// differ in their inactive genes take up a single place. The archive is kept sorted from the best
+/// fitness to the worst.
+pub struct HallOfFame {
+    capacity: usize,
+    /// The expression of each member and the member itself.
+    members: Vec<(String, Chromosome)>,
+}
+
+impl HallOfFame {
+    /// Creates an empty archive that holds at most `capacity` chromosomes.
+    pub fn new(capacity: usize) -> HallOfFame {
+        return HallOfFame { capacity, members: Vec::with_capacity(capacity + 1) };
+    }
+
+    /// Offers evaluated chromosomes to the archive.
+    ///
+    /// A chromosome whose expression is already archived replaces the archived copy when its fitness is better.
+    ///
+    /// # Arguments
+    ///
+    /// * `candidates` - Chromosomes whose `fitness_value` is up to date.
+    pub fn update<'a>(&mut self, candidates: impl IntoIterator<Item = &'a Chromosome>) {
+        for candidate in candidates {
+            if !is_valid(candidate) || self.capacity == 0 {
+                continue;
+            }
+            if self.members.len() == self.capacity && candidate.fitness_value >= self.members[self.capacity - 1].1.fitness_value {
+                continue;
+            }
+            let expression = candidate.function_string();
+            if let Some(position) = self.members.iter().position(|(e, _)| *e == expression) {
+                if candidate.fitness_value >= self.members[position].1.fitness_value {
+                    continue;
+                }
+                self.members.remove(position);
+            }
+            let position = self.members.partition_point(|(_, c)| c.fitness_value <= candidate.fitness_value);
+            self.members.insert(position, (expression, candidate.clone()));
+            self.members.truncate(self.capacity);
+        }
+    }
+
+    /// Rescores every member on `dataset`, since members may have been scored on different batches.
+    ///
+    /// Members that are invalid on `dataset` are dropped.
+    pub fn rescore(&mut self, dataset: &Dataset) {
+        for (_, chromosome) in &mut self.members {
+            chromosome.evaluate_fitness_error(dataset);
+        }
+        self.members.retain(|(_, chromosome)| is_valid(chromosome));
+        // Equally fit members are listed simplest first
+        self.members.sort_by(|(_, a), (_, b)| {
+            a.fitness_value.total_cmp(&b.fitness_value).then(a.active_genes().len().cmp(&b.active_genes().len()))
+        });
+    }
+
+    /// The archived chromosomes, best first.
+    pub fn members(&self) -> impl Iterator<Item = &Chromosome> {
+        return self.members.iter().map(|(_, chromosome)| chromosome);
+    }
+
+    /// Writes the members to a CSV file with their rank, fitness, complexity (number of active genes) and expression.
+    pub fn write(&self, file_name: &str, variable_names: &[String]) {
+        let file = File::create(file_name).unwrap_or_else(|e| panic!("Could not create {}: {}", file_name, e));
+        let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+        writer.write_record(["rank", "fitness", "complexity", "expression"]).expect("Problem writing to file");
+        for (rank, chromosome) in self.members().enumerate() {
+            writer.write_record([
+                (rank + 1).to_string(),
+                chromosome.fitness_value.to_string(),
+                chromosome.active_genes().len().to_string(),
+                chromosome.function_string_with_names(variable_names),
+            ]).expect("Problem writing to file");
+        }
+        writer.flush().expect("Problem writing to file");
+    }
+}
+
+/// The trade-off between error and complexity (number of active genes) among the chromosomes seen during a run.
+///
+/// For every complexity only the chromosome with the lowest fitness is kept, and only while no
+/// simpler chromosome is at least as fit, so the fitness strictly decreases as the complexity grows.
+pub struct ParetoArchive {
+    /// The members by their complexity.
+    members: BTreeMap<usize, Chromosome>,
+}
+
+impl Default for ParetoArchive {
+    fn default() -> Self {
+        return ParetoArchive::new();
+    }
+}
+
+impl ParetoArchive {
+    /// Creates an empty archive.
+    pub fn new() -> ParetoArchive {
+        return ParetoArchive { members: BTreeMap::new() };
+    }
+
+    /// Offers evaluated chromosomes to the archive.
+    ///
+    /// # Arguments
+    ///
+    /// * `candidates` - Chromosomes whose `fitness_value` is up to date.
+    pub fn update<'a>(&mut self, candidates: impl IntoIterator<Item = &'a Chromosome>) {
+        let mut changed = false;
+        for candidate in candidates {
+            if !is_valid(candidate) {
+                continue;
+            }
+            let complexity = candidate.active_genes().len();
+            if self.members.get(&complexity).is_none_or(|member| candidate.fitness_value < member.fitness_value) {
+                self.members.insert(complexity, candidate.clone());
+                changed = true;
+            }
+        }
+        if changed {
+            self.remove_dominated();
+        }
+    }
+
+    /// Rescores every member on `dataset`, since members may have been scored on different batches.
+    ///
+    /// Members that are invalid on `dataset` or no longer on the front are dropped.
+    pub fn rescore(&mut self, dataset: &Dataset) {
+        for chromosome in self.members.values_mut() {
+            chromosome.evaluate_fitness_error(dataset);
+        }
+        self.members.retain(|_, chromosome| is_valid(chromosome));
+        self.remove_dominated();
+    }
+
+    /// Drops every member that is at least as complex as a member that is at least as fit.
+    fn remove_dominated(&mut self) {
+        let mut best = f64::INFINITY;
+        self.members.retain(|_, chromosome| {
+            if chromosome.fitness_value < best {
+                best = chromosome.fitness_value;
+                return true;
+            }
+            return false;
+        });
+    }
+
+    /// The members, from the simplest to the most complex.
+    pub fn members(&self) -> impl Iterator<Item = &Chromosome> {
+        return self.members.values();
+    }
+
+    /// Writes the members to a CSV file with their complexity (number of active genes), fitness and expression.
+    pub fn write(&self, file_name: &str, variable_names: &[String]) {
+        let file = File::create(file_name).unwrap_or_else(|e| panic!("Could not create {}: {}", file_name, e));
+        let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+        writer.write_record(["complexity", "fitness", "expression"]).expect("Problem writing to file");
+        for chromosome in self.members() {
+            writer.write_record([
+                chromosome.active_genes().len().to_string(),
+                chromosome.fitness_value.to_string(),
+                chromosome.function_string_with_names(variable_names),
+            ]).expect("Problem writing to file");
+        }
+        writer.flush().expect("Problem writing to file");
+    }
+}
+
+/// The MAP-Elites archive: the fittest chromosome found for every combination of descriptors.
+///
+/// A chromosome is described by its complexity (number of active genes) and the number of
+/// distinct variables it uses. The archive illuminates how good a solution can be for each of
+/// these combinations rather than only finding the single best one.
+pub struct EliteArchive {
+    /// The elite of each cell by its descriptors.
+    cells: BTreeMap<(usize, usize), Chromosome>,
+}
+
+impl Default for EliteArchive {
+    fn default() -> Self {
+        return EliteArchive::new();
+    }
+}
+
+impl EliteArchive {
+    /// Creates an empty archive.
+    pub fn new() -> EliteArchive {
+        return EliteArchive { cells: BTreeMap::new() };
+    }
+
+    /// The cell a chromosome belongs to: its complexity and how many distinct variables it uses.
+    pub fn descriptors(chromosome: &Chromosome) -> (usize, usize) {
+        return (chromosome.active_genes().len(), chromosome.variables_used().len());
+    }
+
+    /// Puts an evaluated chromosome in its cell if the cell is empty or its elite is less fit.
+    ///
+    /// # Returns
+    ///
+    /// Whether the chromosome became an elite.
+    pub fn offer(&mut self, chromosome: &Chromosome) -> bool {
+        if !is_valid(chromosome) {
+            return false;
+        }
+        let cell = EliteArchive::descriptors(chromosome);
+        if self.cells.get(&cell).is_some_and(|elite| elite.fitness_value <= chromosome.fitness_value) {
+            return false;
+        }
+        self.cells.insert(cell, chromosome.clone());
+        return true;
+    }
+
+    /// Whether no chromosome has been archived yet.
+    pub fn is_empty(&self) -> bool {
+        return self.cells.is_empty();
+    }
+
+    /// How many cells are filled.
+    pub fn len(&self) -> usize {
+        return self.cells.len();
+    }
+
+    /// The elites, ordered by complexity and then by number of variables.
+    pub fn members(&self) -> impl Iterator<Item = &Chromosome> {
+        return self.cells.values();
+    }
+
+    /// The elites, so that they can be rescored. Rescoring does not change their cells.
+    pub fn members_mut(&mut self) -> impl Iterator<Item = &mut Chromosome> {
+        return self.cells.values_mut();
+    }
+
+    /// Rescores every elite on `dataset`, since elites may have been scored on different batches.
+    pub fn rescore(&mut self, dataset: &Dataset) {
+        for chromosome in self.members_mut() {
+            chromosome.evaluate_fitness_error(dataset);
+        }
+    }
+
+    /// Writes the elites to a CSV file with their descriptors, fitness and expression.
+    pub fn write(&self, file_name: &str, variable_names: &[String]) {
+        let file = File::create(file_name).unwrap_or_else(|e| panic!("Could not create {}: {}", file_name, e));
+        let mut writer = csv::Writer::from_writer(BufWriter::new(file));
+        writer.write_record(["complexity", "variables", "fitness", "expression"]).expect("Problem writing to file");
+        for ((complexity, variables), chromosome) in &self.cells {
+            writer.write_record([
+                complexity.to_string(),
+                variables.to_string(),
+                chromosome.fitness_value.to_string(),
+                chromosome.function_string_with_names(variable_names),
+            ]).expect("Problem writing to file");
+        }
+        writer.flush().expect("Problem writing to file");
+    }
+}
+
+/// Whether the chromosome has a usable fitness, i.e. its output neither overflowed nor became NaN.
+fn is_valid(chromosome: &Chromosome) -> bool {
+    return chromosome.fitness_value < f64::MAX;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chromosome::{Chromosome, Gene};
+    use crate::functions::PrimitiveId;
+
+    use super::*;
+
+    fn scored(genes: Vec<Gene>, fitness: f64) -> Chromosome {
+        let mut chromosome = Chromosome::new_from_genes_array(genes);
+        chromosome.fitness_value = fitness;
+        return chromosome;
+    }
+
+    #[test]
+    /// Ensures that the archive keeps the best distinct expressions in order
+    fn test_keeps_best_distinct() {
+        let mut hall_of_fame = HallOfFame::new(2);
+        let x0 = scored(vec![Gene::new_variable(0)], 3.0);
+        // Same expression as `x0` through an inactive gene, with a better fitness
+        let x0_again = scored(vec![Gene::new_variable(1), Gene::new_variable(0)], 2.0);
+        let sum = scored(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)], 1.0);
+        let x1 = scored(vec![Gene::new_variable(1)], 5.0);
+        hall_of_fame.update([&x0, &x1, &x0_again, &sum]);
+
+        let fitness: Vec<f64> = hall_of_fame.members().map(|c| c.fitness_value).collect();
+        assert_eq!(fitness, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    /// Ensures that the archive keeps the best chromosome of each complexity only while it beats every simpler one
+    fn test_pareto_archive() {
+        let mut archive = ParetoArchive::new();
+        let x0 = scored(vec![Gene::new_variable(0)], 3.0);
+        let x1 = scored(vec![Gene::new_variable(1)], 2.0);
+        let sum = scored(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)], 2.5);
+        archive.update([&x0, &sum]);
+        assert_eq!(archive.members().map(|c| c.fitness_value).collect::<Vec<f64>>(), vec![3.0, 2.5]);
+        // A better single gene makes the sum redundant
+        archive.update([&x1]);
+        assert_eq!(archive.members().map(|c| c.fitness_value).collect::<Vec<f64>>(), vec![2.0]);
+    }
+
+    #[test]
+    /// Ensures that each cell keeps its fittest chromosome
+    fn test_elite_archive() {
+        let mut archive = EliteArchive::new();
+        let x0 = scored(vec![Gene::new_variable(0)], 3.0);
+        let x1 = scored(vec![Gene::new_variable(1)], 2.0);
+        let sum = scored(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)], 4.0);
+        assert!(archive.offer(&x0));
+        assert!(archive.offer(&x1));
+        assert!(!archive.offer(&x0));
+        assert!(archive.offer(&sum));
+        assert_eq!(archive.len(), 2);
+        assert_eq!(archive.members().map(|c| c.fitness_value).collect::<Vec<f64>>(), vec![2.0, 4.0]);
+    }
+}
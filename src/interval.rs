@@ -0,0 +1,139 @@
+use crate::chromosome::{Chromosome, Gene};
+use crate::chromosome::GeneType::{Binary, Conditional, Constant, Unary, Variable};
+use crate::functions::PrimitiveId;
+use crate::io::Dataset;
+
+/// A closed range that holds every value an expression takes on the rows of a dataset, except NaN.
+/// The empty interval, with NaN bounds, stands for a value that is NaN on every row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    /// A value that is NaN on every row.
+    pub const EMPTY: Interval = Interval { lo: f64::NAN, hi: f64::NAN };
+    /// A value nothing is known about.
+    pub const ALL: Interval = Interval { lo: f64::NEG_INFINITY, hi: f64::INFINITY };
+
+    /// The smallest interval holding `values`, ignoring NaN. Empty when every value is NaN.
+    pub fn hull(values: impl IntoIterator<Item=f64>) -> Interval {
+        return values.into_iter().filter(|value| !value.is_nan()).fold(Interval::EMPTY, |interval, value| Interval {
+            lo: if interval.lo <= value { interval.lo } else { value },
+            hi: if interval.hi >= value { interval.hi } else { value },
+        });
+    }
+
+    /// Whether the value is NaN on every row.
+    pub fn is_empty(&self) -> bool {
+        return self.lo.is_nan();
+    }
+
+    /// Whether `value` lies in the interval.
+    fn contains(&self, value: f64) -> bool {
+        return self.lo <= value && value <= self.hi;
+    }
+
+    /// Whether the value is NaN or the same infinity on every row, which makes the error of a chromosome invalid.
+    pub fn always_fails(&self) -> bool {
+        return self.is_empty() || (self.lo == self.hi && self.lo.is_infinite());
+    }
+}
+
+/// The range of each variable of the dataset over its rows.
+pub fn variable_bounds(dataset: &Dataset) -> Vec<Interval> {
+    return (0..dataset.variable_names.len()).map(|variable| Interval::hull(dataset.rows.iter().map(|row| row[variable]))).collect();
+}
+
+/// Bounds the value of every output gene of the chromosome with interval arithmetic, given the
+/// range of each variable. The bounds are sound but loose: functions are only evaluated at the
+/// ends of the input intervals, and where that would not bound them, e.g. a division by an interval
+/// holding 0, nothing is assumed about their value.
+///
+/// # Arguments
+///
+/// * `chromosome` - The chromosome to bound.
+/// * `bounds` - The range of each variable, see `variable_bounds`.
+pub fn output_intervals(chromosome: &Chromosome, bounds: &[Interval]) -> Vec<Interval> {
+    let mut intervals = vec![Interval::ALL; chromosome.genes.len()];
+    for pos in chromosome.active_genes() {
+        let gene = &chromosome.genes[pos];
+        let inputs: Vec<Interval> = gene.inputs().into_iter().map(|input| intervals[input]).collect();
+        intervals[pos] = match gene.type_of_gene {
+            Constant(value) => Interval { lo: value, hi: value },
+            Variable(variable) => bounds[variable],
+            Conditional => Interval::hull([inputs[2].lo, inputs[2].hi, inputs[3].lo, inputs[3].hi]),
+            Unary | Binary => apply(gene, &inputs),
+        };
+    }
+    return chromosome.output_genes().into_iter().map(|output| intervals[output]).collect();
+}
+
+/// Whether interval arithmetic shows that an output of the chromosome is NaN or infinite on every
+/// row, so that evaluating it on the rows would only find it invalid.
+pub fn always_fails(chromosome: &Chromosome, bounds: &[Interval]) -> bool {
+    return output_intervals(chromosome, bounds).iter().any(Interval::always_fails);
+}
+
+/// Bounds the value of a unary or binary function gene whose inputs lie in `inputs`.
+fn apply(gene: &Gene, inputs: &[Interval]) -> Interval {
+    // The gene's own operation, so that the bounds follow its protection, and NaN inputs behave as they do on the rows
+    let at_ends = || match *inputs {
+        [a] => Interval::hull([a.lo, a.hi].map(|x| (gene.ops)(x, 0.0).0)),
+        [a, b] => Interval::hull([(a.lo, b.lo), (a.lo, b.hi), (a.hi, b.lo), (a.hi, b.hi)].map(|(x, y)| (gene.ops)(x, y).0)),
+        _ => Interval::ALL,
+    };
+    return match (gene.primitive, inputs) {
+        (PrimitiveId::AND | PrimitiveId::OR | PrimitiveId::XOR | PrimitiveId::NOT | PrimitiveId::LT | PrimitiveId::GT, _) => Interval { lo: 0.0, hi: 1.0 },
+        // Monotone in each input, so the extremes are at the ends
+        (PrimitiveId::ADD | PrimitiveId::SUB | PrimitiveId::MUL | PrimitiveId::MAX | PrimitiveId::MIN, _)
+        | (PrimitiveId::NEG | PrimitiveId::CUBE | PrimitiveId::EXP | PrimitiveId::TANH | PrimitiveId::FLOOR, _) => at_ends(),
+        // Singular at 0, but monotone on either side
+        (PrimitiveId::DIV, [_, divisor]) if divisor.contains(0.0) => Interval::ALL,
+        (PrimitiveId::DIV, _) => at_ends(),
+        (PrimitiveId::LOG | PrimitiveId::LOG2 | PrimitiveId::SQRT | PrimitiveId::INV, [a]) if a.contains(0.0) => Interval::ALL,
+        (PrimitiveId::LOG | PrimitiveId::LOG2 | PrimitiveId::SQRT | PrimitiveId::INV, _) => at_ends(),
+        // Even, so the minimum is at 0 when the input crosses it
+        (PrimitiveId::SQUARE | PrimitiveId::ABS, [a]) if a.lo < 0.0 && 0.0 < a.hi => Interval { lo: 0.0, hi: at_ends().hi },
+        (PrimitiveId::SQUARE | PrimitiveId::ABS, _) => at_ends(),
+        // Periodic, and NaN at infinity
+        (PrimitiveId::SIN | PrimitiveId::COS, [a]) if a.is_empty() || (a.lo == a.hi && a.lo.is_infinite()) => Interval::EMPTY,
+        (PrimitiveId::SIN | PrimitiveId::COS, _) => Interval { lo: -1.0, hi: 1.0 },
+        (PrimitiveId::TAN, [a]) if a.is_empty() || (a.lo == a.hi && a.lo.is_infinite()) => Interval::EMPTY,
+        _ => Interval::ALL,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::functions::{PrimitiveId, Protection};
+    use crate::io::{DatasetOptions, open_dataset, read_csv};
+
+    use super::*;
+
+    #[test]
+    /// Ensures that interval arithmetic bounds the outputs over the variable ranges, and only flags
+    /// chromosomes that are invalid on every row
+    fn test_interval_prescreening() {
+        let bounds = variable_bounds(&read_csv(open_dataset("test.csv"), &DatasetOptions::default()));
+        assert_eq!(bounds[0], Interval { lo: 0.5, hi: 4.0 });
+        let chromosome = |genes: Vec<Gene>| Chromosome::new_from_genes_array([Gene::new_variable(0), Gene::new_variable(1)].into_iter().chain(genes).collect());
+        let bound = |genes: Vec<Gene>| output_intervals(&chromosome(genes), &bounds)[0];
+
+        assert_eq!(bound(vec![Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_unary2(2, PrimitiveId::NEG)]), Interval { lo: -8.0, hi: -1.0 });
+        assert_eq!(bound(vec![Gene::new_unary2(0, PrimitiveId::NEG), Gene::new_unary2(2, PrimitiveId::SQUARE)]), Interval { lo: 0.25, hi: 16.0 });
+        assert_eq!(bound(vec![Gene::new_unary2(0, PrimitiveId::SIN)]), Interval { lo: -1.0, hi: 1.0 });
+        assert_eq!(bound(vec![Gene::new_binary2(0, 1, PrimitiveId::DIV)]), Interval { lo: 0.125, hi: 8.0 });
+        // The range of x0 - x1 holds 0
+        assert_eq!(bound(vec![Gene::new_binary2(0, 1, PrimitiveId::SUB), Gene::new_binary2(0, 2, PrimitiveId::DIV)]), Interval::ALL);
+
+        let log_of_negative = vec![Gene::new_unary2(0, PrimitiveId::NEG), Gene::new_unary2(2, PrimitiveId::LOG).protected(Protection::Nan)];
+        assert!(always_fails(&chromosome(log_of_negative), &bounds));
+        assert!(!always_fails(&chromosome(vec![Gene::new_unary2(0, PrimitiveId::NEG), Gene::new_unary2(2, PrimitiveId::LOG)]), &bounds), "the standard log takes the magnitude");
+        let overflow = vec![Gene::new_constant(Some(10.0)), Gene::new_unary2(2, PrimitiveId::EXP), Gene::new_unary2(3, PrimitiveId::EXP)];
+        assert!(always_fails(&chromosome(overflow), &bounds));
+        let nan_branch_discarded = vec![Gene::new_unary2(0, PrimitiveId::NEG), Gene::new_unary2(2, PrimitiveId::LOG).protected(Protection::Nan), Gene::new_binary2(3, 1, PrimitiveId::MAX)];
+        assert!(!always_fails(&chromosome(nan_branch_discarded), &bounds), "max ignores NaN");
+    }
+}
@@ -1,51 +1,1348 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufRead, BufReader, BufWriter, Read, stdin};
 use std::io::Write;
 
 use csv::ReaderBuilder;
+use flate2::read::MultiGzDecoder;
+use rand::seq::index::sample;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::chromosome::Complexity;
+use crate::population::PopulationStatistics;
+use crate::rng::rng;
+
+/// The file formats a dataset can be read from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DatasetFormat {
+    Csv,
+    /// One JSON object of numeric fields per line.
+    Jsonl,
+    Parquet,
+    Arrow,
+    /// The sparse `label index:value ...` format used by LIBSVM.
+    LibSvm,
+    /// The Weka attribute-relation file format.
+    Arff,
+}
+
+impl DatasetFormat {
+    /// Guesses the format from the extension of a file name, ignoring a trailing `.gz` or `.zst`.
+    pub fn from_location(location: &str) -> DatasetFormat {
+        let location = location.trim_end_matches(".gz").trim_end_matches(".zst");
+        return match location.rsplit('.').next().unwrap_or_default() {
+            "jsonl" | "ndjson" => DatasetFormat::Jsonl,
+            "parquet" => DatasetFormat::Parquet,
+            "arrow" | "feather" | "ipc" => DatasetFormat::Arrow,
+            "libsvm" | "svm" => DatasetFormat::LibSvm,
+            "arff" => DatasetFormat::Arff,
+            _ => DatasetFormat::Csv,
+        };
+    }
+}
+
+impl Display for DatasetFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatasetFormat::Csv => write!(f, "csv"),
+            DatasetFormat::Jsonl => write!(f, "jsonl"),
+            DatasetFormat::Parquet => write!(f, "parquet"),
+            DatasetFormat::Arrow => write!(f, "arrow"),
+            DatasetFormat::LibSvm => write!(f, "libsvm"),
+            DatasetFormat::Arff => write!(f, "arff"),
+        }
+    }
+}
+
+/// What to do with cells that have no value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingPolicy {
+    /// Stop with an error.
+    Error,
+    /// Remove every row with a missing value.
+    DropRow,
+    /// Replace missing values with the mean of their column.
+    MeanImpute,
+    /// Replace missing values with a constant.
+    Constant(f64),
+}
+
+/// What was done to the missing values of a dataset when it was loaded.
+#[derive(Debug, Clone, Default)]
+pub struct MissingReport {
+    /// The number of rows removed because they had a missing value.
+    pub dropped_rows: usize,
+    /// How many cells of each column were imputed, by column name. Columns without imputed cells are left out.
+    pub imputed: BTreeMap<String, usize>,
+}
+
+/// How columns holding text are turned into numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CategoricalEncoding {
+    /// Stop with an error when a column is not numeric.
+    Error,
+    /// One 0/1 column per category.
+    OneHot,
+    /// A single column holding the index of the category.
+    Ordinal,
+}
+
+/// The mapping applied to a categorical column when the dataset was loaded. It is saved with
+/// the model so that new data is encoded the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnEncoding {
+    /// The name of the column in the original data.
+    pub column: String,
+    /// The distinct values of the column, numbers first and then text. The ordinal encoding of a
+    /// value is its index.
+    pub categories: Vec<String>,
+    pub one_hot: bool,
+}
+
+impl ColumnEncoding {
+    /// Returns the names of the columns this encoding produces, e.g. `color=red` for one-hot encodings.
+    pub fn column_names(&self) -> Vec<String> {
+        return match self.one_hot {
+            true => self.categories.iter().map(|category| format!("{}={}", self.column, category)).collect(),
+            false => vec![self.column.clone()],
+        };
+    }
+
+    /// Encodes a single value of the column.
+    ///
+    /// Missing values and categories that were not seen when the encoding was fitted are NaN, so
+    /// they are handled by the `MissingPolicy`.
+    pub fn encode(&self, value: &str) -> Vec<f64> {
+        let value = value.trim();
+        let index = match is_missing(value) {
+            true => None,
+            false => self.categories.iter().position(|category| category == value),
+        };
+        return match (self.one_hot, index) {
+            (true, Some(index)) => (0..self.categories.len()).map(|i| if i == index { 1.0 } else { 0.0 }).collect(),
+            (true, None) => vec![f64::NAN; self.categories.len()],
+            (false, index) => vec![index.map_or(f64::NAN, |i| i as f64)],
+        };
+    }
+}
+
+/// How the columns of a dataset are rescaled after loading.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ScalingMethod {
+    /// Subtract the mean and divide by the standard deviation.
+    ZScore,
+    /// Map the smallest value to 0 and the largest to 1.
+    MinMax,
+}
+
+/// The parameters of a fitted rescaling of one column, `scaled = (value - offset) / scale`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Scaler {
+    pub method: ScalingMethod,
+    pub offset: f64,
+    pub scale: f64,
+}
+
+impl Scaler {
+    /// Fits a scaler to the values of a column. Constant columns get a scale of 1.
+    pub fn fit(method: ScalingMethod, values: &[f64]) -> Scaler {
+        let n = values.len() as f64;
+        let (offset, scale) = match method {
+            ScalingMethod::ZScore => {
+                let mean = values.iter().sum::<f64>() / n;
+                (mean, (values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt())
+            }
+            ScalingMethod::MinMax => {
+                let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+                (min, values.iter().copied().fold(f64::NEG_INFINITY, f64::max) - min)
+            }
+        };
+        return Scaler { method, offset, scale: if scale > 0.0 { scale } else { 1.0 } };
+    }
+
+    pub fn transform(&self, value: f64) -> f64 {
+        return (value - self.offset) / self.scale;
+    }
+
+    pub fn inverse(&self, value: f64) -> f64 {
+        return value * self.scale + self.offset;
+    }
+}
+
+/// Returns true if a field is one of the usual markers for a missing value: an empty field, `?`, `NA`, `N/A`, `NaN` or `null`.
+pub fn is_missing(field: &str) -> bool {
+    let field = field.trim();
+    return field.is_empty() || field == "?" || ["na", "n/a", "nan", "null"].contains(&field.to_lowercase().as_str());
+}
+
+/// Options controlling how a dataset file is parsed.
+#[derive(Debug, Clone)]
+pub struct DatasetOptions {
+    /// The format of the file. Guessed from the file name when `None`.
+    pub format: Option<DatasetFormat>,
+    /// The names or zero-based indices of the columns to regress toward. Defaults to the last column.
+    pub target_columns: Vec<String>,
+    /// The byte separating fields, e.g. `b';'` for European CSVs.
+    pub delimiter: u8,
+    /// The character used as the decimal separator in numbers.
+    pub decimal: char,
+    /// The character grouping the digits of numbers, e.g. `.` in `1.234,5`. `None` reads a number
+    /// with any other separator as text.
+    pub thousands: Option<char>,
+    /// Whether the first row of a CSV file holds the column names. Without a header the variables
+    /// are named `v0`, `v1`, ...
+    pub header: bool,
+    /// What to do with missing values.
+    pub missing: MissingPolicy,
+    /// How text columns are encoded.
+    pub categorical: CategoricalEncoding,
+    /// Columns that are encoded as categories even when all of their values are numbers.
+    pub categorical_columns: Vec<String>,
+    /// How the variables are rescaled. `None` keeps the values as they are.
+    pub normalize: Option<ScalingMethod>,
+    /// Whether the target is rescaled as well when `normalize` is set.
+    pub normalize_target: bool,
+    /// The name or zero-based index of a column of non-negative sample weights, which scale how
+    /// much each row counts in the error. `None` weighs every row equally.
+    pub weight_column: Option<String>,
+    /// The fraction of the rows held out for validation, see `Dataset::validation_rows`. 0 holds out nothing.
+    pub validation_fraction: f64,
+}
+
+impl Default for DatasetOptions {
+    fn default() -> Self {
+        return DatasetOptions {
+            format: None,
+            target_columns: Vec::new(),
+            delimiter: b',',
+            decimal: '.',
+            thousands: None,
+            header: true,
+            missing: MissingPolicy::Error,
+            categorical: CategoricalEncoding::OneHot,
+            categorical_columns: Vec::new(),
+            normalize: None,
+            normalize_target: false,
+            weight_column: None,
+            validation_fraction: 0.0,
+        };
+    }
+}
+
+/// Parses a number written with the given decimal and thousands separators.
+///
+/// Only the given thousands separator is removed, so `1.234,5` is read as `1234.5` with the decimal
+/// separator `,` and the thousands separator `.`. Without a thousands separator, a number holding
+/// any separator but the decimal one is ambiguous: `3,14` could be 3.14 or 314, so it is not read
+/// as a number.
+///
+/// # Returns
+///
+/// `None` if the field is not a number.
+pub fn parse_number(field: &str, decimal: char, thousands: Option<char>) -> Option<f64> {
+    let mut field = field.trim().to_string();
+    if let Some(separator) = thousands {
+        field = field.replace(separator, "");
+    }
+    if decimal != '.' {
+        // A . that is neither separator must not be read as the decimal point
+        if field.contains('.') {
+            return None;
+        }
+        field = field.replace(decimal, ".");
+    }
+    return field.parse::<f64>().ok();
+}
+
+/// Opens a dataset file, transparently decompressing it when the name ends in `.gz` or `.zst`.
+///
+/// A location of `-` reads from stdin.
+pub fn open_dataset(location: &str) -> Box<dyn Read> {
+    return open_location(location, || Box::new(stdin().lock()));
+}
+
+/// Opens `location` like `open_dataset`, calling `stdin` for the reader of `-`.
+fn open_location(location: &str, stdin: impl FnOnce() -> Box<dyn Read>) -> Box<dyn Read> {
+    if location == "-" {
+        return stdin();
+    }
+    let file = BufReader::new(File::open(location).unwrap_or_else(|e| panic!("Could not open {}: {}", location, e)));
+    return if location.ends_with(".gz") {
+        Box::new(MultiGzDecoder::new(file))
+    } else if location.ends_with(".zst") {
+        Box::new(zstd::Decoder::with_buffer(file).unwrap())
+    } else {
+        Box::new(file)
+    };
+}
+
+/// Reads CSV data into a `Dataset`.
+///
+/// The first row is used as the column names, unless `options.header` is false, in which case the
+/// variables are named `v0`, `v1`, ...
+///
+/// The target columns are moved to the end of every row, so that the remaining columns are the
+/// variables in their original order. Columns containing text are encoded as categories.
+///
+/// # Arguments
+///
+/// * `reader` - Where the CSV data is read from, usually the result of `open_dataset`.
+/// * `options` - How the file is parsed and which column is the target.
+pub fn read_csv<R: Read>(reader: R, options: &DatasetOptions) -> Dataset {
+    let mut rdr = ReaderBuilder::new().has_headers(false).flexible(true).delimiter(options.delimiter).from_reader(reader);
+    let mut records = rdr.records().map(|r| r.unwrap()).peekable();
+    let num_columns = records.peek().expect("The dataset is empty").len();
+    let headers = match options.header {
+        true => records.next(),
+        false => None,
+    };
 
-pub fn read_csv(location: &str) -> Vec<Vec<f64>> {
     let mut csv = Vec::new();
-    let rdr = ReaderBuilder::new().from_path(location);
+    for record in records {
+        let line = record.position().map_or(0, |p| p.line());
+        if record.len() != num_columns {
+            panic!("Line {} has {} fields but {} were expected. Is the delimiter correct?", line, record.len(), num_columns);
+        }
+        csv.push(record.iter().map(|x| x.to_string()).collect())
+    }
+
+    return match headers {
+        Some(h) => parse_table(h.iter().map(|name| name.trim().to_string()).collect(), csv, options),
+        None => {
+            let mut dataset = parse_table((0..num_columns).map(|i| format!("v{}", i)).collect(), csv, options);
+            let targets = dataset.target_names.len();
+            if dataset.encodings.is_empty() {
+                dataset.variable_names = (0..num_columns - targets - dataset.weight_name.iter().len()).map(|i| format!("v{}", i)).collect();
+            }
+            if dataset.weight_name.is_some() {
+                dataset.weight_name = Some("weight".to_string());
+            }
+            dataset.target_names = match targets {
+                1 => vec!["target".to_string()],
+                _ => (0..targets).map(|i| format!("target{}", i)).collect(),
+            };
+            dataset
+        }
+    };
+}
+
+/// Reads any dataset file, using the format in `options` or guessing it from the extension of `location`.
+///
+/// Parquet and Arrow IPC files require the `arrow` feature.
+#[tracing::instrument(level = "debug", skip(options))]
+pub fn read_dataset(location: &str, options: &DatasetOptions) -> Dataset {
+    let format = options.format.unwrap_or_else(|| DatasetFormat::from_location(location));
+    return match format {
+        DatasetFormat::Csv => read_csv(open_dataset(location), options),
+        DatasetFormat::Jsonl => read_jsonl(open_dataset(location), options),
+        DatasetFormat::LibSvm => read_libsvm(open_dataset(location), options),
+        DatasetFormat::Arff => read_arff(open_dataset(location), options),
+        #[cfg(feature = "arrow")]
+        DatasetFormat::Parquet => read_parquet(location, options),
+        #[cfg(feature = "arrow")]
+        DatasetFormat::Arrow => read_arrow_ipc(location, options),
+        #[cfg(not(feature = "arrow"))]
+        DatasetFormat::Parquet | DatasetFormat::Arrow => panic!("Reading {} files requires building with the arrow feature", format),
+    };
+}
+
+/// Reads JSON lines into a `Dataset`, where each line is an object mapping field names to numbers.
+///
+/// Every field of any object becomes a column, in the order the fields are first written. Strings
+/// are read like CSV fields, so text values are encoded as categories, and `true`/`false` are read
+/// as 1 and 0. Fields that are `null` or absent are missing values.
+///
+/// # Arguments
+///
+/// * `reader` - Where the JSON lines are read from, usually the result of `open_dataset`.
+/// * `options` - Decides which field is the target.
+pub fn read_jsonl<R: Read>(reader: R, options: &DatasetOptions) -> Dataset {
+    let mut column_names: Vec<String> = Vec::new();
+    let mut objects = Vec::new();
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.expect("Problem reading JSON lines");
+        if line.trim().is_empty() {
+            continue;
+        }
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&line)
+            .unwrap_or_else(|e| panic!("Line {} is not a JSON object: {}", i + 1, e));
+        for name in object.keys() {
+            if !column_names.contains(name) {
+                column_names.push(name.clone());
+            }
+        }
+        objects.push((i, object));
+    }
+    let rows: Vec<Vec<String>> = objects.iter().map(|(i, object)| column_names.iter().map(|name| match object.get(name) {
+        Some(serde_json::Value::Number(x)) => x.to_string(),
+        Some(serde_json::Value::Bool(x)) => u8::from(*x).to_string(),
+        Some(serde_json::Value::String(x)) => x.clone(),
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(_) => panic!("Line {} has no numeric value for {}", i + 1, name),
+    }).collect()).collect();
+    if rows.is_empty() {
+        panic!("The dataset is empty");
+    }
+    // JSON numbers are always written with a . and no thousands separator
+    return parse_table(column_names, rows, &DatasetOptions { decimal: '.', thousands: None, ..options.clone() });
+}
+
+/// Reads data in the sparse LIBSVM format into a `Dataset`.
+///
+/// Each line is `label index:value index:value ...` with one-based feature indices. Features that
+/// are not listed on a line are 0. The features are named `f1`, `f2`, ... and the label `label`,
+/// which is the default target.
+///
+/// # Arguments
+///
+/// * `reader` - Where the data is read from, usually the result of `open_dataset`.
+/// * `options` - Decides which column is the target.
+pub fn read_libsvm<R: Read>(reader: R, options: &DatasetOptions) -> Dataset {
+    let mut sparse_rows: Vec<(f64, Vec<(usize, f64)>)> = Vec::new();
+    let mut num_features = 0;
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.expect("Problem reading LIBSVM file");
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let label = parse_number(fields.next().unwrap(), '.', None)
+            .unwrap_or_else(|| panic!("Line {} does not start with a numeric label", i + 1));
+        let features: Vec<(usize, f64)> = fields.map(|field| {
+            let parsed = field.split_once(':')
+                .and_then(|(index, value)| Some((index.parse::<usize>().ok()?, parse_number(value, '.', None)?)));
+            match parsed {
+                Some((index, value)) if index > 0 => (index, value),
+                _ => panic!("Line {} has the feature \"{}\" which is not index:value with a one-based index", i + 1, field),
+            }
+        }).collect();
+        num_features = features.iter().map(|&(index, _)| index).fold(num_features, usize::max);
+        sparse_rows.push((label, features));
+    }
+    if sparse_rows.is_empty() {
+        panic!("The dataset is empty");
+    }
+
+    let rows = sparse_rows.into_iter().map(|(label, features)| {
+        let mut row = vec![0.0; num_features + 1];
+        features.into_iter().for_each(|(index, value)| row[index - 1] = value);
+        row[num_features] = label;
+        row
+    }).collect();
+    let mut column_names: Vec<String> = (1..=num_features).map(|i| format!("f{}", i)).collect();
+    column_names.push("label".to_string());
+    return into_dataset(column_names, rows, options);
+}
+
+/// Reads a Weka ARFF file into a `Dataset`.
+///
+/// Numeric attributes are used as they are and nominal attributes are replaced by the index of
+/// their value in the attribute declaration. String and date attributes are skipped. Both the
+/// dense and the sparse (`{index value, ...}`) data sections are supported, and an attribute left
+/// out of a sparse row takes the value 0, or the first declared value of a nominal attribute.
+/// Values are split with the csv reader, so quoted values may contain commas.
+///
+/// # Arguments
+///
+/// * `reader` - Where the data is read from, usually the result of `open_dataset`.
+/// * `options` - Decides which attribute is the target. Defaults to the last attribute that is used.
+pub fn read_arff<R: Read>(reader: R, options: &DatasetOptions) -> Dataset {
+    /// An attribute declared in the header, `None` for attributes that are skipped.
+    type Attribute = Option<Vec<String>>;
+
+    fn unquote(s: &str) -> String {
+        return s.trim().trim_matches(|c| c == '\'' || c == '"').to_string();
+    }
+
+    /// Splits comma-separated ARFF values, quoted with whichever of `'` and `"` comes first.
+    fn fields(text: &str) -> Vec<String> {
+        let quote = text.chars().find(|&c| c == '\'' || c == '"').unwrap_or('\'');
+        let mut rdr = ReaderBuilder::new().has_headers(false).quote(quote as u8).from_reader(text.as_bytes());
+        let record = match rdr.records().next() {
+            Some(record) => record.expect("Problem reading ARFF values"),
+            None => return Vec::new(),
+        };
+        // A quote that does not open the field, as in `a, 'b,c'` or the sparse `3 'b,c'`, is read
+        // as text, so the pieces of such a value are joined again
+        let mut values: Vec<String> = Vec::new();
+        let mut open = false;
+        for field in record.iter() {
+            match open {
+                true => values.last_mut().unwrap().push_str(&format!(",{}", field)),
+                false => values.push(field.to_string()),
+            }
+            if field.matches(quote).count() % 2 == 1 {
+                open = !open;
+            }
+        }
+        return values.iter().map(|value| value.trim().to_string()).collect();
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    let mut attributes: Vec<Attribute> = Vec::new();
+    let mut rows = Vec::new();
+    let mut in_data = false;
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.expect("Problem reading ARFF file");
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('%') {
+            continue;
+        }
+        let lowercase = line.to_lowercase();
+        if !in_data {
+            if lowercase.starts_with("@attribute") {
+                let declaration = line["@attribute".len()..].trim();
+                let (name, kind) = match declaration.chars().next() {
+                    Some(quote @ ('\'' | '"')) => {
+                        let end = declaration[1..].find(quote).expect("Unterminated attribute name") + 1;
+                        (&declaration[..=end], &declaration[end + 1..])
+                    }
+                    _ => declaration.split_once(char::is_whitespace).expect("Attribute without a type"),
+                };
+                let kind = kind.trim();
+                names.push(unquote(name));
+                attributes.push(match kind.to_lowercase().as_str() {
+                    "numeric" | "real" | "integer" => Some(Vec::new()),
+                    _ if kind.starts_with('{') => Some(fields(kind.trim_matches(|c| c == '{' || c == '}')).iter().map(|v| unquote(v)).collect()),
+                    _ => None,
+                });
+            } else if lowercase.starts_with("@data") {
+                in_data = true;
+            }
+            continue;
+        }
+
+        let values: Vec<String> = match line.starts_with('{') {
+            true => {
+                let mut values: Vec<String> = attributes.iter()
+                    .map(|attribute| attribute.as_ref().and_then(|nominal| nominal.first()).map_or("0".to_string(), String::clone))
+                    .collect();
+                for entry in fields(line.trim_matches(|c| c == '{' || c == '}')).iter().filter(|e| !e.is_empty()) {
+                    let (index, value) = entry.split_once(' ').expect("Sparse ARFF entries must be index value");
+                    let index = index.parse::<usize>().expect("Sparse ARFF index is not a number");
+                    if index >= values.len() {
+                        panic!("Line {} has the sparse index {} but only {} attributes were declared", i + 1, index, values.len());
+                    }
+                    values[index] = value.to_string();
+                }
+                values
+            }
+            false => fields(line),
+        };
+        if values.len() != attributes.len() {
+            panic!("Line {} has {} values but {} attributes were declared", i + 1, values.len(), attributes.len());
+        }
+        rows.push(values.iter().zip(&attributes).filter_map(|(value, attribute)| {
+            let value = unquote(value);
+            match attribute {
+                None => None,
+                _ if value == "?" => Some(f64::NAN),
+                Some(nominal) if nominal.is_empty() => Some(parse_number(&value, '.', None)
+                    .unwrap_or_else(|| panic!("Line {} has the value \"{}\" which is not a number", i + 1, value))),
+                Some(nominal) => Some(nominal.iter().position(|v| *v == value)
+                    .unwrap_or_else(|| panic!("Line {} has the value \"{}\" which is not a declared nominal value", i + 1, value)) as f64),
+            }
+        }).collect());
+    }
+    if rows.is_empty() {
+        panic!("The dataset is empty");
+    }
+
+    let column_names = names.into_iter().zip(&attributes).filter(|(_, a)| a.is_some()).map(|(name, _)| name).collect();
+    return into_dataset(column_names, rows, options);
+}
+
+/// Reads an Apache Parquet file into a `Dataset`, using every numeric and string column.
+#[cfg(feature = "arrow")]
+pub fn read_parquet(location: &str, options: &DatasetOptions) -> Dataset {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let file = File::open(location).unwrap_or_else(|e| panic!("Could not open {}: {}", location, e));
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).expect("Not a valid Parquet file");
+    let schema = builder.schema().clone();
+    let batches = builder.build().unwrap().map(|batch| batch.expect("Problem reading Parquet file"));
+    return read_record_batches(&schema, batches, options);
+}
+
+/// Reads an Arrow IPC (Feather v2) file into a `Dataset`, using every numeric and string column.
+#[cfg(feature = "arrow")]
+pub fn read_arrow_ipc(location: &str, options: &DatasetOptions) -> Dataset {
+    let file = File::open(location).unwrap_or_else(|e| panic!("Could not open {}: {}", location, e));
+    let reader = arrow_ipc::reader::FileReader::try_new(BufReader::new(file), None).expect("Not a valid Arrow IPC file");
+    let schema = reader.schema();
+    let batches = reader.map(|batch| batch.expect("Problem reading Arrow IPC file"));
+    return read_record_batches(&schema, batches, options);
+}
+
+/// Converts Arrow record batches into a `Dataset`.
+///
+/// Numeric and string columns are used, and every other column is skipped. String columns are
+/// encoded as categories as in `read_csv`.
+#[cfg(feature = "arrow")]
+fn read_record_batches(
+    schema: &arrow_schema::Schema,
+    batches: impl Iterator<Item=arrow_array::RecordBatch>,
+    options: &DatasetOptions,
+) -> Dataset {
+    use arrow_array::Array;
+    use arrow_array::cast::AsArray;
+    use arrow_schema::DataType;
+
+    let used: Vec<usize> = (0..schema.fields().len())
+        .filter(|&i| schema.field(i).data_type().is_numeric() || matches!(schema.field(i).data_type(), DataType::Utf8 | DataType::LargeUtf8))
+        .collect();
+    let column_names = used.iter().map(|&i| schema.field(i).name().clone()).collect();
+
+    let mut rows = Vec::new();
+    for batch in batches {
+        // Numbers are written the shortest way that reads back as the same value
+        let columns: Vec<_> = used.iter()
+            .map(|&i| arrow_cast::cast(batch.column(i), &DataType::Utf8).unwrap())
+            .collect();
+        for row in 0..batch.num_rows() {
+            rows.push(columns.iter().map(|column| match column.is_null(row) {
+                true => String::new(),
+                false => column.as_string::<i32>().value(row).to_string(),
+            }).collect());
+        }
+    }
+    if rows.is_empty() {
+        panic!("The dataset is empty");
+    }
+    return parse_table(column_names, rows, &DatasetOptions { decimal: '.', thousands: None, ..options.clone() });
+}
+
+/// Runs `query` against a database and reads the result into a `Dataset`.
+///
+/// `connection` is either `sqlite:<path>` (requires the `sqlite` feature) or a `postgres://` URL
+/// (requires the `postgres` feature). Every column returned by the query is used, and columns
+/// holding text are encoded as categories as in `read_csv`.
+#[cfg_attr(not(all(feature = "sqlite", feature = "postgres")), allow(unused_variables))]
+#[tracing::instrument(level = "debug", skip(connection, options))]
+pub fn read_query(connection: &str, query: &str, options: &DatasetOptions) -> Dataset {
+    if let Some(path) = connection.strip_prefix("sqlite:") {
+        #[cfg(feature = "sqlite")]
+        return read_sqlite(path, query, options);
+        #[cfg(not(feature = "sqlite"))]
+        panic!("Querying SQLite databases requires building with the sqlite feature");
+    }
+    if connection.starts_with("postgres://") || connection.starts_with("postgresql://") {
+        #[cfg(feature = "postgres")]
+        return read_postgres(connection, query, options);
+        #[cfg(not(feature = "postgres"))]
+        panic!("Querying Postgres databases requires building with the postgres feature");
+    }
+    panic!("Unsupported connection string {}. Use sqlite:<path> or postgres://...", connection);
+}
+
+/// Reads the result of a query on a SQLite database file into a `Dataset`.
+#[cfg(feature = "sqlite")]
+fn read_sqlite(path: &str, query: &str, options: &DatasetOptions) -> Dataset {
+    use rusqlite::types::ValueRef;
+
+    let connection = rusqlite::Connection::open(path).unwrap_or_else(|e| panic!("Could not open {}: {}", path, e));
+    let mut statement = connection.prepare(query).unwrap_or_else(|e| panic!("Invalid query: {}", e));
+    let column_names: Vec<String> = statement.column_names().iter().map(|name| name.to_string()).collect();
+    let num_columns = column_names.len();
+
+    let mut rows = Vec::new();
+    let mut results = statement.query([]).unwrap();
+    while let Some(row) = results.next().unwrap() {
+        rows.push((0..num_columns).map(|i| match row.get_ref(i).unwrap() {
+            ValueRef::Integer(x) => x.to_string(),
+            ValueRef::Real(x) => x.to_string(),
+            ValueRef::Text(x) => String::from_utf8_lossy(x).into_owned(),
+            ValueRef::Null => String::new(),
+            ValueRef::Blob(_) => panic!("Column {} is not numeric", column_names[i]),
+        }).collect());
+    }
+    if rows.is_empty() {
+        panic!("The query returned no rows");
+    }
+    // Text columns are encoded as categories like those of a CSV file, and numbers are written with a .
+    return parse_table(column_names, rows, &DatasetOptions { decimal: '.', thousands: None, ..options.clone() });
+}
+
+/// Reads the result of a query on a Postgres database into a `Dataset`.
+///
+/// Integer, floating point and text columns are supported, and text columns are encoded as
+/// categories. Other types, such as `numeric`, have to be cast in the query, e.g. `SELECT price::float8 ...`.
+#[cfg(feature = "postgres")]
+fn read_postgres(connection: &str, query: &str, options: &DatasetOptions) -> Dataset {
+    use postgres::types::Type;
+
+    let mut client = postgres::Client::connect(connection, postgres::NoTls)
+        .unwrap_or_else(|e| panic!("Could not connect to the database: {}", e));
+    let results = client.query(query, &[]).unwrap_or_else(|e| panic!("Invalid query: {}", e));
+    let Some(first) = results.first() else {
+        panic!("The query returned no rows");
+    };
+    let column_names: Vec<String> = first.columns().iter().map(|c| c.name().to_string()).collect();
+
+    let rows = results.iter().map(|row| {
+        row.columns().iter().enumerate().map(|(i, column)| {
+            let value = match *column.type_() {
+                Type::INT2 => row.get::<_, Option<i16>>(i).map(|x| x.to_string()),
+                Type::INT4 => row.get::<_, Option<i32>>(i).map(|x| x.to_string()),
+                Type::INT8 => row.get::<_, Option<i64>>(i).map(|x| x.to_string()),
+                Type::FLOAT4 => row.get::<_, Option<f32>>(i).map(|x| x.to_string()),
+                Type::FLOAT8 => row.get::<_, Option<f64>>(i).map(|x| x.to_string()),
+                Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => row.get::<_, Option<String>>(i),
+                _ => panic!("Column {} has type {} which is not supported. Cast it to float8 in the query", column.name(), column.type_()),
+            };
+            value.unwrap_or_default()
+        }).collect()
+    }).collect();
+    // Text columns are encoded as categories like those of a CSV file, and numbers are written with a .
+    return parse_table(column_names, rows, &DatasetOptions { decimal: '.', thousands: None, ..options.clone() });
+}
+
+/// Parses a table of text fields into a `Dataset`, encoding the columns that contain text as categories.
+///
+/// Target columns are always encoded as ordinal when they contain text.
+///
+/// # Arguments
+///
+/// * `column_names` - The name of every column in `rows`.
+/// * `rows` - The fields of every column, one `Vec` per row.
+/// * `options` - How numbers are written, which columns are the targets and how categories and missing values are handled.
+fn parse_table(column_names: Vec<String>, rows: Vec<Vec<String>>, options: &DatasetOptions) -> Dataset {
+    let targets = target_indices(&column_names, options);
+    let weight = weight_index(&column_names, &targets, options);
+    let encodings: Vec<Option<ColumnEncoding>> = column_names.iter().enumerate().map(|(c, name)| {
+        let text = rows.iter().map(|row| row[c].trim()).find(|x| !is_missing(x) && parse_number(x, options.decimal, options.thousands).is_none());
+        if text.is_none() && !options.categorical_columns.contains(name) {
+            return None;
+        }
+        if weight == Some(c) {
+            panic!("The weight column {} must hold numbers", name);
+        }
+        if let (Some(value), CategoricalEncoding::Error) = (text, options.categorical) {
+            panic!("Column {} contains the value \"{}\" which is not a number", name, value);
+        }
+        // Numeric categories are sorted by value, followed by the text categories in alphabetical order
+        let mut categories: Vec<&str> = rows.iter().map(|row| row[c].trim()).filter(|x| !is_missing(x)).collect::<BTreeSet<&str>>().into_iter().collect();
+        categories.sort_by(|a, b| match (parse_number(a, options.decimal, options.thousands), parse_number(b, options.decimal, options.thousands)) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            (x, y) => x.is_none().cmp(&y.is_none()).then(a.cmp(b)),
+        });
+        Some(ColumnEncoding {
+            column: name.clone(),
+            categories: categories.into_iter().map(str::to_string).collect(),
+            one_hot: options.categorical == CategoricalEncoding::OneHot && !targets.contains(&c),
+        })
+    }).collect();
+
+    let mut encoded_names = Vec::new();
+    let mut encoded_targets = vec![0; targets.len()];
+    let mut encoded_weight = None;
+    for (c, encoding) in encodings.iter().enumerate() {
+        if let Some(t) = targets.iter().position(|&target| target == c) {
+            encoded_targets[t] = encoded_names.len();
+        }
+        if weight == Some(c) {
+            encoded_weight = Some(encoded_names.len());
+        }
+        match encoding {
+            Some(encoding) => encoded_names.extend(encoding.column_names()),
+            None => encoded_names.push(column_names[c].clone()),
+        }
+    }
+
+    let encoded_rows = rows.iter().map(|row| {
+        let mut encoded = Vec::with_capacity(encoded_names.len());
+        for (field, encoding) in row.iter().zip(&encodings) {
+            match encoding {
+                Some(encoding) => encoded.extend(encoding.encode(field)),
+                None if is_missing(field) => encoded.push(f64::NAN),
+                None => encoded.push(parse_number(field, options.decimal, options.thousands).unwrap()),
+            }
+        }
+        encoded
+    }).collect();
+
+    let mut dataset = build_dataset(encoded_names, encoded_rows, &encoded_targets, encoded_weight, options);
+    dataset.encodings = encodings.into_iter().flatten().collect();
+    return dataset;
+}
+
+/// Builds a `Dataset` from complete rows, moving the target columns chosen by `options` to the end of every row.
+///
+/// # Arguments
+///
+/// * `column_names` - The name of every column in `rows`.
+/// * `rows` - The values of every column, one `Vec` per row.
+/// * `options` - Decides which columns are the targets and weights and how missing values are handled.
+fn into_dataset(column_names: Vec<String>, rows: Vec<Vec<f64>>, options: &DatasetOptions) -> Dataset {
+    // Columns named by `categorical_columns` are encoded the same way as those of the text formats
+    if column_names.iter().any(|name| options.categorical_columns.contains(name)) {
+        let rows = rows.into_iter().map(|row| row.into_iter().map(|x| if x.is_nan() { String::new() } else { x.to_string() }).collect()).collect();
+        return parse_table(column_names, rows, &DatasetOptions { decimal: '.', thousands: None, ..options.clone() });
+    }
+    let targets = target_indices(&column_names, options);
+    let weight = weight_index(&column_names, &targets, options);
+    return build_dataset(column_names, rows, &targets, weight, options);
+}
+
+/// Returns the indices of the target columns chosen by `options`, defaulting to the last column.
+fn target_indices(column_names: &[String], options: &DatasetOptions) -> Vec<usize> {
+    if options.target_columns.is_empty() {
+        return vec![column_names.len() - 1];
+    }
+    let targets: Vec<usize> = options.target_columns.iter().map(|column| resolve_column(column_names, column)).collect();
+    if targets.iter().enumerate().any(|(i, target)| targets[..i].contains(target)) {
+        panic!("A target column is given more than once");
+    }
+    if targets.len() >= column_names.len() {
+        panic!("Every column is a target, so there are no variables left");
+    }
+    return targets;
+}
+
+/// Returns the index of the weight column chosen by `options`, if any.
+fn weight_index(column_names: &[String], targets: &[usize], options: &DatasetOptions) -> Option<usize> {
+    let weight = resolve_column(column_names, options.weight_column.as_ref()?);
+    if targets.contains(&weight) {
+        panic!("The weight column {} cannot also be a target", column_names[weight]);
+    }
+    if targets.len() + 1 >= column_names.len() {
+        panic!("Every column is a target or the weight, so there are no variables left");
+    }
+    return Some(weight);
+}
+
+/// Builds a `Dataset` from complete rows, moving the `targets` columns to the end of every row in the given order,
+/// preceded by the `weight` column if there is one.
+///
+/// When `options.validation_fraction` is above 0, that fraction of the rows is held out first. Missing
+/// values, which the readers store as NaN, are then handled according to `options.missing` and the
+/// columns rescaled according to `options.normalize`, both fitted on the remaining rows only and
+/// applied unchanged to the held out rows.
+fn build_dataset(column_names: Vec<String>, rows: Vec<Vec<f64>>, targets: &[usize], weight: Option<usize>, options: &DatasetOptions) -> Dataset {
+    let order: Vec<usize> = (0..column_names.len()).filter(|c| !targets.contains(c) && weight != Some(*c))
+        .chain(weight)
+        .chain(targets.iter().copied())
+        .collect();
+    let mut rows: Vec<Vec<f64>> = rows.into_iter().map(|row| order.iter().map(|&c| row[c]).collect()).collect();
+    let mut column_names: Vec<String> = order.iter().map(|&c| column_names[c].clone()).collect();
 
-    for r in rdr.unwrap().records() {
-        let record = r.unwrap();
-        let mut temp_csv = Vec::new();
-        record.iter().for_each(|x| temp_csv.push(x.parse::<f64>().unwrap()));
-        csv.push(temp_csv)
+    let mut validation_rows = match options.validation_fraction > 0.0 {
+        true => {
+            if rows.len() < 2 {
+                panic!("The dataset needs at least two rows to hold out a validation set");
+            }
+            rows.shuffle(&mut rng());
+            let split_size = ((rows.len() as f64 * options.validation_fraction).round() as usize).clamp(1, rows.len() - 1);
+            rows.split_off(rows.len() - split_size)
+        }
+        false => Vec::new(),
+    };
+    let missing = handle_missing(&mut rows, &mut validation_rows, &column_names, options.missing);
+    let target_names = column_names.split_off(column_names.len() - targets.len());
+    let weight_name = weight.and_then(|_| column_names.pop());
+    if rows.is_empty() {
+        panic!("The dataset has no rows left after removing rows with missing values");
     }
-    return csv;
+    if options.validation_fraction > 0.0 && validation_rows.is_empty() {
+        panic!("The validation set has no rows left after removing rows with missing values");
+    }
+    if options.normalize_target && targets.len() > 1 {
+        panic!("The target can only be normalized when there is a single target column");
+    }
+    if let Some(name) = &weight_name {
+        if let Some(row) = rows.iter().chain(&validation_rows).find(|row| !(row[column_names.len()] >= 0.0 && row[column_names.len()].is_finite())) {
+            panic!("A row has the weight {}, but weights must be non-negative numbers", row[column_names.len()]);
+        }
+        if rows.iter().all(|row| row[column_names.len()] == 0.0) {
+            panic!("Every weight in column {} is 0", name);
+        }
+    }
+
+    let mut scalers: Vec<Scaler> = Vec::new();
+    if let Some(method) = options.normalize {
+        // The variables, and the target at the end of the rows
+        let columns: Vec<usize> = (0..column_names.len()).chain(options.normalize_target.then(|| column_names.len() + weight_name.iter().len())).collect();
+        for &column in &columns {
+            let values: Vec<f64> = rows.iter().map(|row| row[column]).collect();
+            scalers.push(Scaler::fit(method, &values));
+        }
+        for row in rows.iter_mut().chain(&mut validation_rows) {
+            columns.iter().zip(&scalers).for_each(|(&column, scaler)| row[column] = scaler.transform(row[column]));
+        }
+    }
+    let target_scaler = match options.normalize_target {
+        true => scalers.pop(),
+        false => None,
+    };
+
+    tracing::debug!(rows = rows.len(), variables = column_names.len(), targets = ?target_names, weight = ?weight_name, "dataset loaded");
+    return Dataset { rows, variable_names: column_names, target_names, weight_name, missing, encodings: Vec::new(), scalers, target_scaler, validation_rows };
+}
+
+/// Applies a `MissingPolicy` to every NaN in `rows` and `validation_rows`. Column means are computed
+/// from `rows` alone, and only the cells of `rows` are recorded as imputed.
+fn handle_missing(rows: &mut Vec<Vec<f64>>, validation_rows: &mut Vec<Vec<f64>>, column_names: &[String], policy: MissingPolicy) -> MissingReport {
+    let mut report = MissingReport::default();
+    match policy {
+        MissingPolicy::Error => {
+            if let Some(column) = rows.iter().chain(validation_rows.iter()).find_map(|row| row.iter().position(|x| x.is_nan())) {
+                panic!("A row has a missing value for {}. Use --missing to drop or impute missing values", column_names[column]);
+            }
+        }
+        MissingPolicy::DropRow => {
+            let before = rows.len() + validation_rows.len();
+            rows.retain(|row| !row.iter().any(|x| x.is_nan()));
+            validation_rows.retain(|row| !row.iter().any(|x| x.is_nan()));
+            report.dropped_rows = before - rows.len() - validation_rows.len();
+        }
+        MissingPolicy::MeanImpute | MissingPolicy::Constant(_) => {
+            let replacements: Vec<f64> = (0..column_names.len()).map(|column| match policy {
+                MissingPolicy::Constant(x) => x,
+                _ => {
+                    let present: Vec<f64> = rows.iter().map(|row| row[column]).filter(|x| !x.is_nan()).collect();
+                    if present.is_empty() {
+                        panic!("Column {} has no values to compute a mean from", column_names[column]);
+                    }
+                    present.iter().sum::<f64>() / present.len() as f64
+                }
+            }).collect();
+            for row in rows.iter_mut() {
+                for (column, x) in row.iter_mut().enumerate() {
+                    if x.is_nan() {
+                        *x = replacements[column];
+                        *report.imputed.entry(column_names[column].clone()).or_insert(0) += 1;
+                    }
+                }
+            }
+            for row in validation_rows.iter_mut() {
+                row.iter_mut().zip(&replacements).filter(|(x, _)| x.is_nan()).for_each(|(x, replacement)| *x = *replacement);
+            }
+        }
+    }
+    return report;
+}
+
+/// Finds the index of a column given either its name or its zero-based index.
+fn resolve_column(column_names: &[String], column: &str) -> usize {
+    if let Some(index) = column_names.iter().position(|name| name == column) {
+        return index;
+    }
+    return match column.parse::<usize>() {
+        Ok(index) if index < column_names.len() => index,
+        _ => panic!("Column {} is neither a column name nor an index below {}", column, column_names.len()),
+    };
 }
 
-pub type Dataset = Vec<Vec<f64>>;
+/// The values the GP is regressing on.
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    /// Each row holds the variables followed by the weight, if there is a weight column, and the target values.
+    pub rows: Vec<Vec<f64>>,
+    /// The names of the variables, in the same order as they appear in `rows`.
+    pub variable_names: Vec<String>,
+    /// The names of the targets, in the same order as they appear at the end of `rows`.
+    pub target_names: Vec<String>,
+    /// The name of the column of sample weights, see `Dataset::weight`. `None` weighs every row equally.
+    pub weight_name: Option<String>,
+    /// How missing values were handled when the dataset was loaded.
+    pub missing: MissingReport,
+    /// The encodings applied to categorical columns when the dataset was loaded.
+    pub encodings: Vec<ColumnEncoding>,
+    /// The scaler applied to each variable, empty when the variables were not normalized.
+    pub scalers: Vec<Scaler>,
+    /// The scaler applied to the target, if it was normalized.
+    pub target_scaler: Option<Scaler>,
+    /// The rows held out for validation by `DatasetOptions::validation_fraction`, with missing values
+    /// and scaling handled by what was fitted on `rows`. Empty when nothing was held out.
+    pub validation_rows: Vec<Vec<f64>>,
+}
 
 pub struct DataToWrite {
     pub(crate) generation: usize,
     pub(crate) fitness: f64,
+    pub(crate) statistics: PopulationStatistics,
+    /// The complexity of the best chromosome.
+    pub(crate) complexity: Complexity,
+}
+
+impl DataToWrite {
+    /// The column names matching the `Display` output. Columns are only ever appended, so that
+    /// readers of older files can pick columns by position: 0.2.0 added `unique` through `best_depth`
+    /// after `invalid`.
+    pub const HEADER: &'static str = "generation, best, min, mean, median, std, mean_size, invalid, unique, phenotypic_diversity, mean_depth, best_size, best_depth";
 }
 
 impl Display for DataToWrite {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "{}, {}", self.generation, self.fitness)
+        let s = &self.statistics;
+        writeln!(f, "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}", self.generation, self.fitness, s.min, s.mean, s.median, s.std, s.mean_size,
+                 s.invalid, s.unique, s.phenotypic_diversity, s.mean_depth, self.complexity.active_genes, self.complexity.depth)
     }
 }
 
+#[tracing::instrument(level = "debug", skip(data))]
 pub fn write_graph_data(data: Vec<DataToWrite>, file_name: &str) {
     let file = File::create(file_name).unwrap();
     let mut file = BufWriter::new(file);
+    writeln!(file, "{}", DataToWrite::HEADER).expect("Problem writing to file");
     for row in data {
-        writeln!(file, "{}, {}", row.generation, row.fitness).expect("Problem writing to file")
+        write!(file, "{}", row).expect("Problem writing to file")
     }
 }
 
+#[allow(clippy::len_without_is_empty)]
 pub trait DatasetTraits {
     fn get_num_variables(&self) -> usize;
+    fn sample_rows(&self, size: usize) -> Dataset;
+    fn with_rows(&self, rows: Vec<Vec<f64>>) -> Dataset;
+    fn split(&self, fraction: f64) -> (Dataset, Dataset);
+    fn len(&self) -> usize;
+    fn weight(&self, row: &[f64]) -> f64;
 }
 
 impl DatasetTraits for Dataset {
+    /// Returns the number of variables, which is every column except the targets and the weight.
     fn get_num_variables(&self) -> usize {
-        return self[0].len() - 2;
+        return self.variable_names.len();
+    }
+
+    /// Returns a random subset of the rows of the dataset, drawn without replacement.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The number of rows to draw. If it is larger than the dataset, every row is returned.
+    fn sample_rows(&self, size: usize) -> Dataset {
+        if size >= self.len() {
+            return self.clone();
+        }
+        return self.with_rows(sample(&mut rng(), self.len(), size)
+            .into_iter()
+            .map(|i| self.rows[i].clone())
+            .collect());
+    }
+
+    /// Creates a new `Dataset` with the same columns as this one but with the given rows.
+    fn with_rows(&self, rows: Vec<Vec<f64>>) -> Dataset {
+        return Dataset {
+            rows,
+            variable_names: self.variable_names.clone(),
+            target_names: self.target_names.clone(),
+            weight_name: self.weight_name.clone(),
+            missing: MissingReport::default(),
+            encodings: self.encodings.clone(),
+            scalers: self.scalers.clone(),
+            target_scaler: self.target_scaler,
+            validation_rows: Vec::new(),
+        };
+    }
+
+    /// Randomly splits the rows into two datasets.
+    ///
+    /// # Arguments
+    ///
+    /// * `fraction` - The fraction of the rows that go into the second dataset, e.g. 0.2 for a validation set.
+    ///
+    /// # Returns
+    ///
+    /// A tuple of the remaining rows and the split off rows. Both hold at least one row.
+    fn split(&self, fraction: f64) -> (Dataset, Dataset) {
+        if self.len() < 2 {
+            panic!("The dataset needs at least two rows to be split");
+        }
+        let mut rows = self.rows.clone();
+        rows.shuffle(&mut rng());
+        let split_size = ((self.len() as f64 * fraction).round() as usize).clamp(1, self.len() - 1);
+        let split_rows = rows.split_off(self.len() - split_size);
+        return (self.with_rows(rows), self.with_rows(split_rows));
+    }
+
+    /// Returns the number of rows in the dataset.
+    fn len(&self) -> usize {
+        return self.rows.len();
+    }
+
+    /// Returns how much `row`, one of the rows of the dataset, counts in the error: its value in the
+    /// weight column, or 1 without one.
+    fn weight(&self, row: &[f64]) -> f64 {
+        return match self.weight_name {
+            Some(_) => row[self.variable_names.len()],
+            None => 1.0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column_names(n: usize) -> Vec<String> {
+        return (0..n).map(|i| format!("c{}", i)).collect();
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number(" 3.5 ", '.', None), Some(3.5));
+        assert_eq!(parse_number("-1e3", '.', None), Some(-1000.0));
+        assert_eq!(parse_number("abc", '.', None), None);
+        // Without a thousands separator, the other separator makes a number ambiguous
+        assert_eq!(parse_number("3,14", '.', None), None);
+        assert_eq!(parse_number("3.14", ',', None), None);
+        assert_eq!(parse_number("3,5", ',', None), Some(3.5));
+        assert_eq!(parse_number("1.234,5", ',', Some('.')), Some(1234.5));
+        assert_eq!(parse_number("1,234.5", '.', Some(',')), Some(1234.5));
+        assert_eq!(parse_number("1 234,5", ',', Some(' ')), Some(1234.5));
+    }
+
+    #[test]
+    fn test_read_arff_dense() {
+        let arff = "% A comment\n@relation test\n@attribute x numeric\n@attribute note string\n@attribute y REAL\n\n@data\n1.5,abc,2\n?,def,4\n";
+        let dataset = read_arff(arff.as_bytes(), &DatasetOptions { missing: MissingPolicy::Constant(0.0), ..DatasetOptions::default() });
+        assert_eq!(dataset.variable_names, vec!["x"]);
+        assert_eq!(dataset.target_names, vec!["y"]);
+        assert_eq!(dataset.rows, vec![vec![1.5, 2.0], vec![0.0, 4.0]]);
+    }
+
+    #[test]
+    fn test_read_arff_nominal_and_quoted() {
+        let arff = "@attribute 'the color' {red, 'dark, blue', green}\n@attribute y numeric\n@data\n'dark, blue',1\ngreen,2\nred,3\n";
+        let dataset = read_arff(arff.as_bytes(), &DatasetOptions::default());
+        assert_eq!(dataset.variable_names, vec!["the color"]);
+        assert_eq!(dataset.rows, vec![vec![1.0, 1.0], vec![2.0, 2.0], vec![0.0, 3.0]]);
+    }
+
+    #[test]
+    fn test_read_arff_sparse() {
+        let arff = "@attribute x numeric\n@attribute kind {b, 'a,c'}\n@attribute y numeric\n@data\n{0 2, 2 5}\n{1 'a,c', 2 1}\n{}\n";
+        let dataset = read_arff(arff.as_bytes(), &DatasetOptions::default());
+        // Omitted attributes are 0, or the first value of a nominal attribute
+        assert_eq!(dataset.rows, vec![vec![2.0, 0.0, 5.0], vec![0.0, 1.0, 1.0], vec![0.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn test_read_jsonl() {
+        let jsonl = "{\"x\": 1.5, \"flag\": true, \"y\": 2}\n\n{\"x\": null, \"flag\": false, \"z\": 7, \"y\": 4}\n{\"y\": 6, \"x\": 3}\n";
+        let options = DatasetOptions { target_columns: vec!["y".to_string()], missing: MissingPolicy::Constant(0.0), ..DatasetOptions::default() };
+        let dataset = read_jsonl(jsonl.as_bytes(), &options);
+        // z only appears on the second line, and is missing on the others
+        assert_eq!(dataset.variable_names, vec!["x", "flag", "z"]);
+        assert_eq!(dataset.rows, vec![vec![1.5, 1.0, 0.0, 2.0], vec![0.0, 0.0, 7.0, 4.0], vec![3.0, 0.0, 0.0, 6.0]]);
+        assert_eq!(dataset.missing.imputed, BTreeMap::from([("flag".to_string(), 1), ("x".to_string(), 1), ("z".to_string(), 2)]));
+    }
+
+    #[test]
+    fn test_read_libsvm() {
+        let libsvm = "1 1:0.5 3:2 # a comment\n\n-1 2:1.5\n";
+        let dataset = read_libsvm(libsvm.as_bytes(), &DatasetOptions::default());
+        assert_eq!(dataset.variable_names, vec!["f1", "f2", "f3"]);
+        assert_eq!(dataset.target_names, vec!["label"]);
+        assert_eq!(dataset.rows, vec![vec![0.5, 0.0, 2.0, 1.0], vec![0.0, 1.5, 0.0, -1.0]]);
+    }
+
+    #[test]
+    /// Ensures that the columns of the numeric formats named as categorical are encoded like those of a CSV file
+    fn test_categorical_columns_of_numeric_formats() {
+        let options = DatasetOptions { categorical: CategoricalEncoding::Ordinal, categorical_columns: vec!["f2".to_string()], ..DatasetOptions::default() };
+        let dataset = read_libsvm("1 1:0.5 2:7\n-1 2:1.5\n0 1:2\n".as_bytes(), &options);
+        assert_eq!(dataset.variable_names, vec!["f1", "f2"]);
+        assert_eq!(dataset.rows, vec![vec![0.5, 2.0, 1.0], vec![0.0, 1.0, -1.0], vec![2.0, 0.0, 0.0]]);
+        assert_eq!(dataset.encodings[0].categories, vec!["0", "1.5", "7"]);
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    /// Ensures that text columns of a query are encoded as categories rather than rejected
+    fn test_read_sqlite_text_columns() {
+        let path = std::env::temp_dir().join("rust_gp_categories.sqlite");
+        let _ = std::fs::remove_file(&path);
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        connection.execute_batch("CREATE TABLE data (colour TEXT, x REAL, y INTEGER);
+                                  INSERT INTO data VALUES ('red', 0.5, 1), ('blue', 1.5, 2), (NULL, 2.5, 3), ('red', 3.5, 4);").unwrap();
+        let options = DatasetOptions { categorical: CategoricalEncoding::Ordinal, missing: MissingPolicy::MeanImpute, ..DatasetOptions::default() };
+        let dataset = read_query(&format!("sqlite:{}", path.display()), "SELECT colour, x, y FROM data", &options);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dataset.variable_names, vec!["colour", "x"]);
+        assert_eq!(dataset.encodings[0].categories, vec!["blue", "red"]);
+        assert_eq!(dataset.rows[..2], [vec![1.0, 0.5, 1.0], vec![0.0, 1.5, 2.0]]);
+    }
+
+    #[test]
+    fn test_open_compressed_dataset() {
+        let csv = std::fs::read("test.csv").unwrap();
+        let expected = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let directory = std::env::temp_dir();
+
+        // A gzip file of two members, as written by appending to it, is read in full
+        let (first, second) = csv.split_at(csv.len() / 2);
+        let mut gz = Vec::new();
+        for member in [first, second] {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(member).unwrap();
+            gz.extend(encoder.finish().unwrap());
+        }
+        let zst = zstd::encode_all(csv.as_slice(), 0).unwrap();
+
+        for (name, compressed) in [("rust_gp_open_dataset.csv.gz", gz), ("rust_gp_open_dataset.csv.zst", zst)] {
+            let path = directory.join(name);
+            std::fs::write(&path, &compressed).unwrap();
+            let mut decompressed = Vec::new();
+            open_dataset(path.to_str().unwrap()).read_to_end(&mut decompressed).unwrap();
+            assert_eq!(decompressed, csv, "{}", name);
+            let dataset = read_dataset(path.to_str().unwrap(), &DatasetOptions::default());
+            assert_eq!((dataset.variable_names, dataset.rows), (expected.variable_names.clone(), expected.rows.clone()), "{}", name);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_open_stdin() {
+        let piped = || -> Box<dyn Read> { Box::new("x0,y\n1,2\n3,4\n".as_bytes()) };
+        let dataset = read_csv(open_location("-", piped), &DatasetOptions::default());
+        assert_eq!((dataset.variable_names, dataset.rows), (vec!["x0".to_string()], vec![vec![1.0, 2.0], vec![3.0, 4.0]]));
+        // Any other location is opened as a file
+        let mut file = String::new();
+        open_location("test.csv", || panic!("stdin was read instead of test.csv")).read_to_string(&mut file).unwrap();
+        assert!(file.starts_with("x0,x1,x2,y"));
+    }
+
+    #[test]
+    /// Ensures that the first row is data rather than column names without a header, even when it holds text
+    fn test_read_csv_without_header() {
+        let csv = "a,1.5,2\nb,2.5,4\n";
+        let options = DatasetOptions { header: false, categorical: CategoricalEncoding::Ordinal, ..DatasetOptions::default() };
+        let dataset = read_csv(csv.as_bytes(), &options);
+        assert_eq!(dataset.variable_names, vec!["v0", "v1"]);
+        assert_eq!(dataset.target_names, vec!["target"]);
+        assert_eq!(dataset.rows, vec![vec![0.0, 1.5, 2.0], vec![1.0, 2.5, 4.0]]);
+
+        // With a header a numeric first row still names the columns
+        let dataset = read_csv("1,2\n3,4\n".as_bytes(), &DatasetOptions::default());
+        assert_eq!((dataset.variable_names, dataset.target_names), (vec!["1".to_string()], vec!["2".to_string()]));
+        assert_eq!(dataset.rows, vec![vec![3.0, 4.0]]);
+    }
+
+    #[test]
+    fn test_missing_mean_impute() {
+        let options = DatasetOptions { missing: MissingPolicy::MeanImpute, ..DatasetOptions::default() };
+        let rows = vec![vec![1.0, 10.0], vec![f64::NAN, 20.0], vec![3.0, 30.0]];
+        let dataset = into_dataset(column_names(2), rows, &options);
+        assert_eq!(dataset.rows[1], vec![2.0, 20.0]);
+        assert_eq!(dataset.missing.imputed, BTreeMap::from([("c0".to_string(), 1)]));
+    }
+
+    #[test]
+    fn test_missing_drop_row() {
+        let options = DatasetOptions { missing: MissingPolicy::DropRow, ..DatasetOptions::default() };
+        let rows = vec![vec![1.0, 10.0], vec![2.0, f64::NAN], vec![3.0, 30.0]];
+        let dataset = into_dataset(column_names(2), rows, &options);
+        assert_eq!(dataset.rows, vec![vec![1.0, 10.0], vec![3.0, 30.0]]);
+        assert_eq!(dataset.missing.dropped_rows, 1);
+    }
+
+    #[test]
+    fn test_validation_rows_use_training_fit() {
+        let options = DatasetOptions { missing: MissingPolicy::MeanImpute, normalize: Some(ScalingMethod::MinMax), validation_fraction: 0.5, ..DatasetOptions::default() };
+        let rows = vec![vec![1.0, 1.0], vec![2.0, 2.0], vec![4.0, 3.0], vec![f64::NAN, 4.0], vec![100.0, 5.0], vec![-100.0, 6.0]];
+        let dataset = into_dataset(column_names(2), rows, &options);
+        assert_eq!((dataset.rows.len(), dataset.validation_rows.len()), (3, 3));
+        // The scaler spans the training rows alone, and the validation rows are scaled with it
+        let training: Vec<f64> = dataset.rows.iter().map(|row| row[0]).collect();
+        assert_eq!(training.iter().copied().fold(f64::INFINITY, f64::min), 0.0);
+        assert_eq!(training.iter().copied().fold(f64::NEG_INFINITY, f64::max), 1.0);
+        let scaler = dataset.scalers[0];
+        let raw = |row: &Vec<f64>| (scaler.inverse(row[0]) * 1e9).round() / 1e9;
+        // A missing value is imputed with the mean of the training rows, wherever it ended up
+        let present: Vec<f64> = dataset.rows.iter().filter(|row| row[1] != 4.0).map(raw).collect();
+        let imputed = dataset.rows.iter().chain(&dataset.validation_rows).find(|row| row[1] == 4.0).map(raw).unwrap();
+        assert!((imputed - present.iter().sum::<f64>() / present.len() as f64).abs() < 1e-6);
+        let mut values: Vec<f64> = dataset.rows.iter().chain(&dataset.validation_rows).filter(|row| row[1] != 4.0).map(raw).collect();
+        values.sort_by(f64::total_cmp);
+        assert_eq!(values, vec![-100.0, 1.0, 2.0, 4.0, 100.0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_scaler() {
+        let values = [1.0, 2.0, 3.0, 4.0];
+        let z_score = Scaler::fit(ScalingMethod::ZScore, &values);
+        // The population standard deviation of 1, 2, 3 and 4 is sqrt(1.25)
+        assert_eq!((z_score.offset, z_score.scale), (2.5, 1.25f64.sqrt()));
+        assert!((z_score.transform(1.0) + 1.5 / 1.25f64.sqrt()).abs() < 1e-12);
+        let min_max = Scaler::fit(ScalingMethod::MinMax, &[2.0, 4.0, 10.0]);
+        assert_eq!((min_max.offset, min_max.scale), (2.0, 8.0));
+        // Values outside the fitted range are mapped linearly beyond 0 and 1
+        assert_eq!([min_max.transform(2.0), min_max.transform(6.0), min_max.transform(18.0), min_max.transform(-6.0)], [0.0, 0.5, 2.0, -1.0]);
+        for scaler in [z_score, min_max] {
+            for value in [-7.5, 0.0, 3.25, 1e6] {
+                assert!((scaler.inverse(scaler.transform(value)) - value).abs() < 1e-9);
+            }
+        }
+        // A constant column is only shifted
+        assert_eq!(Scaler::fit(ScalingMethod::ZScore, &[3.0, 3.0]).scale, 1.0);
+        assert_eq!(Scaler::fit(ScalingMethod::MinMax, &[3.0, 3.0]).transform(5.0), 2.0);
+    }
+
+    #[test]
+    fn test_z_score_uses_training_rows() {
+        let options = DatasetOptions { normalize: Some(ScalingMethod::ZScore), normalize_target: true, validation_fraction: 0.25, ..DatasetOptions::default() };
+        let rows: Vec<Vec<f64>> = (0..8).map(|i| vec![i as f64, 10.0 * i as f64]).collect();
+        let dataset = into_dataset(column_names(2), rows, &options);
+        assert_eq!((dataset.rows.len(), dataset.validation_rows.len(), dataset.scalers.len()), (6, 2, 1));
+        // The variable and the target are centred and scaled on the training rows alone
+        for column in [0, 1] {
+            let values: Vec<f64> = dataset.rows.iter().map(|row| row[column]).collect();
+            let mean = values.iter().sum::<f64>() / 6.0;
+            let std = (values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 6.0).sqrt();
+            assert!(mean.abs() < 1e-12 && (std - 1.0).abs() < 1e-12, "{:?}", values);
+        }
+        // The inverse restores the raw values, of the held out rows too
+        let raw: Vec<[f64; 2]> = dataset.rows.iter().chain(&dataset.validation_rows)
+            .map(|row| [dataset.scalers[0].inverse(row[0]), dataset.target_scaler.unwrap().inverse(row[1])])
+            .collect();
+        assert!(raw.iter().all(|[x, y]| (y - 10.0 * x).abs() < 1e-9));
+        let mut sorted: Vec<f64> = raw.iter().map(|[x, _]| x.round()).collect();
+        sorted.sort_by(f64::total_cmp);
+        assert_eq!(sorted, (0..8).map(|i| i as f64).collect::<Vec<f64>>());
+    }
+
+    #[test]
+    fn test_target_column() {
+        let target = |column: &str| DatasetOptions { target_columns: vec![column.to_string()], ..DatasetOptions::default() };
+        let by_name = read_csv(open_dataset("test.csv"), &target("x1"));
+        assert_eq!((by_name.variable_names, by_name.target_names), (vec!["x0".to_string(), "x2".to_string(), "y".to_string()], vec!["x1".to_string()]));
+        assert_eq!(by_name.rows[0], vec![1.5, 3.0, 6.0, 2.0]);
+        let by_index = read_csv(open_dataset("test.csv"), &target("0"));
+        assert_eq!(by_index.target_names, vec!["x0"]);
+        assert_eq!(by_index.rows[0], vec![2.0, 3.0, 6.0, 1.5]);
+
+        // A name wins over an index, and a name that is not a column is rejected
+        let names: Vec<String> = ["a", "0", "b"].map(String::from).to_vec();
+        assert_eq!((resolve_column(&names, "0"), resolve_column(&names, "2")), (1, 2));
+        for missing in ["z", "3"] {
+            let panic = std::panic::catch_unwind(|| resolve_column(&names, missing)).unwrap_err();
+            assert_eq!(panic.downcast_ref::<String>().unwrap(), &format!("Column {} is neither a column name nor an index below 3", missing));
+        }
+    }
+
+    #[test]
+    fn test_multiple_targets() {
+        let options = DatasetOptions { target_columns: vec!["c2".to_string(), "0".to_string()], ..DatasetOptions::default() };
+        let dataset = into_dataset(column_names(4), vec![vec![1.0, 2.0, 3.0, 4.0]], &options);
+        assert_eq!(dataset.rows, vec![vec![2.0, 4.0, 3.0, 1.0]]);
+        assert_eq!(dataset.variable_names, vec!["c1", "c3"]);
+        assert_eq!(dataset.target_names, vec!["c2", "c0"]);
+    }
+
+    #[test]
+    fn test_weight_column() {
+        let options = DatasetOptions { weight_column: Some("c1".to_string()), normalize: Some(ScalingMethod::MinMax), normalize_target: true, ..DatasetOptions::default() };
+        let dataset = into_dataset(column_names(3), vec![vec![1.0, 2.0, 10.0], vec![3.0, 0.5, 20.0]], &options);
+        // The weight sits between the variables and the target, and is not rescaled
+        assert_eq!(dataset.rows, vec![vec![0.0, 2.0, 0.0], vec![1.0, 0.5, 1.0]]);
+        assert_eq!((dataset.variable_names.clone(), dataset.weight_name.clone()), (vec!["c0".to_string()], Some("c1".to_string())));
+        assert_eq!(dataset.weight(&dataset.rows[1]), 0.5);
+        assert_eq!(dataset.split(0.5).0.weight_name, dataset.weight_name);
+    }
+}
@@ -0,0 +1,29 @@
+#![allow(clippy::needless_return)]
+//! Symbolic regression and classification with Cartesian genetic programming: the library behind
+//! the `rust_gp` binary. `gp::gp` runs evolution on a dataset with the given `PopulationParameters`.
+
+pub mod bandit;
+pub mod chromosome;
+pub mod egraph;
+pub mod fitness;
+pub mod functions;
+pub mod gp;
+pub mod grammar;
+pub mod hall_of_fame;
+pub mod interval;
+pub mod io;
+pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod model;
+pub mod moea;
+pub mod optimize;
+pub mod population;
+pub mod report;
+pub mod rng;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod units;
+
+pub use fitness::Objective;
+pub use population::PopulationParameters;
@@ -0,0 +1,64 @@
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// How much the run reports while it evolves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the final expression is printed.
+    Quiet,
+    /// The best fitness of every generation and the final report.
+    Normal,
+    /// Also the population statistics of every generation and restart events.
+    Verbose,
+    /// Also the validation error and elapsed time of every generation, and the timing of every span.
+    Debug,
+}
+
+impl Verbosity {
+    /// Maps the command line flags onto a verbosity level.
+    ///
+    /// # Arguments
+    ///
+    /// * `verbose` - How many times `-v` was given.
+    /// * `quiet` - Whether `--quiet` was given. Takes precedence over `verbose`.
+    pub fn from_flags(verbose: u8, quiet: bool) -> Verbosity {
+        return match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        };
+    }
+
+    /// The tracing filter directive used when `RUST_LOG` is not set. Dependencies only ever report warnings.
+    fn directive(&self) -> &'static str {
+        return match self {
+            Verbosity::Quiet => "warn",
+            Verbosity::Normal => "rust_gp=info,warn",
+            Verbosity::Verbose => "rust_gp=debug,warn",
+            Verbosity::Debug => "rust_gp=trace,warn",
+        };
+    }
+}
+
+/// Installs the global tracing subscriber.
+///
+/// Events are written to stderr so that stdout only carries the final expression. The `RUST_LOG`
+/// environment variable overrides the level picked by `verbosity`, e.g.
+/// `RUST_LOG=rust_gp::io=debug,info` shows dataset loading details on top of the normal output.
+///
+/// # Arguments
+///
+/// * `verbosity` - The level chosen on the command line.
+pub fn init(verbosity: Verbosity) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(verbosity.directive()));
+    let span_events = match verbosity >= Verbosity::Debug {
+        true => FmtSpan::CLOSE,
+        false => FmtSpan::NONE,
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events)
+        .with_writer(std::io::stderr)
+        .init();
+}
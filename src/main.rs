@@ -1,16 +1,25 @@
 #![allow(clippy::needless_return)]
 
 use std::env;
+use std::sync::Arc;
 
-use clap::{Arg, Command, value_parser};
+use clap::{Arg, ArgAction, Command, value_parser};
 
-use crate::io::read_csv;
-
-mod chromosome;
-mod functions;
-mod gp;
-mod io;
-mod population;
+use rust_gp::bandit::OperatorSelection;
+use rust_gp::chromosome::{Chromosome, ComplexityLimits, expression_variables, ConstantDistribution, ConstantDomain, CrossoverOperator, Distance, Grid, InvalidRows, MutationOperator, Notation};
+use rust_gp::fitness::{Huber, metric_named, Quantile, Task};
+use rust_gp::functions::{FunctionSet, Protection};
+use rust_gp::grammar::Grammar;
+use rust_gp::io::{CategoricalEncoding, DatasetFormat, DatasetOptions, MissingPolicy, read_dataset, read_query, ScalingMethod};
+use rust_gp::logging::Verbosity;
+use rust_gp::model::Model;
+use rust_gp::population::{DuplicatePolicy, PopulationParameters, RateAdaptation, Selection, Strategy, SubsamplePolicy};
+use rust_gp::report::Scores;
+use rust_gp::rng;
+use rust_gp::units::Units;
+use rust_gp::{gp, logging};
+#[cfg(feature = "scripting")]
+use rust_gp::scripting;
 
 fn main() {
     let x = env::current_dir().unwrap().display().to_string();
@@ -19,19 +28,422 @@ fn main() {
         .version("0.1.0")
         .author("")
         .about("Genetic Program that uses an acyclic graph representation to perform symbolic regression. \
-                Symbolic regression is performed using the last column of the provided dataset.")
+                Symbolic regression is performed using the last column of the provided dataset unless \
+                another target column is given.")
         .arg(Arg::new("file")
             .short('f')
             .long("file")
-            .help(format!("A CSV file containing the values you are trying to regress toward with symbolic regression. \
-                              Current path: {}", x))
+            .help(format!("A CSV file (optionally .gz or .zst compressed), or a Parquet/Arrow file when built with the arrow feature, containing the values you are trying to regress toward with symbolic regression. \
+                              Use - to read from stdin. Current path: {}", x))
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("format")
+            .long("format")
+            .help("The format of the dataset file. Guessed from the file extension when not provided")
+            .value_parser(["csv", "jsonl", "parquet", "arrow", "libsvm", "arff"]))
+        .arg(Arg::new("missing")
+            .long("missing")
+            .help("What to do with missing values (empty, ?, NA, NaN or null): \
+                   error, drop-row, mean-impute or constant=X")
+            .default_value("error")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("categorical")
+            .long("categorical")
+            .help("How columns containing text are encoded. A text target column is always ordinal encoded")
+            .default_value("onehot")
+            .value_parser(["onehot", "ordinal", "error"]))
+        .arg(Arg::new("categorical columns")
+            .long("categorical-columns")
+            .help("Comma separated names of numeric columns that should also be encoded as categories")
+            .value_delimiter(',')
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("normalize")
+            .long("normalize")
+            .help("Rescale the variables with a z-score or to the range [0, 1]. The scalers are saved with the model")
+            .value_parser(["zscore", "minmax"]))
+        .arg(Arg::new("normalize target")
+            .long("normalize-target")
+            .help("Also rescale the target. The final error is reported in the original units as well")
+            .requires("normalize")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("validation fraction")
+            .long("validation-fraction")
+            .help("The fraction of rows held out for validation. The reported model is the one with the lowest validation error")
+            .default_value("0")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("patience")
+            .long("patience")
+            .help("Stop early when the validation error (or training error without validation) has not improved for this many generations")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("min delta")
+            .long("min-delta")
+            .help("The smallest decrease in error that counts as an improvement for --patience")
+            .default_value("0")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("stop at fitness")
+            .long("stop-at-fitness")
+            .help("Stop as soon as the best training error drops below this value")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("restart after")
+            .long("restart-after")
+            .help("Reinitialize the population when the best fitness has not improved for this many generations")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("restart keep")
+            .long("restart-keep")
+            .help("How many of the fittest chromosomes are kept when the population is restarted")
+            .default_value("1")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("adapt rates")
+            .long("adapt-rates")
+            .help("How the mutation and crossover chances change over the run: not at all (fixed) or by the \
+                   1/5 success rule (one-fifth), which raises them while more than a fifth of the children \
+                   are fitter than their parents and lowers them otherwise")
+            .default_value("fixed")
+            .value_parser(["fixed", "one-fifth"]))
+        .arg(Arg::new("self adaptive")
+            .long("self-adaptive")
+            .help("Give every individual its own mutation and crossover chances, starting from the configured ones, \
+                   which children inherit with a log-normal perturbation so that good chances evolve")
+            .conflicts_with("adapt rates")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("mutation operators")
+            .long("mutation-operators")
+            .help("Comma separated mutation operators a child can be mutated with, each optionally followed by :rate \
+                   to use it in proportion to the rate (default 1), e.g. point:2,constant:1. The operators replace a \
+                   random gene (point), replace random genes until an active one changes (active), rewire an input of \
+                   a function (pointer), swap the function of a gene (function), give a constant a new value (constant) \
+                   move the output to another gene (output), nudge a constant the output uses by a Gaussian (gaussian), \
+                   or replace an active gene with the earlier gene or constant closest to the outputs it needs for the \
+                   expression to hit the targets of the probe rows (backpropagation), copy a block of genes over another \
+                   part of the chromosome (duplication), move a block of genes elsewhere (transposition), insert a \
+                   random gene (insertion) or delete a gene (deletion)")
+            .value_delimiter(',')
+            .default_value("point")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("crossover")
+            .long("crossover")
+            .help("How two children exchange genes: every gene from a random position on (one-point), every gene \
+                   between two random positions (two-point), each gene with even odds (uniform), the genes \
+                   feeding a random active gene (subgraph), or the genes feeding the gene whose outputs on the \
+                   probe rows differ the most (semantic-different) or the least (semantic-similar) between the parents")
+            .default_value("one-point")
+            .value_parser(["one-point", "two-point", "uniform", "subgraph", "semantic-different", "semantic-similar"]))
+        .arg(Arg::new("headless chicken")
+            .long("headless-chicken")
+            .help("The probability of replacing a random suffix of a child, or the genes feeding one of its active genes, \
+                   with random genes (headless-chicken macro-mutation), on top of the other operators")
+            .default_value("0")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("constant distribution")
+            .long("constant-distribution")
+            .help("Where the values of random constants are drawn from")
+            .default_value("uniform")
+            .value_parser(["uniform", "normal", "log-uniform"]))
+        .arg(Arg::new("constant parameters")
+            .long("constant-parameters")
+            .help("The bounds of the uniform and log-uniform constant distributions, or the mean and standard \
+                   deviation of the normal one, e.g. -100,100")
+            .value_delimiter(',')
+            .allow_hyphen_values(true)
+            .default_value("0,1")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("constant domain")
+            .long("constant-domain")
+            .help("Which values constants may take. Random, mutated and optimized constants are all rounded into it")
+            .default_value("real")
+            .value_parser(["real", "integer", "rational"]))
+        .arg(Arg::new("max denominator")
+            .long("max-denominator")
+            .help("The largest denominator of rational constants")
+            .default_value("10")
+            .value_parser(value_parser!(u32).range(1..)))
+        .arg(Arg::new("protection")
+            .long("protection")
+            .help("What division, log, log2 and sqrt return where they are undefined: the standard protection (±the \
+                   largest number for division by 0, and log, log2 and sqrt of the magnitude), 1, the numerator (or input), or NaN, \
+                   which makes the row invalid, see --invalid-row-penalty")
+            .default_value("standard")
+            .value_parser(["standard", "one", "numerator", "nan"]))
+        .arg(Arg::new("invalid row penalty")
+            .long("invalid-row-penalty")
+            .help("Leave the rows where the output of a chromosome is NaN or infinite out of its error, and multiply the \
+                   error by one plus this much times the fraction of such rows. By default, a single such row makes \
+                   the chromosome invalid, with the worst possible fitness")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("metric")
+            .long("metric")
+            .help("How the error of a chromosome is measured: the mean squared error, mean absolute error, root mean \
+                   squared error, 1 - R², mean absolute percentage error, Huber loss (see --huber-delta) or quantile \
+                   loss (see --quantile), or for classification the log-loss, 1 - accuracy, or for two classes 1 - ROC-AUC \
+                   or 1 - average precision of the scores, which suit imbalanced classes but only rank the rows, so the \
+                   threshold of 0 the confusion matrix uses is arbitrary. Defaults to mse, or log-loss for classification")
+            .value_parser(["mse", "mae", "rmse", "r2", "mape", "huber", "quantile", "log-loss", "accuracy", "roc-auc",
+                "average-precision"]))
+        .arg(Arg::new("task")
+            .long("task")
+            .help("Regress toward the target, or classify its labels. Two classes, 0 and 1 or two text labels, \
+                   are classified with the sigmoid of the expression, predicting 1 where it is positive. More \
+                   classes get one output expression each, and the highest one wins. Classification reports a \
+                   confusion matrix")
+            .value_parser(["regression", "classification"])
+            .default_value("regression"))
+        .arg(Arg::new("huber delta")
+            .long("huber-delta")
+            .help("The residual where the Huber loss turns from squared to linear, so that larger errors, e.g. \
+                   from outliers, weigh less")
+            .value_parser(value_parser!(f64))
+            .default_value("1.0"))
+        .arg(Arg::new("quantile")
+            .long("quantile")
+            .help("The quantile of the target the quantile loss fits, between 0 and 1, e.g. 0.5 for the median or \
+                   0.9 for an upper bound")
+            .value_parser(value_parser!(f64))
+            .default_value("0.5"))
+        .arg(Arg::new("function set")
+            .long("function-set")
+            .help("The primitives of function genes: arithmetic and other real functions, or boolean logic (and, or, \
+                   xor, not on inputs thresholded at 0.5) and comparisons, which output 0 or 1, for rule induction")
+            .default_value("arithmetic")
+            .value_parser(["arithmetic", "boolean"]))
+        .arg(Arg::new("functions")
+            .long("functions")
+            .help("Exactly which primitives function genes use, replacing the function set, e.g. add,sub,mul,div,sin,log. \
+                   Each can be followed by :weight to draw it in proportion to the weight (default 1) among the \
+                   primitives with as many inputs, e.g. add:3,mul:3,min,max. The primitives are add, sub, mul, div, max, min, square, log2, sin, cos, tan, exp, log, sqrt, abs, \
+                   neg, inv, cube, tanh, floor, and, or, xor, not, lt, gt and iflt (the conditional)")
+            .value_delimiter(',')
+            .conflicts_with("function set"))
+        .arg(Arg::new("typed")
+            .long("typed")
+            .help("Strongly typed GP: function genes only take inputs of the types their functions expect, so boolean \
+                   functions (and, or, xor, not) only combine comparisons (lt, gt) and other boolean functions, and \
+                   real functions only take real values")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("grammar")
+            .long("grammar")
+            .help("A file of rules that new and mutated genes must follow, one per line: forbid <functions> under \
+                   <functions> (e.g. forbid sin cos under sin cos), nest <depth> <functions> (at most that many of the \
+                   functions nested in one another, e.g. nest 1 exp log), and no-self-products (no mul(x, x), \
+                   square(x) or cube(x) of a variable)"))
+        .arg(Arg::new("primitives")
+            .long("primitives")
+            .help("A Rhai script whose functions, which must take one or two numbers, become primitives with the same \
+                   names. They join the function set, or can be chosen with --functions. Requires building with the \
+                   scripting feature"))
+        .arg(Arg::new("constant sigma")
+            .long("constant-sigma")
+            .help("The standard deviation of the Gaussian mutation of constants")
+            .default_value("0.1")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("final constant sigma")
+            .long("final-constant-sigma")
+            .help("Anneal the standard deviation of the Gaussian mutation geometrically from --constant-sigma \
+                   in the first generation to this in the last, so constants are tuned ever more finely")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("operator selection")
+            .long("operator-selection")
+            .help("How the mutation operator of each child is chosen: uniformly (fixed), by the upper confidence \
+                   bound of each operator's recent success rate (ucb), or in proportion to it (probability-matching). \
+                   The usage and success of every operator is logged each generation with -v")
+            .default_value("fixed")
+            .value_parser(["fixed", "ucb", "probability-matching"]))
+        .arg(Arg::new("min operator probability")
+            .long("min-operator-probability")
+            .help("The least likely each enabled mutation operator is to be chosen with probability matching, \
+                   so that every operator keeps being tried. Defaults to a fifth shared among the enabled operators")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("diversity threshold")
+            .long("diversity-threshold")
+            .help("Hypermutate when the fraction of distinct expressions in the population drops below this value")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("hypermutation chance")
+            .long("hypermutation-chance")
+            .help("The mutation chance used while hypermutating")
+            .default_value("1.0")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("hypermutation generations")
+            .long("hypermutation-generations")
+            .help("How many generations hypermutation lasts before the configured mutation chance is restored")
+            .default_value("5")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("immigrants")
+            .long("immigrants")
+            .help("The fraction of the population, from the least fit, replaced by random individuals when hypermutation starts")
+            .default_value("0")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("save model")
+            .long("save-model")
+            .help("A JSON file the best chromosome is written to, together with the column names and encodings")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("seed")
+            .long("seed")
+            .help("Seed the random number generator, so that the run can be repeated. Without it a random seed is used, \
+                   which is recorded in the report")
+            .value_parser(value_parser!(u64)))
+        .arg(Arg::new("report")
+            .long("report")
+            .help("A JSON file the report of the run is written to: the expression, its scores on the training and validation sets, its complexity and the parameters of the run")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("notation")
+            .long("notation")
+            .help("How the expression printed at the end of the run, in the report and by simplify is written: with infix operators such as x0*x1 + 3, as prefix function calls such as add(mul(x0, x1), 3), as S-expressions, or as LaTeX math to paste into a paper")
+            .default_value("infix")
+            .global(true)
+            .value_parser(["infix", "prefix", "sexpr", "latex"]))
+        .arg(Arg::new("canonical")
+            .long("canonical")
+            .help("Rewrite the best expression into its canonical, smallest form by equality saturation before it is printed, \
+                   reported and saved, so that equal formulas are written the same way")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("database")
+            .long("database")
+            .help("A database connection string (sqlite:<path> or postgres://...) used with --query instead of --file")
+            .requires("query")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("query")
+            .short('q')
+            .long("query")
+            .help("A SELECT statement whose numeric columns are used as the dataset")
+            .requires("database")
+            .conflicts_with("file")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("target column")
+            .short('t')
+            .long("target-column")
+            .help("The name or zero-based index of the column to regress toward. Defaults to the last column. \
+                   Several comma separated columns are regressed toward at once, with one output per column")
+            .value_delimiter(',')
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("weight column")
+            .long("weight-column")
+            .help("The name or zero-based index of a column of non-negative sample weights, e.g. the durations of \
+                   trials. Each row counts in the error in proportion to its weight, and the column is not a variable")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("delimiter")
+            .short('d')
+            .long("delimiter")
+            .help("The character separating the fields of the CSV file. Use \\t for tabs")
+            .default_value(",")
+            .global(true)
             .value_parser(value_parser!(String)))
+        .arg(Arg::new("decimal")
+            .long("decimal")
+            .help("The decimal separator used by numbers in the CSV file, e.g. , for 3,14")
+            .default_value(".")
+            .global(true)
+            .value_parser(value_parser!(char)))
+        .arg(Arg::new("thousands")
+            .long("thousands")
+            .help("The thousands separator used by numbers in the CSV file, e.g. . for 1.234,5. \
+                   Without one, numbers holding a separator other than the decimal one are read as text")
+            .global(true)
+            .value_parser(value_parser!(char)))
+        .arg(Arg::new("no header")
+            .long("no-header")
+            .help("The CSV file has no header row, so its first row is data. The variables are then named v0, v1, ...")
+            .global(true)
+            .action(ArgAction::SetTrue))
         .arg(Arg::new("num genes")
             .short('n')
             .long("genes")
-            .help("The number of genes in the Chromosome")
+            .help("The number of genes in new chromosomes. Only the insertion and deletion mutations change it")
             .default_value("100")
             .value_parser(value_parser!(usize)))
+        .arg(Arg::new("rows")
+            .long("rows")
+            .help("Arrange the genes in a Cartesian GP grid with this many rows, filled column by column. A gene \
+                   may only use genes from earlier columns. One row lets a gene use any earlier gene")
+            .default_value("1")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("columns")
+            .long("columns")
+            .help("The number of columns of the grid, so that new chromosomes have rows × columns genes")
+            .conflicts_with("num genes")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("levels back")
+            .long("levels-back")
+            .help("How many columns back the inputs of a gene may reach, as in Cartesian GP. By default a gene \
+                   may use every earlier column")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("multigene")
+            .long("multigene")
+            .help("Multi-gene GP: the last this many genes are roots whose outputs are combined into the prediction \
+                   by least-squares weights and a bias, refitted whenever a chromosome is evaluated")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("linear scaling")
+            .long("linear-scaling")
+            .help("Scale the output of every chromosome by the least-squares slope and intercept before computing its \
+                   error (linear scaling). The reported error and expression include the scaling")
+            .conflicts_with("multigene")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("optimize constants")
+            .long("optimize-constants")
+            .help("Tune the constants of the champion with Nelder-Mead against the training error at the end of the \
+                   run, evaluating at most this many sets of constants")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("optimize interval")
+            .long("optimize-interval")
+            .help("Also tune the constants of the best chromosome every this many generations")
+            .requires("optimize constants")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("simplify interval")
+            .long("simplify-interval")
+            .help("Simplify the expressions of the whole population every this many generations, e.g. x + 0 to x \
+                   and functions of constants to constants, to keep them readable")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("fold constants")
+            .long("fold-constants")
+            .help("Collapse every function of constants into a single constant before a chromosome is evaluated, \
+                   which shrinks the expressions and speeds up their evaluation")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("local learning")
+            .long("local-learning")
+            .help("Fit the constants of every chromosome with at most this many Levenberg-Marquardt iterations whenever \
+                   it is evaluated. The fitted constants are kept in its genes and passed on to its children")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("hill climb")
+            .long("hill-climb")
+            .help("Every generation, tweak an input or the function of single genes of the best chromosome and keep \
+                   the tweaks that lower the training error, evaluating at most this many tweaks")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("memetic")
+            .long("memetic")
+            .help("Memetic evolution: hill climb every child with at most this many single-gene tweaks before it is \
+                   scored, keeping those that lower its error. Each tweak costs an evaluation")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("max genes")
+            .long("max-genes")
+            .help("The most genes a chromosome may have. Chromosomes only change length with the insertion and \
+                   deletion mutations, and longer children are rejected in favour of their parents")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("max active genes")
+            .long("max-active-genes")
+            .help("The most genes that may contribute to an expression. Larger children are rejected in favour of their parents")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("max depth")
+            .long("max-depth")
+            .help("The deepest an expression may nest, counting a variable or constant as depth 1. Deeper children are rejected in favour of their parents")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("prescreen")
+            .long("prescreen")
+            .help("Bound the outputs of every chromosome with interval arithmetic over the range of each variable before \
+                   evaluating it, and give those that are certain to be NaN or infinite on every row, e.g. log(x) of a \
+                   negative x with --protection nan, the worst fitness without evaluating them on the rows. \
+                   Ignored for classification, where an infinite score is a confident prediction")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("units")
+            .long("units")
+            .help("The physical units of columns of the dataset, as products and quotients of powers of SI base units \
+                   (m, kg, s, A, K, mol, cd), e.g. x=m,t=s,v=m/s,E=kg*m^2/s^2. Other columns are dimensionless. \
+                   Dimensionally inconsistent expressions, e.g. x + t, sin(x) or an output with other units than its \
+                   target, are rejected like children beyond the complexity limits. Constants take any units. Units \
+                   are meaningless once the columns are normalized")
+            .value_delimiter(','))
+        .arg(Arg::new("units penalty")
+            .long("units-penalty")
+            .help("Penalize dimensionally inconsistent expressions instead of rejecting them: their error is multiplied \
+                   by one plus this much per inconsistency")
+            .requires("units")
+            .value_parser(value_parser!(f64)))
         .arg(Arg::new("generations")
             .short('g')
             .long("generations")
@@ -56,19 +468,718 @@ fn main() {
             .help("")
             .default_value("0.5")
             .value_parser(value_parser!(f64)))
+        .arg(Arg::new("selection")
+            .long("selection")
+            .help("How parents are chosen: the fittest of a random tournament, with a probability \
+                   proportional to 1 / (1 + fitness) (roulette), with a probability that falls \
+                   linearly or exponentially with fitness rank, or by filtering on the error of each \
+                   row in a random order (lexicase). epsilon-lexicase also keeps individuals within the \
+                   median absolute deviation of the best error on each row")
+            .default_value("tournament")
+            .value_parser(["tournament", "roulette", "linear-rank", "exponential-rank", "lexicase", "epsilon-lexicase"]))
+        .arg(Arg::new("duplicates")
+            .long("duplicates")
+            .help("What happens to children that encode the same expression as a parent or another child: \
+                   keep them (allow), mutate them until they differ (mutate) or replace them with random individuals (reject)")
+            .default_value("allow")
+            .value_parser(["allow", "mutate", "reject"]))
+        .arg(Arg::new("rank pressure")
+            .long("rank-pressure")
+            .help("How many times more often linear rank selection picks the best individual than the average one, between 1 and 2")
+            .default_value("1.5")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("rank base")
+            .long("rank-base")
+            .help("The ratio between the chances of consecutive ranks in exponential rank selection, between 0 and 1. \
+                   Smaller values increase the selection pressure")
+            .default_value("0.99")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("sharing radius")
+            .long("sharing-radius")
+            .help("Enable fitness sharing: individuals closer than this distance (between 0 and 1, see --distance) \
+                   share their fitness, which keeps the population diverse")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("distance")
+            .long("distance")
+            .help("How the similarity of individuals is measured for fitness sharing, crowding and speciation: \
+                   the fraction of differing genes (genotypic) or how differently they predict a sample of rows (behavioral)")
+            .default_value("genotypic")
+            .value_parser(["genotypic", "behavioral"]))
+        .arg(Arg::new("probe size")
+            .long("probe-size")
+            .help("How many rows are sampled to compare the predictions of individuals, for the behavioral distance, \
+                   novelty search and the semantic operators. The phenotypic diversity is measured on the same rows, \
+                   and is only reported when one of those is used")
+            .default_value("32")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("novelty")
+            .long("novelty")
+            .help("Select parents partly by how differently they predict a sample of rows compared to the population \
+                   and an archive of past behaviors, to escape deceptive fitness landscapes. The value, from 0 to 1, \
+                   is how much novelty counts against fitness; 1 is pure novelty search")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("novelty neighbours")
+            .long("novelty-neighbours")
+            .help("How many nearest behaviors the novelty of an individual is averaged over")
+            .default_value("15")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("tournament size")
+            .short('k')
+            .long("tournament-size")
+            .help("How many individuals compete in each tournament when selecting parents. \
+                   Larger tournaments increase the selection pressure")
+            .default_value("2")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("strategy")
+            .long("strategy")
+            .help("How each generation is produced: replace the whole population (generational), \
+                   a few of the worst individuals at a time (steady-state), keep the best of parents \
+                   and children (mu-plus-lambda, where mu is the population size), or mutate only the \
+                   best individual (one-plus-lambda, the usual Cartesian GP hill climber), or let children \
+                   replace their most similar parent when they are fitter (crowding), or breed within age \
+                   layers that are regularly seeded with random individuals (alps), or only within species \
+                   of similar individuals (speciation), or from an archive of the best individual for every \
+                   combination of size and number of variables used (map-elites)")
+            .default_value("generational")
+            .value_parser(["generational", "steady-state", "mu-plus-lambda", "one-plus-lambda", "crowding", "alps", "speciation", "map-elites"]))
+        .arg(Arg::new("elites file")
+            .long("elites-file")
+            .help("Where the MAP-Elites archive is written at the end of a map-elites run")
+            .default_value("map_elites.csv")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("species threshold")
+            .long("species-threshold")
+            .help("The distance (between 0 and 1, see --distance) below which individuals belong to the same species")
+            .default_value("0.3")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("species protection")
+            .long("species-protection")
+            .help("How many generations a new species keeps at least as many offspring as it has members")
+            .default_value("5")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("moea")
+            .long("moea")
+            .help("Minimize the error and the number of active genes together with a multi-objective algorithm \
+                   instead of --strategy, and print the Pareto front (size, fitness and expression of each \
+                   trade-off) instead of a single expression")
+            .conflicts_with("strategy")
+            .value_parser(["nsga2", "spea2"]))
+        .arg(Arg::new("archive size")
+            .long("archive-size")
+            .help("How many chromosomes the SPEA2 archive keeps. Defaults to the population size")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("alps layers")
+            .long("alps-layers")
+            .help("How many age layers ALPS splits the population into")
+            .default_value("5")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("age gap")
+            .long("age-gap")
+            .help("How often (in generations) ALPS replaces its youngest layer. The age limits of the layers are multiples of this")
+            .default_value("10")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("steady state offspring")
+            .long("steady-state-offspring")
+            .help("How many children steady-state evolution breeds before replacing the worst individuals")
+            .default_value("2")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("temperature")
+            .long("temperature")
+            .help("Replace the worst individual in steady-state evolution by simulated annealing, starting at this \
+                   temperature. A child whose error is a fraction d worse than the worst individual's still replaces \
+                   it with probability exp(-d / temperature)")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("final temperature")
+            .long("final-temperature")
+            .help("The annealing temperature in the last generation, which it cools to geometrically")
+            .default_value("0.001")
+            .value_parser(value_parser!(f64)))
+        .arg(Arg::new("lambda")
+            .long("lambda")
+            .help("How many children mu-plus-lambda and one-plus-lambda breed each generation. \
+                   Defaults to the population size for mu-plus-lambda and 4 for one-plus-lambda")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("batch size")
+            .short('b')
+            .long("batch-size")
+            .help("The number of randomly chosen rows used to evaluate fitness each generation. \
+                   Uses the full dataset when not provided")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("subsample")
+            .long("subsample")
+            .help("How the batch rows are chosen: uniformly at random or stratified by target quantiles")
+            .default_value("random")
+            .value_parser(["random", "stratified"]))
+        .arg(Arg::new("strata")
+            .long("strata")
+            .help("The number of target quantile bins used by stratified subsampling")
+            .default_value("4")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("full evaluation interval")
+            .long("full-eval-interval")
+            .help("How often (in generations) the full dataset is used instead of a batch")
+            .default_value("10")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("hall of fame")
+            .long("hall-of-fame")
+            .help("Archive this many of the best distinct expressions seen during the run and write them \
+                   with their fitness and complexity at the end")
+            .default_value("0")
+            .value_parser(value_parser!(usize)))
+        .arg(Arg::new("hall of fame file")
+            .long("hall-of-fame-file")
+            .help("Where the hall of fame is written")
+            .default_value("hall_of_fame.csv")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("pareto front file")
+            .long("pareto-front-file")
+            .help("Track the simplest expression for every level of error seen during the run, whatever the \
+                   strategy, and write this trade-off between complexity and fitness to this CSV file at the end")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("metrics address")
+            .long("metrics-address")
+            .help("Serve Prometheus metrics (generation, best and mean fitness, evaluations per second and memory usage) \
+                   at this address, e.g. 0.0.0.0:9184. Requires building with the metrics feature")
+            .value_parser(value_parser!(String)))
+        .arg(Arg::new("verbose")
+            .short('v')
+            .long("verbose")
+            .help("Print population statistics and restart events every generation. \
+                   Repeat (-vv) to also print the validation error and elapsed time. \
+                   RUST_LOG overrides this, e.g. RUST_LOG=rust_gp::io=debug")
+            .action(ArgAction::Count))
+        .arg(Arg::new("quiet")
+            .long("quiet")
+            .help("Print nothing but the final expression")
+            .conflicts_with("verbose")
+            .action(ArgAction::SetTrue))
+        .subcommand(Command::new("predict")
+            .about("Apply a model saved with --save-model to new data and write its predictions as CSV, one column per target")
+            .arg(Arg::new("model")
+                .long("model")
+                .help("The JSON file the model was saved to")
+                .required(true)
+                .value_parser(value_parser!(String)))
+            .arg(Arg::new("file")
+                .short('f')
+                .long("file")
+                .help("A CSV file (optionally .gz or .zst compressed) with the columns the model was trained on. \
+                       The target column is not needed. Use - to read from stdin")
+                .required(true)
+                .value_parser(value_parser!(String)))
+            .arg(Arg::new("out")
+                .short('o')
+                .long("out")
+                .help("The CSV file the predictions are written to. They are written to stdout when not provided")
+                .value_parser(value_parser!(String))))
+        .subcommand(Command::new("simplify")
+            .about("Simplify an expression, or the expression of a model saved with --save-model, and print it in canonical form, see --canonical, \
+                    in the notation chosen with --notation")
+            .arg(Arg::new("expression")
+                .short('e')
+                .long("expression")
+                .help("An expression in the notation printed at the end of a run, e.g. \"add(x0, mul(1, 0))\", in infix notation, e.g. \"x0 + 1 * 0\", \
+                       or as an S-expression, e.g. \"(add x0 (mul 1 0))\"")
+                .required_unless_present("model")
+                .conflicts_with("model")
+                .value_parser(value_parser!(String)))
+            .arg(Arg::new("model")
+                .long("model")
+                .help("The JSON file a model was saved to")
+                .value_parser(value_parser!(String))))
+        .subcommand(Command::new("evaluate")
+            .about("Score an expression, or a model saved with --save-model, on a dataset and print its MSE, MAE, RMSE and R², \
+                    or the log-loss, accuracy and confusion matrix of a classifier")
+            .arg(Arg::new("expression")
+                .short('e')
+                .long("expression")
+                .help("An expression in the notation printed at the end of a run, e.g. \"add(x0, mul(2, x1))\", in infix \
+                       notation, e.g. \"x0 + 2 * x1\", or as an S-expression, e.g. \"(add x0 (mul 2 x1))\", using the \
+                       column names of the dataset")
+                .required_unless_present("model")
+                .conflicts_with("model")
+                .value_parser(value_parser!(String)))
+            .arg(Arg::new("model")
+                .long("model")
+                .help("The JSON file a model was saved to. The dataset is encoded and scaled like its training data")
+                .value_parser(value_parser!(String)))
+            .arg(Arg::new("file")
+                .short('f')
+                .long("file")
+                .help("A CSV file (optionally .gz or .zst compressed) with the variables and the target. Use - to read from stdin")
+                .required(true)
+                .value_parser(value_parser!(String)))
+            .arg(Arg::new("target column")
+                .short('t')
+                .long("target-column")
+                .help("The name or zero-based index of the column the expression predicts. Defaults to the last column. \
+                       Several comma separated columns are predicted by expressions separated by ;")
+                .conflicts_with("model")
+                .value_delimiter(',')
+                .value_parser(value_parser!(String))))
         .get_matches();
 
-    if *matches.get_one::<usize>("population").unwrap() % 2 == 0 {
+    logging::init(Verbosity::from_flags(matches.get_count("verbose"), matches.get_flag("quiet")));
+
+    if matches.get_one::<usize>("population").unwrap().is_multiple_of(2) {
         panic!("The number of individuals in the population must be odd for elitism to work")
     }
+    if matches.get_one::<f64>("sharing radius").is_some_and(|&radius| !(radius > 0.0 && radius <= 1.0)) {
+        panic!("The sharing radius must be between 0 and 1")
+    }
+    if *matches.get_one::<usize>("age gap").unwrap() == 0 || *matches.get_one::<usize>("alps layers").unwrap() == 0 {
+        panic!("The ALPS age gap and number of layers must be at least 1")
+    }
+    if matches.get_one::<f64>("diversity threshold").is_some_and(|threshold| !(0.0..=1.0).contains(threshold)) {
+        panic!("The diversity threshold must be between 0 and 1")
+    }
+    if !(0.0..=1.0).contains(matches.get_one::<f64>("hypermutation chance").unwrap()) || !(0.0..=1.0).contains(matches.get_one::<f64>("immigrants").unwrap()) {
+        panic!("The hypermutation chance and the fraction of immigrants must be between 0 and 1")
+    }
+    if matches.get_one::<f64>("novelty").is_some_and(|weight| !(0.0..=1.0).contains(weight)) {
+        panic!("The novelty weight must be between 0 and 1")
+    }
+    if matches.get_one::<usize>("archive size") == Some(&0) {
+        panic!("The SPEA2 archive size must be at least 1")
+    }
+    if *matches.get_one::<usize>("tournament size").unwrap() == 0 {
+        panic!("The tournament size must be at least 1")
+    }
 
-    let dataset = read_csv(matches.get_one::<String>("file").expect("File must be provided"));
-    gp::gp(
-        *matches.get_one::<usize>("generations").unwrap(),
-        *matches.get_one::<usize>("population").unwrap(),
-        *matches.get_one::<usize>("num genes").unwrap(),
-        *matches.get_one::<f64>("mutation chance").unwrap(),
-        *matches.get_one::<f64>("crossover chance").unwrap(),
-        dataset,
-    );
+    if matches.get_one::<usize>("batch size").is_some_and(|&size| size == 0) {
+        panic!("The batch size must be greater than 0")
+    }
+
+    if !(0.0..1.0).contains(matches.get_one::<f64>("validation fraction").unwrap()) {
+        panic!("The validation fraction must be at least 0 and less than 1")
+    }
+
+    if *matches.get_one::<usize>("strata").unwrap() == 0 {
+        panic!("The number of strata must be greater than 0")
+    }
+
+    if *matches.get_one::<usize>("full evaluation interval").unwrap() == 0 {
+        panic!("The full evaluation interval must be greater than 0")
+    }
+
+    let subsample = match (matches.get_one::<usize>("batch size"), matches.get_one::<String>("subsample").unwrap().as_str()) {
+        (None, _) => SubsamplePolicy::Full,
+        (Some(&size), "stratified") => SubsamplePolicy::Stratified { size, strata: *matches.get_one::<usize>("strata").unwrap() },
+        (Some(&size), _) => SubsamplePolicy::Random(size),
+    };
+
+    let delimiter = match matches.get_one::<String>("delimiter").unwrap().as_str() {
+        "\\t" | "tab" => b'\t',
+        d if d.len() == 1 => d.as_bytes()[0],
+        d => panic!("The delimiter must be a single character, got {}", d),
+    };
+    let decimal = *matches.get_one::<char>("decimal").unwrap();
+    if char::from(delimiter) == decimal {
+        panic!("The delimiter and the decimal separator must be different")
+    }
+    let thousands = matches.get_one::<char>("thousands").copied();
+    if thousands.is_some_and(|thousands| thousands == decimal || thousands == char::from(delimiter)) {
+        panic!("The thousands separator must differ from the delimiter and the decimal separator")
+    }
+    let header = !matches.get_flag("no header");
+    // How a model reads its data, the encoding and scaling are those of its training data
+    let model_options = DatasetOptions { delimiter, decimal, thousands, header, ..DatasetOptions::default() };
+
+    let notation = match matches.get_one::<String>("notation").unwrap().as_str() {
+        "prefix" => Notation::Prefix,
+        "sexpr" => Notation::SExpression,
+        "latex" => Notation::Latex,
+        _ => Notation::Infix,
+    };
+
+    if let Some(("predict", predict)) = matches.subcommand() {
+        let model = Model::load(predict.get_one::<String>("model").unwrap());
+        model.predict_file(predict.get_one::<String>("file").unwrap(), predict.get_one::<String>("out").map(String::as_str), &model_options);
+        return;
+    }
+    if let Some(("simplify", simplify)) = matches.subcommand() {
+        let (mut chromosome, variable_names) = match simplify.get_one::<String>("model") {
+            Some(path) => {
+                let model = Model::load(path);
+                (model.chromosome(), model.variable_names)
+            }
+            None => {
+                let expression = simplify.get_one::<String>("expression").unwrap();
+                let variable_names = expression_variables(expression);
+                (Chromosome::parse(expression, &variable_names), variable_names)
+            }
+        };
+        chromosome.simplify();
+        println!("{}", chromosome.canonical().expression(notation, &variable_names));
+        return;
+    }
+    if let Some(("evaluate", evaluate)) = matches.subcommand() {
+        let file = evaluate.get_one::<String>("file").unwrap();
+        let (mut chromosome, dataset, task) = match evaluate.get_one::<String>("model") {
+            Some(path) => {
+                let model = Model::load(path);
+                (model.chromosome(), model.read_dataset(file, &model_options), model.task)
+            }
+            None => {
+                let options = DatasetOptions {
+                    target_columns: evaluate.get_many::<String>("target column").map_or(Vec::new(), |c| c.cloned().collect()),
+                    delimiter,
+                    decimal,
+                    thousands,
+                    header,
+                    ..DatasetOptions::default()
+                };
+                let dataset = read_dataset(file, &options);
+                (Chromosome::parse(evaluate.get_one::<String>("expression").unwrap(), &dataset.variable_names), dataset, Task::Regression)
+            }
+        };
+        // A single output, or a weighted sum of outputs, classifies two classes
+        let classes = match (task, chromosome.outputs.len(), &chromosome.weights) {
+            (Task::Regression, _, _) => 0,
+            (Task::Classification, outputs, None) if outputs > 1 => outputs,
+            (Task::Classification, _, _) => 2,
+        };
+        // The fitness is the first metric, the MSE or the log-loss
+        let metrics = Scores::metrics(task, classes);
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::clone(&metrics[0]);
+        println!("{}", Scores::new(&chromosome, &dataset, &metrics, classes));
+        if dataset.target_scaler.is_some() {
+            println!("MSE in original units: {}", chromosome.original_scale_mse(&dataset));
+        }
+        return;
+    }
+
+    let format = matches.get_one::<String>("format").map(|format| match format.as_str() {
+        "jsonl" => DatasetFormat::Jsonl,
+        "parquet" => DatasetFormat::Parquet,
+        "arrow" => DatasetFormat::Arrow,
+        "libsvm" => DatasetFormat::LibSvm,
+        "arff" => DatasetFormat::Arff,
+        _ => DatasetFormat::Csv,
+    });
+
+    let missing = match matches.get_one::<String>("missing").unwrap().as_str() {
+        "error" => MissingPolicy::Error,
+        "drop-row" => MissingPolicy::DropRow,
+        "mean-impute" => MissingPolicy::MeanImpute,
+        policy => match policy.strip_prefix("constant=").map(str::parse::<f64>) {
+            Some(Ok(x)) => MissingPolicy::Constant(x),
+            _ => panic!("Unknown missing value policy {}", policy),
+        },
+    };
+
+    let categorical = match matches.get_one::<String>("categorical").unwrap().as_str() {
+        "ordinal" => CategoricalEncoding::Ordinal,
+        "error" => CategoricalEncoding::Error,
+        _ => CategoricalEncoding::OneHot,
+    };
+    let categorical_columns: Vec<String> = matches.get_many::<String>("categorical columns").map_or(Vec::new(), |c| c.cloned().collect());
+    if categorical == CategoricalEncoding::Error && !categorical_columns.is_empty() {
+        panic!("--categorical-columns cannot be used with --categorical error")
+    }
+
+    let options = DatasetOptions {
+        format,
+        target_columns: matches.get_many::<String>("target column").map_or(Vec::new(), |c| c.cloned().collect()),
+        delimiter,
+        decimal,
+        thousands,
+        header,
+        missing,
+        categorical,
+        categorical_columns,
+        normalize: matches.get_one::<String>("normalize").map(|method| match method.as_str() {
+            "minmax" => ScalingMethod::MinMax,
+            _ => ScalingMethod::ZScore,
+        }),
+        normalize_target: matches.get_flag("normalize target"),
+        weight_column: matches.get_one::<String>("weight column").cloned(),
+        validation_fraction: *matches.get_one::<f64>("validation fraction").unwrap(),
+    };
+    // Seeded before the dataset is loaded, since holding out the validation rows is random too
+    let seed = matches.get_one::<u64>("seed").copied().unwrap_or_else(rand::random);
+    rng::seed(seed);
+    let dataset = match matches.get_one::<String>("query") {
+        Some(query) => read_query(matches.get_one::<String>("database").unwrap(), query, &options),
+        None => read_dataset(matches.get_one::<String>("file").expect("File must be provided"), &options),
+    };
+    let strategy = match matches.get_one::<String>("moea").map_or(matches.get_one::<String>("strategy").unwrap().as_str(), |moea| moea.as_str()) {
+        "nsga2" => Strategy::Nsga2,
+        "spea2" => Strategy::Spea2 {
+            archive_size: *matches.get_one::<usize>("archive size").unwrap_or(matches.get_one::<usize>("population").unwrap()),
+        },
+        "steady-state" => Strategy::SteadyState { offspring: *matches.get_one::<usize>("steady state offspring").unwrap() },
+        "mu-plus-lambda" => Strategy::MuPlusLambda {
+            lambda: *matches.get_one::<usize>("lambda").unwrap_or(matches.get_one::<usize>("population").unwrap()),
+        },
+        "crowding" => Strategy::Crowding,
+        "speciation" => Strategy::Speciation {
+            threshold: *matches.get_one::<f64>("species threshold").unwrap(),
+            protection: *matches.get_one::<usize>("species protection").unwrap(),
+        },
+        "map-elites" => Strategy::MapElites,
+        "alps" => Strategy::Alps {
+            layers: *matches.get_one::<usize>("alps layers").unwrap(),
+            age_gap: *matches.get_one::<usize>("age gap").unwrap(),
+        },
+        "one-plus-lambda" => Strategy::OnePlusLambda { lambda: *matches.get_one::<usize>("lambda").unwrap_or(&4) },
+        _ => Strategy::Generational,
+    };
+    let selection = match matches.get_one::<String>("selection").unwrap().as_str() {
+        "roulette" => Selection::Roulette,
+        "lexicase" => Selection::Lexicase,
+        "epsilon-lexicase" => Selection::EpsilonLexicase,
+        "linear-rank" => {
+            let pressure = *matches.get_one::<f64>("rank pressure").unwrap();
+            if !(1.0..=2.0).contains(&pressure) {
+                panic!("The rank pressure must be between 1 and 2")
+            }
+            Selection::LinearRank { pressure }
+        }
+        "exponential-rank" => {
+            let base = *matches.get_one::<f64>("rank base").unwrap();
+            if !(base > 0.0 && base < 1.0) {
+                panic!("The rank base must be between 0 and 1")
+            }
+            Selection::ExponentialRank { base }
+        }
+        _ => Selection::Tournament(*matches.get_one::<usize>("tournament size").unwrap()),
+    };
+    let mut mutation_operators: Vec<(MutationOperator, f64)> = Vec::new();
+    for operator in matches.get_many::<String>("mutation operators").unwrap() {
+        let (name, rate) = operator.split_once(':').unwrap_or((operator, "1"));
+        let operator = match name {
+            "point" => MutationOperator::Point,
+            "active" => MutationOperator::Active,
+            "pointer" => MutationOperator::Pointer,
+            "function" => MutationOperator::Function,
+            "constant" => MutationOperator::Constant,
+            "output" => MutationOperator::Output,
+            "gaussian" => MutationOperator::Gaussian,
+            "backpropagation" => MutationOperator::Backpropagation,
+            "duplication" => MutationOperator::Duplication,
+            "transposition" => MutationOperator::Transposition,
+            "insertion" => MutationOperator::Insertion,
+            "deletion" => MutationOperator::Deletion,
+            _ => panic!("Unknown mutation operator {}", name),
+        };
+        let rate: f64 = rate.parse().unwrap_or_else(|_| panic!("The rate of the {} mutation must be a number", name));
+        if !(rate >= 0.0 && rate.is_finite()) {
+            panic!("The rate of the {} mutation must not be negative", name)
+        }
+        if mutation_operators.iter().any(|&(o, _)| o == operator) {
+            panic!("The {} mutation is given more than once", name)
+        }
+        mutation_operators.push((operator, rate));
+    }
+    let constant_sigma = *matches.get_one::<f64>("constant sigma").unwrap();
+    let final_constant_sigma = matches.get_one::<f64>("final constant sigma").copied();
+    if !(constant_sigma > 0.0 && final_constant_sigma.is_none_or(|sigma| sigma > 0.0)) {
+        panic!("The standard deviations of the Gaussian mutation must be positive")
+    }
+    let temperature = matches.get_one::<f64>("temperature").copied();
+    let final_temperature = *matches.get_one::<f64>("final temperature").unwrap();
+    if temperature.is_some_and(|t| !(t > 0.0 && final_temperature > 0.0)) {
+        panic!("The annealing temperatures must be positive")
+    }
+    if temperature.is_some() && !matches!(strategy, Strategy::SteadyState { .. }) {
+        panic!("Simulated-annealing replacement needs the steady-state strategy")
+    }
+    let constant_parameters: Vec<f64> = matches.get_many::<f64>("constant parameters").unwrap().copied().collect();
+    let [a, b] = constant_parameters[..] else {
+        panic!("The constant distribution takes two parameters")
+    };
+    let constants = match matches.get_one::<String>("constant distribution").unwrap().as_str() {
+        "normal" => ConstantDistribution::Normal { mean: a, std: b },
+        "log-uniform" => ConstantDistribution::LogUniform { low: a, high: b },
+        _ => ConstantDistribution::Uniform { low: a, high: b },
+    };
+    let valid_constants = match constants {
+        ConstantDistribution::Uniform { low, high } => low < high,
+        ConstantDistribution::Normal { std, .. } => std > 0.0,
+        ConstantDistribution::LogUniform { low, high } => 0.0 < low && low < high,
+    };
+    if !valid_constants {
+        panic!("The constant distribution needs a lower bound below the upper (above 0 for log-uniform), or a positive standard deviation")
+    }
+    let constant_domain = match matches.get_one::<String>("constant domain").unwrap().as_str() {
+        "integer" => ConstantDomain::Integer,
+        "rational" => ConstantDomain::Rational { max_denominator: *matches.get_one::<u32>("max denominator").unwrap() },
+        _ => ConstantDomain::Real,
+    };
+    let headless_chicken = *matches.get_one::<f64>("headless chicken").unwrap();
+    if !(0.0..=1.0).contains(&headless_chicken) {
+        panic!("The headless-chicken probability must be between 0 and 1")
+    }
+    let grid = Grid { rows: *matches.get_one::<usize>("rows").unwrap(), levels_back: matches.get_one::<usize>("levels back").copied() };
+    if grid.rows == 0 || grid.levels_back == Some(0) {
+        panic!("The grid must have at least one row and a levels-back limit of at least 1")
+    }
+    let num_genes = match matches.get_one::<usize>("columns") {
+        Some(columns) => grid.rows * columns,
+        None => *matches.get_one::<usize>("num genes").unwrap(),
+    };
+    let multigene = matches.get_one::<usize>("multigene").copied();
+    if multigene.is_some_and(|roots| roots == 0 || roots > num_genes) {
+        panic!("Multi-gene GP needs at least one root gene and at most as many as there are genes")
+    }
+    let optimize_interval = matches.get_one::<usize>("optimize interval").copied();
+    if optimize_interval == Some(0) {
+        panic!("The constant optimization interval must be at least 1 generation")
+    }
+    let simplify_interval = matches.get_one::<usize>("simplify interval").copied();
+    if simplify_interval == Some(0) {
+        panic!("The simplification interval must be at least 1 generation")
+    }
+    let enabled_operators = mutation_operators.iter().filter(|&&(_, rate)| rate > 0.0).count().max(1);
+    let operator_selection = match matches.get_one::<String>("operator selection").unwrap().as_str() {
+        "ucb" => OperatorSelection::Ucb { exploration: 0.5 },
+        // Together the operators always get at least a fifth of the children unless told otherwise
+        "probability-matching" => OperatorSelection::ProbabilityMatching {
+            min_probability: matches.get_one::<f64>("min operator probability").copied().unwrap_or(0.2 / enabled_operators as f64),
+        },
+        _ => OperatorSelection::Fixed,
+    };
+    // The floors of all operators must leave a share, however small, to divide by success
+    if let OperatorSelection::ProbabilityMatching { min_probability } = operator_selection {
+        if !(0.0..=1.0).contains(&(min_probability * enabled_operators as f64)) {
+            panic!("The minimum operator probability must be at least 0 and at most 1 divided by the {} enabled mutation operators", enabled_operators)
+        }
+    }
+    // Loaded before the function set is created, which they join
+    #[cfg(feature = "scripting")]
+    let primitives = matches.get_one::<String>("primitives").map_or(Vec::new(), |path| scripting::load_primitives(path));
+    #[cfg(not(feature = "scripting"))]
+    let primitives: Vec<String> = match matches.contains_id("primitives") {
+        true => panic!("User-defined primitives require building with the scripting feature"),
+        false => Vec::new(),
+    };
+    let task = match matches.get_one::<String>("task").unwrap().as_str() {
+        "classification" => Task::Classification,
+        _ => Task::Regression,
+    };
+    // They rank the scores of two classes, which a regression has no labels for. More classes are
+    // rejected once the labels are counted
+    if let Some(metric) = matches.get_one::<String>("metric").filter(|metric| ["roc-auc", "average-precision"].contains(&metric.as_str())) {
+        if task != Task::Classification {
+            panic!("The {} metric needs binary classification, use --task classification", metric)
+        }
+    }
+    let params = PopulationParameters {
+        generations: *matches.get_one::<usize>("generations").unwrap(),
+        population_size: *matches.get_one::<usize>("population").unwrap(),
+        num_genes,
+        mut_chance: *matches.get_one::<f64>("mutation chance").unwrap(),
+        crossover_chance: *matches.get_one::<f64>("crossover chance").unwrap(),
+        strategy,
+        selection,
+        sharing_radius: matches.get_one::<f64>("sharing radius").copied(),
+        distance: match matches.get_one::<String>("distance").unwrap().as_str() {
+            "behavioral" => Distance::Behavioral,
+            _ => Distance::Genotypic,
+        },
+        probe_size: *matches.get_one::<usize>("probe size").unwrap(),
+        duplicates: match matches.get_one::<String>("duplicates").unwrap().as_str() {
+            "mutate" => DuplicatePolicy::Mutate,
+            "reject" => DuplicatePolicy::Reject,
+            _ => DuplicatePolicy::Allow,
+        },
+        novelty: matches.get_one::<f64>("novelty").copied(),
+        novelty_neighbours: *matches.get_one::<usize>("novelty neighbours").unwrap(),
+        subsample,
+        full_evaluation_interval: *matches.get_one::<usize>("full evaluation interval").unwrap(),
+        patience: matches.get_one::<usize>("patience").copied(),
+        min_delta: *matches.get_one::<f64>("min delta").unwrap(),
+        stop_at_fitness: matches.get_one::<f64>("stop at fitness").copied(),
+        restart_after: matches.get_one::<usize>("restart after").copied(),
+        restart_keep: *matches.get_one::<usize>("restart keep").unwrap(),
+        rate_adaptation: match matches.get_one::<String>("adapt rates").unwrap().as_str() {
+            "one-fifth" => RateAdaptation::OneFifth,
+            _ => RateAdaptation::Fixed,
+        },
+        self_adaptive: matches.get_flag("self adaptive"),
+        mutation_operators,
+        operator_selection,
+        crossover: match matches.get_one::<String>("crossover").unwrap().as_str() {
+            "two-point" => CrossoverOperator::TwoPoint,
+            "uniform" => CrossoverOperator::Uniform,
+            "subgraph" => CrossoverOperator::Subgraph,
+            "semantic-different" => CrossoverOperator::Semantic { most_different: true },
+            "semantic-similar" => CrossoverOperator::Semantic { most_different: false },
+            _ => CrossoverOperator::OnePoint,
+        },
+        headless_chicken,
+        constant_sigma,
+        final_constant_sigma,
+        grid,
+        multigene,
+        constants,
+        constant_domain,
+        protection: match matches.get_one::<String>("protection").unwrap().as_str() {
+            "one" => Protection::One,
+            "numerator" => Protection::Numerator,
+            "nan" => Protection::Nan,
+            _ => Protection::Standard,
+        },
+        function_set: match matches.get_many::<String>("functions") {
+            Some(functions) => {
+                let weights: Vec<(&str, f64)> = functions
+                    .map(|function| {
+                        let (name, weight) = function.split_once(':').unwrap_or((function, "1"));
+                        (name, weight.parse().unwrap_or_else(|_| panic!("The weight of {} must be a number", name)))
+                    })
+                    .collect();
+                FunctionSet::from_weights(&weights)
+            }
+            None if matches.get_one::<String>("function set").unwrap() == "boolean" => FunctionSet::boolean().including(&primitives),
+            None => FunctionSet::arithmetic().including(&primitives),
+        },
+        typed: matches.get_flag("typed"),
+        grammar: matches.get_one::<String>("grammar").map_or(Grammar::default(), |path| Grammar::load(path)),
+        invalid_rows: matches.get_one::<f64>("invalid row penalty").map_or(InvalidRows::Worst, |&penalty| InvalidRows::Penalize(penalty)),
+        task,
+        metric: match matches.get_one::<String>("metric").map_or(if task == Task::Classification { "log-loss" } else { "mse" }, |metric| metric.as_str()) {
+            "huber" => Arc::new(Huber::new(*matches.get_one::<f64>("huber delta").unwrap())),
+            "quantile" => Arc::new(Quantile::new(*matches.get_one::<f64>("quantile").unwrap())),
+            name => metric_named(name),
+        },
+        objective: None,
+        linear_scaling: matches.get_flag("linear scaling"),
+        optimize_constants: matches.get_one::<usize>("optimize constants").copied(),
+        optimize_interval,
+        simplify_interval,
+        fold_constants: matches.get_flag("fold constants"),
+        local_learning: matches.get_one::<usize>("local learning").copied(),
+        hill_climb: matches.get_one::<usize>("hill climb").copied(),
+        memetic: matches.get_one::<usize>("memetic").copied(),
+        temperature,
+        final_temperature,
+        diversity_threshold: matches.get_one::<f64>("diversity threshold").copied(),
+        hypermutation_chance: *matches.get_one::<f64>("hypermutation chance").unwrap(),
+        hypermutation_generations: *matches.get_one::<usize>("hypermutation generations").unwrap(),
+        immigrants: *matches.get_one::<f64>("immigrants").unwrap(),
+        model_path: matches.get_one::<String>("save model").cloned(),
+        report_path: matches.get_one::<String>("report").cloned(),
+        notation,
+        canonical: matches.get_flag("canonical"),
+        hall_of_fame: *matches.get_one::<usize>("hall of fame").unwrap(),
+        hall_of_fame_path: matches.get_one::<String>("hall of fame file").unwrap().clone(),
+        elites_path: matches.get_one::<String>("elites file").unwrap().clone(),
+        pareto_front_path: matches.get_one::<String>("pareto front file").cloned(),
+        metrics_address: matches.get_one::<String>("metrics address").cloned(),
+        limits: ComplexityLimits {
+            max_active_genes: matches.get_one::<usize>("max active genes").copied(),
+            max_genes: matches.get_one::<usize>("max genes").copied(),
+            max_depth: matches.get_one::<usize>("max depth").copied(),
+        },
+        units: matches.get_many::<String>("units").map(|units| {
+            let annotations: Vec<(&str, &str)> = units
+                .map(|annotation| annotation.split_once('=').unwrap_or_else(|| panic!("The units {} must be given as column=units", annotation)))
+                .collect();
+            Units::new(&dataset, &annotations, matches.get_one::<f64>("units penalty").copied())
+        }),
+        prescreen: matches.get_flag("prescreen"),
+        seed,
+    };
+    gp::gp(params, dataset);
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+use std::thread;
+use std::time::Instant;
+
+use prometheus::{Encoder, Gauge, IntCounter, IntGauge, Registry, TextEncoder};
+use tiny_http::{Header, Response, Server};
+
+use crate::population::PopulationStatistics;
+
+/// Prometheus metrics describing the progress of a run, served over HTTP so long jobs can be monitored.
+pub struct Metrics {
+    generation: IntGauge,
+    best_fitness: Gauge,
+    mean_fitness: Gauge,
+    evaluations: IntCounter,
+    evaluations_per_second: Gauge,
+    /// When the previous generation was recorded and how many evaluations had been done by then.
+    last: (Instant, usize),
+}
+
+impl Metrics {
+    /// Registers the metrics and starts serving them on a background thread.
+    ///
+    /// Every request to the server, whatever its path, is answered with the metrics in the
+    /// Prometheus text format. On Linux the resident memory and CPU time of the process are included.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Where to listen, e.g. `0.0.0.0:9184`.
+    pub fn serve(address: &str) -> Metrics {
+        let registry = Registry::new_custom(Some("rust_gp".to_string()), None).unwrap();
+        let metrics = Metrics {
+            generation: IntGauge::new("generation", "The current generation").unwrap(),
+            best_fitness: Gauge::new("best_fitness", "The fitness of the best chromosome").unwrap(),
+            mean_fitness: Gauge::new("mean_fitness", "The mean fitness of the valid chromosomes").unwrap(),
+            evaluations: IntCounter::new("evaluations_total", "The number of fitness evaluations").unwrap(),
+            evaluations_per_second: Gauge::new("evaluations_per_second", "Fitness evaluations per second in the last generation").unwrap(),
+            last: (Instant::now(), 0),
+        };
+        registry.register(Box::new(metrics.generation.clone())).unwrap();
+        registry.register(Box::new(metrics.best_fitness.clone())).unwrap();
+        registry.register(Box::new(metrics.mean_fitness.clone())).unwrap();
+        registry.register(Box::new(metrics.evaluations.clone())).unwrap();
+        registry.register(Box::new(metrics.evaluations_per_second.clone())).unwrap();
+        #[cfg(target_os = "linux")]
+        registry.register(Box::new(prometheus::process_collector::ProcessCollector::for_self())).unwrap();
+
+        let server = Server::http(address).unwrap_or_else(|e| panic!("Could not serve metrics on {}: {}", address, e));
+        tracing::info!(address, "serving Prometheus metrics");
+        thread::spawn(move || {
+            let content_type = Header::from_bytes("Content-Type", TextEncoder::new().format_type()).unwrap();
+            for request in server.incoming_requests() {
+                let mut body = Vec::new();
+                TextEncoder::new().encode(&registry.gather(), &mut body).expect("Problem encoding the metrics");
+                let _ = request.respond(Response::from_data(body).with_header(content_type.clone()));
+            }
+        });
+        return metrics;
+    }
+
+    /// Updates the metrics at the end of a generation.
+    ///
+    /// # Arguments
+    ///
+    /// * `generation` - The generation that just finished.
+    /// * `best` - The fitness of the best chromosome.
+    /// * `statistics` - The statistics of the population.
+    /// * `evaluations` - The total number of fitness evaluations so far.
+    pub fn record(&mut self, generation: usize, best: f64, statistics: &PopulationStatistics, evaluations: usize) {
+        let (last_time, last_evaluations) = self.last;
+        let seconds = last_time.elapsed().as_secs_f64();
+        self.generation.set(generation as i64);
+        self.best_fitness.set(best);
+        self.mean_fitness.set(statistics.mean);
+        self.evaluations.inc_by((evaluations - last_evaluations) as u64);
+        if seconds > 0.0 {
+            self.evaluations_per_second.set((evaluations - last_evaluations) as f64 / seconds);
+        }
+        self.last = (Instant::now(), evaluations);
+    }
+}
@@ -0,0 +1,276 @@
+use std::fs::File;
+use std::io::{stdout, BufReader, BufWriter, Write};
+use std::sync::Arc;
+
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::chromosome::{Chromosome, Gene, GeneType, Layout};
+use crate::fitness::{predicted_class, Task};
+use crate::functions::{FunctionRegistry, PrimitiveId, Protection};
+use crate::io::{ColumnEncoding, Dataset, DatasetOptions, is_missing, MissingReport, open_dataset, parse_number, Scaler};
+
+/// A gene in a form that can be written to a file. Operators are stored by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GeneRecord {
+    Constant { value: f64 },
+    Variable { index: usize },
+    Unary { operator: String, left: usize },
+    Binary { operator: String, left: usize, right: usize },
+    /// `branches[0]` when `operator(left, right)` holds, `branches[1]` otherwise.
+    Conditional { operator: String, left: usize, right: usize, branches: [usize; 2] },
+}
+
+/// A trained chromosome together with everything needed to apply it to new data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Model {
+    /// The expression in the same notation as printed at the end of a run.
+    pub expression: String,
+    pub fitness: f64,
+    pub genes: Vec<GeneRecord>,
+    /// The positions of the genes whose values are the predictions, one per target.
+    pub outputs: Vec<usize>,
+    /// With multi-gene GP, the bias and the weights that combine the outputs into the prediction.
+    pub weights: Option<Vec<f64>>,
+    /// The variables the genes refer to, in the order of their indices.
+    pub variable_names: Vec<String>,
+    /// The names of the targets, in the same order as `outputs`.
+    pub target_names: Vec<String>,
+    /// The encodings of the categorical columns of the training data.
+    pub encodings: Vec<ColumnEncoding>,
+    /// The scalers applied to the variables before they are passed to the genes.
+    pub scalers: Vec<Scaler>,
+    /// The scaler of the target. Predictions are passed through its inverse.
+    pub target_scaler: Option<Scaler>,
+    /// What the protected functions of the genes return where their plain versions are undefined.
+    #[serde(default)]
+    pub protection: Protection,
+    /// Whether the model predicts a value or a class label.
+    #[serde(default)]
+    pub task: Task,
+}
+
+impl Model {
+    /// Creates a `Model` from a chromosome and the dataset it was trained on for `task`.
+    pub fn new(chromosome: &Chromosome, dataset: &Dataset, task: Task) -> Model {
+        let genes = chromosome.genes.iter().map(|gene| match gene.type_of_gene {
+            GeneType::Constant(value) => GeneRecord::Constant { value },
+            GeneType::Variable(index) => GeneRecord::Variable { index },
+            GeneType::Unary => GeneRecord::Unary { operator: gene.get_operator(), left: gene.left_ptr },
+            GeneType::Binary => GeneRecord::Binary { operator: gene.get_operator(), left: gene.left_ptr, right: gene.right_ptr },
+            GeneType::Conditional => GeneRecord::Conditional { operator: gene.get_operator(), left: gene.left_ptr, right: gene.right_ptr, branches: gene.branch_ptrs },
+        }).collect();
+
+        return Model {
+            expression: chromosome.function_string_with_names(&dataset.variable_names),
+            fitness: chromosome.fitness_value,
+            genes,
+            outputs: chromosome.output_genes(),
+            weights: chromosome.weights.clone(),
+            variable_names: dataset.variable_names.clone(),
+            target_names: dataset.target_names.clone(),
+            encodings: dataset.encodings.clone(),
+            scalers: dataset.scalers.clone(),
+            target_scaler: dataset.target_scaler,
+            protection: chromosome.layout.protection,
+            task,
+        };
+    }
+
+    /// Reads a model written by `save`.
+    ///
+    /// # Panics
+    ///
+    /// When the file cannot be read or is not a model.
+    #[tracing::instrument(level = "debug")]
+    pub fn load(file_name: &str) -> Model {
+        let file = File::open(file_name).unwrap_or_else(|e| panic!("Could not open {}: {}", file_name, e));
+        return serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|e| panic!("{} is not a model: {}", file_name, e));
+    }
+
+    /// Rebuilds the chromosome the model was created from.
+    ///
+    /// # Panics
+    ///
+    /// When a gene uses an operator that is not in the `FunctionRegistry`, e.g. a primitive that was
+    /// registered by a script that has not been run.
+    pub fn chromosome(&self) -> Chromosome {
+        let primitive = |operator: &str, arity: usize| -> PrimitiveId {
+            return FunctionRegistry::global().find(operator, arity)
+                .unwrap_or_else(|| panic!("The model uses the unknown operator {}", operator));
+        };
+        let genes = self.genes.iter().map(|gene| match gene {
+            GeneRecord::Constant { value } => Gene::new_constant(Some(*value)),
+            GeneRecord::Variable { index } => Gene::new_variable(*index),
+            GeneRecord::Unary { operator, left } => Gene::new_unary2(*left, primitive(operator, 1)).protected(self.protection),
+            GeneRecord::Binary { operator, left, right } => Gene::new_binary2(*left, *right, primitive(operator, 2)).protected(self.protection),
+            GeneRecord::Conditional { operator, left, right, branches } => Gene::new_conditional2(*left, *right, *branches, primitive(operator, 4)),
+        }).collect();
+        let mut chromosome = Chromosome::new_from_genes_array(genes);
+        chromosome.outputs = self.outputs.clone();
+        chromosome.weights = self.weights.clone();
+        chromosome.layout = Arc::new(Layout { protection: self.protection, ..Layout::default() });
+        chromosome.fitness_value = self.fitness;
+        return chromosome;
+    }
+
+    /// Where each of the columns `names` the model uses comes from in a table whose columns are
+    /// `column_names`: the column, and for encoded columns the encoding and which of the values it produces.
+    ///
+    /// # Panics
+    ///
+    /// When a column the model uses is missing.
+    fn sources(&self, column_names: &[String], names: &[String]) -> Vec<(usize, Option<(&ColumnEncoding, usize)>)> {
+        let column = |name: &str| column_names.iter().position(|column| column == name)
+            .unwrap_or_else(|| panic!("The data has no column {}, which the model uses", name));
+        return names.iter().map(|name| {
+            for encoding in &self.encodings {
+                if let Some(position) = encoding.column_names().iter().position(|encoded| encoded == name) {
+                    return (column(&encoding.column), Some((encoding, position)));
+                }
+            }
+            return (column(name), None);
+        }).collect();
+    }
+
+    /// Reads the rows of a CSV file, encoded and scaled like the training data: the variables,
+    /// followed by the targets when `targets` is true. Missing values are NaN.
+    ///
+    /// The first row is the header unless `options.header` is false, in which case the columns are
+    /// named `v0`, `v1`, ... as when training. Only the delimiter, separators and header of `options`
+    /// are used, the rest was fixed when training.
+    ///
+    /// # Panics
+    ///
+    /// When a column the model uses is missing or a numeric column contains text.
+    fn read_rows(&self, input: &str, options: &DatasetOptions, targets: bool) -> Vec<Vec<f64>> {
+        let (decimal, thousands) = (options.decimal, options.thousands);
+        let mut rdr = ReaderBuilder::new().has_headers(false).delimiter(options.delimiter).from_reader(open_dataset(input));
+        let mut records = rdr.records().map(|r| r.unwrap()).peekable();
+        let first = records.peek().expect("The data is empty");
+        let column_names: Vec<String> = match options.header {
+            true => records.next().unwrap().iter().map(|name| name.trim().to_string()).collect(),
+            false => (0..first.len()).map(|i| format!("v{}", i)).collect(),
+        };
+        let names = match targets {
+            true => [self.variable_names.as_slice(), &self.target_names].concat(),
+            false => self.variable_names.clone(),
+        };
+        let sources = self.sources(&column_names, &names);
+        // Variables and a normalized target are scaled, by their position in the row
+        let scalers: Vec<Option<Scaler>> = match self.scalers.is_empty() {
+            true => vec![None; self.variable_names.len()],
+            false => self.scalers.iter().copied().map(Some).collect(),
+        }.into_iter().chain(std::iter::repeat(self.target_scaler)).take(names.len()).collect();
+
+        return records.map(|record| {
+            sources.iter().zip(&names).zip(&scalers).map(|((&(column, encoding), name), scaler)| {
+                let field = record.get(column).unwrap_or("");
+                let value = match encoding {
+                    Some((encoding, position)) => encoding.encode(field)[position],
+                    None if is_missing(field) => f64::NAN,
+                    None => parse_number(field, decimal, thousands).unwrap_or_else(|| panic!("Column {} contains the value \"{}\" which is not a number", name, field.trim())),
+                };
+                return scaler.map_or(value, |scaler| scaler.transform(value));
+            }).collect()
+        }).collect();
+    }
+
+    /// Reads a CSV file with the columns the model was trained on, the targets included, into a
+    /// `Dataset` encoded and scaled like the training data, on which the chromosome of the model
+    /// can be scored. See `read_rows`.
+    pub fn read_dataset(&self, input: &str, options: &DatasetOptions) -> Dataset {
+        return Dataset {
+            rows: self.read_rows(input, options, true),
+            variable_names: self.variable_names.clone(),
+            target_names: self.target_names.clone(),
+            weight_name: None,
+            missing: MissingReport::default(),
+            encodings: self.encodings.clone(),
+            scalers: self.scalers.clone(),
+            target_scaler: self.target_scaler,
+            validation_rows: Vec::new(),
+        };
+    }
+
+    /// The prediction of `chromosome`, the chromosome of the model, for a row of variables that
+    /// were encoded and scaled like the training data: one value per target, in the original
+    /// units of the target, or the class label for classifiers.
+    fn predict(&self, chromosome: &Chromosome, variables: &Vec<f64>) -> Vec<String> {
+        let outputs = chromosome.output_values(variables);
+        if self.task == Task::Classification {
+            if outputs.iter().any(|output| output.is_nan()) {
+                return vec![f64::NAN.to_string()];
+            }
+            let class = predicted_class(&outputs);
+            // Text labels were ordinal encoded, so their index is the class
+            let label = self.encodings.iter().find(|encoding| self.target_names.contains(&encoding.column))
+                .map_or(class.to_string(), |encoding| encoding.categories[class].clone());
+            return vec![label];
+        }
+        return outputs.iter().map(|&output| self.target_scaler.map_or(output, |scaler| scaler.inverse(output)).to_string()).collect();
+    }
+
+    /// Applies the model to every row of a CSV file and writes the predictions as CSV, with one
+    /// column per target. Columns the model does not use, such as the target, are ignored, and rows
+    /// with missing values are predicted as NaN. See `read_rows`.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The CSV file, see `open_dataset`.
+    /// * `output` - Where the predictions are written. `None` writes them to stdout.
+    /// * `options` - How the input is parsed: its delimiter, separators and whether it has a header.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn predict_file(&self, input: &str, output: Option<&str>, options: &DatasetOptions) {
+        let rows = self.read_rows(input, options, false);
+        let chromosome = self.chromosome();
+
+        let writer: Box<dyn Write> = match output {
+            Some(path) => Box::new(File::create(path).unwrap_or_else(|e| panic!("Could not create {}: {}", path, e))),
+            None => Box::new(stdout().lock()),
+        };
+        let mut wtr = csv::Writer::from_writer(BufWriter::new(writer));
+        wtr.write_record(&self.target_names).expect("Problem writing the predictions");
+        for row in &rows {
+            wtr.write_record(self.predict(&chromosome, row)).expect("Problem writing the predictions");
+        }
+        wtr.flush().expect("Problem writing the predictions");
+        tracing::debug!(rows = rows.len(), "predictions written");
+    }
+
+    /// Writes the model to a JSON file.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn save(&self, file_name: &str) {
+        let file = File::create(file_name).unwrap_or_else(|e| panic!("Could not create {}: {}", file_name, e));
+        serde_json::to_writer_pretty(BufWriter::new(file), self).expect("Problem writing the model");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::functions::PrimitiveId;
+    use crate::io::{open_dataset, read_csv};
+
+    use super::*;
+
+    #[test]
+    /// Ensures that a saved model rebuilds a chromosome that predicts the same values, protection included
+    fn test_model_round_trip() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Some(0.0)),
+                                                                   Gene::new_binary2(0, 2, PrimitiveId::DIV).protected(Protection::One),
+                                                                   Gene::new_unary2(1, PrimitiveId::SQUARE), Gene::new_binary2(3, 4, PrimitiveId::ADD)]);
+        Arc::make_mut(&mut chromosome.layout).protection = Protection::One;
+        let json = serde_json::to_string(&Model::new(&chromosome, &dataset, Task::Regression)).unwrap();
+        let model: Model = serde_json::from_str(&json).unwrap();
+        let rebuilt = model.chromosome();
+
+        assert_eq!(rebuilt.function_string(), chromosome.function_string());
+        for row in &dataset.rows {
+            assert_eq!(rebuilt.output_values(row), chromosome.output_values(row));
+        }
+        assert_eq!(model.predict(&rebuilt, &vec![3.0, 2.0, 0.0]), vec!["5".to_string()]);
+        assert_eq!(model.sources(&["x2".to_string(), "x0".to_string(), "x1".to_string()], &model.variable_names).iter().map(|source| source.0).collect::<Vec<usize>>(), vec![1, 2, 0]);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::chromosome::Chromosome;
+
+/// The objectives minimized by the multi-objective algorithms: the error and the number of active genes.
+///
+/// NaN errors count as infinitely bad.
+pub fn objectives(chromosome: &Chromosome) -> [f64; 2] {
+    let error = if chromosome.fitness_value.is_nan() { f64::INFINITY } else { chromosome.fitness_value };
+    return [error, chromosome.active_genes().len() as f64];
+}
+
+/// Whether `a` is at least as good as `b` in every objective and strictly better in one.
+pub fn dominates(a: &[f64; 2], b: &[f64; 2]) -> bool {
+    return a.iter().zip(b).all(|(x, y)| x <= y) && a.iter().zip(b).any(|(x, y)| x < y);
+}
+
+/// Sorts solutions into fronts of mutually non-dominated solutions, as in NSGA-II.
+///
+/// # Arguments
+///
+/// * `objectives` - The objectives of every solution.
+///
+/// # Returns
+///
+/// The indices of the solutions in each front. The first front is the Pareto front, the second
+/// front is the Pareto front once the first is removed, and so on.
+pub fn non_dominated_sort(objectives: &[[f64; 2]]) -> Vec<Vec<usize>> {
+    let n = objectives.len();
+    let mut dominated_by: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut domination_count = vec![0; n];
+    for i in 0..n {
+        for j in 0..n {
+            if dominates(&objectives[i], &objectives[j]) {
+                dominated_by[i].push(j);
+            } else if dominates(&objectives[j], &objectives[i]) {
+                domination_count[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut front: Vec<usize> = (0..n).filter(|&i| domination_count[i] == 0).collect();
+    while !front.is_empty() {
+        let mut next = Vec::new();
+        for &i in &front {
+            for &j in &dominated_by[i] {
+                domination_count[j] -= 1;
+                if domination_count[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next;
+    }
+    return fronts;
+}
+
+/// Computes the crowding distance of every solution in a front, as in NSGA-II.
+///
+/// The crowding distance is the sum over the objectives of the normalized gap between a
+/// solution's neighbours. The solutions at the ends of each objective get an infinite distance,
+/// so the extremes of the front are always kept.
+///
+/// # Arguments
+///
+/// * `objectives` - The objectives of every solution.
+/// * `front` - The indices of the solutions in the front.
+///
+/// # Returns
+///
+/// The crowding distance of each solution, in the same order as `front`.
+pub fn crowding_distance(objectives: &[[f64; 2]], front: &[usize]) -> Vec<f64> {
+    let mut distance = vec![0.0; front.len()];
+    if front.len() < 3 {
+        return vec![f64::INFINITY; front.len()];
+    }
+    for objective in [0, 1] {
+        let value = |position: usize| objectives[front[position]][objective];
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| value(a).total_cmp(&value(b)));
+        let range = value(order[order.len() - 1]) - value(order[0]);
+        distance[order[0]] = f64::INFINITY;
+        distance[order[order.len() - 1]] = f64::INFINITY;
+        if !(range > 0.0 && range.is_finite()) {
+            continue;
+        }
+        for k in 1..order.len() - 1 {
+            distance[order[k]] += (value(order[k + 1]) - value(order[k - 1])) / range;
+        }
+    }
+    return distance;
+}
+
+/// Rescales every objective to between 0 and 1 over the given solutions, so that distances weigh them equally.
+///
+/// Infinite values (NaN errors) are put at 2, further than any finite value.
+fn normalized(objectives: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    let mut result = objectives.to_vec();
+    for objective in [0, 1] {
+        let finite = objectives.iter().map(|o| o[objective]).filter(|v| v.is_finite());
+        let (min, max) = finite.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)));
+        let range = if max > min { max - min } else { 1.0 };
+        for o in &mut result {
+            o[objective] = if o[objective].is_finite() { (o[objective] - min) / range } else { 2.0 };
+        }
+    }
+    return result;
+}
+
+/// The Euclidean distances from one solution to every solution, in normalized objective space.
+fn distances_from(normalized: &[[f64; 2]], i: usize) -> Vec<f64> {
+    return normalized.iter()
+        .map(|o| ((o[0] - normalized[i][0]).powi(2) + (o[1] - normalized[i][1]).powi(2)).sqrt())
+        .collect();
+}
+
+/// Computes the SPEA2 fitness of every solution, where lower is better.
+///
+/// The strength of a solution is how many solutions it dominates, and its raw fitness is the sum
+/// of the strengths of the solutions that dominate it, so non-dominated solutions have a raw fitness
+/// of 0. The density `1 / (distance to the k-th nearest neighbour + 2)`, with k the square root of
+/// the number of solutions, is added to break ties and is always below 1.
+pub fn spea2_fitness(objectives: &[[f64; 2]]) -> Vec<f64> {
+    let n = objectives.len();
+    let strength: Vec<usize> = (0..n).map(|i| (0..n).filter(|&j| dominates(&objectives[i], &objectives[j])).count()).collect();
+    let normalized = normalized(objectives);
+    let k = (n as f64).sqrt() as usize;
+    return (0..n)
+        .map(|i| {
+            let raw: usize = (0..n).filter(|&j| dominates(&objectives[j], &objectives[i])).map(|j| strength[j]).sum();
+            let mut distances = distances_from(&normalized, i);
+            distances.sort_by(f64::total_cmp);
+            let density = 1.0 / (distances[k.min(n - 1)] + 2.0);
+            return raw as f64 + density;
+        })
+        .collect();
+}
+
+/// Chooses the next SPEA2 archive of `size` solutions.
+///
+/// All non-dominated solutions (fitness below 1) are kept. If there are fewer than `size`, the
+/// best dominated solutions fill the archive. If there are more, the solution closest to its
+/// nearest neighbour is removed repeatedly, using the second nearest neighbour and so on to break
+/// ties, which keeps the archive spread out along the front.
+///
+/// # Arguments
+///
+/// * `objectives` - The objectives of every solution.
+/// * `fitness` - The SPEA2 fitness of every solution, from `spea2_fitness`.
+/// * `size` - How many solutions the archive holds.
+///
+/// # Returns
+///
+/// The indices of the solutions in the archive.
+pub fn spea2_select(objectives: &[[f64; 2]], fitness: &[f64], size: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..objectives.len()).collect();
+    order.sort_by(|&a, &b| fitness[a].total_cmp(&fitness[b]));
+    let non_dominated = order.iter().filter(|&&i| fitness[i] < 1.0).count();
+    if non_dominated <= size {
+        order.truncate(size);
+        return order;
+    }
+
+    // Copies of a solution are at distance 0 from it, so truncation removes them before anything else
+    let mut seen = Vec::new();
+    let (archive, copies): (Vec<usize>, Vec<usize>) = order[..non_dominated].iter()
+        .partition(|&&i| if seen.contains(&objectives[i]) { false } else { seen.push(objectives[i]); true });
+    if archive.len() <= size {
+        return archive.into_iter().chain(copies).take(size).collect();
+    }
+    let archive_objectives: Vec<[f64; 2]> = archive.iter().map(|&i| objectives[i]).collect();
+    let normalized = normalized(&archive_objectives);
+    // The other members of the archive from the nearest to the furthest, with their distances
+    let neighbours: Vec<Vec<(f64, usize)>> = (0..archive.len())
+        .map(|i| {
+            let mut neighbours: Vec<(f64, usize)> = distances_from(&normalized, i).into_iter().zip(0..).filter(|&(_, j)| j != i).collect();
+            neighbours.sort_by(|a, b| a.0.total_cmp(&b.0));
+            return neighbours;
+        })
+        .collect();
+    let mut removed = vec![false; archive.len()];
+    for _ in size..archive.len() {
+        let remaining_distances = |i: usize| neighbours[i].iter().filter(|&&(_, j)| !removed[j]).map(|&(distance, _)| distance);
+        let closest = (0..archive.len())
+            .filter(|&i| !removed[i])
+            .min_by(|&a, &b| {
+                return remaining_distances(a).zip(remaining_distances(b))
+                    .map(|(x, y)| x.total_cmp(&y))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(std::cmp::Ordering::Equal);
+            })
+            .unwrap();
+        removed[closest] = true;
+    }
+    return (0..archive.len()).filter(|&i| !removed[i]).map(|i| archive[i]).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Ensures that solutions are sorted into the right fronts
+    fn test_non_dominated_sort() {
+        let objectives = [[1.0, 5.0], [2.0, 2.0], [5.0, 1.0], [3.0, 3.0], [4.0, 4.0], [2.0, 2.0]];
+        let mut fronts = non_dominated_sort(&objectives);
+        fronts.iter_mut().for_each(|front| front.sort());
+        assert_eq!(fronts, vec![vec![0, 1, 2, 5], vec![3], vec![4]]);
+    }
+
+    #[test]
+    /// Ensures that non-dominated solutions have a SPEA2 fitness below 1 and dominated ones above
+    fn test_spea2_fitness() {
+        let objectives = [[1.0, 3.0], [2.0, 2.0], [3.0, 1.0], [3.0, 3.0]];
+        let fitness = spea2_fitness(&objectives);
+        assert!(fitness[..3].iter().all(|&f| f < 1.0));
+        // Dominated by the first three, which each dominate only it
+        assert!(fitness[3] >= 3.0);
+    }
+
+    #[test]
+    /// Ensures that truncating the archive removes the most crowded solution
+    fn test_spea2_select() {
+        let objectives = [[0.0, 10.0], [5.0, 5.0], [5.1, 4.9], [10.0, 0.0], [10.0, 10.0]];
+        let fitness = spea2_fitness(&objectives);
+        let mut archive = spea2_select(&objectives, &fitness, 3);
+        archive.sort();
+        assert!(archive == vec![0, 1, 3] || archive == vec![0, 2, 3]);
+        assert_eq!(spea2_select(&objectives, &fitness, 5).len(), 5);
+    }
+
+    #[test]
+    /// Ensures that the extremes of a front are kept and the rest are spaced by their neighbours
+    fn test_crowding_distance() {
+        let objectives = [[0.0, 4.0], [1.0, 2.0], [3.0, 1.0], [4.0, 0.0]];
+        let distance = crowding_distance(&objectives, &[0, 1, 2, 3]);
+        assert_eq!(distance[0], f64::INFINITY);
+        assert_eq!(distance[3], f64::INFINITY);
+        assert_eq!(distance[1], 3.0 / 4.0 + 3.0 / 4.0);
+        assert_eq!(distance[2], 3.0 / 4.0 + 2.0 / 4.0);
+    }
+}
@@ -0,0 +1,347 @@
+use std::cell::Cell;
+
+use crate::chromosome::{least_squares, Chromosome, ComplexityLimits};
+use crate::io::Dataset;
+use crate::rng::random;
+
+/// Minimizes `f` with the Nelder-Mead simplex method, which needs no derivatives.
+///
+/// The initial simplex steps each coordinate of `start` by a tenth of its magnitude, or by 0.1 for
+/// coordinates near 0. NaN values of `f` count as infinitely bad. The search stops once the budget
+/// is spent or the values at the vertices of the simplex agree to within 1e-12.
+///
+/// # Arguments
+///
+/// * `f` - The function to minimize.
+/// * `start` - Where the search starts. Must not be empty.
+/// * `max_evaluations` - How many times `f` may be called at most.
+///
+/// # Returns
+///
+/// The best point found, its value and how many times `f` was called.
+pub fn nelder_mead(mut f: impl FnMut(&[f64]) -> f64, start: &[f64], max_evaluations: usize) -> (Vec<f64>, f64, usize) {
+    let evaluations = Cell::new(0);
+    let mut value = |x: &[f64]| {
+        evaluations.set(evaluations.get() + 1);
+        let y = f(x);
+        if y.is_nan() { f64::INFINITY } else { y }
+    };
+    let n = start.len();
+    let mut simplex: Vec<(Vec<f64>, f64)> = vec![(start.to_vec(), value(start))];
+    for i in 0..n {
+        let mut vertex = start.to_vec();
+        vertex[i] += if vertex[i].abs() > 1.0 { 0.1 * vertex[i] } else { 0.1 };
+        let y = value(&vertex);
+        simplex.push((vertex, y));
+    }
+
+    // Standard coefficients for reflection, expansion, contraction and shrinking
+    let (alpha, gamma, rho, sigma) = (1.0, 2.0, 0.5, 0.5);
+    let along = |from: &[f64], to: &[f64], t: f64| -> Vec<f64> { from.iter().zip(to).map(|(a, b)| a + t * (b - a)).collect() };
+    while evaluations.get() + 2 <= max_evaluations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        if (simplex[n].1 - simplex[0].1).abs() <= 1e-12 {
+            break;
+        }
+        let centroid: Vec<f64> = (0..n).map(|i| simplex[..n].iter().map(|(x, _)| x[i]).sum::<f64>() / n as f64).collect();
+        let worst = simplex[n].clone();
+        let reflected = along(&centroid, &worst.0, -alpha);
+        let reflected_value = value(&reflected);
+        if reflected_value < simplex[0].1 {
+            let expanded = along(&centroid, &worst.0, -gamma);
+            let expanded_value = value(&expanded);
+            simplex[n] = if expanded_value < reflected_value { (expanded, expanded_value) } else { (reflected, reflected_value) };
+        } else if reflected_value < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_value);
+        } else {
+            // Contract towards the better of the worst and the reflected point
+            let outside = reflected_value < worst.1;
+            let contracted = match outside {
+                true => along(&centroid, &reflected, rho),
+                false => along(&centroid, &worst.0, rho),
+            };
+            let contracted_value = value(&contracted);
+            if contracted_value < reflected_value.min(worst.1) {
+                simplex[n] = (contracted, contracted_value);
+            } else {
+                if evaluations.get() + n > max_evaluations {
+                    break;
+                }
+                let best = simplex[0].0.clone();
+                for vertex in &mut simplex[1..] {
+                    vertex.0 = along(&best, &vertex.0, sigma);
+                    vertex.1 = value(&vertex.0);
+                }
+            }
+        }
+    }
+    let (x, y) = simplex.into_iter().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+    return (x, y, evaluations.get());
+}
+
+/// Tunes the active constants of a chromosome with Nelder-Mead against its error on `dataset`, and
+/// writes them back into its genes if that lowers the error.
+///
+/// The fitness of the chromosome is left as its error on `dataset` either way. Weights of a
+/// multi-gene chromosome are refitted for every candidate set of constants.
+///
+/// # Arguments
+///
+/// * `chromosome` - The chromosome to tune.
+/// * `dataset` - The rows to tune the constants on.
+/// * `max_evaluations` - How many candidate sets of constants may be evaluated at most.
+///
+/// # Returns
+///
+/// How many times the chromosome was evaluated.
+pub fn optimize_constants(chromosome: &mut Chromosome, dataset: &Dataset, max_evaluations: usize) -> usize {
+    let positions = chromosome.active_constants();
+    let before = chromosome.evaluate_fitness_error(dataset);
+    if positions.is_empty() || max_evaluations == 0 {
+        return 1;
+    }
+    let mut candidate = chromosome.clone();
+    let (constants, after, evaluations) = nelder_mead(|values| {
+        candidate.set_constants(&positions, values);
+        candidate.evaluate_fitness_error(dataset)
+    }, &chromosome.constants(&positions), max_evaluations);
+    if after < before {
+        chromosome.set_constants(&positions, &constants);
+        chromosome.evaluate_fitness_error(dataset);
+        return evaluations + 2;
+    }
+    return evaluations + 1;
+}
+
+/// The sum of the squares of `residuals`, infinite when any of them is NaN or infinite.
+fn sum_of_squares(residuals: &[f64]) -> f64 {
+    let total: f64 = residuals.iter().map(|r| r * r).sum();
+    return if total.is_finite() { total } else { f64::INFINITY };
+}
+
+/// Minimizes the sum of the squares of `residuals` with the Levenberg-Marquardt method.
+///
+/// The Jacobian is estimated by forward differences. Each iteration solves the damped normal
+/// equations as an augmented least-squares problem, raising the damping tenfold until a step lowers
+/// the error and lowering it tenfold after each step that does. The search stops early once a step
+/// barely lowers the error or no damping finds a better point.
+///
+/// # Arguments
+///
+/// * `residuals` - The residuals at a point.
+/// * `start` - Where the search starts.
+/// * `max_iterations` - How many Jacobians may be estimated at most.
+///
+/// # Returns
+///
+/// The best point found, its sum of squared residuals and how many times `residuals` was called.
+pub fn levenberg_marquardt(mut residuals: impl FnMut(&[f64]) -> Vec<f64>, start: &[f64], max_iterations: usize) -> (Vec<f64>, f64, usize) {
+    let mut x = start.to_vec();
+    let mut r = residuals(&x);
+    let mut cost = sum_of_squares(&r);
+    let mut evaluations = 1;
+    let mut damping = 1e-3;
+    for _ in 0..max_iterations {
+        if !cost.is_finite() || cost == 0.0 {
+            break;
+        }
+        let columns: Vec<Vec<f64>> = (0..x.len())
+            .map(|j| {
+                let step = 1e-6 * x[j].abs().max(1.0);
+                let mut shifted = x.clone();
+                shifted[j] += step;
+                evaluations += 1;
+                residuals(&shifted).iter().zip(&r).map(|(a, b)| (a - b) / step).collect()
+            })
+            .collect();
+        let scales: Vec<f64> = columns.iter().map(|column| column.iter().map(|d| d * d).sum::<f64>().max(1e-12)).collect();
+        let mut accepted = false;
+        // Beyond this much damping the step is too small to matter
+        while damping < 1e12 {
+            // Rows of sqrt(damping * scale) on the diagonal turn least squares into the damped normal equations
+            let features: Vec<Vec<f64>> = (0..r.len()).map(|i| columns.iter().map(|column| column[i]).collect())
+                .chain(scales.iter().enumerate().map(|(j, scale)| (0..x.len()).map(|k| if k == j { (damping * scale).sqrt() } else { 0.0 }).collect()))
+                .collect();
+            let targets: Vec<f64> = r.iter().map(|v| -v).chain(std::iter::repeat_n(0.0, x.len())).collect();
+            let candidate: Vec<f64> = x.iter().zip(least_squares(&features, &targets)).map(|(a, step)| a + step).collect();
+            let candidate_r = residuals(&candidate);
+            evaluations += 1;
+            let candidate_cost = sum_of_squares(&candidate_r);
+            if candidate_cost < cost {
+                let converged = cost - candidate_cost <= 1e-12 * cost;
+                (x, r, cost) = (candidate, candidate_r, candidate_cost);
+                damping /= 10.0;
+                accepted = !converged;
+                break;
+            }
+            damping *= 10.0;
+        }
+        if !accepted {
+            break;
+        }
+    }
+    return (x, cost, evaluations);
+}
+
+/// Fits the active constants of a chromosome to `dataset` with Levenberg-Marquardt and writes them
+/// back into its genes if that lowers its fitness.
+///
+/// Levenberg-Marquardt minimizes the sum of squared residuals, which is not what every metric or
+/// objective measures, so the fitted constants are only kept when `Chromosome::fitness` improves.
+/// Chromosomes without active constants are left alone. Weights of a multi-gene chromosome are
+/// refitted for every candidate set of constants.
+///
+/// # Arguments
+///
+/// * `chromosome` - The chromosome whose constants are fitted.
+/// * `dataset` - The rows to fit the constants on.
+/// * `max_iterations` - How many Levenberg-Marquardt iterations may be run at most.
+///
+/// # Returns
+///
+/// How many times a chromosome was evaluated, and whether the constants changed.
+pub fn fit_constants(chromosome: &mut Chromosome, dataset: &Dataset, max_iterations: usize) -> (usize, bool) {
+    let positions = chromosome.active_constants();
+    if positions.is_empty() || max_iterations == 0 {
+        return (0, false);
+    }
+    let start = chromosome.constants(&positions);
+    let mut candidate = chromosome.clone();
+    let residuals = |values: &[f64]| {
+        candidate.set_constants(&positions, values);
+        candidate.fit_weights(dataset);
+        candidate.residuals(dataset)
+    };
+    let (constants, _, evaluations) = levenberg_marquardt(residuals, &start, max_iterations);
+    candidate.set_constants(&positions, &constants);
+    candidate.fit_weights(dataset);
+    chromosome.fit_weights(dataset);
+    // The fitted and the original constants are both evaluated once more to compare them
+    let evaluations = evaluations + 2;
+    if candidate.fitness(dataset) < chromosome.fitness(dataset) {
+        *chromosome = candidate;
+        return (evaluations, true);
+    }
+    return (evaluations, false);
+}
+
+/// Refines a chromosome by hill climbing: rewires an input or swaps the function of a random gene
+/// and keeps the change whenever it lowers the error on `dataset`.
+///
+/// Tweaks that leave the expression unchanged, e.g. of inactive genes, or that break `limits` are
+/// discarded without being evaluated. The fitness of the chromosome is left as its error on `dataset`.
+///
+/// # Arguments
+///
+/// * `chromosome` - The chromosome to refine.
+/// * `dataset` - The rows the error is measured on.
+/// * `num_variables` - The number of variables in the dataset.
+/// * `limits` - How complex the chromosome may become.
+/// * `max_evaluations` - How many tweaked chromosomes may be evaluated at most.
+///
+/// # Returns
+///
+/// How many times a chromosome was evaluated, and how many tweaks were kept.
+pub fn hill_climb(chromosome: &mut Chromosome, dataset: &Dataset, num_variables: usize, limits: &ComplexityLimits,
+                  max_evaluations: usize) -> (usize, usize) {
+    let mut fitness = chromosome.evaluate_fitness_error(dataset);
+    let (mut evaluations, mut improvements) = (1, 0);
+    // Bounds the number of attempts when few tweaks change the expression
+    for _ in 0..max_evaluations * 10 {
+        if evaluations > max_evaluations {
+            break;
+        }
+        let mut candidate = chromosome.clone();
+        match random() {
+            true => candidate.mutate_pointer(num_variables),
+            false => candidate.mutate_function(num_variables),
+        }
+        if candidate.structure_hash() == chromosome.structure_hash() || !candidate.within(limits) || !candidate.obeys_grammar() {
+            continue;
+        }
+        evaluations += 1;
+        if candidate.evaluate_fitness_error(dataset) < fitness {
+            fitness = candidate.fitness_value;
+            *chromosome = candidate;
+            improvements += 1;
+        }
+    }
+    return (evaluations, improvements);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::chromosome::Gene;
+    use crate::functions::PrimitiveId;
+    use crate::fitness::Mae;
+    use crate::io::{open_dataset, read_csv, DatasetOptions};
+
+    #[test]
+    /// Ensures that Nelder-Mead finds the minimum of the Rosenbrock function within its budget
+    fn test_nelder_mead() {
+        let rosenbrock = |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2);
+        let (x, y, evaluations) = nelder_mead(rosenbrock, &[-1.2, 1.0], 2000);
+        assert!(evaluations <= 2000);
+        assert!(y < 1e-8, "{}", y);
+        assert!((x[0] - 1.0).abs() < 1e-3 && (x[1] - 1.0).abs() < 1e-3, "{:?}", x);
+    }
+
+    #[test]
+    /// Ensures that a wrong constant is tuned towards the one that fits the data, y = x0 * x1 + x2
+    fn test_optimize_constants() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_variable(2),
+                                                                   Gene::new_constant(Some(0.3)), Gene::new_binary2(0, 1, PrimitiveId::MUL),
+                                                                   Gene::new_binary2(2, 3, PrimitiveId::MUL), Gene::new_binary2(4, 5, PrimitiveId::ADD)]);
+        let before = chromosome.evaluate_fitness_error(&dataset);
+        optimize_constants(&mut chromosome, &dataset, 200);
+        assert!(chromosome.fitness_value < before && chromosome.fitness_value < 1e-10);
+        assert!((chromosome.constants(&[3])[0] - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    /// Ensures that Levenberg-Marquardt fits an exponential curve and that wrong constants are fitted in place
+    fn test_levenberg_marquardt() {
+        let xs = [0.0, 0.5, 1.0, 1.5, 2.0, 2.5];
+        let curve = |p: &[f64]| -> Vec<f64> { xs.iter().map(|x| p[0] * (p[1] * x).exp() - 2.0 * (0.7 * x).exp()).collect() };
+        let (p, cost, _) = levenberg_marquardt(curve, &[1.0, 0.1], 50);
+        assert!(cost < 1e-16, "{}", cost);
+        assert!((p[0] - 2.0).abs() < 1e-6 && (p[1] - 0.7).abs() < 1e-6, "{:?}", p);
+
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_variable(2),
+                                                                   Gene::new_constant(Some(-4.0)), Gene::new_binary2(0, 1, PrimitiveId::MUL),
+                                                                   Gene::new_binary2(2, 3, PrimitiveId::MUL), Gene::new_binary2(4, 5, PrimitiveId::ADD)]);
+        let (evaluations, changed) = fit_constants(&mut chromosome, &dataset, 5);
+        assert!(changed && evaluations > 2);
+        assert!((chromosome.constants(&[3])[0] - 1.0).abs() < 1e-6);
+        assert!(chromosome.evaluate_fitness_error(&dataset) < 1e-10);
+
+        // Least squares pulls the constant of x0 + c toward the mean residual, which is worse under
+        // the MAE than the median one the constant starts at, so the fit is rejected
+        let dataset = read_csv("x0,y\n0,0\n0,0\n0,9\n".as_bytes(), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_constant(Some(0.0)), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::new(Mae);
+        let before = chromosome.fitness(&dataset);
+        assert!(!fit_constants(&mut chromosome, &dataset, 5).1);
+        assert_eq!((chromosome.constants(&[1])[0], chromosome.fitness(&dataset)), (0.0, before));
+    }
+
+    #[test]
+    /// Ensures that hill climbing only keeps tweaks that lower the error and stays within its budget
+    fn test_hill_climb() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        // x0 + x1 + x2 is one function swap away from x0 * x1 + x2
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_variable(2),
+                                                                   Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_binary2(3, 2, PrimitiveId::ADD)]);
+        let before = chromosome.evaluate_fitness_error(&dataset);
+        let (evaluations, improvements) = hill_climb(&mut chromosome, &dataset, 3, &ComplexityLimits::default(), 300);
+        assert!(evaluations <= 301);
+        assert!(improvements > 0);
+        assert!(chromosome.fitness_value < before);
+        assert_eq!(chromosome.fitness_value, chromosome.error(&dataset));
+    }
+}
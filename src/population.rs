@@ -1,8 +1,170 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use rand::Rng;
-use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use rand::seq::SliceRandom;
+use rand::seq::index::sample;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelExtend, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+use crate::bandit::{OperatorMix, OperatorSelection};
+use crate::chromosome::{behavior_distance, Chromosome, ComplexityLimits, ConstantDistribution, ConstantDomain, CrossoverOperator, Distance, Grid, InvalidRows, Layout, MutationOperator, Notation};
+use crate::fitness::{FitnessMetric, Objective, Task};
+use crate::functions::{FunctionSet, Protection};
+use crate::grammar::Grammar;
+use crate::hall_of_fame::EliteArchive;
+use crate::interval::{always_fails, Interval};
+use crate::io::{Dataset, DatasetTraits};
+use crate::moea;
+use crate::optimize::{fit_constants, hill_climb};
+use crate::rng;
+use crate::units::Units;
+
+/// Decides which rows of the dataset are used to evaluate fitness in a generation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubsamplePolicy {
+    /// Every row is used.
+    Full,
+    /// `size` rows are drawn uniformly at random.
+    Random(usize),
+    /// The rows are split into `strata` bins by target quantile and `size` rows are drawn
+    /// proportionally from each bin, so every region of the target range stays represented.
+    Stratified { size: usize, strata: usize },
+}
+
+impl SubsamplePolicy {
+    /// Draws the rows to evaluate on for this generation.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - The full dataset.
+    ///
+    /// # Returns
+    ///
+    /// `None` when the full dataset should be used, otherwise the sampled rows.
+    pub fn sample(&self, dataset: &Dataset) -> Option<Dataset> {
+        return match *self {
+            SubsamplePolicy::Full => None,
+            SubsamplePolicy::Random(size) => Some(dataset.sample_rows(size)),
+            SubsamplePolicy::Stratified { size, strata } => Some(stratified_sample(dataset, size, strata)),
+        };
+    }
+}
+
+/// Draws roughly `size` rows from `dataset`, taking from each target quantile bin in proportion to its size.
+///
+/// Every bin contributes at least one row, so the result can be slightly larger than `size` when
+/// there are many small bins.
+fn stratified_sample(dataset: &Dataset, size: usize, strata: usize) -> Dataset {
+    if size >= dataset.len() {
+        return dataset.clone();
+    }
+
+    let mut order: Vec<usize> = (0..dataset.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (row_a, row_b) = (&dataset.rows[a], &dataset.rows[b]);
+        row_a[row_a.len() - 1].total_cmp(&row_b[row_b.len() - 1])
+    });
+
+    let bin_size = dataset.len().div_ceil(strata.clamp(1, dataset.len()));
+    let mut rng = rng::rng();
+    let mut rows = Vec::with_capacity(size + strata);
+    for bin in order.chunks(bin_size) {
+        let take = ((bin.len() * size) as f64 / dataset.len() as f64).round().max(1.0) as usize;
+        for i in sample(&mut rng, bin.len(), take.min(bin.len())) {
+            rows.push(dataset.rows[bin[i]].clone());
+        }
+    }
+    return dataset.with_rows(rows);
+}
+
+/// How each generation is produced from the previous one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// The whole population is replaced by offspring every generation, keeping only the best.
+    Generational,
+    /// `offspring` children are bred at a time and replace the worst individuals. A generation
+    /// is as many of these steps as it takes to breed a population's worth of children.
+    SteadyState { offspring: usize },
+    /// (μ+λ): `lambda` children are bred from the μ individuals of the population and the best μ
+    /// of parents and children together survive.
+    MuPlusLambda { lambda: usize },
+    /// (1+λ): the best individual is the only parent of `lambda` mutants, and is replaced by the
+    /// best mutant when that mutant is at least as fit.
+    OnePlusLambda { lambda: usize },
+    /// Deterministic crowding: the population is paired up at random, each pair breeds two children,
+    /// and each child replaces the more similar parent only if it is fitter.
+    Crowding,
+    /// Age-Layered Population Structure: the population is split into `layers` layers of increasing
+    /// maximum age, parents are only drawn from a layer and the one below it, and the youngest
+    /// layer is replaced with random chromosomes every `age_gap` generations.
+    Alps { layers: usize, age_gap: usize },
+    /// NEAT-style speciation: chromosomes within `threshold` distance of a species'
+    /// representative belong to it, offspring are shared out between species by their mean
+    /// fitness, and parents only mate within their species. Species younger than `protection`
+    /// generations keep at least as many offspring as they have members.
+    Speciation { threshold: f64, protection: usize },
+    /// NSGA-II: the error and the number of active genes are minimized together. Parents and
+    /// children are sorted into non-dominated fronts and the best fronts survive, with ties in the
+    /// last front broken by crowding distance.
+    Nsga2,
+    /// SPEA2: the error and the number of active genes are minimized together. An external archive
+    /// of `archive_size` chromosomes keeps the best trade-offs found, parents are drawn from the
+    /// archive only, and every chromosome's fitness is how strongly it is dominated plus a density term.
+    Spea2 { archive_size: usize },
+    /// MAP-Elites: the fittest chromosome of every combination of complexity and number of distinct
+    /// variables is archived, and every generation is bred from random elites.
+    MapElites,
+}
+
+impl Strategy {
+    /// Whether the strategy optimizes error and size together and produces a Pareto front.
+    pub fn is_multi_objective(&self) -> bool {
+        return matches!(self, Strategy::Nsga2 | Strategy::Spea2 { .. });
+    }
+}
+
+/// How parents are chosen from the population.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    /// The fittest of this many randomly drawn chromosomes.
+    Tournament(usize),
+    /// Fitness-proportionate: a chromosome is drawn with a probability proportional to `1 / (1 + fitness)`.
+    /// Negative fitness values are shifted so that the lowest is 0.
+    Roulette,
+    /// Lexicase: the rows are shuffled and, row by row, only the chromosomes with the lowest error on
+    /// that row are kept until a single one is left. Favours specialists that solve some rows well.
+    Lexicase,
+    /// Lexicase where every chromosome within epsilon of the lowest error on a row is kept. Epsilon is
+    /// the median absolute deviation of the population's errors on that row, so that near ties on
+    /// continuous targets are not decided by noise.
+    EpsilonLexicase,
+    /// The probability of being drawn falls linearly with rank. The best chromosome is drawn
+    /// `pressure` times as often as the average one, and `pressure` must be between 1 and 2.
+    LinearRank { pressure: f64 },
+    /// The probability of being drawn is proportional to `base` to the power of the rank, where the
+    /// best chromosome has rank 0 and `base` is between 0 and 1.
+    ExponentialRank { base: f64 },
+}
 
-use crate::chromosome::Chromosome;
-use crate::io::Dataset;
+/// What happens to a child that encodes the same expression as a parent or an earlier child.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Duplicates are kept.
+    Allow,
+    /// Duplicates are mutated in an active gene until they are unique, giving up after a few tries.
+    Mutate,
+    /// Duplicates are replaced by new random chromosomes.
+    Reject,
+}
+
+impl Selection {
+    /// Whether the selection needs the error of every chromosome on every row.
+    pub fn uses_case_errors(&self) -> bool {
+        return matches!(self, Selection::Lexicase | Selection::EpsilonLexicase);
+    }
+}
 
 pub struct PopulationParameters {
     pub generations: usize,
@@ -10,22 +172,725 @@ pub struct PopulationParameters {
     pub num_genes: usize,
     pub mut_chance: f64,
     pub crossover_chance: f64,
+    /// How each generation is produced from the previous one.
+    pub strategy: Strategy,
+    /// How parents are chosen.
+    pub selection: Selection,
+    /// The distance within which chromosomes share their fitness. `None` disables fitness sharing.
+    pub sharing_radius: Option<f64>,
+    /// How distances between chromosomes are measured for fitness sharing, crowding and speciation.
+    pub distance: Distance,
+    /// What happens to children of `mate` that duplicate a parent or another child.
+    pub duplicates: DuplicatePolicy,
+    /// How many rows the outputs are compared on for behavioral distances and novelty search.
+    pub probe_size: usize,
+    /// How much parent selection is driven by behavioral novelty rather than fitness, from 0 to 1.
+    /// `None` disables novelty search.
+    pub novelty: Option<f64>,
+    /// How many nearest neighbours the novelty of a chromosome is averaged over.
+    pub novelty_neighbours: usize,
+    /// Which rows are used to evaluate fitness each generation.
+    pub subsample: SubsamplePolicy,
+    /// How often (in generations) the full dataset is used when subsampling.
+    pub full_evaluation_interval: usize,
+    /// Stop when the validation error (or the training error without a validation set) of the best
+    /// chromosome has not improved for this many generations. `None` always runs every generation.
+    pub patience: Option<usize>,
+    /// The smallest decrease in error that counts as an improvement for early stopping.
+    pub min_delta: f64,
+    /// Stop as soon as the training error of the best chromosome drops below this value.
+    pub stop_at_fitness: Option<f64>,
+    /// Reinitialize the population after this many generations without the best fitness improving.
+    pub restart_after: Option<usize>,
+    /// How many of the fittest chromosomes survive a restart.
+    pub restart_keep: usize,
+    /// How the mutation and crossover chances change over the run.
+    pub rate_adaptation: RateAdaptation,
+    /// Whether every chromosome carries its own mutation and crossover rates, which evolve with it.
+    pub self_adaptive: bool,
+    /// The mutation operators children can be mutated with and the rate of each, relative to the others.
+    pub mutation_operators: Vec<(MutationOperator, f64)>,
+    /// How the mutation operator of each child is chosen.
+    pub operator_selection: OperatorSelection,
+    /// How children exchange genes.
+    pub crossover: CrossoverOperator,
+    /// The probability of a headless-chicken macro-mutation of each child.
+    pub headless_chicken: f64,
+    /// The standard deviation of the Gaussian mutation of constants in the first generation.
+    pub constant_sigma: f64,
+    /// The standard deviation of the Gaussian mutation in the last generation, reached geometrically.
+    /// `None` keeps `constant_sigma` for the whole run.
+    pub final_constant_sigma: Option<f64>,
+    /// How the genes are arranged, which limits the inputs each gene may use.
+    pub grid: Grid,
+    /// Combine this many root genes into a prediction with least-squares weights and a bias
+    /// (multi-gene GP). `None` uses one output gene per target.
+    pub multigene: Option<usize>,
+    /// Where the values of random constants are drawn from.
+    pub constants: ConstantDistribution,
+    /// Which values constants may take, e.g. only integers when the formula is known to have integer coefficients.
+    pub constant_domain: ConstantDomain,
+    /// What division, log, log2 and sqrt return where they are undefined.
+    pub protection: Protection,
+    /// The primitives function genes are drawn from.
+    pub function_set: FunctionSet,
+    /// Only wire genes to inputs of the types their functions take (strongly typed GP).
+    pub typed: bool,
+    /// The rules the expressions of chromosomes must follow.
+    pub grammar: Grammar,
+    /// What the error of a chromosome is when its output is NaN or infinite on some rows.
+    pub invalid_rows: InvalidRows,
+    /// Whether the run regresses or classifies.
+    pub task: Task,
+    /// How the error of chromosomes is measured.
+    pub metric: Arc<dyn FitnessMetric>,
+    /// A custom fitness that replaces the error under `metric`. Only available to library users.
+    pub objective: Option<Objective>,
+    /// Scale the output of every chromosome by a least-squares slope and intercept before computing
+    /// its error (Keijzer's linear scaling).
+    pub linear_scaling: bool,
+    /// Tune the constants of the best chromosome with Nelder-Mead, evaluating at most this many sets of
+    /// constants each time. `None` leaves the constants to evolution.
+    pub optimize_constants: Option<usize>,
+    /// Tune the best chromosome every this many generations as well as at the end of the run. `None`
+    /// only tunes the final champion.
+    pub optimize_interval: Option<usize>,
+    /// Simplify every chromosome of the population every this many generations, see
+    /// `Chromosome::simplify`. `None` leaves the expressions as they evolve.
+    pub simplify_interval: Option<usize>,
+    /// Fold the subgraphs of constants of every chromosome into single constants before it is
+    /// evaluated, see `Chromosome::fold_constants`.
+    pub fold_constants: bool,
+    /// Fit the constants of every chromosome with at most this many Levenberg-Marquardt iterations
+    /// whenever it is evaluated, and keep the fitted constants in its genes. `None` does not fit them.
+    pub local_learning: Option<usize>,
+    /// Hill climb every child with at most this many evaluations when it is bred, so that it is
+    /// refined before it competes (memetic evolution). `None` does not.
+    pub memetic: Option<usize>,
+    /// Hill climb the best chromosome every generation with at most this many evaluations. `None` does not.
+    pub hill_climb: Option<usize>,
+    /// The temperature of simulated-annealing replacement in the first generation of steady-state
+    /// evolution. `None` lets every child replace the worst individual.
+    pub temperature: Option<f64>,
+    /// The temperature in the last generation. The temperature cools geometrically towards it.
+    pub final_temperature: f64,
+    /// Start hypermutation when the fraction of distinct expressions in the population drops below
+    /// this. `None` disables hypermutation.
+    pub diversity_threshold: Option<f64>,
+    /// The mutation chance while hypermutation lasts.
+    pub hypermutation_chance: f64,
+    /// How many generations hypermutation lasts once it starts.
+    pub hypermutation_generations: usize,
+    /// The fraction of the population, from the least fit, replaced by random immigrants when hypermutation starts.
+    pub immigrants: f64,
+    /// Where the best chromosome is saved at the end of the run.
+    pub model_path: Option<String>,
+    /// Where the report of the run is written as JSON, see `Report`.
+    pub report_path: Option<String>,
+    /// How the expressions printed at the end of the run are written.
+    pub notation: Notation,
+    /// Whether the best chromosome is canonicalized by equality saturation before it is printed,
+    /// reported and saved, see `Chromosome::canonical`.
+    pub canonical: bool,
+    /// How many of the best distinct chromosomes ever seen are archived. 0 disables the archive.
+    pub hall_of_fame: usize,
+    /// Where the archived chromosomes are written at the end of the run.
+    pub hall_of_fame_path: String,
+    /// Where the MAP-Elites archive is written at the end of a MAP-Elites run.
+    pub elites_path: String,
+    /// Where the trade-off between error and complexity seen during the run is written. `None` does not track it.
+    pub pareto_front_path: Option<String>,
+    /// Where Prometheus metrics are served while the run evolves. Requires the `metrics` feature.
+    pub metrics_address: Option<String>,
+    /// How complex the evolved expressions may become.
+    pub limits: ComplexityLimits,
+    /// The units of the columns of the dataset, which dimensionally inconsistent expressions are
+    /// checked against. `None` does not check dimensions.
+    pub units: Option<Units>,
+    /// Prescreen chromosomes with interval arithmetic over the range of each variable, and give those
+    /// whose outputs are NaN or infinite on every row the worst fitness without evaluating them.
+    /// Classifiers are not prescreened.
+    pub prescreen: bool,
+    /// The seed of the generator every random choice of the run is drawn from, see `rng::seed`.
+    pub seed: u64,
 }
 
+#[allow(clippy::len_without_is_empty)]
 pub trait PopulationTraits {
-    fn mate(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64;
+    fn mate(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection, dataset: &Dataset) -> f64;
+    fn steady_state(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection, offspring: usize, dataset: &Dataset) -> f64;
+    fn mu_plus_lambda(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection, lambda: usize, dataset: &Dataset) -> f64;
+    fn one_plus_lambda(&mut self, num_variables: usize, lambda: usize, dataset: &Dataset) -> f64;
+    fn crowding(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64;
+    #[allow(clippy::too_many_arguments)]
+    fn alps(&mut self, num_variables: usize, num_genes: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection,
+            layers: usize, age_gap: usize, generation: usize, dataset: &Dataset) -> f64;
+    fn speciate(&mut self, threshold: f64);
+    #[allow(clippy::too_many_arguments)]
+    fn speciation(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection,
+                  threshold: f64, protection: usize, dataset: &Dataset) -> f64;
+    fn nsga2(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64;
+    fn spea2(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, archive_size: usize, dataset: &Dataset) -> f64;
+    fn pareto_front(&self) -> Vec<&Chromosome>;
+    fn map_elites(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64;
     fn find_best_min(&mut self);
-    fn tournament_selection(&self) -> &Chromosome;
+    fn select(&self, selection: Selection) -> &Chromosome;
+    fn tournament_selection(&self, size: usize) -> &Chromosome;
+    fn roulette_selection(&self) -> &Chromosome;
+    fn rank_selection(&self, rank: usize) -> &Chromosome;
+    fn lexicase_selection(&self, epsilon: bool) -> &Chromosome;
     fn get_random_chromosome(&self) -> &Chromosome;
+    #[allow(dead_code)]
     fn all_accessed(&mut self);
-    fn initialize(size: usize, num_genes: usize, dataset: &Dataset) -> Population;
+    fn initialize(size: usize, num_genes: usize, dataset: &Dataset, layout: Layout) -> Population;
+    fn restart(&mut self, keep: usize, num_genes: usize, num_variables: usize);
     fn evaluate(&mut self, dataset: &Dataset);
+    fn share_fitness(&mut self, radius: f64);
+    fn score_novelty(&mut self);
+    fn statistics(&self) -> PopulationStatistics;
+    fn offspring_outcomes(&mut self) -> Vec<OffspringOutcome>;
     fn len(&self) -> usize;
 }
 
+/// Summary of the fitness values of a population in one generation.
+///
+/// Chromosomes with a fitness of `f64::MAX` are invalid (their output overflowed) and are only
+/// counted in `invalid`, since they would dominate the other statistics.
+#[derive(Debug, Clone, Default)]
+pub struct PopulationStatistics {
+    pub min: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub std: f64,
+    /// The mean number of active genes.
+    pub mean_size: f64,
+    /// The mean depth of the expressions, see `Chromosome::depth`.
+    pub mean_depth: f64,
+    pub invalid: usize,
+    /// How many distinct expressions the population holds, see `Chromosome::structure_hash`.
+    pub unique: usize,
+    /// The standard deviation of the outputs of the valid chromosomes on each probe row, averaged
+    /// over the rows. It drops towards 0 as the population converges on a single behavior. NaN
+    /// when no behaviors are recorded, see `Population::probe`.
+    pub phenotypic_diversity: f64,
+}
+
+/// What became of a child bred in the last generation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffspringOutcome {
+    /// The operator that mutated the child, `None` when it was not mutated.
+    pub mutation: Option<MutationOperator>,
+    /// Whether the child is fitter than its fitter parent.
+    pub improved: bool,
+}
+
+/// The fraction of children that are fitter than their parents, `None` when no children were bred.
+pub fn success_rate(outcomes: &[OffspringOutcome]) -> Option<f64> {
+    return match outcomes.len() {
+        0 => None,
+        children => Some(outcomes.iter().filter(|o| o.improved).count() as f64 / children as f64),
+    };
+}
+
+/// Crosses over and mutates two new children in place.
+///
+/// Children that carry their own operator rates (self-adaptation) first perturb the rates they
+/// inherited, then use them instead of `crossover_chance` and `mutation_chance`. Crossover uses
+/// the rate of the first child.
+///
+/// # Arguments
+///
+/// * `offspring_one` - The first child.
+/// * `offspring_two` - The second child.
+/// * `crossover_chance` - The probability of crossover.
+/// * `mutation_chance` - The probability of mutating each child.
+/// * `operators` - How likely each mutation operator is.
+/// * `num_variables` - The number of variables in the chromosomes.
+fn vary(offspring_one: &mut Chromosome, offspring_two: &mut Chromosome, crossover_chance: f64, mutation_chance: f64, operators: &OperatorMix, num_variables: usize) {
+    for offspring in [&mut *offspring_one, &mut *offspring_two] {
+        if let Some(rates) = &mut offspring.rates {
+            rates.perturb();
+        }
+    }
+    let crossover_chance = offspring_one.rates.map_or(crossover_chance, |rates| rates.crossover);
+    if rng::rng().gen_bool(crossover_chance) { offspring_one.crossover(offspring_two, operators.crossover); }
+    for offspring in [offspring_one, offspring_two] {
+        if rng::rng().gen_bool(offspring.rates.map_or(mutation_chance, |rates| rates.mutation)) { offspring.mutate_with(operators.choose(), operators.sigma, operators.probe.as_deref(), num_variables); }
+        if operators.headless_chicken > 0.0 && rng::rng().gen_bool(operators.headless_chicken) { offspring.headless_chicken(num_variables); }
+    }
+}
+
+/// Takes a population, crossover chance, mutation chance, and number of variables as input
+/// and returns a tuple of two new offspring chromosomes.
+///
+/// # Arguments
+///
+/// * `population` - A reference to a `Population` instance.
+/// * `crossover_chance` - The chance of crossover as a floating-point number between 0 and 1.
+/// * `mutation_chance` - The chance of mutation as a floating-point number between 0 and 1.
+/// * `selection` - How each parent is chosen.
+/// * `num_variables` - The number of variables in the chromosomes.
+///
+/// # Returns
+///
+/// A tuple containing two `Chromosome` instances representing the new offspring. A child beyond
+/// the complexity limits of the population is replaced by a copy of its parent.
+///
+/// # Examples
+///
+/// ```ignore
+/// let population = Population::new();
+/// let crossover_chance = 0.8;
+/// let mutation_chance = 0.1;
+/// let num_variables = 5;
+///
+/// let (offspring_one, offspring_two) = get_new_offspring(&population, crossover_chance, mutation_chance, Selection::Tournament(2), num_variables);
+///
+/// assert_eq!(offspring_one.num_variables(), num_variables);
+/// assert_eq!(offspring_two.num_variables(), num_variables);
+/// ```
+fn get_new_offspring(population: &Population, crossover_chance: f64, mutation_chance: f64, selection: Selection, num_variables: usize) -> (Chromosome, Chromosome) {
+    let parent_one = population.select(selection);
+    let parent_two = population.select(selection);
+    return breed(population, parent_one, parent_two, crossover_chance, mutation_chance, num_variables);
+}
+
+/// Crosses over and mutates copies of two parents, rejecting children the population does not allow.
+///
+/// # Arguments
+///
+/// * `population` - The population whose operators, complexity limits and units are used.
+/// * `parent_one` - The first parent.
+/// * `parent_two` - The second parent.
+/// * `crossover_chance` - The chance of crossover as a floating-point number between 0 and 1.
+/// * `mutation_chance` - The chance of mutation as a floating-point number between 0 and 1.
+/// * `num_variables` - The number of variables in the chromosomes.
+///
+/// # Returns
+///
+/// The two children, in the order of their parents. A child beyond the complexity limits, or
+/// dimensionally inconsistent when inconsistencies are rejected, is replaced by a copy of its parent.
+fn breed(population: &Population, parent_one: &Chromosome, parent_two: &Chromosome, crossover_chance: f64, mutation_chance: f64, num_variables: usize) -> (Chromosome, Chromosome) {
+    let mut offspring_one = parent_one.offspring();
+    let mut offspring_two = parent_two.offspring();
+
+    vary(&mut offspring_one, &mut offspring_two, crossover_chance, mutation_chance, &population.operators, num_variables);
+
+    // Children that grew beyond the complexity limits, or that are dimensionally inconsistent when
+    // inconsistencies are rejected, are rejected and their parents copied instead
+    let allowed = |offspring: &Chromosome| offspring.within(&population.limits) && population.units.as_ref().is_none_or(|units| units.allows(offspring));
+    if !allowed(&offspring_one) { offspring_one = parent_one.offspring(); }
+    if !allowed(&offspring_two) { offspring_two = parent_two.offspring(); }
+
+    return (offspring_one, offspring_two);
+}
+
+/// The standard deviation of the recorded outputs of the valid chromosomes on each probe row,
+/// averaged over the rows. Non-finite outputs are ignored. NaN when no behaviors were recorded.
+fn phenotypic_diversity(population: &[Chromosome]) -> f64 {
+    if population.iter().all(|c| c.behavior.is_empty()) {
+        return f64::NAN;
+    }
+    let behaviors: Vec<&Vec<f64>> = population.iter().filter(|c| c.fitness_value < f64::MAX).map(|c| &c.behavior).collect();
+    let rows = behaviors.iter().map(|b| b.len()).min().unwrap_or(0);
+    if rows == 0 {
+        return 0.0;
+    }
+    let total: f64 = (0..rows)
+        .map(|row| {
+            let outputs: Vec<f64> = behaviors.iter().map(|b| b[row]).filter(|x| x.is_finite()).collect();
+            if outputs.is_empty() {
+                return 0.0;
+            }
+            let mean = outputs.iter().sum::<f64>() / outputs.len() as f64;
+            return (outputs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / outputs.len() as f64).sqrt();
+        })
+        .sum();
+    return total / rows as f64;
+}
+
+/// How the mutation and crossover chances change over a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateAdaptation {
+    /// The configured chances are used throughout.
+    Fixed,
+    /// The 1/5 success rule: when more than a fifth of the children are fitter than their parents
+    /// the chances grow to explore further, and when fewer are they shrink to refine what was found.
+    OneFifth,
+}
+
+impl RateAdaptation {
+    /// The mutation and crossover chances for the next generation.
+    ///
+    /// # Arguments
+    ///
+    /// * `chances` - The current mutation and crossover chances.
+    /// * `success_rate` - The fraction of the last children that were fitter than their parents, if any were bred.
+    pub fn adapt(&self, chances: (f64, f64), success_rate: Option<f64>) -> (f64, f64) {
+        return match (self, success_rate) {
+            (RateAdaptation::OneFifth, Some(success_rate)) => (one_fifth_rule(chances.0, success_rate), one_fifth_rule(chances.1, success_rate)),
+            _ => chances,
+        };
+    }
+}
+
+/// How much the 1/5 success rule scales the operator chances by each generation.
+const ONE_FIFTH_FACTOR: f64 = 0.85;
+
+/// Adapts an operator chance by the 1/5 success rule, keeping it between 0.01 and 1.
+///
+/// # Arguments
+///
+/// * `chance` - The current chance.
+/// * `success_rate` - The fraction of the last children that were fitter than their parents.
+fn one_fifth_rule(chance: f64, success_rate: f64) -> f64 {
+    let adapted = match success_rate {
+        s if s > 0.2 => chance / ONE_FIFTH_FACTOR,
+        s if s < 0.2 => chance * ONE_FIFTH_FACTOR,
+        _ => chance,
+    };
+    return adapted.clamp(0.01, 1.0);
+}
+
+/// How many times a duplicate child is mutated before it is kept as it is.
+const DUPLICATE_MUTATION_ATTEMPTS: usize = 10;
+
+/// Makes children that encode the same expression as a parent or an earlier child unique, see `DuplicatePolicy`.
+///
+/// # Arguments
+///
+/// * `parents` - The population the children were bred from.
+/// * `children` - The new children.
+/// * `policy` - What to do with duplicates.
+/// * `num_variables` - The number of variables in the chromosomes.
+fn suppress_duplicates(parents: &[Chromosome], children: &mut [Chromosome], policy: DuplicatePolicy, num_variables: usize) {
+    let mut seen: HashSet<u64> = parents.iter().map(Chromosome::structure_hash).collect();
+    for child in children {
+        let mut hash = child.structure_hash();
+        match policy {
+            DuplicatePolicy::Allow => {}
+            DuplicatePolicy::Mutate => {
+                for _ in 0..DUPLICATE_MUTATION_ATTEMPTS {
+                    if !seen.contains(&hash) {
+                        break;
+                    }
+                    child.mutate_active(num_variables);
+                    hash = child.structure_hash();
+                }
+            }
+            DuplicatePolicy::Reject => {
+                if seen.contains(&hash) {
+                    *child = Chromosome::new_random(child.genes.len(), num_variables, &child.layout);
+                    hash = child.structure_hash();
+                }
+            }
+        }
+        seen.insert(hash);
+    }
+}
+
+/// The fitness used to rank chromosomes against each other, where NaN counts as the worst possible.
+fn ranking_fitness(chromosome: &Chromosome) -> f64 {
+    return if chromosome.fitness_value.is_nan() { f64::INFINITY } else { chromosome.fitness_value };
+}
+
+/// Whether simulated-annealing replacement lets a child with fitness `child` replace an individual
+/// with fitness `replaced`.
+///
+/// A child at least as fit is always accepted. A child whose error is worse by a fraction `d` of the
+/// error it replaces is accepted with probability `exp(-d / temperature)`.
+fn anneal_accepts(child: f64, replaced: f64, temperature: f64) -> bool {
+    if child <= replaced {
+        return true;
+    }
+    let worse_by = (child - replaced) / replaced.abs().max(f64::MIN_POSITIVE);
+    return rng::rng().gen::<f64>() < (-worse_by / temperature).exp();
+}
+
+/// Tournament selection from an arbitrary group of chromosomes. Ties go to the last one drawn, like `tournament_selection`.
+fn pool_tournament<'a>(pool: &[&'a Chromosome], size: usize) -> &'a Chromosome {
+    let mut rng = rng::rng();
+    let mut winner = pool[rng.gen_range(0..pool.len())];
+    for _ in 1..size {
+        let challenger = pool[rng.gen_range(0..pool.len())];
+        winner = if winner.shared_fitness() < challenger.shared_fitness() { winner } else { challenger };
+    }
+    return winner;
+}
+
+/// Like `ranking_fitness`, but for choosing parents, so it includes fitness sharing.
+fn selection_fitness(chromosome: &Chromosome) -> f64 {
+    let fitness = chromosome.shared_fitness();
+    return if fitness.is_nan() { f64::INFINITY } else { fitness };
+}
+
+/// The weight of a rank under linear ranking, where rank 0 is the best. The best weighs `pressure`,
+/// the worst `2 - pressure`, and the weights of a population of `n` sum to `n`.
+fn linear_rank_weight(rank: usize, n: usize, pressure: f64) -> f64 {
+    return match n {
+        1 => 1.0,
+        _ => pressure - (2.0 * pressure - 2.0) * rank as f64 / (n - 1) as f64,
+    };
+}
+
+/// Maps a uniform draw `u` from `[0, 1)` to a rank under exponential ranking, where rank `r` of a
+/// population of `n` is drawn with probability `base^r * (1 - base) / (1 - base^n)`.
+///
+/// This is the inverse of the cumulative distribution of a geometric distribution truncated to the population.
+fn exponential_rank(u: f64, n: usize, base: f64) -> usize {
+    let rank = ((1.0 - u * (1.0 - base.powi(n as i32))).ln() / base.ln()).floor() as usize;
+    return rank.min(n - 1);
+}
+
+/// The weights of roulette-wheel selection, `1 / (1 + fitness)` for each chromosome.
+///
+/// Custom objectives can go below 0, where the weight would be infinite or negative, so when the
+/// lowest valid fitness is negative every fitness is shifted up by it first. Invalid chromosomes
+/// (NaN or `f64::MAX` fitness) get a weight of 0.
+fn roulette_weights(population: &[Chromosome]) -> Vec<f64> {
+    let valid = |c: &Chromosome| c.fitness_value < f64::MAX && !c.shared_fitness().is_nan();
+    let lowest = population.iter().filter(|c| valid(c)).map(Chromosome::shared_fitness).fold(0.0, f64::min);
+    return population.iter().map(|c| if valid(c) { 1.0 / (1.0 + c.shared_fitness() - lowest) } else { 0.0 }).collect();
+}
+
+/// The state of novelty search, which rewards chromosomes for behaving unlike the population and
+/// the chromosomes that came before them.
+pub struct NoveltySearch {
+    /// How much parent selection is driven by novelty rather than fitness, from 0 to 1.
+    pub weight: f64,
+    /// How many nearest neighbours the novelty of a chromosome is averaged over.
+    pub neighbours: usize,
+    /// The behaviors of past chromosomes that were the most novel of their population.
+    pub archive: Vec<Vec<f64>>,
+}
+
+impl NoveltySearch {
+    /// Starts novelty search with an empty archive.
+    pub fn new(weight: f64, neighbours: usize) -> NoveltySearch {
+        return NoveltySearch { weight, neighbours, archive: Vec::new() };
+    }
+}
+
+/// The novelty of a behavior: the mean distance to its `neighbours` nearest other behaviors.
+fn sparseness<'a>(behavior: &[f64], others: impl Iterator<Item = &'a [f64]>, neighbours: usize) -> f64 {
+    let mut distances: Vec<f64> = others.map(|other| behavior_distance(behavior, other)).collect();
+    let k = neighbours.min(distances.len());
+    if k == 0 {
+        return 0.0;
+    }
+    distances.select_nth_unstable_by(k - 1, f64::total_cmp);
+    return distances[..k].iter().sum::<f64>() / k as f64;
+}
+
+/// A group of genotypically similar chromosomes, used by speciation.
+pub struct Species {
+    /// The member new chromosomes are compared against, chosen at random from the last generation.
+    pub representative: Chromosome,
+    /// How many generations the species has existed for.
+    pub age: usize,
+    /// The indices of the members in the population.
+    pub members: Vec<usize>,
+}
+
+/// Shares out `total` offspring in proportion to `scores`, giving the rounding leftovers to the largest fractions.
+fn allocate(scores: &[f64], total: usize) -> Vec<usize> {
+    let sum: f64 = scores.iter().sum();
+    let shares: Vec<f64> = scores.iter().map(|score| score / sum * total as f64).collect();
+    let mut allocation: Vec<usize> = shares.iter().map(|share| share.floor() as usize).collect();
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| (shares[b] - shares[b].floor()).total_cmp(&(shares[a] - shares[a].floor())));
+    let leftover = total - allocation.iter().sum::<usize>();
+    for &i in order.iter().take(leftover) {
+        allocation[i] += 1;
+    }
+    return allocation;
+}
+
 pub struct Population {
     pub(crate) population: Vec<Chromosome>,
     pub(crate) best: Chromosome,
+    /// How many fitness evaluations have been done so far.
+    pub(crate) evaluations: usize,
+    /// Whether evaluation keeps the per-row errors of every chromosome, which lexicase selection needs.
+    pub(crate) keep_case_errors: bool,
+    /// The epsilon of every row for epsilon-lexicase selection, updated on every evaluation. `None`
+    /// when epsilon-lexicase is not used.
+    pub(crate) case_epsilons: Option<Vec<f64>>,
+    /// The distance within which chromosomes share their fitness, updated on every evaluation.
+    /// `None` disables fitness sharing.
+    pub(crate) sharing_radius: Option<f64>,
+    /// The species of the last generation when speciation is used.
+    pub(crate) species: Vec<Species>,
+    /// The rows whose outputs describe the behavior of a chromosome, needed by behavioral distances,
+    /// novelty search and the semantic operators. `None` records no behaviors.
+    pub(crate) probe: Option<Arc<Dataset>>,
+    /// How distances between chromosomes are measured for fitness sharing, crowding and speciation.
+    pub(crate) distance: Distance,
+    /// Novelty search, updated on every evaluation. `None` selects parents by fitness alone.
+    pub(crate) novelty: Option<NoveltySearch>,
+    /// The external archive of SPEA2, empty with every other strategy.
+    pub(crate) archive: Vec<Chromosome>,
+    /// The MAP-Elites archive, empty with every other strategy.
+    pub(crate) elites: EliteArchive,
+    /// What happens to children of `mate` that duplicate a parent or another child.
+    pub(crate) duplicates: DuplicatePolicy,
+    /// How complex chromosomes may become. Children beyond the limits are rejected where they are
+    /// bred, and any chromosome beyond them is scored with the worst possible fitness.
+    pub(crate) limits: ComplexityLimits,
+    /// The units of the columns of the dataset, see `Units`. `None` does not check dimensions.
+    pub(crate) units: Option<Units>,
+    /// The range of each variable of the training set. Chromosomes whose outputs interval arithmetic
+    /// bounds to NaN or infinity from these get the worst possible fitness without being evaluated.
+    /// `None` evaluates every chromosome.
+    pub(crate) bounds: Option<Vec<Interval>>,
+    /// How likely each mutation operator is this generation.
+    pub(crate) operators: OperatorMix,
+    /// Whether scoring records the outputs of every gene, which semantic crossover and semantic backpropagation need.
+    pub(crate) semantics: bool,
+    /// The shape of newly created chromosomes.
+    pub(crate) layout: Arc<Layout>,
+    /// How many Levenberg-Marquardt iterations fit the constants of each chromosome when it is evaluated. `None` does not fit them.
+    pub(crate) local_learning: Option<usize>,
+    /// How many tweaks hill climbing evaluates on every child when it is bred (memetic evolution).
+    /// `None` scores children as they were bred.
+    pub(crate) memetic: Option<usize>,
+    /// The temperature of simulated-annealing replacement in steady-state evolution this generation.
+    /// `None` lets every child replace the worst individual.
+    pub(crate) temperature: Option<f64>,
+    /// The children of the last (1+λ) generation, kept apart so that the population keeps its size.
+    pub(crate) offspring: Vec<Chromosome>,
+}
+
+/// Computes the median absolute deviation of the errors on every row, ignoring NaN and infinite errors.
+fn median_absolute_deviations(population: &[Chromosome]) -> Vec<f64> {
+    fn median(values: &mut [f64]) -> f64 {
+        if values.is_empty() {
+            return 0.0;
+        }
+        let middle = values.len() / 2;
+        values.select_nth_unstable_by(middle, f64::total_cmp);
+        return values[middle];
+    }
+
+    let num_cases = population.iter().map(|c| c.case_errors.len()).min().unwrap_or(0);
+    let mut errors = Vec::with_capacity(population.len());
+    return (0..num_cases)
+        .map(|case| {
+            errors.clear();
+            errors.extend(population.iter().map(|c| c.case_errors[case]).filter(|e| e.is_finite()));
+            let center = median(&mut errors);
+            errors.iter_mut().for_each(|e| *e = (*e - center).abs());
+            return median(&mut errors);
+        })
+        .collect();
+}
+
+/// What is recorded about a chromosome when it is evaluated, besides its fitness.
+struct Scoring {
+    /// Keep the per-row errors.
+    keep_case_errors: bool,
+    /// Record the outputs on these rows for behavioral distances.
+    probe: Option<Arc<Dataset>>,
+    /// Chromosomes beyond these limits get the worst possible fitness.
+    limits: ComplexityLimits,
+    /// Dimensionally inconsistent chromosomes are penalized or get the worst possible fitness.
+    units: Option<Units>,
+    /// The range of each variable, which chromosomes are prescreened against with interval arithmetic.
+    bounds: Option<Vec<Interval>>,
+    /// Record the outputs of every gene on the probe rows for semantic crossover.
+    semantics: bool,
+    /// Fit the constants with this many Levenberg-Marquardt iterations before evaluating.
+    local_learning: Option<usize>,
+    /// Hill climb new children with this many evaluations, see `refine`.
+    memetic: Option<usize>,
+    /// How many times chromosomes were evaluated, hill climbing included.
+    evaluations: AtomicUsize,
+}
+
+impl Scoring {
+    /// Evaluates a chromosome on `dataset` and records whatever else is needed.
+    fn score(&self, chromosome: &mut Chromosome, dataset: &Dataset) -> f64 {
+        // Chromosomes that interval arithmetic shows to be invalid on every row are not evaluated on
+        // the rows, and are not counted as evaluations
+        let culled = self.bounds.as_ref().is_some_and(|bounds| always_fails(chromosome, bounds));
+        if !culled {
+            self.evaluations.fetch_add(1, Ordering::Relaxed);
+        }
+        // Lamarckian: the fitted constants are kept, so children inherit them
+        if let (Some(iterations), false) = (self.local_learning, culled) {
+            let (evaluations, _) = fit_constants(chromosome, dataset, iterations);
+            self.evaluations.fetch_add(evaluations, Ordering::Relaxed);
+        }
+        // Evaluated first, since that fits the weights the behavior of a multi-gene chromosome depends on
+        let fitness = match (culled, self.keep_case_errors) {
+            (false, true) => chromosome.evaluate_fitness_cases(dataset),
+            (false, false) => chromosome.evaluate_fitness_error(dataset),
+            (true, keep_case_errors) => {
+                if keep_case_errors {
+                    chromosome.case_errors = vec![f64::MAX; dataset.rows.len()];
+                }
+                chromosome.fitness_value = f64::MAX;
+                f64::MAX
+            }
+        };
+        match self.probe.as_deref() {
+            Some(probe) if self.semantics => chromosome.record_semantics(probe),
+            Some(probe) => chromosome.record_behavior(probe),
+            None => {}
+        }
+        if !chromosome.within(&self.limits) {
+            chromosome.fitness_value = f64::MAX;
+            return f64::MAX;
+        }
+        if let Some(units) = &self.units {
+            chromosome.fitness_value = units.score(chromosome, fitness);
+            return chromosome.fitness_value;
+        }
+        return fitness;
+    }
+
+    /// Refines a newly bred chromosome by hill climbing when memetic evolution is used. It is done
+    /// once, when the child enters the population, and not whenever a survivor is scored again.
+    fn refine(&self, chromosome: &mut Chromosome, dataset: &Dataset) {
+        let Some(budget) = self.memetic else {
+            return;
+        };
+        if self.bounds.as_ref().is_some_and(|bounds| always_fails(chromosome, bounds)) {
+            return;
+        }
+        // Lamarckian: the refined genes are kept, so children inherit them
+        let (evaluations, _) = hill_climb(chromosome, dataset, dataset.get_num_variables(), &self.limits, budget);
+        self.evaluations.fetch_add(evaluations, Ordering::Relaxed);
+    }
+
+    /// Refines and then scores a newly bred chromosome, see `refine` and `score`.
+    fn score_offspring(&self, chromosome: &mut Chromosome, dataset: &Dataset) {
+        self.refine(chromosome, dataset);
+        let _ = self.score(chromosome, dataset);
+    }
+
+    /// Returns how many times chromosomes were evaluated so far.
+    fn evaluations(&self) -> usize {
+        return self.evaluations.load(Ordering::Relaxed);
+    }
+}
+
+impl Population {
+    /// What evaluating a chromosome records with the settings of the population, with a fresh
+    /// count of evaluations.
+    fn scoring(&self) -> Scoring {
+        return Scoring {
+            keep_case_errors: self.keep_case_errors,
+            probe: self.probe.clone(),
+            limits: self.limits,
+            units: self.units.clone(),
+            bounds: self.bounds.clone(),
+            semantics: self.semantics,
+            local_learning: self.local_learning,
+            memetic: self.memetic,
+            evaluations: AtomicUsize::new(0),
+        };
+    }
 }
 
 impl PopulationTraits for Population {
@@ -36,59 +901,31 @@ impl PopulationTraits for Population {
     /// * `num_variables` - The number of variables in the dataset.
     /// * `crossover_chance` - The probability of crossover.
     /// * `mutation_chance` - The probability of mutation.
+    /// * `selection` - How each parent is chosen.
     ///
     /// # Returns
     ///
     /// A tuple containing the new population and the fitness value of the best individual.
     /// Also replaces the population in memory
-    fn mate(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64 {
-        /// Takes a population, crossover chance, mutation chance, and number of variables as input
-        /// and returns a tuple of two new offspring chromosomes.
-        ///
-        /// # Arguments
-        ///
-        /// * `population` - A reference to a `Population` instance.
-        /// * `crossover_chance` - The chance of crossover as a floating-point number between 0 and 1.
-        /// * `mutation_chance` - The chance of mutation as a floating-point number between 0 and 1.
-        /// * `num_variables` - The number of variables in the chromosomes.
-        ///
-        /// # Returns
-        ///
-        /// A tuple containing two `Chromosome` instances representing the new offspring.
-        ///
-        /// # Examples
-        ///
-        /// ```
-        /// let population = Population::new();
-        /// let crossover_chance = 0.8;
-        /// let mutation_chance = 0.1;
-        /// let num_variables = 5;
-        ///
-        /// let (offspring_one, offspring_two) = get_new_offspring(&population, crossover_chance, mutation_chance, num_variables);
-        ///
-        /// assert_eq!(offspring_one.num_variables(), num_variables);
-        /// assert_eq!(offspring_two.num_variables(), num_variables);
-        /// ```
-        fn get_new_offspring(population: &Population, crossover_chance: f64, mutation_chance: f64, num_variables: usize) -> (Chromosome, Chromosome) {
-            let mut offspring_one = population.tournament_selection().clone();
-            let mut offspring_two = population.tournament_selection().clone();
-
-            if rand::thread_rng().gen_bool(crossover_chance) { offspring_one.cross_with(&mut offspring_two, None); }
-            if rand::thread_rng().gen_bool(mutation_chance) { offspring_one.mutate(num_variables); }
-            if rand::thread_rng().gen_bool(mutation_chance) { offspring_two.mutate(num_variables); }
-
-            return (offspring_one, offspring_two);
-        }
-
-        let mut new_population: Vec<Chromosome> = (1..self.population.len())
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn mate(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection, dataset: &Dataset) -> f64 {
+        let mut new_population: Vec<Chromosome> = rng::seeds(self.population.len() / 2)
             .into_par_iter()
-            .step_by(2)
-            .flat_map(|_| {
-                let (offspring_one, offspring_two) = get_new_offspring(self, crossover_chance, mutation_chance, num_variables);
+            .flat_map(|seed| {
+                let (offspring_one, offspring_two) = rng::with_seed(seed, || get_new_offspring(self, crossover_chance, mutation_chance, selection, num_variables));
                 return vec![offspring_one, offspring_two];
             })
             .collect();
 
+        if self.duplicates != DuplicatePolicy::Allow {
+            suppress_duplicates(&self.population, &mut new_population, self.duplicates, num_variables);
+        }
+        // Only the children are refined, the best was refined when it was bred
+        let scoring = self.scoring();
+        let seeds = rng::seeds(new_population.len());
+        new_population.par_iter_mut().zip(seeds).for_each(|(c, seed)| rng::with_seed(seed, || scoring.refine(c, dataset)));
+        self.evaluations += scoring.evaluations();
+
         // Elitism by adding the best out of the entire population to the new population
         new_population.push(self.best.clone()); // Population best has not been updated yet
 
@@ -99,11 +936,618 @@ impl PopulationTraits for Population {
         return self.best.fitness_value;
     }
 
+    /// Breeds a generation's worth of children a few at a time, each replacing the worst individual.
+    ///
+    /// Unlike `mate`, parents and children coexist, so a good individual only leaves the population
+    /// once enough better children have been bred. With a `temperature`, a child less fit than the
+    /// worst individual only replaces it by simulated-annealing acceptance, see `anneal_accepts`.
+    /// The population must already be evaluated on `dataset`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the dataset.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `selection` - How each parent is chosen.
+    /// * `offspring` - How many children are bred before they are inserted.
+    /// * `dataset` - The rows the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the best individual.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn steady_state(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection, offspring: usize, dataset: &Dataset) -> f64 {
+        let offspring = offspring.clamp(1, self.len());
+        for _ in 0..self.len() / offspring {
+            let mut children: Vec<Chromosome> = (0..offspring.div_ceil(2))
+                .flat_map(|_| {
+                    let (offspring_one, offspring_two) = get_new_offspring(self, crossover_chance, mutation_chance, selection, num_variables);
+                    return [offspring_one, offspring_two];
+                })
+                .take(offspring)
+                .collect();
+            let scoring = self.scoring();
+            let seeds = rng::seeds(children.len());
+            children.par_iter_mut().zip(seeds).for_each(|(c, seed)| rng::with_seed(seed, || scoring.score_offspring(c, dataset)));
+            self.evaluations += scoring.evaluations();
+
+            for child in children {
+                let worst = (0..self.len())
+                    .max_by(|&a, &b| ranking_fitness(&self.population[a]).total_cmp(&ranking_fitness(&self.population[b])))
+                    .unwrap();
+                if self.temperature.is_none_or(|t| anneal_accepts(ranking_fitness(&child), ranking_fitness(&self.population[worst]), t)) {
+                    self.population[worst] = child;
+                }
+            }
+            self.find_best_min();
+        }
+        return self.best.fitness_value;
+    }
+
+    /// Produces the next generation with a (μ+λ) scheme, where μ is the size of the population.
+    ///
+    /// `lambda` children are bred from the current population and the best μ of parents and children
+    /// survive, so the best individual is never lost. The population must already be evaluated on `dataset`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the dataset.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `selection` - How each parent is chosen.
+    /// * `lambda` - How many children are bred.
+    /// * `dataset` - The rows the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the best individual.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn mu_plus_lambda(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection, lambda: usize, dataset: &Dataset) -> f64 {
+        let mu = self.len();
+        let mut children: Vec<Chromosome> = rng::seeds(lambda.div_ceil(2))
+            .into_par_iter()
+            .flat_map_iter(|seed| {
+                let (offspring_one, offspring_two) = rng::with_seed(seed, || get_new_offspring(self, crossover_chance, mutation_chance, selection, num_variables));
+                return [offspring_one, offspring_two];
+            })
+            .collect();
+        children.truncate(lambda);
+        let scoring = self.scoring();
+        let seeds = rng::seeds(children.len());
+        children.par_iter_mut().zip(seeds).for_each(|(c, seed)| rng::with_seed(seed, || scoring.score_offspring(c, dataset)));
+        self.evaluations += scoring.evaluations();
+
+        self.population.append(&mut children);
+        self.population.sort_by(|a, b| ranking_fitness(a).total_cmp(&ranking_fitness(b)));
+        self.population.truncate(mu);
+        self.find_best_min();
+        return self.best.fitness_value;
+    }
+
+    /// Produces the next generation with the (1+λ) evolution strategy usual for Cartesian GP.
+    ///
+    /// The best individual is the only parent. Each of the `lambda` children is a copy of it with
+    /// a single active mutation, and a child that is at least as fit as the parent becomes the new
+    /// parent. Accepting equally fit children lets the genome drift through neutral changes.
+    /// Crossover is not used. The population must already be evaluated on `dataset`. The children
+    /// are bred into `offspring`, and a child that becomes the parent also replaces the worst
+    /// individual, so that the population keeps its size and holds the recent parents.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the dataset.
+    /// * `lambda` - How many mutants are bred.
+    /// * `dataset` - The rows the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the best individual.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn one_plus_lambda(&mut self, num_variables: usize, lambda: usize, dataset: &Dataset) -> f64 {
+        let parent = self.best.clone();
+        let scoring = self.scoring();
+        self.offspring.clear();
+        self.offspring.par_extend(rng::seeds(lambda)
+            .into_par_iter()
+            .map(|seed| rng::with_seed(seed, || {
+                let mut child = parent.offspring();
+                child.mutate_active(num_variables);
+                scoring.score_offspring(&mut child, dataset);
+                return child;
+            })));
+        self.evaluations += scoring.evaluations();
+
+        // The last of the best children wins ties, so that neutral drift is not biased towards the first child
+        if let Some(child) = self.offspring.iter().rev().min_by(|a, b| ranking_fitness(a).total_cmp(&ranking_fitness(b))) {
+            if ranking_fitness(child) <= ranking_fitness(&parent) {
+                self.best = child.clone();
+                let worst = (0..self.len()).max_by(|&a, &b| ranking_fitness(&self.population[a]).total_cmp(&ranking_fitness(&self.population[b])));
+                if let Some(worst) = worst {
+                    // The outcome of the child is counted in `offspring`, not again in the population
+                    self.population[worst] = Chromosome { parent_fitness: None, mutation_operator: None, ..child.clone() };
+                }
+            }
+        }
+        return self.best.fitness_value;
+    }
+
+    /// Produces the next generation by deterministic crowding.
+    ///
+    /// The population is shuffled into pairs of parents. Each pair is crossed over and mutated into
+    /// two children, which are checked against the limits and duplicate policy like the children of
+    /// `mate`. Each child is matched with the parent it is closest to, and it replaces that parent
+    /// only if its fitness is lower. Since children compete with similar
+    /// parents instead of the whole population, separate niches survive. The population must
+    /// already be evaluated on `dataset`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the dataset.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `dataset` - The rows the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the best individual.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn crowding(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64 {
+        let metric = self.distance;
+        self.population.shuffle(&mut rng::rng());
+        // One seed per full pair, so an odd chromosome out is left alone
+        let mut children: Vec<Chromosome> = rng::seeds(self.len() / 2)
+            .into_par_iter()
+            .enumerate()
+            .flat_map(|(i, seed)| {
+                let (child_one, child_two) = rng::with_seed(seed, || breed(self, &self.population[2 * i], &self.population[2 * i + 1], crossover_chance, mutation_chance, num_variables));
+                return vec![child_one, child_two];
+            })
+            .collect();
+        if self.duplicates != DuplicatePolicy::Allow {
+            suppress_duplicates(&self.population, &mut children, self.duplicates, num_variables);
+        }
+        let scoring = self.scoring();
+        let seeds = rng::seeds(children.len());
+        children.par_iter_mut().zip(seeds).for_each(|(child, seed)| rng::with_seed(seed, || scoring.score_offspring(child, dataset)));
+
+        self.population.par_chunks_mut(2).zip(children.par_chunks_mut(2)).for_each(|(pair, children)| {
+            let straight = pair[0].distance(&children[0], metric) + pair[1].distance(&children[1], metric);
+            let crossed = pair[0].distance(&children[1], metric) + pair[1].distance(&children[0], metric);
+            if crossed < straight {
+                children.swap(0, 1);
+            }
+            for (parent, child) in pair.iter_mut().zip(children.iter_mut()) {
+                if ranking_fitness(child) < ranking_fitness(parent) {
+                    std::mem::swap(parent, child);
+                }
+            }
+        });
+        self.evaluations += scoring.evaluations();
+        self.find_best_min();
+        return self.best.fitness_value;
+    }
+
+    /// Produces the next generation with the Age-Layered Population Structure (ALPS).
+    ///
+    /// The population is split into `layers` equal layers. Layer `k` only holds chromosomes up to
+    /// `age_gap * (k + 1)^2` generations old, except for the last layer which has no limit. Every
+    /// generation all chromosomes age by one, and each layer breeds as many children as it holds
+    /// from parents in itself and the layer below. Children are as old as their oldest parent. The
+    /// fittest of the layer and its children that are young enough stay; the ones that are too old
+    /// replace the worst chromosome of the next layer if they are fitter. Every `age_gap` generations
+    /// the first layer is replaced with random chromosomes, so fresh genetic material keeps entering.
+    ///
+    /// Parents are chosen by tournament, using the tournament size of `selection` or 2 for other selection schemes.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the dataset.
+    /// * `num_genes` - The number of genes of new random chromosomes.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `selection` - Decides the tournament size.
+    /// * `layers` - How many age layers the population is split into.
+    /// * `age_gap` - How often the first layer is replaced, and the unit of the age limits.
+    /// * `generation` - The current generation.
+    /// * `dataset` - The rows the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the best individual.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn alps(&mut self, num_variables: usize, num_genes: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection,
+            layers: usize, age_gap: usize, generation: usize, dataset: &Dataset) -> f64 {
+        let scoring = self.scoring();
+        let operators = &self.operators;
+        let n = self.len();
+        let layers = layers.clamp(1, n);
+        let bounds: Vec<usize> = (0..=layers).map(|k| k * n / layers).collect();
+        let max_age = |k: usize| if k + 1 == layers { usize::MAX } else { age_gap * (k + 1) * (k + 1) };
+        let tournament_size = match selection {
+            Selection::Tournament(size) => size,
+            _ => 2,
+        };
+        let layout = Arc::clone(&self.layout);
+        let new_random = || {
+            let mut chromosome = Chromosome::new_random(num_genes, num_variables, &layout);
+            let _ = scoring.score(&mut chromosome, dataset);
+            return chromosome;
+        };
+
+        self.population.iter_mut().for_each(|c| c.age += 1);
+        let mut current: Vec<Vec<Chromosome>> = (0..layers).map(|k| self.population[bounds[k]..bounds[k + 1]].to_vec()).collect();
+        if generation.is_multiple_of(age_gap) {
+            current[0] = (0..current[0].len()).map(|_| new_random()).collect();
+        }
+
+        // From the oldest layer down, so chromosomes promoted into a layer are not bred again this generation
+        for k in (0..layers).rev() {
+            let size = bounds[k + 1] - bounds[k];
+            let pool: Vec<&Chromosome> = match k {
+                0 => current[0].iter().collect(),
+                _ => current[k].iter().chain(&current[k - 1]).collect(),
+            };
+            let mut children: Vec<Chromosome> = rng::seeds(size.div_ceil(2))
+                .into_par_iter()
+                .flat_map_iter(|seed| rng::with_seed(seed, || {
+                    let mut offspring_one = pool_tournament(&pool, tournament_size).offspring();
+                    let mut offspring_two = pool_tournament(&pool, tournament_size).offspring();
+                    let age = offspring_one.age.max(offspring_two.age);
+                    vary(&mut offspring_one, &mut offspring_two, crossover_chance, mutation_chance, operators, num_variables);
+                    for offspring in [&mut offspring_one, &mut offspring_two] {
+                        offspring.age = age;
+                        scoring.score_offspring(offspring, dataset);
+                    }
+                    return [offspring_one, offspring_two];
+                }))
+                .collect();
+            children.truncate(size);
+
+            let mut candidates = std::mem::take(&mut current[k]);
+            candidates.append(&mut children);
+            let (mut fitting, too_old): (Vec<Chromosome>, Vec<Chromosome>) = candidates.into_iter().partition(|c| c.age <= max_age(k));
+            for chromosome in too_old {
+                let above = &mut current[k + 1];
+                let worst = (0..above.len())
+                    .max_by(|&a, &b| ranking_fitness(&above[a]).total_cmp(&ranking_fitness(&above[b])))
+                    .unwrap();
+                if ranking_fitness(&chromosome) < ranking_fitness(&above[worst]) {
+                    above[worst] = chromosome;
+                }
+            }
+            fitting.sort_by(|a, b| ranking_fitness(a).total_cmp(&ranking_fitness(b)));
+            fitting.truncate(size);
+            while fitting.len() < size {
+                fitting.push(new_random());
+            }
+            current[k] = fitting;
+        }
+        self.evaluations += scoring.evaluations();
+
+        self.population = current.concat();
+        self.find_best_min();
+        return self.best.fitness_value;
+    }
+
+    /// Assigns every chromosome to the first species whose representative is within `threshold`
+    /// distance, founding a new species when there is none. Species that are left
+    /// without members die out, and the others pick a random member as their next representative.
+    fn speciate(&mut self, threshold: f64) {
+        let metric = self.distance;
+        self.species.iter_mut().for_each(|species| species.members.clear());
+        for (i, chromosome) in self.population.iter().enumerate() {
+            match self.species.iter_mut().find(|species| species.representative.distance(chromosome, metric) < threshold) {
+                Some(species) => species.members.push(i),
+                None => self.species.push(Species { representative: chromosome.clone(), age: 0, members: vec![i] }),
+            }
+        }
+        self.species.retain(|species| !species.members.is_empty());
+        let mut rng = rng::rng();
+        for species in &mut self.species {
+            species.representative = self.population[*species.members.choose(&mut rng).unwrap()].clone();
+        }
+    }
+
+    /// Produces the next generation with NEAT-style speciation.
+    ///
+    /// The population is first divided into species with `speciate`. Each species is then given a
+    /// share of the offspring in proportion to the mean of `1 / (1 + fitness)` over its members,
+    /// except that species younger than `protection` generations get at least as many offspring as
+    /// they have members, taken from the species with the most offspring. Parents are chosen by
+    /// tournament among the members of one species only, using the tournament size of `selection`
+    /// or 2 for other selection schemes. The best chromosome is always kept. The population must
+    /// already be evaluated on `dataset`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the dataset.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `selection` - Decides the tournament size.
+    /// * `threshold` - The distance below which chromosomes belong to the same species.
+    /// * `protection` - How many generations new species are protected for.
+    /// * `dataset` - The rows the new population is evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the best individual.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn speciation(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, selection: Selection,
+                  threshold: f64, protection: usize, dataset: &Dataset) -> f64 {
+        self.speciate(threshold);
+        tracing::debug!(species = self.species.len(), "speciated");
+        let tournament_size = match selection {
+            Selection::Tournament(size) => size,
+            _ => 2,
+        };
+
+        let scores: Vec<f64> = self.species.iter()
+            .map(|species| {
+                let goodness = |i: &usize| match self.population[*i].fitness_value < f64::MAX {
+                    true => 1.0 / (1.0 + self.population[*i].fitness_value),
+                    false => 0.0,
+                };
+                return species.members.iter().map(goodness).sum::<f64>() / species.members.len() as f64;
+            })
+            .collect();
+        let offspring = self.len() - 1;
+        let mut allocation = match scores.iter().sum::<f64>() > 0.0 {
+            true => allocate(&scores, offspring),
+            false => allocate(&self.species.iter().map(|species| species.members.len() as f64).collect::<Vec<f64>>(), offspring),
+        };
+        for (i, species) in self.species.iter().enumerate() {
+            while species.age < protection && allocation[i] < species.members.len() {
+                let donor = (0..allocation.len())
+                    .filter(|&j| self.species[j].age >= protection)
+                    .max_by_key(|&j| allocation[j])
+                    .filter(|&j| allocation[j] > 0);
+                match donor {
+                    Some(j) => {
+                        allocation[j] -= 1;
+                        allocation[i] += 1;
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        let mut new_population: Vec<Chromosome> = self.species.par_iter()
+            .zip(allocation)
+            .zip(rng::seeds(self.species.len()))
+            .flat_map_iter(|((species, count), seed)| rng::with_seed(seed, || {
+                let pool: Vec<&Chromosome> = species.members.iter().map(|&i| &self.population[i]).collect();
+                let mut children = Vec::with_capacity(count + 1);
+                while children.len() < count {
+                    let mut offspring_one = pool_tournament(&pool, tournament_size).offspring();
+                    let mut offspring_two = pool_tournament(&pool, tournament_size).offspring();
+                    vary(&mut offspring_one, &mut offspring_two, crossover_chance, mutation_chance, &self.operators, num_variables);
+                    children.push(offspring_one);
+                    children.push(offspring_two);
+                }
+                children.truncate(count);
+                return children;
+            }))
+            .collect();
+        self.species.iter_mut().for_each(|species| species.age += 1);
+        // Only the children are refined, the best was refined when it was bred
+        let scoring = self.scoring();
+        let seeds = rng::seeds(new_population.len());
+        new_population.par_iter_mut().zip(seeds).for_each(|(c, seed)| rng::with_seed(seed, || scoring.refine(c, dataset)));
+        self.evaluations += scoring.evaluations();
+
+        // Elitism by adding the best out of the entire population to the new population
+        new_population.push(self.best.clone());
+        self.population = new_population;
+        self.evaluate(dataset);
+        return self.best.fitness_value;
+    }
+
+    /// Produces the next generation with NSGA-II, minimizing both the error and the number of active genes.
+    ///
+    /// Parents are chosen by binary tournaments that prefer the lower front and then the larger
+    /// crowding distance. As many children as the population holds are bred, and the parents and
+    /// children are sorted into non-dominated fronts together. Whole fronts survive while they fit,
+    /// and the last front that does not fit is cut by crowding distance. The chromosome with the
+    /// lowest error always survives since it is an extreme of the first front. The population must
+    /// already be evaluated on `dataset`.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables in the dataset.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `dataset` - The rows the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the individual with the lowest error.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn nsga2(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64 {
+        let n = self.len();
+        let objectives: Vec<[f64; 2]> = self.population.iter().map(moea::objectives).collect();
+        let mut rank = vec![0; n];
+        let mut crowding = vec![0.0; n];
+        for (front_rank, front) in moea::non_dominated_sort(&objectives).iter().enumerate() {
+            for (&i, distance) in front.iter().zip(moea::crowding_distance(&objectives, front)) {
+                rank[i] = front_rank;
+                crowding[i] = distance;
+            }
+        }
+        let crowded_tournament = || {
+            let (a, b) = (rng::rng().gen_range(0..n), rng::rng().gen_range(0..n));
+            let a_wins = rank[a] < rank[b] || (rank[a] == rank[b] && crowding[a] > crowding[b]);
+            return &self.population[if a_wins { a } else { b }];
+        };
+
+        let scoring = self.scoring();
+        let operators = &self.operators;
+        let mut children: Vec<Chromosome> = rng::seeds(n.div_ceil(2))
+            .into_par_iter()
+            .flat_map_iter(|seed| rng::with_seed(seed, || {
+                let mut offspring_one = crowded_tournament().offspring();
+                let mut offspring_two = crowded_tournament().offspring();
+                vary(&mut offspring_one, &mut offspring_two, crossover_chance, mutation_chance, operators, num_variables);
+                for offspring in [&mut offspring_one, &mut offspring_two] {
+                    scoring.score_offspring(offspring, dataset);
+                }
+                return [offspring_one, offspring_two];
+            }))
+            .collect();
+        self.evaluations += scoring.evaluations();
+        children.truncate(n);
+
+        let mut combined = std::mem::take(&mut self.population);
+        combined.append(&mut children);
+        let objectives: Vec<[f64; 2]> = combined.iter().map(moea::objectives).collect();
+        let mut survivors: Vec<usize> = Vec::with_capacity(n);
+        for front in moea::non_dominated_sort(&objectives) {
+            if survivors.len() + front.len() <= n {
+                survivors.extend(front);
+                continue;
+            }
+            let distance = moea::crowding_distance(&objectives, &front);
+            let mut order: Vec<usize> = (0..front.len()).collect();
+            order.sort_by(|&a, &b| distance[b].total_cmp(&distance[a]));
+            survivors.extend(order.iter().take(n - survivors.len()).map(|&k| front[k]));
+            break;
+        }
+        let mut combined: Vec<Option<Chromosome>> = combined.into_iter().map(Some).collect();
+        self.population = survivors.iter().map(|&i| combined[i].take().unwrap()).collect();
+        self.find_best_min();
+        return self.best.fitness_value;
+    }
+
+    /// Creates the next generation with SPEA2.
+    ///
+    /// The archive is rescored on `dataset` and merged with the population. The SPEA2 fitness of
+    /// the merged chromosomes picks the new archive, and binary tournaments on that fitness within
+    /// the archive pick the parents of a whole new population of children.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `archive_size` - How many chromosomes the archive keeps.
+    /// * `dataset` - The rows the archive and the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the individual with the lowest error.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn spea2(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, archive_size: usize, dataset: &Dataset) -> f64 {
+        let n = self.len();
+        let scoring = self.scoring();
+        let operators = &self.operators;
+        self.archive.par_iter_mut().for_each(|chromosome| { let _ = scoring.score(chromosome, dataset); });
+
+        let mut combined = std::mem::take(&mut self.archive);
+        combined.extend(self.population.iter().cloned());
+        let objectives: Vec<[f64; 2]> = combined.iter().map(moea::objectives).collect();
+        let fitness = moea::spea2_fitness(&objectives);
+        let selected = moea::spea2_select(&objectives, &fitness, archive_size);
+        let fitness: Vec<f64> = selected.iter().map(|&i| fitness[i]).collect();
+        let mut combined: Vec<Option<Chromosome>> = combined.into_iter().map(Some).collect();
+        self.archive = selected.iter().map(|&i| combined[i].take().unwrap()).collect();
+
+        let archive = &self.archive;
+        let binary_tournament = || {
+            let (a, b) = (rng::rng().gen_range(0..archive.len()), rng::rng().gen_range(0..archive.len()));
+            return &archive[if fitness[a] < fitness[b] { a } else { b }];
+        };
+        let mut children: Vec<Chromosome> = rng::seeds(n.div_ceil(2))
+            .into_par_iter()
+            .flat_map_iter(|seed| rng::with_seed(seed, || {
+                let mut offspring_one = binary_tournament().offspring();
+                let mut offspring_two = binary_tournament().offspring();
+                vary(&mut offspring_one, &mut offspring_two, crossover_chance, mutation_chance, operators, num_variables);
+                for offspring in [&mut offspring_one, &mut offspring_two] {
+                    scoring.score_offspring(offspring, dataset);
+                }
+                return [offspring_one, offspring_two];
+            }))
+            .collect();
+        self.evaluations += scoring.evaluations();
+        children.truncate(n);
+        self.population = children;
+        self.find_best_min();
+        return self.best.fitness_value;
+    }
+
+    /// Returns the non-dominated chromosomes of the population (and of the SPEA2 archive) in terms
+    /// of error and number of active genes, one per distinct expression, from the simplest to the most complex.
+    fn pareto_front(&self) -> Vec<&Chromosome> {
+        let candidates: Vec<&Chromosome> = self.population.iter().chain(&self.archive).collect();
+        let objectives: Vec<[f64; 2]> = candidates.iter().map(|c| moea::objectives(c)).collect();
+        let mut front: Vec<&Chromosome> = moea::non_dominated_sort(&objectives)
+            .first()
+            .map_or(Vec::new(), |front| front.iter().map(|&i| candidates[i]).collect());
+        front.sort_by(|a, b| moea::objectives(a)[1].total_cmp(&moea::objectives(b)[1]));
+        let mut seen = HashSet::new();
+        front.retain(|c| seen.insert(c.function_string()));
+        return front;
+    }
+
+    /// Creates the next generation with MAP-Elites.
+    ///
+    /// The elites are rescored on `dataset` and the evaluated population is offered to the archive.
+    /// The new population is bred from parents drawn uniformly from the elites, so every cell gets
+    /// the same chance to improve, whatever its fitness compared to the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_variables` - The number of variables.
+    /// * `crossover_chance` - The probability of crossover.
+    /// * `mutation_chance` - The probability of mutation.
+    /// * `dataset` - The rows the elites and the children are evaluated on.
+    ///
+    /// # Returns
+    ///
+    /// The fitness value of the individual with the lowest error.
+    #[tracing::instrument(level = "debug", skip_all)]
+    fn map_elites(&mut self, num_variables: usize, crossover_chance: f64, mutation_chance: f64, dataset: &Dataset) -> f64 {
+        let n = self.len();
+        let scoring = self.scoring();
+        let operators = &self.operators;
+        self.elites.members_mut().for_each(|chromosome| { let _ = scoring.score(chromosome, dataset); });
+        for chromosome in &self.population {
+            self.elites.offer(chromosome);
+        }
+        if self.elites.is_empty() {
+            self.evaluations += scoring.evaluations();
+            // Nothing valid has been found yet, so the population is bred as usual
+            return self.mate(num_variables, crossover_chance, mutation_chance, Selection::Tournament(2), dataset);
+        }
+
+        let elites: Vec<&Chromosome> = self.elites.members().collect();
+        let random_elite = || elites[rng::rng().gen_range(0..elites.len())];
+        let children: Vec<Chromosome> = rng::seeds(n.div_ceil(2))
+            .into_par_iter()
+            .flat_map_iter(|seed| rng::with_seed(seed, || {
+                let mut offspring_one = random_elite().offspring();
+                let mut offspring_two = random_elite().offspring();
+                vary(&mut offspring_one, &mut offspring_two, crossover_chance, mutation_chance, operators, num_variables);
+                for offspring in [&mut offspring_one, &mut offspring_two] {
+                    scoring.score_offspring(offspring, dataset);
+                }
+                return [offspring_one, offspring_two];
+            }))
+            .collect();
+        self.evaluations += scoring.evaluations();
+        self.population = children;
+        self.population.truncate(n);
+        for chromosome in &self.population {
+            self.elites.offer(chromosome);
+        }
+        self.find_best_min();
+        return self.best.fitness_value;
+    }
+
     /// Returns the chromosome with the minimum fitness value in the given `Population`.
     ///
     /// # Example
     ///
-    /// ```
+    /// ```ignore
     /// let population: Vec<Chromosome> = vec![...];
     /// let best_chromosome = population.find_best_min();
     /// ```
@@ -119,34 +1563,124 @@ impl PopulationTraits for Population {
         }
     }
 
-    /// Performs tournament selection with k = 2 for the population
+    /// Chooses a parent with the given selection scheme.
+    fn select(&self, selection: Selection) -> &Chromosome {
+        return match selection {
+            Selection::Tournament(size) => self.tournament_selection(size),
+            Selection::Roulette => self.roulette_selection(),
+            Selection::Lexicase => self.lexicase_selection(false),
+            Selection::EpsilonLexicase => self.lexicase_selection(true),
+            Selection::LinearRank { pressure } => {
+                let n = self.len();
+                let mut remaining = rng::rng().gen_range(0.0..n as f64);
+                let rank = (0..n).find(|&rank| { remaining -= linear_rank_weight(rank, n, pressure); remaining < 0.0 }).unwrap_or(n - 1);
+                self.rank_selection(rank)
+            }
+            Selection::ExponentialRank { base } => self.rank_selection(exponential_rank(rng::rng().gen(), self.len(), base)),
+        };
+    }
+
+    /// Performs tournament selection with k = `size` for the population
+    ///
+    /// Randomly selects `size` chromosomes (with replacement) and returns the one with the minimum
+    /// fitness value. If several chromosomes share the minimum fitness value, the last one drawn is returned.
+    ///
+    /// # Arguments
     ///
-    /// Randomly selects two chromosomes and returns the one with the minimum fitness value.
-    /// If both chromosomes have the same fitness value, the first chromosome is returned.
+    /// * `size` - How many chromosomes compete. Larger tournaments increase the selection pressure.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// use genetic_algorithm::Population;
     ///
     /// let population = Population::new();
-    /// let best_chromosome = population.best_min_random();
+    /// let best_chromosome = population.tournament_selection(2);
     /// println!("Best chromosome: {:?}", best_chromosome);
     /// ```
-    fn tournament_selection(&self) -> &Chromosome {
-        let c1 = self.get_random_chromosome();
-        let c2 = self.get_random_chromosome();
-        return if c1.fitness_value < c2.fitness_value { c1 } else { c2 };
+    fn tournament_selection(&self, size: usize) -> &Chromosome {
+        let mut winner = self.get_random_chromosome();
+        for _ in 1..size {
+            let challenger = self.get_random_chromosome();
+            winner = if winner.shared_fitness() < challenger.shared_fitness() { winner } else { challenger };
+        }
+        return winner;
+    }
+
+    /// Performs fitness-proportionate (roulette-wheel) selection for the population.
+    ///
+    /// Since fitness is minimized, each chromosome is weighted by `1 / (1 + fitness)`, which is
+    /// largest for a perfect fit of 0, see `roulette_weights`. Invalid chromosomes (NaN or
+    /// `f64::MAX` fitness) have no chance of being chosen. If every chromosome is invalid a random
+    /// one is returned.
+    fn roulette_selection(&self) -> &Chromosome {
+        let weights = roulette_weights(&self.population);
+        let total: f64 = weights.iter().sum();
+        if !(total > 0.0 && total.is_finite()) {
+            return self.get_random_chromosome();
+        }
+        let mut remaining = rng::rng().gen_range(0.0..total);
+        for (chromosome, weight) in self.population.iter().zip(&weights) {
+            remaining -= weight;
+            if remaining < 0.0 {
+                return chromosome;
+            }
+        }
+        // Rounding can leave a little weight over, which belongs to the last valid chromosome
+        let last = weights.iter().rposition(|&weight| weight > 0.0).unwrap();
+        return &self.population[last];
+    }
+
+    /// Returns the chromosome with the given rank, where rank 0 is the fittest.
+    ///
+    /// Rank based selection only depends on the order of the fitness values, so it is not thrown off
+    /// by values spanning many orders of magnitude or by `f64::MAX` sentinels. NaN ranks last.
+    fn rank_selection(&self, rank: usize) -> &Chromosome {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.select_nth_unstable_by(rank, |&a, &b| selection_fitness(&self.population[a]).total_cmp(&selection_fitness(&self.population[b])));
+        return &self.population[order[rank]];
+    }
+
+    /// Performs lexicase selection for the population.
+    ///
+    /// The rows are visited in a random order and at each row only the chromosomes with the lowest
+    /// error on it survive, until one chromosome is left or the rows run out, in which case a random
+    /// survivor is returned. NaN errors count as infinitely bad. The chromosomes must have been
+    /// evaluated with `keep_case_errors` set.
+    ///
+    /// # Arguments
+    ///
+    /// * `epsilon` - Whether chromosomes within `case_epsilons` of the lowest error also survive (epsilon-lexicase).
+    fn lexicase_selection(&self, epsilon: bool) -> &Chromosome {
+        let mut rng = rng::rng();
+        let mut candidates: Vec<&Chromosome> = self.population.iter().collect();
+        let num_cases = candidates.iter().map(|c| c.case_errors.len()).min().unwrap_or(0);
+        let mut cases: Vec<usize> = (0..num_cases).collect();
+        cases.shuffle(&mut rng);
+
+        let error = |c: &Chromosome, case: usize| if c.case_errors[case].is_nan() { f64::INFINITY } else { c.case_errors[case] };
+        for case in cases {
+            if candidates.len() == 1 {
+                break;
+            }
+            let best = candidates.iter().map(|c| error(c, case)).fold(f64::INFINITY, f64::min);
+            let threshold = match (epsilon, &self.case_epsilons) {
+                (true, Some(epsilons)) if case < epsilons.len() => best + epsilons[case],
+                _ => best,
+            };
+            candidates.retain(|c| error(c, case) <= threshold);
+        }
+        return candidates[rng.gen_range(0..candidates.len())];
     }
 
     /// Returns a reference to a randomly selected `Chromosome` from the `self` vector.
     fn get_random_chromosome(&self) -> &Chromosome {
-        return &self.population[rand::thread_rng().gen_range(0..self.len())];
+        return &self.population[rng::rng().gen_range(0..self.len())];
     }
 
     fn all_accessed(&mut self) {
         let mut count = 0;
-        for mut c in &mut self.population {
+        for c in &mut self.population {
             if !c.accessed {
                 count += 1;
             }
@@ -155,47 +1689,217 @@ impl PopulationTraits for Population {
         assert_eq!(count, 0, "Not all chromosomes in this population were evaluated");
     }
 
-    fn initialize(size: usize, num_genes: usize, dataset: &Dataset) -> Population {
+    #[tracing::instrument(level = "debug", skip(dataset))]
+    fn initialize(size: usize, num_genes: usize, dataset: &Dataset, layout: Layout) -> Population {
+        let layout = Arc::new(layout);
         let mut population = Population {
-            population: (0..size).into_iter().map(|_| Chromosome::new_x(num_genes, dataset[0].len() - 2)).collect(),
+            population: (0..size).map(|_| Chromosome::new_random(num_genes, dataset.get_num_variables(), &layout)).collect(),
             best: Chromosome::new(),
+            evaluations: 0,
+            keep_case_errors: false,
+            case_epsilons: None,
+            sharing_radius: None,
+            species: Vec::new(),
+            probe: None,
+            distance: Distance::Genotypic,
+            novelty: None,
+            archive: Vec::new(),
+            elites: EliteArchive::new(),
+            duplicates: DuplicatePolicy::Allow,
+            limits: ComplexityLimits::default(),
+            units: None,
+            bounds: None,
+            operators: OperatorMix::default(),
+            semantics: false,
+            layout,
+            local_learning: None,
+            memetic: None,
+            temperature: None,
+            offspring: Vec::new(),
         };
         population.find_best_min();
         return population;
     }
 
-    /// Evaluates the fitness of each chromosome in the population using the mean squared error (MSE)
-    /// as the fitness function.
+    /// Replaces all but the fittest chromosomes with newly generated random ones.
     ///
+    /// Used to escape premature convergence. The best chromosome found so far is kept in `best`
+    /// either way, so it survives through elitism even when `keep` is 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - How many of the fittest chromosomes are preserved.
+    /// * `num_genes` - The number of genes of the new chromosomes.
+    /// * `num_variables` - The number of variables in the dataset.
+    #[tracing::instrument(level = "debug", skip(self))]
+    fn restart(&mut self, keep: usize, num_genes: usize, num_variables: usize) {
+        self.population.sort_by(|a, b| a.fitness_value.total_cmp(&b.fitness_value));
+        let keep = keep.min(self.len());
+        for chromosome in &mut self.population[keep..] {
+            *chromosome = Chromosome::new_random(num_genes, num_variables, &self.layout);
+        }
+    }
+
+    /// Evaluates the fitness of each chromosome in the population as its error under the metric of
+    /// the layout, the mean squared error (MSE) by default.
+    ///
+    /// The current best chromosome is rescored against the same dataset so that it stays comparable
+    /// to the rest of the population when evaluating on mini-batches.
     ///
     /// # Arguments
     ///
     /// * `dataset` - A reference to a `Dataset` containing the data to evaluate the chromosomes
-    /// against.
+    ///   against.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// use genetic_algorithm::Population;
     ///
     /// let mut population = Population::new();
-    /// let dataset = vec![
-    ///     vec![1.0, 2.0, 3.0],
-    ///     vec![4.0, 5.0, 6.0],
-    ///     vec![7.0, 8.0, 9.0]
-    /// ];
+    /// let dataset = read_csv(open_dataset("data.csv"), &DatasetOptions::default());
     /// population.evaluate(&dataset);
     /// ```
     ///
     /// # Returns
     ///
     /// None.
+    #[tracing::instrument(level = "debug", skip_all, fields(rows = dataset.len()))]
     fn evaluate(&mut self, dataset: &Dataset) {
-        // let min = self.population.par_iter_mut().map(|mut i| { let _ = i.evaluate_fitness_mse(dataset); }).min();
-        self.population.par_iter_mut().for_each(|mut i| { let _ = i.evaluate_fitness_mse(dataset); });
+        // The best may have been scored on a different batch of rows, so it is rescored first
+        let scoring = self.scoring();
+        if !self.best.genes.is_empty() {
+            scoring.score(&mut self.best, dataset);
+        }
+        // let min = self.population.par_iter_mut().map(|mut i| { let _ = i.evaluate_fitness_error(dataset); }).min();
+        self.population.par_iter_mut().for_each(|i| { let _ = scoring.score(i, dataset); });
+        self.evaluations += scoring.evaluations();
+        if self.case_epsilons.is_some() {
+            self.case_epsilons = Some(median_absolute_deviations(&self.population));
+        }
+        if let Some(radius) = self.sharing_radius {
+            self.share_fitness(radius);
+        }
+        if self.novelty.is_some() {
+            self.score_novelty();
+        }
         self.find_best_min();
     }
 
+    /// Sets the niche count of every chromosome for fitness sharing.
+    ///
+    /// Each chromosome within `radius` distance of another adds `1 - distance / radius`
+    /// to its niche count, including itself. Parent selection then prefers chromosomes that are
+    /// both fit and unlike the rest of the population. This is quadratic in the population size.
+    ///
+    /// # Arguments
+    ///
+    /// * `radius` - The sharing radius, between 0 and 1.
+    fn share_fitness(&mut self, radius: f64) {
+        let metric = self.distance;
+        let population = &self.population;
+        let niche_counts: Vec<f64> = population.par_iter()
+            .map(|chromosome| {
+                return population.iter()
+                    .map(|other| chromosome.distance(other, metric))
+                    .filter(|&distance| distance < radius)
+                    .map(|distance| 1.0 - distance / radius)
+                    .sum();
+            })
+            .collect();
+        for (chromosome, niche_count) in self.population.iter_mut().zip(niche_counts) {
+            chromosome.niche_count = niche_count;
+        }
+    }
+
+    /// Sets the novelty score of every chromosome for novelty search.
+    ///
+    /// The novelty of a chromosome is the mean behavioral distance to its nearest neighbours among
+    /// the rest of the population and the archive; invalid chromosomes are not novel at all. The
+    /// score blends the rank of the fitness and the rank of the novelty, each scaled to between 0
+    /// and 1, by the novelty weight. The most novel chromosome is then added to the archive.
+    fn score_novelty(&mut self) {
+        let Some(novelty) = &mut self.novelty else {
+            return;
+        };
+        let population = &self.population;
+        let archive = &novelty.archive;
+        let values: Vec<f64> = (0..population.len())
+            .into_par_iter()
+            .map(|i| {
+                if population[i].fitness_value.is_nan() || population[i].fitness_value == f64::MAX {
+                    return 0.0;
+                }
+                let others = population.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, c)| c.behavior.as_slice());
+                return sparseness(&population[i].behavior, others.chain(archive.iter().map(|b| b.as_slice())), novelty.neighbours);
+            })
+            .collect();
+
+        let n = population.len();
+        let scale = n.saturating_sub(1).max(1) as f64;
+        let mut by_fitness: Vec<usize> = (0..n).collect();
+        by_fitness.sort_by(|&a, &b| ranking_fitness(&population[a]).total_cmp(&ranking_fitness(&population[b])));
+        let mut by_novelty: Vec<usize> = (0..n).collect();
+        by_novelty.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+        let mut scores = vec![0.0; n];
+        for (rank, &i) in by_fitness.iter().enumerate() {
+            scores[i] += (1.0 - novelty.weight) * rank as f64 / scale;
+        }
+        for (rank, &i) in by_novelty.iter().enumerate() {
+            scores[i] += novelty.weight * rank as f64 / scale;
+        }
+        if let Some(&most_novel) = by_novelty.first().filter(|&&i| values[i] > 0.0) {
+            novelty.archive.push(population[most_novel].behavior.clone());
+        }
+        for (chromosome, score) in self.population.iter_mut().zip(scores) {
+            chromosome.novelty_score = Some(score);
+        }
+    }
+
+    /// Computes the fitness, size and diversity statistics of the current population.
+    fn statistics(&self) -> PopulationStatistics {
+        let mut fitness: Vec<f64> = self.population.iter()
+            .map(|c| c.fitness_value)
+            .filter(|&f| f < f64::MAX)
+            .collect();
+        fitness.sort_by(f64::total_cmp);
+        let invalid = self.len() - fitness.len();
+        let mean_size = self.population.iter().map(|c| c.active_genes().len()).sum::<usize>() as f64 / self.len() as f64;
+        let mean_depth = self.population.iter().map(Chromosome::depth).sum::<usize>() as f64 / self.len() as f64;
+        let unique = self.population.iter().map(Chromosome::structure_hash).collect::<HashSet<u64>>().len();
+        let phenotypic_diversity = phenotypic_diversity(&self.population);
+        if fitness.is_empty() {
+            return PopulationStatistics { min: f64::MAX, mean: f64::MAX, median: f64::MAX, std: 0.0, mean_size, mean_depth, invalid, unique, phenotypic_diversity };
+        }
+
+        let n = fitness.len() as f64;
+        let mean = fitness.iter().sum::<f64>() / n;
+        let median = match fitness.len() % 2 {
+            0 => (fitness[fitness.len() / 2 - 1] + fitness[fitness.len() / 2]) / 2.0,
+            _ => fitness[fitness.len() / 2],
+        };
+        let std = (fitness.iter().map(|f| (f - mean).powi(2)).sum::<f64>() / n).sqrt();
+        return PopulationStatistics { min: fitness[0], mean, median, std, mean_size, mean_depth, invalid, unique, phenotypic_diversity };
+    }
+
+    /// Returns whether each new child in the population is fitter than its fitter parent and which
+    /// operator mutated it, and marks the children as counted.
+    fn offspring_outcomes(&mut self) -> Vec<OffspringOutcome> {
+        let mut outcomes = Vec::new();
+        for chromosome in self.population.iter_mut().chain(&mut self.offspring) {
+            if let Some(parent_fitness) = chromosome.parent_fitness.take() {
+                outcomes.push(OffspringOutcome {
+                    mutation: chromosome.mutation_operator.take(),
+                    improved: chromosome.fitness_value < parent_fitness,
+                });
+            }
+        }
+        // The best is a copy of a population member and must not be counted again when it is carried over
+        self.best.parent_fitness = None;
+        self.best.mutation_operator = None;
+        return outcomes;
+    }
+
     /// Returns the length of the population.
     ///
     /// # Returns
@@ -204,7 +1908,7 @@ impl PopulationTraits for Population {
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// let population = vec![1, 2, 3];
     /// let count = len(&population);
     /// assert_eq!(count, 3);
@@ -212,4 +1916,258 @@ impl PopulationTraits for Population {
     fn len(&self) -> usize {
         return self.population.len();
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::chromosome::Gene;
+    use crate::functions::PrimitiveId;
+
+    use super::*;
+
+    #[test]
+    /// Ensures that every target quantile bin is represented in proportion to its size, without
+    /// drawing a row twice, and that small bins still contribute a row
+    fn test_stratified_sample() {
+        let csv: String = std::iter::once("x0,y".to_string()).chain((0..100).map(|i| format!("{},{}", i, (i * 37) % 100))).collect::<Vec<String>>().join("\n");
+        let dataset = crate::io::read_csv(csv.as_bytes(), &crate::io::DatasetOptions::default());
+        let sample = stratified_sample(&dataset, 20, 4);
+        let mut targets: Vec<f64> = sample.rows.iter().map(|row| row[1]).collect();
+        for quartile in 0..4 {
+            let bin = quartile as f64 * 25.0..(quartile + 1) as f64 * 25.0;
+            assert_eq!(targets.iter().filter(|target| bin.contains(target)).count(), 5, "{:?}", targets);
+        }
+        // The targets are distinct, so a row drawn twice would repeat one
+        targets.sort_by(f64::total_cmp);
+        targets.dedup();
+        assert_eq!(targets.len(), 20);
+        assert!(sample.rows.iter().all(|row| dataset.rows.contains(row)));
+
+        // Bins of 4, 4 and 2 rows give 2, 2 and 1 of 5 rows
+        let sample = stratified_sample(&dataset.with_rows(dataset.rows[..10].to_vec()), 5, 3);
+        let mut targets: Vec<f64> = sample.rows.iter().map(|row| row[1]).collect();
+        targets.sort_by(f64::total_cmp);
+        let mut sorted: Vec<f64> = dataset.rows[..10].iter().map(|row| row[1]).collect();
+        sorted.sort_by(f64::total_cmp);
+        let bins = [&sorted[..4], &sorted[4..8], &sorted[8..]];
+        assert_eq!(bins.map(|bin| targets.iter().filter(|target| bin.contains(target)).count()), [2, 2, 1]);
+        assert_eq!(stratified_sample(&dataset, 100, 4).rows, dataset.rows);
+    }
+
+    #[test]
+    /// Ensures that annealing always accepts fitter children and worse ones less often as it cools
+    fn test_anneal_accepts() {
+        assert!(anneal_accepts(1.0, 2.0, 1e-9));
+        assert!(!anneal_accepts(f64::INFINITY, 2.0, 1e9));
+        assert!((0..100).all(|_| !anneal_accepts(2.2, 2.0, 1e-3)));
+        let accepted = (0..1000).filter(|_| anneal_accepts(2.2, 2.0, 10.0)).count();
+        assert!(accepted > 900, "{}", accepted);
+    }
+
+    #[test]
+    /// Ensures that offspring are shared out in proportion to the scores and add up to the total
+    fn test_allocate() {
+        assert_eq!(allocate(&[1.0, 1.0, 2.0], 8), vec![2, 2, 4]);
+        assert_eq!(allocate(&[1.0, 1.0, 1.0], 10).iter().sum::<usize>(), 10);
+        assert_eq!(allocate(&[3.0, 1.0], 3), vec![2, 1]);
+    }
+
+    #[test]
+    /// Ensures that the epsilon of each row is the median absolute deviation of the finite errors on it
+    fn test_median_absolute_deviations() {
+        let population: Vec<Chromosome> = [[1.0, 0.0], [2.0, f64::NAN], [4.0, 5.0], [8.0, f64::INFINITY], [9.0, 5.0]]
+            .iter()
+            .map(|errors| {
+                let mut chromosome = Chromosome::new();
+                chromosome.case_errors = errors.to_vec();
+                return chromosome;
+            })
+            .collect();
+        // Row 0: median 4, deviations [3, 2, 0, 4, 5]. Row 1: median 5 of [0, 5, 5], deviations [5, 0, 0]
+        assert_eq!(median_absolute_deviations(&population), vec![3.0, 0.0]);
+    }
+
+    #[test]
+    /// Ensures that the chances grow above a fifth of successes, shrink below it and stay within bounds
+    fn test_one_fifth_rule() {
+        assert_eq!(one_fifth_rule(0.5, 0.5), 0.5 / ONE_FIFTH_FACTOR);
+        assert_eq!(one_fifth_rule(0.5, 0.1), 0.5 * ONE_FIFTH_FACTOR);
+        assert_eq!(one_fifth_rule(0.5, 0.2), 0.5);
+        assert_eq!(one_fifth_rule(1.0, 1.0), 1.0);
+        assert_eq!(one_fifth_rule(0.01, 0.0), 0.01);
+        assert_eq!(RateAdaptation::Fixed.adapt((0.5, 0.5), Some(1.0)), (0.5, 0.5));
+        assert_eq!(RateAdaptation::OneFifth.adapt((0.5, 0.5), None), (0.5, 0.5));
+    }
+
+    #[test]
+    /// Ensures that phenotypic diversity averages the spread of outputs over the rows and skips invalid chromosomes
+    fn test_phenotypic_diversity() {
+        let population: Vec<Chromosome> = [(vec![1.0, 0.0], 1.0), (vec![3.0, 0.0], 1.0), (vec![100.0, 100.0], f64::MAX)]
+            .into_iter()
+            .map(|(behavior, fitness)| {
+                let mut chromosome = Chromosome::new();
+                chromosome.behavior = behavior;
+                chromosome.fitness_value = fitness;
+                return chromosome;
+            })
+            .collect();
+        // Row 0 has a standard deviation of 1 and row 1 of 0
+        assert_eq!(phenotypic_diversity(&population), 0.5);
+        assert!(phenotypic_diversity(&[Chromosome::new()]).is_nan());
+    }
+
+    #[test]
+    /// Ensures that novelty is the mean distance to the nearest behaviors only
+    fn test_sparseness() {
+        let others: Vec<Vec<f64>> = vec![vec![0.0], vec![1.0], vec![3.0]];
+        let others = || others.iter().map(|b| b.as_slice());
+        assert_eq!(sparseness(&[0.0], others(), 1), 0.0);
+        assert_eq!(sparseness(&[0.0], others(), 2), (0.0 + 0.5) / 2.0);
+        assert_eq!(sparseness(&[0.0], others(), 10), (0.0 + 0.5 + 0.75) / 3.0);
+        assert_eq!(sparseness(&[0.0], std::iter::empty(), 3), 0.0);
+    }
+
+    #[test]
+    /// Ensures that memetic hill climbing refines children once, counts its evaluations, and leaves rescored survivors alone
+    fn test_memetic_refines_children_once() {
+        let dataset = crate::io::read_csv(crate::io::open_dataset("test.csv"), &crate::io::DatasetOptions::default());
+        let mut population = Population::initialize(10, 20, &dataset, Layout::default());
+        population.memetic = Some(20);
+        population.evaluate(&dataset);
+        assert_eq!(population.evaluations, 10);
+
+        let expressions = |population: &Population| population.population.iter().map(|c| c.function_string()).collect::<Vec<String>>();
+        let before = expressions(&population);
+        population.evaluate(&dataset);
+        // The best and every survivor are scored once more, without being climbed again
+        assert_eq!(population.evaluations, 10 + 11);
+        assert_eq!(expressions(&population), before);
+
+        population.mu_plus_lambda(3, 0.5, 0.5, Selection::Tournament(2), 4, &dataset);
+        // Each child is climbed, which evaluates it at least once more, before it is scored
+        assert!(population.evaluations >= 10 + 11 + 4 * 2, "{}", population.evaluations);
+        assert!(population.evaluations <= 10 + 11 + 4 * (1 + 21), "{}", population.evaluations);
+    }
+
+    #[test]
+    /// Ensures that (1+λ) breeds its children into a separate buffer, keeps the size of the
+    /// population, and never loses the parent
+    fn test_one_plus_lambda_keeps_population_size() {
+        let dataset = crate::io::read_csv(crate::io::open_dataset("test.csv"), &crate::io::DatasetOptions::default());
+        let mut population = Population::initialize(11, 20, &dataset, Layout::default());
+        population.evaluate(&dataset);
+        for _ in 0..5 {
+            let parent = population.best.fitness_value;
+            let best = population.one_plus_lambda(3, 4, &dataset);
+            assert!(best <= parent);
+            assert_eq!((population.len(), population.offspring.len()), (11, 4));
+        }
+        assert_eq!(population.offspring_outcomes().len(), 4);
+    }
+
+    #[test]
+    /// Ensures that crowding rejects children beyond the complexity limits, like the other strategies
+    fn test_crowding_respects_limits() {
+        let dataset = crate::io::read_csv(crate::io::open_dataset("test.csv"), &crate::io::DatasetOptions::default());
+        let mut population = Population::initialize(10, 20, &dataset, Layout::default());
+        let max_depth = population.population.iter().map(Chromosome::depth).max();
+        population.limits = ComplexityLimits { max_depth, ..ComplexityLimits::default() };
+        population.evaluate(&dataset);
+        for _ in 0..10 {
+            population.crowding(dataset.get_num_variables(), 1.0, 1.0, &dataset);
+            assert!(population.population.iter().all(|c| c.within(&population.limits)));
+        }
+    }
+
+    #[test]
+    /// Ensures that chromosomes the prescreen culls get the worst fitness without counting as evaluations
+    fn test_culled_chromosomes_are_not_evaluations() {
+        let dataset = crate::io::read_csv(crate::io::open_dataset("test.csv"), &crate::io::DatasetOptions::default());
+        let log_of_negative = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_unary2(0, PrimitiveId::NEG),
+                                                                    Gene::new_unary2(1, PrimitiveId::LOG).protected(Protection::Nan)]);
+        let valid = Chromosome::new_from_string("x0 + x1", &dataset.variable_names);
+        let mut population = Population::initialize(3, 20, &dataset, Layout::default());
+        population.population = vec![log_of_negative.clone(), valid.clone(), log_of_negative];
+        population.best = valid;
+        population.bounds = Some(crate::interval::variable_bounds(&dataset));
+        population.evaluate(&dataset);
+        // Only the valid chromosome and the best, which is rescored, were evaluated
+        assert_eq!(population.evaluations, 2);
+        assert_eq!(population.population.iter().filter(|c| c.fitness_value == f64::MAX).count(), 2);
+    }
+
+    #[test]
+    /// Ensures that roulette selection favours fitter chromosomes in proportion to their weights,
+    /// also when the fitness is negative
+    fn test_roulette_selection() {
+        let dataset = crate::io::read_csv(crate::io::open_dataset("test.csv"), &crate::io::DatasetOptions::default());
+        let mut population = Population::initialize(4, 20, &dataset, Layout::default());
+        for (chromosome, fitness) in population.population.iter_mut().zip([-3.0, -2.0, 1.0, f64::MAX]) {
+            chromosome.fitness_value = fitness;
+        }
+        // Shifted up by 3, the valid fitness values are 0, 1 and 4
+        assert_eq!(roulette_weights(&population.population), vec![1.0, 0.5, 0.2, 0.0]);
+
+        rng::seed(1);
+        let mut counts = [0usize; 4];
+        for _ in 0..17_000 {
+            let chosen = population.roulette_selection();
+            counts[population.population.iter().position(|c| std::ptr::eq(c, chosen)).unwrap()] += 1;
+        }
+        // The weights sum to 1.7, so the expected counts are 10000, 5000 and 2000
+        assert_eq!(counts[3], 0);
+        for (count, expected) in counts.iter().zip([10_000.0, 5_000.0, 2_000.0]) {
+            assert!((*count as f64 - expected).abs() < expected * 0.05, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    /// Ensures that linear and exponential ranking map ranks to the intended probabilities, and
+    /// that both favour the best chromosome
+    fn test_rank_selection_probabilities() {
+        let weights: Vec<f64> = (0..5).map(|rank| linear_rank_weight(rank, 5, 1.5)).collect();
+        assert_eq!(weights, vec![1.5, 1.25, 1.0, 0.75, 0.5]);
+        assert_eq!(linear_rank_weight(0, 1, 1.5), 1.0);
+
+        // Evenly spaced draws hit each rank in proportion to its probability
+        let (n, base, draws) = (4, 0.5, 15_000);
+        let mut counts = [0usize; 4];
+        for i in 0..draws {
+            counts[exponential_rank(i as f64 / draws as f64, n, base)] += 1;
+        }
+        // 0.5^r / (1 - 0.5^4) * 0.5 gives 8/15, 4/15, 2/15 and 1/15
+        assert_eq!(counts, [8_000, 4_000, 2_000, 1_000]);
+
+        let dataset = crate::io::read_csv(crate::io::open_dataset("test.csv"), &crate::io::DatasetOptions::default());
+        let mut population = Population::initialize(5, 20, &dataset, Layout::default());
+        for (i, chromosome) in population.population.iter_mut().enumerate() {
+            chromosome.fitness_value = 5.0 - i as f64;
+        }
+        rng::seed(1);
+        for selection in [Selection::LinearRank { pressure: 2.0 }, Selection::ExponentialRank { base: 0.5 }] {
+            let mut counts = [0usize; 5];
+            for _ in 0..5_000 {
+                let chosen = population.select(selection);
+                counts[population.population.iter().position(|c| std::ptr::eq(c, chosen)).unwrap()] += 1;
+            }
+            // The last chromosome is the fittest, so it is drawn most often and the first the least
+            assert!(counts.windows(2).all(|pair| pair[0] < pair[1]), "{:?}: {:?}", selection, counts);
+        }
+    }
+
+    #[test]
+    /// Ensures that the evaluations of fitting the constants with Levenberg-Marquardt are counted
+    fn test_local_learning_evaluations() {
+        let dataset = crate::io::read_csv(crate::io::open_dataset("test.csv"), &crate::io::DatasetOptions::default());
+        let chromosome = Chromosome::new_from_string("x0 * x1 + -4 * x2", &dataset.variable_names);
+        let mut population = Population::initialize(3, 20, &dataset, Layout::default());
+        population.population = vec![chromosome.clone(); 3];
+        population.best = chromosome;
+        population.evaluate(&dataset);
+        let plain = population.evaluations;
+
+        population.local_learning = Some(5);
+        population.evaluate(&dataset);
+        // Every fit evaluates the chromosome at least once, and twice more to compare the constants
+        assert!(population.evaluations - plain >= 3 * plain, "{} after {}", population.evaluations, plain);
+    }
+}
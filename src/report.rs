@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chromosome::{Chromosome, Complexity};
+use crate::chromosome::GeneType::Variable;
+use crate::fitness::{Accuracy, AveragePrecision, check_labels, ConfusionMatrix, FitnessMetric, LogLoss, Mae, Mse, RocAuc, RSquared, Rmse, Task};
+use crate::io::Dataset;
+use crate::population::PopulationParameters;
+
+/// How well a chromosome does on one dataset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scores {
+    /// The fitness the run minimized, see `Chromosome::fitness`.
+    pub fitness: f64,
+    /// The value of each metric by name. Scores such as R² or the accuracy are reported as
+    /// themselves rather than as the error that is minimized. `None` when the chromosome is invalid
+    /// under the metric.
+    pub metrics: BTreeMap<String, Option<f64>>,
+    /// `confusion_matrix[actual][predicted]` for classification runs.
+    pub confusion_matrix: Option<Vec<Vec<usize>>>,
+}
+
+impl Scores {
+    /// The metrics that are usually reported for `task`: the MSE, MAE, RMSE and R² of regressors,
+    /// and the log-loss and accuracy of classifiers of `classes` classes, with the ROC-AUC and
+    /// average precision of binary classifiers.
+    pub fn metrics(task: Task, classes: usize) -> Vec<Arc<dyn FitnessMetric>> {
+        return match (task, classes) {
+            (Task::Regression, _) => vec![Arc::new(Mse), Arc::new(Mae), Arc::new(Rmse), Arc::new(RSquared)],
+            (Task::Classification, 2) => vec![Arc::new(LogLoss), Arc::new(Accuracy), Arc::new(RocAuc), Arc::new(AveragePrecision)],
+            (Task::Classification, _) => vec![Arc::new(LogLoss), Arc::new(Accuracy)],
+        };
+    }
+
+    /// Scores `chromosome` on `dataset` under `metrics`, with a confusion matrix when it classifies `classes` classes.
+    ///
+    /// # Panics
+    ///
+    /// When a label of a classification dataset is not one of the `classes`, see `check_labels`.
+    pub fn new(chromosome: &Chromosome, dataset: &Dataset, metrics: &[Arc<dyn FitnessMetric>], classes: usize) -> Scores {
+        if classes > 0 {
+            check_labels(dataset, classes);
+        }
+        let mut scored = chromosome.clone();
+        let metrics = metrics.iter().map(|metric| {
+            Arc::make_mut(&mut scored.layout).metric = Arc::clone(metric);
+            let error = scored.error(dataset);
+            let value = (error < f64::MAX).then(|| if metric.is_complement() { 1.0 - error } else { error });
+            (metric.name().to_string(), value)
+        }).collect();
+        return Scores {
+            fitness: chromosome.fitness(dataset),
+            metrics,
+            confusion_matrix: (classes > 0).then(|| ConfusionMatrix::new(chromosome, dataset, classes).counts),
+        };
+    }
+}
+
+impl Display for Scores {
+    /// E.g. `fitness 0.25, mse 0.25, r2 0.98`, followed by the confusion matrix, if any.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "fitness {}", self.fitness)?;
+        for (name, value) in &self.metrics {
+            match value {
+                Some(value) => write!(f, ", {} {}", name, value)?,
+                None => write!(f, ", {} invalid", name)?,
+            }
+        }
+        if let Some(counts) = &self.confusion_matrix {
+            write!(f, "\n{}", ConfusionMatrix { counts: counts.clone() })?;
+        }
+        return Ok(());
+    }
+}
+
+/// Everything about the result of a run: the expression, how well it does, how complex it is,
+/// and how the run went. Logged at the end of every run, and written as JSON with `--report`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// The expression as printed at the end of the run.
+    pub expression: String,
+    /// The expression in each notation, by name.
+    pub notations: BTreeMap<String, String>,
+    pub training: Scores,
+    pub validation: Option<Scores>,
+    /// The MSE on the training set in the original units of the target, when it was normalized.
+    pub original_scale_mse: Option<f64>,
+    pub complexity: Complexity,
+    /// How many active genes read each variable the expression uses, by name.
+    pub variable_usage: BTreeMap<String, usize>,
+    /// The command line of the run.
+    pub arguments: Vec<String>,
+    /// The main parameters of the run, by name.
+    pub parameters: BTreeMap<String, String>,
+    /// How many generations ran, fewer than asked for when the run stopped early.
+    pub generations: usize,
+    pub evaluations: usize,
+    pub elapsed_seconds: f64,
+    /// The seed the run was started with, which repeats the run with `--seed`.
+    pub seed: u64,
+}
+
+impl Report {
+    /// The report of a run whose result is `best`.
+    ///
+    /// # Arguments
+    ///
+    /// * `best` - The chromosome the run produced, evaluated on `dataset`.
+    /// * `dataset` - The training set.
+    /// * `validation` - The validation set, if part of the data was held out.
+    /// * `params` - The parameters of the run.
+    /// * `classes` - How many classes a classification run has, 0 for regression.
+    /// * `generations` - How many generations ran.
+    /// * `evaluations` - How many chromosomes were evaluated.
+    /// * `elapsed` - How long the run took.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(best: &Chromosome, dataset: &Dataset, validation: Option<&Dataset>, params: &PopulationParameters, classes: usize,
+               generations: usize, evaluations: usize, elapsed: Duration) -> Report {
+        let mut metrics = Scores::metrics(params.task, classes);
+        if !metrics.contains(&params.metric) {
+            metrics.push(Arc::clone(&params.metric));
+        }
+        let expression = best.expression(params.notation, &dataset.variable_names);
+        let mut variable_usage = BTreeMap::new();
+        for pos in best.active_genes() {
+            if let Variable(variable) = best.genes[pos].type_of_gene {
+                *variable_usage.entry(dataset.variable_names[variable].clone()).or_insert(0) += 1;
+            }
+        }
+        let parameters = BTreeMap::from([
+            ("task", format!("{:?}", params.task)),
+            ("metric", params.metric.name().to_string()),
+            ("strategy", format!("{:?}", params.strategy)),
+            ("selection", format!("{:?}", params.selection)),
+            ("generations", params.generations.to_string()),
+            ("population_size", params.population_size.to_string()),
+            ("genes", params.num_genes.to_string()),
+            ("mutation_chance", params.mut_chance.to_string()),
+            ("crossover_chance", params.crossover_chance.to_string()),
+        ]).into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+        return Report {
+            notations: BTreeMap::from([
+                ("infix".to_string(), best.to_infix(&dataset.variable_names)),
+                ("prefix".to_string(), best.function_string_with_names(&dataset.variable_names)),
+                ("sexpr".to_string(), best.to_sexpr(&dataset.variable_names)),
+                ("latex".to_string(), best.to_latex(&dataset.variable_names)),
+            ]),
+            expression,
+            training: Scores::new(best, dataset, &metrics, classes),
+            validation: validation.map(|validation| Scores::new(best, validation, &metrics, classes)),
+            original_scale_mse: dataset.target_scaler.map(|_| best.original_scale_mse(dataset)),
+            complexity: best.complexity(),
+            variable_usage,
+            arguments: std::env::args().collect(),
+            parameters,
+            generations,
+            evaluations,
+            elapsed_seconds: elapsed.as_secs_f64(),
+            seed: params.seed,
+        };
+    }
+
+    /// Writes the report to a JSON file.
+    ///
+    /// # Panics
+    ///
+    /// When the file cannot be written.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn save(&self, file_name: &str) {
+        let file = File::create(file_name).unwrap_or_else(|e| panic!("Could not create {}: {}", file_name, e));
+        serde_json::to_writer_pretty(BufWriter::new(file), self).expect("Problem writing the report");
+    }
+}
+
+impl Display for Report {
+    /// One section per line, for people to read.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "expression: {}", self.expression)?;
+        writeln!(f, "training: {}", self.training)?;
+        if let Some(validation) = &self.validation {
+            writeln!(f, "validation: {}", validation)?;
+        }
+        if let Some(mse) = self.original_scale_mse {
+            writeln!(f, "MSE in original units: {}", mse)?;
+        }
+        writeln!(f, "complexity: {}", self.complexity)?;
+        let usage: Vec<String> = self.variable_usage.iter().map(|(name, count)| format!("{} {}", name, count)).collect();
+        writeln!(f, "variables: {}", if usage.is_empty() { "none".to_string() } else { usage.join(", ") })?;
+        write!(f, "run: {} generations, {} evaluations, {:.3} s, seed {}", self.generations, self.evaluations, self.elapsed_seconds, self.seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chromosome::Gene;
+    use crate::fitness::metric_named;
+    use crate::functions::PrimitiveId;
+    use crate::io::{DatasetOptions, open_dataset, read_csv};
+
+    use super::*;
+
+    #[test]
+    /// Ensures that the scores report R² as itself rather than as the error that is minimized, and
+    /// that a regression run has no confusion matrix
+    fn test_scores() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let mut chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::ADD)]);
+        let mse = chromosome.evaluate_fitness_error(&dataset);
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::new(RSquared);
+        let r2 = 1.0 - chromosome.evaluate_fitness_error(&dataset);
+        Arc::make_mut(&mut chromosome.layout).metric = Arc::new(Mse);
+
+        let scores = Scores::new(&chromosome, &dataset, &[metric_named("mse"), metric_named("r2")], 0);
+        assert_eq!(scores.fitness, mse);
+        assert_eq!(scores.metrics, BTreeMap::from([("mse".to_string(), Some(mse)), ("r2".to_string(), Some(r2))]));
+        assert_eq!(scores.confusion_matrix, None);
+        assert!(scores.to_string().starts_with(&format!("fitness {}, mse {}", mse, mse)));
+    }
+
+    #[test]
+    /// Ensures that a report read back from its JSON equals the report that was written
+    fn test_report_json_round_trip() {
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let chromosome = Chromosome::new_from_genes_array(vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_binary2(0, 1, PrimitiveId::DIV)]);
+        let scores = Scores::new(&chromosome, &dataset, &[metric_named("mse"), metric_named("r2")], 0);
+        let report = Report {
+            expression: chromosome.to_infix(&dataset.variable_names),
+            notations: BTreeMap::from([("sexpr".to_string(), chromosome.to_sexpr(&dataset.variable_names))]),
+            training: scores.clone(),
+            validation: Some(scores),
+            original_scale_mse: None,
+            complexity: chromosome.complexity(),
+            variable_usage: BTreeMap::from([("x0".to_string(), 1), ("x1".to_string(), 1)]),
+            arguments: vec!["rust_gp".to_string(), "--seed".to_string(), "3".to_string()],
+            parameters: BTreeMap::from([("generations".to_string(), "5".to_string())]),
+            generations: 5,
+            evaluations: 1234,
+            elapsed_seconds: 0.1,
+            seed: 3,
+        };
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<Report>(&json).unwrap(), report);
+    }
+}
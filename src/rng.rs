@@ -0,0 +1,98 @@
+//! The random number generator every random choice of a run is drawn from.
+//!
+//! Each thread holds a `StdRng`, which `rng` hands out like `rand::thread_rng`. A run seeds the
+//! generator of its thread once with `seed`. Work that is spread over threads first draws one seed
+//! per task from that generator with `seeds`, and runs each task under its own seed with
+//! `with_seed`, so a seeded run makes the same choices whichever thread runs a task.
+
+use std::cell::RefCell;
+
+use rand::distributions::{Distribution, Standard};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+thread_local! {
+    static GENERATOR: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// A handle to the generator of the current thread, see `rng`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunRng;
+
+impl RngCore for RunRng {
+    fn next_u32(&mut self) -> u32 {
+        return GENERATOR.with(|generator| generator.borrow_mut().next_u32());
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        return GENERATOR.with(|generator| generator.borrow_mut().next_u64());
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        GENERATOR.with(|generator| generator.borrow_mut().fill_bytes(dest));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        return GENERATOR.with(|generator| generator.borrow_mut().try_fill_bytes(dest));
+    }
+}
+
+/// Returns the generator of the current thread. Unless it was seeded, it starts from entropy.
+pub fn rng() -> RunRng {
+    return RunRng;
+}
+
+/// Draws a random value from the generator of the current thread, like `rand::random`.
+pub fn random<T>() -> T where Standard: Distribution<T> {
+    return rng().gen();
+}
+
+/// Seeds the generator of the current thread, so that the run it drives can be repeated.
+pub fn seed(seed: u64) {
+    GENERATOR.with(|generator| *generator.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Draws a seed for each of `count` tasks that are about to run in parallel, see `with_seed`.
+pub fn seeds(count: usize) -> Vec<u64> {
+    return (0..count).map(|_| random()).collect();
+}
+
+/// Runs `task` with the generator of the current thread seeded with `seed`, and then restores the
+/// generator it had before.
+///
+/// # Arguments
+///
+/// * `seed` - One of the seeds drawn by `seeds` before the parallel work started.
+/// * `task` - The work of a single task, which draws from `rng` as usual.
+pub fn with_seed<T>(seed: u64, task: impl FnOnce() -> T) -> T {
+    let outer = GENERATOR.with(|generator| generator.replace(StdRng::seed_from_u64(seed)));
+    let result = task();
+    GENERATOR.with(|generator| *generator.borrow_mut() = outer);
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    use super::*;
+
+    #[test]
+    /// Ensures that a seed repeats the draws, also of tasks spread over threads, and that a task
+    /// leaves the generator of its thread as it found it
+    fn test_seeded_draws() {
+        let draws = || {
+            seed(42);
+            let first: u64 = random();
+            let tasks: Vec<u64> = seeds(64).into_par_iter().map(|task| with_seed(task, || rng().gen_range(0..1000))).collect();
+            return (first, tasks, random::<u64>());
+        };
+        assert_eq!(draws(), draws());
+
+        seed(7);
+        let expected: u64 = random();
+        seed(7);
+        with_seed(1, random::<u64>);
+        assert_eq!(random::<u64>(), expected);
+    }
+}
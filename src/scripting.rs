@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use crate::functions::FunctionRegistry;
+
+/// A loaded script and the engine that runs it.
+struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    /// Calls the function `name` of the script on `inputs`.
+    ///
+    /// # Panics
+    ///
+    /// When the function fails or does not return a number.
+    fn call(&self, name: &str, inputs: &[f64]) -> f64 {
+        let result = match *inputs {
+            [x] => self.engine.call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, (x,)),
+            [x, y] => self.engine.call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, (x, y)),
+            _ => unreachable!("Primitives take one or two inputs"),
+        };
+        let value = result.unwrap_or_else(|e| panic!("The primitive {} failed: {}", name, e));
+        return value.as_float()
+            .or_else(|_| value.as_int().map(|value| value as f64))
+            .unwrap_or_else(|type_name| panic!("The primitive {} returned {} instead of a number", name, type_name));
+    }
+}
+
+/// Loads user-defined primitives from the Rhai script at `path`. Every function of the script
+/// becomes a primitive with the same name, which function sets can select like the built-in ones,
+/// see `FunctionRegistry::register`. For example, `fn hypot(x, y) { sqrt(x * x + y * y) }` adds a
+/// binary `hypot`.
+///
+/// # Arguments
+///
+/// * `path` - The script file.
+///
+/// # Returns
+///
+/// The names of the new primitives, in alphabetical order.
+///
+/// # Panics
+///
+/// When the script does not compile, or a function cannot be registered.
+pub fn load_primitives(path: &str) -> Vec<String> {
+    let engine = Engine::new();
+    let ast = engine.compile_file(path.into()).unwrap_or_else(|e| panic!("Could not compile {}: {}", path, e));
+    let mut functions: Vec<(String, usize)> = ast.iter_functions().map(|function| (function.name.to_string(), function.params.len())).collect();
+    functions.sort();
+    let script = Arc::new(Script { engine, ast });
+    for (name, arity) in &functions {
+        let (script, function, arity) = (script.clone(), name.clone(), *arity);
+        FunctionRegistry::register(name, arity, move |x, y| script.call(&function, &[x, y][..arity]), None);
+    }
+    return functions.into_iter().map(|(name, _)| name).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::functions::FunctionSet;
+
+    use super::*;
+
+    #[test]
+    /// Ensures that the functions of a script become primitives that function sets can select
+    fn test_load_primitives() {
+        let path = std::env::temp_dir().join("rust_gp_primitives.rhai");
+        fs::write(&path, "fn hypot(x, y) { sqrt(x * x + y * y) }\nfn twice(x) { 2 * x }\nfn one(x) { 1 }").unwrap();
+        assert_eq!(load_primitives(path.to_str().unwrap()), vec!["hypot", "one", "twice"]);
+
+        let set = FunctionSet::from_names(&["hypot", "twice", "one"]);
+        assert_eq!(set.functions(2).iter().map(|function| function(3.0, 4.0)).collect::<Vec<_>>(), vec![(5.0, "hypot".to_string())]);
+        assert_eq!(set.functions(1).iter().map(|function| function(1.5, 0.0).0).collect::<Vec<_>>(), vec![1.0, 3.0]);
+        let has_hypot = |set: FunctionSet| set.functions(2).iter().any(|function| function(0.0, 0.0).1 == "hypot");
+        assert!(!has_hypot(FunctionSet::arithmetic()));
+        assert!(has_hypot(FunctionSet::arithmetic().including(&["hypot"])));
+        fs::remove_file(path).unwrap();
+    }
+}
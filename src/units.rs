@@ -0,0 +1,237 @@
+use std::ops::{Add, Neg, Sub};
+
+use crate::chromosome::Chromosome;
+use crate::chromosome::GeneType::{Conditional, Constant, Variable};
+use crate::functions::PrimitiveId;
+use crate::io::Dataset;
+
+/// The SI base units, in the order of the exponents of a `Dimension`.
+pub const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// A physical dimension, as the exponent of each SI base unit, e.g. `m/s^2` is `[1, 0, -2, 0, 0, 0, 0]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dimension(pub [i32; 7]);
+
+impl Dimension {
+    /// The dimension of pure numbers.
+    pub const DIMENSIONLESS: Dimension = Dimension([0; 7]);
+
+    /// Parses a product of powers of base units, divided by any others, e.g. `kg*m^2/s^2` or `m/s/s`.
+    /// `1` or an empty string is dimensionless.
+    ///
+    /// # Panics
+    ///
+    /// When a factor is not a base unit or its exponent is not an integer.
+    pub fn parse(units: &str) -> Dimension {
+        let mut dimension = Dimension::DIMENSIONLESS;
+        for (i, part) in units.split('/').enumerate() {
+            let sign = if i == 0 { 1 } else { -1 };
+            for factor in part.split('*').map(str::trim).filter(|factor| !factor.is_empty() && *factor != "1") {
+                let (unit, exponent) = factor.split_once('^').unwrap_or((factor, "1"));
+                let Some(base) = BASE_UNITS.iter().position(|&base| base == unit) else {
+                    panic!("Unknown unit {} in {}, the units are {}", unit, units, BASE_UNITS.join(", "));
+                };
+                let exponent: i32 = exponent.parse().unwrap_or_else(|_| panic!("The exponent of {} in {} must be an integer", unit, units));
+                dimension.0[base] += sign * exponent;
+            }
+        }
+        return dimension;
+    }
+
+    /// The dimension multiplied by itself `power` times.
+    fn pow(self, power: i32) -> Dimension {
+        return Dimension(self.0.map(|exponent| exponent * power));
+    }
+
+    /// The square root of the dimension, `None` when an exponent is odd.
+    fn sqrt(self) -> Option<Dimension> {
+        return self.0.iter().all(|exponent| exponent % 2 == 0).then(|| Dimension(self.0.map(|exponent| exponent / 2)));
+    }
+}
+
+impl Add for Dimension {
+    type Output = Dimension;
+
+    /// The dimension of a product.
+    fn add(self, other: Dimension) -> Dimension {
+        return Dimension(std::array::from_fn(|i| self.0[i] + other.0[i]));
+    }
+}
+
+impl Sub for Dimension {
+    type Output = Dimension;
+
+    /// The dimension of a quotient.
+    fn sub(self, other: Dimension) -> Dimension {
+        return self + -other;
+    }
+}
+
+impl Neg for Dimension {
+    type Output = Dimension;
+
+    /// The dimension of a reciprocal.
+    fn neg(self) -> Dimension {
+        return self.pow(-1);
+    }
+}
+
+/// The physical units of the columns of a dataset, which let dimensionally inconsistent
+/// expressions, such as a length plus a time or the sine of a mass, be rejected or penalized.
+///
+/// Constants take whatever dimension makes the expression consistent, so `c * t^2` can be a length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Units {
+    /// The dimension of each variable.
+    pub variables: Vec<Dimension>,
+    /// The dimension of each target.
+    pub targets: Vec<Dimension>,
+    /// Multiply the error of a chromosome by one plus this much per inconsistency. `None` rejects
+    /// inconsistent chromosomes instead: they are not bred, and get the worst possible fitness.
+    pub penalty: Option<f64>,
+}
+
+impl Units {
+    /// The units of the columns of `dataset`, from `name=units` annotations. Columns without an
+    /// annotation are dimensionless.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - The dataset whose variable and target names the annotations use.
+    /// * `annotations` - The units of some columns, e.g. `("v", "m/s")`, see `Dimension::parse`.
+    /// * `penalty` - See `Units::penalty`.
+    ///
+    /// # Panics
+    ///
+    /// When an annotation names no column of the dataset, or its units cannot be parsed.
+    pub fn new(dataset: &Dataset, annotations: &[(&str, &str)], penalty: Option<f64>) -> Units {
+        let mut units = Units {
+            variables: vec![Dimension::DIMENSIONLESS; dataset.variable_names.len()],
+            targets: vec![Dimension::DIMENSIONLESS; dataset.target_names.len()],
+            penalty,
+        };
+        for &(name, dimension) in annotations {
+            let column = match (dataset.variable_names.iter().position(|n| n == name), dataset.target_names.iter().position(|n| n == name)) {
+                (Some(variable), _) => &mut units.variables[variable],
+                (None, Some(target)) => &mut units.targets[target],
+                (None, None) => panic!("Cannot give units to {}, which is not a column of the dataset", name),
+            };
+            *column = Dimension::parse(dimension);
+        }
+        return units;
+    }
+
+    /// Counts the dimensional inconsistencies of the expression the chromosome encodes: active genes
+    /// whose inputs have dimensions their function does not accept, and outputs whose dimension
+    /// differs from their target's. Multi-gene chromosomes are not checked against their targets,
+    /// since their weights can have any dimension.
+    pub fn violations(&self, chromosome: &Chromosome) -> usize {
+        // None when the value only depends on constants, which can take any dimension
+        let mut dimensions: Vec<Option<Dimension>> = vec![None; chromosome.genes.len()];
+        let mut violations = 0;
+        for pos in chromosome.active_genes() {
+            let gene = &chromosome.genes[pos];
+            let inputs: Vec<Option<Dimension>> = gene.inputs().into_iter().map(|input| dimensions[input]).collect();
+            dimensions[pos] = match gene.type_of_gene {
+                Constant(_) => Some(None),
+                Variable(variable) => Some(Some(self.variables[variable])),
+                Conditional => same(inputs[0], inputs[1]).and_then(|_| same(inputs[2], inputs[3])),
+                _ => infer(gene.primitive, &inputs),
+            }.unwrap_or_else(|| {
+                // Counted once, and the value taken as free so that it is not counted again downstream
+                violations += 1;
+                None
+            });
+        }
+        if chromosome.weights.is_none() {
+            violations += chromosome.output_genes().iter().zip(&self.targets)
+                .filter(|(&output, &target)| dimensions[output].is_some_and(|dimension| dimension != target))
+                .count();
+        }
+        return violations;
+    }
+
+    /// The fitness of a chromosome with the error `fitness`, given its dimensional consistency.
+    pub fn score(&self, chromosome: &Chromosome, fitness: f64) -> f64 {
+        let violations = self.violations(chromosome);
+        return match self.penalty {
+            _ if violations == 0 => fitness,
+            Some(penalty) => (fitness * (1.0 + penalty * violations as f64)).min(f64::MAX),
+            None => f64::MAX,
+        };
+    }
+
+    /// Whether a chromosome may be bred: always when inconsistencies are penalized, and only when it
+    /// has none when they are rejected.
+    pub fn allows(&self, chromosome: &Chromosome) -> bool {
+        return self.penalty.is_some() || self.violations(chromosome) == 0;
+    }
+}
+
+/// Both dimensions when they are the same, or the known one when the other is free. `None` as the
+/// outer value when they differ.
+fn same(a: Option<Dimension>, b: Option<Dimension>) -> Option<Option<Dimension>> {
+    return match (a, b) {
+        (Some(a), Some(b)) if a != b => None,
+        _ => Some(a.or(b)),
+    };
+}
+
+/// The dimension of the output of the unary or binary `primitive` applied to inputs of dimensions
+/// `inputs`, `None` as the outer value when the function does not accept them. Unknown functions,
+/// such as registered primitives, take and return dimensionless values.
+fn infer(primitive: PrimitiveId, inputs: &[Option<Dimension>]) -> Option<Option<Dimension>> {
+    let dimensionless = || inputs.iter().all(|input| input.is_none_or(|dimension| dimension == Dimension::DIMENSIONLESS))
+        .then_some(Some(Dimension::DIMENSIONLESS));
+    let product = |combine: fn(Dimension, Dimension) -> Dimension| Some(inputs[0].zip(inputs[1]).map(|(a, b)| combine(a, b)));
+    return match (primitive, inputs.len()) {
+        (PrimitiveId::ADD | PrimitiveId::SUB | PrimitiveId::MAX | PrimitiveId::MIN, 2) => same(inputs[0], inputs[1]),
+        (PrimitiveId::MUL, 2) => product(|a, b| a + b),
+        (PrimitiveId::DIV, 2) => product(|a, b| a - b),
+        (PrimitiveId::LT | PrimitiveId::GT, 2) => same(inputs[0], inputs[1]).map(|_| Some(Dimension::DIMENSIONLESS)),
+        (PrimitiveId::NEG | PrimitiveId::ABS | PrimitiveId::FLOOR, 1) => Some(inputs[0]),
+        (PrimitiveId::SQUARE, 1) => Some(inputs[0].map(|dimension| dimension.pow(2))),
+        (PrimitiveId::CUBE, 1) => Some(inputs[0].map(|dimension| dimension.pow(3))),
+        (PrimitiveId::INV, 1) => Some(inputs[0].map(|dimension| -dimension)),
+        (PrimitiveId::SQRT, 1) => match inputs[0] {
+            Some(dimension) => dimension.sqrt().map(Some),
+            None => Some(None),
+        },
+        _ => dimensionless(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chromosome::Gene;
+    use crate::functions::PrimitiveId;
+    use crate::io::{DatasetOptions, open_dataset, read_csv};
+
+    use super::*;
+
+    #[test]
+    /// Ensures that units are parsed, and that inconsistent expressions are found with constants taking any units
+    fn test_units() {
+        assert_eq!(Dimension::parse("kg*m^2/s^2"), Dimension([2, 1, -2, 0, 0, 0, 0]));
+        assert_eq!(Dimension::parse("m/s/s"), Dimension::parse("m*s^-2"));
+        assert_eq!(Dimension::parse("1"), Dimension::DIMENSIONLESS);
+
+        // x0 is a length, x1 a time and y a speed
+        let dataset = read_csv(open_dataset("test.csv"), &DatasetOptions::default());
+        let units = Units::new(&dataset, &[("x0", "m"), ("x1", "s"), ("y", "m/s")], None);
+        let terminals = || vec![Gene::new_variable(0), Gene::new_variable(1), Gene::new_constant(Some(2.0)), Gene::new_variable(2)];
+        let violations = |genes: Vec<Gene>| units.violations(&Chromosome::new_from_genes_array(terminals().into_iter().chain(genes).collect()));
+        assert_eq!(violations(vec![Gene::new_binary2(0, 1, PrimitiveId::DIV)]), 0);
+        assert_eq!(violations(vec![Gene::new_binary2(0, 2, PrimitiveId::MUL), Gene::new_binary2(4, 1, PrimitiveId::DIV)]), 0, "constants take any units");
+        assert_eq!(violations(vec![Gene::new_binary2(0, 1, PrimitiveId::ADD)]), 1, "a length plus a time, counted once");
+        assert_eq!(violations(vec![Gene::new_unary2(0, PrimitiveId::SIN), Gene::new_binary2(4, 1, PrimitiveId::MUL)]), 1, "the sine of a length");
+        assert_eq!(violations(vec![Gene::new_unary2(0, PrimitiveId::SQUARE), Gene::new_unary2(4, PrimitiveId::SQRT), Gene::new_binary2(5, 1, PrimitiveId::DIV)]), 0);
+        assert_eq!(violations(vec![Gene::new_unary2(0, PrimitiveId::SQRT), Gene::new_binary2(0, 4, PrimitiveId::DIV)]), 1, "the square root of a length");
+        assert_eq!(violations(vec![Gene::new_binary2(3, 3, PrimitiveId::ADD)]), 1, "a dimensionless output for a speed");
+
+        let penalized = Units { penalty: Some(0.5), ..units.clone() };
+        let inconsistent = Chromosome::new_from_genes_array(terminals().into_iter().chain([Gene::new_binary2(0, 1, PrimitiveId::ADD), Gene::new_binary2(4, 3, PrimitiveId::ADD)]).collect());
+        assert_eq!((units.score(&inconsistent, 2.0), penalized.score(&inconsistent, 2.0)), (f64::MAX, 4.0));
+        assert!(!units.allows(&inconsistent) && penalized.allows(&inconsistent));
+    }
+}